@@ -6,9 +6,10 @@ use near_sdk::{
 };
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::UnorderedMap;
-use near_sdk::json_types::U128;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
 
-use crate::utils::{ext_self, NO_DEPOSIT, ONE_YOCTO, GAS_FOR_FT_TRANSFER, GAS_FOR_RESOLVE_TRANSFER};
+use crate::utils::{ext_self, NO_DEPOSIT, ONE_YOCTO};
 use crate::*;
 
 const U32_STORAGE: StorageUsage = 4;
@@ -28,28 +29,80 @@ const ACC_ID_AS_CLT_KEY_STORAGE: StorageUsage = ACC_ID_AS_KEY_STORAGE + 1;
 // + U128_STORAGE: near_amount storage
 // + U32_STORAGE: tokens HashMap length
 // + U64_STORAGE: storage_used
+// The nonce and last_action_timestamp are not charged, so that accounts registered
+// at the minimum deposit before they were added can still be saved.
 pub const INIT_ACCOUNT_STORAGE: StorageUsage =
     ACC_ID_AS_CLT_KEY_STORAGE + 1 + U128_STORAGE + U32_STORAGE + U64_STORAGE;
 
+// Each registered token: key prefix + token id as key + U128_STORAGE: balance
+const TOKEN_STORAGE: StorageUsage = KEY_PREFIX_ACC + ACC_ID_AS_KEY_STORAGE + U128_STORAGE;
+
+/// Versioned account, older versions are upgraded to `Current` when read
+/// and stored in the new layout on the next save.
+/// New versions must be added right before `Current`, keeping the borsh discriminants of the older ones.
 #[derive(BorshDeserialize, BorshSerialize)]
 pub enum VAccount {
+    V1(AccountV1),
     Current(Account),
 }
 
 impl VAccount {
-    pub fn into_current(self, _account_id: &AccountId) -> Account {
+    pub fn into_current(self, account_id: &AccountId) -> Account {
         match self {
+            VAccount::V1(account) => account.into_current(account_id),
             VAccount::Current(account) => account,
         }
     }
 }
 
+/// Account layout before operation nonce and action timestamp were tracked.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct AccountV1 {
+    pub near_amount: Balance,
+    pub tokens: UnorderedMap<AccountId, Balance>,
+    pub storage_used: StorageUsage,
+}
+
+impl AccountV1 {
+    fn into_current(self, _account_id: &AccountId) -> Account {
+        Account {
+            near_amount: self.near_amount,
+            tokens: self.tokens,
+            storage_used: self.storage_used,
+            nonce: 0,
+            last_action_timestamp: 0,
+        }
+    }
+}
+
 impl From<Account> for VAccount {
     fn from(account: Account) -> Self {
         VAccount::Current(account)
     }
 }
 
+/// Storage state of an account, amounts in yoctoNEAR.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountStorageState {
+    /// Bytes used by the account data.
+    pub used_bytes: U64,
+    /// NEAR deposited for storage.
+    pub total: U128,
+    /// NEAR locked to cover the used bytes.
+    pub locked: U128,
+    /// NEAR that can be withdrawn or used for new tokens.
+    pub available: U128,
+}
+
+/// Return value of a call, along with the new operation nonce of the caller.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WithNonce<T> {
+    pub value: T,
+    pub nonce: u64,
+}
+
 /// Account deposits information and storage cost.
 #[derive(BorshSerialize, BorshDeserialize)]
 pub struct Account {
@@ -57,7 +110,12 @@ pub struct Account {
     pub near_amount: Balance,
     /// Amounts of various tokens deposited to this account.
     pub tokens: UnorderedMap<AccountId, Balance>,
+    /// Bytes used by the account besides its token balances, such as collateral labels.
     pub storage_used: StorageUsage,
+    /// Number of operations done by this account, increased by every mutating call.
+    pub nonce: u64,
+    /// Timestamp of the last mutating call.
+    pub last_action_timestamp: Timestamp,
 }
 
 impl Account {
@@ -68,15 +126,13 @@ impl Account {
                 account_id: account_id.clone(),
             }),
             storage_used: 0,
+            nonce: 0,
+            last_action_timestamp: 0,
         }
     }
 
     pub fn get_balance(&self, token_id: &AccountId) -> Option<Balance> {
-        if let Some(token_balance) = self.tokens.get(token_id) {
-            Some(token_balance)
-        } else {
-            None
-        }
+        self.tokens.get(token_id)
     }
 
     pub fn get_tokens(&self) -> Vec<AccountId> {
@@ -123,21 +179,33 @@ impl Account {
         }
     }
 
+    /// Returns amount of bytes used by this data structure.
+    pub fn storage_bytes(&self) -> StorageUsage {
+        INIT_ACCOUNT_STORAGE + self.tokens.len() * TOKEN_STORAGE + self.storage_used
+    }
+
     /// Returns amount of $NEAR necessary to cover storage used by this data structure.
     pub fn storage_usage(&self) -> Balance {
-        (INIT_ACCOUNT_STORAGE +
-            self.tokens.len() as u64 * (KEY_PREFIX_ACC + ACC_ID_AS_KEY_STORAGE + U128_STORAGE)
-        ) as u128 * env::storage_byte_cost()
+        self.storage_bytes() as u128 * env::storage_byte_cost()
+    }
+
+    /// Returns amount of $NEAR necessary to register `count` more tokens.
+    pub fn storage_usage_for_tokens(count: u64) -> Balance {
+        (count * TOKEN_STORAGE) as u128 * env::storage_byte_cost()
+    }
+
+    pub fn storage_state(&self) -> AccountStorageState {
+        AccountStorageState {
+            used_bytes: U64(self.storage_bytes()),
+            total: U128(self.near_amount),
+            locked: U128(self.storage_usage()),
+            available: U128(self.storage_available()),
+        }
     }
 
     /// Returns how much NEAR is available for storage.
     pub fn storage_available(&self) -> Balance {
-        let locked = self.storage_usage();
-        if self.near_amount > locked {
-            self.near_amount - locked
-        } else {
-            0
-        }
+        self.near_amount.saturating_sub(self.storage_usage())
     }
 
     /// Asserts there is sufficient amount of $NEAR to cover storage usage.
@@ -162,6 +230,13 @@ impl Account {
         }
     }
 
+    /// Increases the operation nonce, records the action time and returns the new nonce.
+    pub(crate) fn bump_nonce(&mut self) -> u64 {
+        self.nonce += 1;
+        self.last_action_timestamp = env::block_timestamp();
+        self.nonce
+    }
+
     /// Unregisters `token_id` from this account balance.
     /// Panics if the `token_id` balance is not 0.
     pub(crate) fn unregister(&mut self, token_id: &AccountId) {
@@ -176,19 +251,20 @@ impl Contract {
     /// Registers given token in the user's account deposit.
     /// Fails if not enough balance on this account to cover storage.
     #[payable]
-    pub fn register_tokens(&mut self, token_ids: Vec<AccountId>) {
+    pub fn register_tokens(&mut self, token_ids: Vec<AccountId>) -> u64 {
         assert_one_yocto();
         self.assert_contract_running();
         let sender_id = env::predecessor_account_id();
         let mut account = self.internal_unwrap_account(&sender_id);
         account.register(&token_ids);
         self.internal_save_account(&sender_id, account);
+        self.internal_bump_nonce(&sender_id)
     }
 
     /// Unregister given token from user's account deposit.
     /// Panics if the balance of any given token is non 0.
     #[payable]
-    pub fn unregister_tokens(&mut self, token_ids: Vec<AccountId>) {
+    pub fn unregister_tokens(&mut self, token_ids: Vec<AccountId>) -> u64 {
         assert_one_yocto();
         self.assert_contract_running();
         let sender_id = env::predecessor_account_id();
@@ -197,6 +273,34 @@ impl Contract {
             account.unregister(&token_id);
         }
         self.internal_save_account(&sender_id, account);
+        self.internal_bump_nonce(&sender_id)
+    }
+
+    /// Moves the caller's lost-found of given token into its deposits, returns the amount claimed.
+    /// The token is registered if needed, so the account must have enough storage.
+    #[payable]
+    pub fn claim_lostfound(&mut self, token_id: AccountId) -> WithNonce<U128> {
+        assert_one_yocto();
+        self.assert_contract_running();
+
+        let sender_id = env::predecessor_account_id();
+        let amount = self.internal_claim_lostfound(&sender_id, token_id);
+        WithNonce { value: U128(amount), nonce: self.internal_bump_nonce(&sender_id) }
+    }
+
+    /// Withdraws given token from the deposits of given user.
+    /// Tokens locked by open collaterals can't be withdrawn.
+    /// Optional unregister will try to remove record of this token from AccountDeposit for given user.
+    /// Unregister will fail if the left over balance is non 0.
+    /// Resolves to the new operation nonce of the account.
+    #[payable]
+    pub fn withdraw(&mut self, token_id: AccountId, amount: U128, unregister: Option<bool>,
+                    unwrap: Option<bool>) -> Promise {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let sender_id = env::predecessor_account_id();
+        let promise = self.internal_withdraw(&sender_id, token_id, amount.into(), unregister, unwrap);
+        self.internal_bump_nonce_then(&sender_id, promise)
     }
 
     #[private]
@@ -212,40 +316,46 @@ impl Contract {
             "{}",
             errors::CALLBACK_POST_WITHDRAW_INVALID
         );
+        self.operation_locks.unlock(&sender_id);
 
         match env::promise_result(0) {
             PromiseResult::NotReady => unreachable!(),
             PromiseResult::Successful(_) => {}
             PromiseResult::Failed => {
                 // This reverts the changes from withdraw function.
-                // If account doesn't exit, deposits to the owner's account as lostfound.
-                let mut failed = false;
-                if let Some(mut account) = self.internal_get_account(&sender_id) {
-                    if account.deposit_with_storage_check(&token_id, amount.0) {
-                        // cause storage already checked, here can directly save
-                        self.accounts.insert(&sender_id, &account.into());
-                    } else {
-                        env::log_str(format!(
-                            "Account {} has not enough storage. Depositing to owner.",
-                            sender_id
-                        ).as_str(),
-                        );
-                        failed = true;
-                    }
-                } else {
-                    env::log_str(format!(
-                        "Account {} is not registered. Depositing to owner.",
-                        sender_id
-                    ).as_str(),
-                    );
-                    failed = true;
-                }
-                if failed {
-                    self.internal_lostfound(&token_id, amount.0);
-                }
+                self.internal_restore_withdrawn(&token_id, &sender_id, amount.0);
+            }
+        };
+    }
+
+    /// Forwards the unwrapped NEAR to the user, or gives the wNEAR back if it couldn't be unwrapped.
+    #[private]
+    pub fn exchange_callback_unwrap(&mut self, sender_id: AccountId, amount: U128) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+        self.operation_locks.unlock(&sender_id);
+
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => {
+                Promise::new(sender_id).transfer(amount.0);
+            }
+            PromiseResult::Failed => {
+                let wnear_id = self.wnear_id.clone().unwrap();
+                self.internal_restore_withdrawn(&wnear_id, &sender_id, amount.0);
             }
         };
     }
+
+    /// Resolves a call whose promise has no value of its own to the new operation nonce of its caller.
+    #[private]
+    pub fn account_callback_nonce(&self, nonce: u64) -> u64 {
+        nonce
+    }
 }
 
 impl Contract {
@@ -254,18 +364,98 @@ impl Contract {
     /// This should be only place to directly use `self.accounts`.
     pub(crate) fn internal_save_account(&mut self, account_id: &AccountId, account: Account) {
         account.assert_storage_usage();
-        self.accounts.insert(&account_id, &account.into());
+        self.accounts.insert(account_id, &account.into());
+        self.account_ids.insert(account_id);
+    }
+
+    /// Removes all the data kept for the account and returns its storage deposit.
+    /// Panics if the account still has token balances, open collaterals or debt.
+    pub(crate) fn internal_unregister_account(&mut self, account_id: &AccountId, mut account: Account) -> Balance {
+        for (_, balance) in account.tokens.iter() {
+            assert_eq!(balance, 0, "{}", errors::NON_ZERO_TOKEN_BALANCE);
+        }
+        assert_eq!(self.debt_pool.query_debt_ratio(account_id), 0, "{}", errors::ACCOUNT_IN_USE);
+        assert_eq!(self.debtpool_rewards.remove_account(account_id), 0, "{}", errors::UNCLAIMED_REWARDS);
+
+        // the collateral lists only hold open collaterals
+        assert!(self.user_collaterals.get(account_id).is_none_or(|collateral_ids| collateral_ids.is_empty()),
+                "{}", errors::ACCOUNT_IN_USE);
+        assert!(self.user_nft_collaterals.get(account_id).is_none_or(|collateral_ids| collateral_ids.is_empty()),
+                "{}", errors::ACCOUNT_IN_USE);
+        assert!(self.user_mt_collaterals.get(account_id).is_none_or(|collateral_ids| collateral_ids.is_empty()),
+                "{}", errors::ACCOUNT_IN_USE);
+        self.user_collaterals.remove(account_id);
+        self.user_nft_collaterals.remove(account_id);
+        self.user_mt_collaterals.remove(account_id);
+
+        let valuation = oracle::Valuation::new(&self.price_oracle, &self.token_list, &self.raft_list);
+        for raft_id in self.raft_list.keys() {
+            assert_eq!(self.debt_pool.query_user_raft_amount(account_id, &raft_id), 0, "{}", errors::ACCOUNT_IN_USE);
+            assert_eq!(self.account_book.query_user_raft_amount(account_id, &raft_id), 0, "{}", errors::ACCOUNT_IN_USE);
+            self.debt_pool.remove_user_raft_amount(&valuation, account_id, &raft_id);
+            self.account_book.remove_user_raft_amount(account_id, &raft_id);
+            self.stop_orders.remove(&(account_id.clone(), raft_id));
+        }
+
+        self.debt_pool.remove_user_value(account_id);
+        self.cross_margin_accounts.remove(account_id);
+        self.position_receivers.remove(account_id);
+        self.deleverage_buffers.remove(account_id);
+        account.tokens.clear();
+        self.accounts.remove(account_id);
+        self.account_ids.remove(account_id);
+        account.near_amount
     }
 
-    /// save token to owner account as lostfound, no need to care about storage
+    /// Increases the operation nonce of a registered account, logs and returns the new value.
+    /// Unregistered accounts, e.g. senders of `ft_transfer_call`, have no nonce and 0 is returned.
+    /// Calls with a return value set by a standard, like `ft_on_transfer`, only have the logged nonce.
+    pub(crate) fn internal_bump_nonce(&mut self, account_id: &AccountId) -> u64 {
+        let mut account = match self.internal_get_account(account_id) {
+            Some(account) => account,
+            None => return 0,
+        };
+        let nonce = account.bump_nonce();
+        self.accounts.insert(account_id, &account.into());
+        env::log_str(format!("Account {} nonce {}", account_id, nonce).as_str());
+        nonce
+    }
+
+    /// Bumps the operation nonce of the account like `internal_bump_nonce`,
+    /// chaining a callback to the promise of the call so that it resolves to the new nonce.
+    pub(crate) fn internal_bump_nonce_then(&mut self, account_id: &AccountId, promise: Promise) -> Promise {
+        let nonce = self.internal_bump_nonce(account_id);
+        promise.then(ext_self::account_callback_nonce(
+            nonce,
+            env::current_account_id(),
+            NO_DEPOSIT,
+            utils::GAS_FOR_NONCE_CALLBACK,
+        ))
+    }
+
+    pub(crate) fn internal_claim_lostfound(&mut self, sender_id: &AccountId, token_id: AccountId) -> Balance {
+        let key = (sender_id.clone(), token_id.clone());
+        let amount = self.lostfound.remove(&key).unwrap_or(0);
+        assert!(amount > 0, "{}", errors::NO_LOSTFOUND);
+
+        let mut account = self.internal_unwrap_account(sender_id);
+        account.deposit(&token_id, amount);
+        self.internal_save_account(sender_id, account);
+
+        Event::ClaimLostfound { account_id: sender_id, token_id: &token_id, amount: U128(amount) }.emit();
+        self.internal_record_operation(OperationKind::ClaimLostfound, sender_id, vec![token_id], vec![amount]);
+        amount
+    }
+
+    /// save token as lostfound of the account, claimable by it later, no need to care about storage
     /// only global whitelisted token can be stored in lost-found
-    pub(crate) fn internal_lostfound(&mut self, token_id: &AccountId, amount: u128) {
+    pub(crate) fn internal_lostfound(&mut self, account_id: &AccountId, token_id: &AccountId, amount: u128) {
         if self.whitelisted_tokens.contains(token_id) {
-            let mut lostfound = self.internal_unwrap_or_default_account(&self.owner_id);
-            lostfound.deposit(token_id, amount);
-            self.accounts.insert(&self.owner_id, &lostfound.into());
+            let key = (account_id.clone(), token_id.clone());
+            let lostfound = self.lostfound.get(&key).unwrap_or(0);
+            self.lostfound.insert(&key, &(lostfound + amount));
         } else {
-            env::panic_str("ERR: non-whitelisted token can NOT deposit into lost-found.");
+            errors::CraftingError::TokenNotWhitelisted.panic();
         }
     }
 
@@ -273,23 +463,23 @@ impl Contract {
     /// If account already exists, adds amount to it.
     /// This should be used when it's known that storage is prepaid.
     pub(crate) fn internal_register_account(&mut self, account_id: &AccountId, amount: Balance) {
-        let mut account = self.internal_unwrap_or_default_account(&account_id);
+        let mut account = self.internal_unwrap_or_default_account(account_id);
         account.near_amount += amount;
-        self.internal_save_account(&account_id, account);
+        self.internal_save_account(account_id, account);
     }
 
     /// storage withdraw
     pub(crate) fn internal_storage_withdraw(&mut self, account_id: &AccountId, amount: Balance) -> u128 {
-        let mut account = self.internal_unwrap_account(&account_id);
+        let mut account = self.internal_unwrap_account(account_id);
         let available = account.storage_available();
-        assert!(available > 0, "ERR_NO_STORAGE_CAN_WITHDRAW");
+        assert!(available > 0, "{}", errors::NO_STORAGE_CAN_WITHDRAW);
         let mut withdraw_amount = amount;
         if amount == 0 {
             withdraw_amount = available;
         }
-        assert!(withdraw_amount <= available, "ERR_STORAGE_WITHDRAW_TOO_MUCH");
+        assert!(withdraw_amount <= available, "{}", errors::STORAGE_WITHDRAW_TOO_MUCH);
         account.near_amount -= withdraw_amount;
-        self.internal_save_account(&account_id, account);
+        self.internal_save_account(account_id, account);
         withdraw_amount
     }
 
@@ -309,7 +499,7 @@ impl Contract {
             errors::TOKEN_NOT_WHITELISTED
         );
         account.deposit(token_id, amount);
-        self.internal_save_account(&sender_id, account);
+        self.internal_save_account(sender_id, account);
     }
 
     pub fn internal_get_account(&self, account_id: &AccountId) -> Option<Account> {
@@ -328,15 +518,32 @@ impl Contract {
             .unwrap_or_else(|| Account::new(account_id))
     }
 
-    /// Returns current balance of given token for given user. If there is nothing recorded, returns 0.
-    pub(crate) fn internal_get_deposit(
-        &self,
-        sender_id: &AccountId,
-        token_id: &AccountId,
-    ) -> Balance {
-        self.internal_get_account(sender_id)
-            .and_then(|x| x.get_balance(token_id))
-            .unwrap_or(0)
+    /// Returns amount of given token backing open collaterals of given user.
+    pub(crate) fn internal_get_locked_collateral(&self, account_id: &AccountId, token_id: &AccountId) -> Balance {
+        self.locked_collaterals.get(&(account_id.clone(), token_id.clone())).unwrap_or(0)
+    }
+
+    /// Withdraws unlocked deposited tokens of the user, locking the user until they are sent.
+    pub(crate) fn internal_withdraw(&mut self, sender_id: &AccountId, token_id: AccountId, amount: Balance,
+                                    unregister: Option<bool>, unwrap: Option<bool>) -> Promise {
+        assert!(amount > 0, "{}", errors::ILLEGAL_WITHDRAW_AMOUNT);
+
+        self.assert_not_blacklisted(sender_id);
+        self.operation_locks.lock(sender_id);
+        let mut account = self.internal_unwrap_account(sender_id);
+        let balance = account.get_balance(&token_id).expect(errors::TOKEN_NOT_REG);
+        let locked = self.internal_get_locked_collateral(sender_id, &token_id);
+        assert!(balance - locked >= amount, "{}", errors::NOT_ENOUGH_TOKENS);
+
+        // Note: subtraction and deregistration will be reverted if the promise fails.
+        account.withdraw(&token_id, amount);
+        if unregister == Some(true) {
+            account.unregister(&token_id);
+        }
+        self.internal_save_account(sender_id, account);
+        Event::Withdraw { account_id: sender_id, token_id: &token_id, amount: U128(amount) }.emit();
+        self.internal_record_operation(OperationKind::Withdraw, sender_id, vec![token_id.clone()], vec![amount]);
+        self.internal_send_tokens_or_near(sender_id, &token_id, amount, unwrap == Some(true))
     }
 
     /// Sends given amount to given user and if it fails, returns it back to user's balance.
@@ -353,14 +560,70 @@ impl Contract {
             None,
             token_id.clone(),
             ONE_YOCTO,
-            GAS_FOR_FT_TRANSFER,
+            self.gas_config.ft_transfer,
         ).then(ext_self::exchange_callback_post_withdraw(
             token_id.clone(),
             sender_id.clone(),
             U128(amount),
             env::current_account_id(),
             NO_DEPOSIT,
-            GAS_FOR_RESOLVE_TRANSFER,
+            self.gas_config.resolve,
+        ))
+    }
+
+    /// Sends tokens like `internal_send_tokens`, unwrapping them into native NEAR if asked and they are wNEAR.
+    /// Tokens must already be subtracted from internal balance.
+    pub(crate) fn internal_send_tokens_or_near(
+        &self,
+        sender_id: &AccountId,
+        token_id: &AccountId,
+        amount: Balance,
+        unwrap: bool,
+    ) -> Promise {
+        if !unwrap || self.wnear_id.as_ref() != Some(token_id) {
+            return self.internal_send_tokens(sender_id, token_id, amount);
+        }
+
+        ext_wrap_near::near_withdraw(
+            U128(amount),
+            token_id.clone(),
+            ONE_YOCTO,
+            utils::GAS_FOR_NEAR_WITHDRAW,
+        ).then(ext_self::exchange_callback_unwrap(
+            sender_id.clone(),
+            U128(amount),
+            env::current_account_id(),
+            NO_DEPOSIT,
+            self.gas_config.resolve,
         ))
     }
+
+    /// Gives back tokens that failed to be sent to the account.
+    /// If account doesn't exit, keeps the tokens as lostfound of the account.
+    fn internal_restore_withdrawn(&mut self, token_id: &AccountId, sender_id: &AccountId, amount: Balance) {
+        let mut failed = false;
+        if let Some(mut account) = self.internal_get_account(sender_id) {
+            if account.deposit_with_storage_check(token_id, amount) {
+                // cause storage already checked, here can directly save
+                self.accounts.insert(sender_id, &account.into());
+            } else {
+                env::log_str(format!(
+                    "Account {} has not enough storage. Depositing to lost-found.",
+                    sender_id
+                ).as_str(),
+                );
+                failed = true;
+            }
+        } else {
+            env::log_str(format!(
+                "Account {} is not registered. Depositing to lost-found.",
+                sender_id
+            ).as_str(),
+            );
+            failed = true;
+        }
+        if failed {
+            self.internal_lostfound(sender_id, token_id, amount);
+        }
+    }
 }
@@ -1,6 +1,7 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::{LookupMap, UnorderedMap};
-use near_sdk::{AccountId, Balance};
+use near_contract_standards::storage_management::StorageBalance;
+use near_sdk::{serde_json, AccountId, Balance, PromiseOrValue, PromiseResult};
 
 use crate::*;
 
@@ -10,13 +11,19 @@ pub struct AccountBook {
     raft_amounts: UnorderedMap<AccountId, Balance>,
     /// Mapping from user and raft to amount of raft that is in debt pool.
     user_raft_amounts: LookupMap<(AccountId, AccountId), Balance>,
+    /// Mapping from raft to number of users holding a non zero amount of it.
+    holder_counts: LookupMap<AccountId, u64>,
+    /// Mapping from raft to amount of raft minted out as tokens and not burned back yet.
+    circulating_amounts: LookupMap<AccountId, Balance>,
 }
 
 impl AccountBook {
     pub(crate) fn new() -> Self {
         Self {
-            raft_amounts: UnorderedMap::new(b"r".to_vec()),
-            user_raft_amounts: LookupMap::new(b"r".to_vec()),
+            raft_amounts: UnorderedMap::new(StorageKey::AccountBookRaftAmounts),
+            user_raft_amounts: LookupMap::new(StorageKey::AccountBookUserRaftAmounts),
+            holder_counts: LookupMap::new(StorageKey::AccountBookHolderCounts),
+            circulating_amounts: LookupMap::new(StorageKey::AccountBookCirculating),
         }
     }
 
@@ -28,6 +35,16 @@ impl AccountBook {
         self.insert_user_raft_amount(user, raft_id, old_amount + raft_amount);
     }
 
+    /// Panics if the user holds less than `raft_amount`.
+    pub(crate) fn burn(&mut self, user: &AccountId, raft_id: &AccountId, raft_amount: Balance) {
+        let old_amount = self.query_user_raft_amount(user, raft_id);
+        assert!(old_amount >= raft_amount, "{}", errors::NOT_ENOUGH_RAFTS);
+        self.insert_user_raft_amount(user, raft_id, old_amount - raft_amount);
+
+        let old_amount = self.query_raft_amount(raft_id);
+        self.insert_raft_amount(raft_id, old_amount - raft_amount);
+    }
+
     pub(crate) fn query_raft_amount(&self, raft_id: &AccountId) -> Balance {
         self.raft_amounts.get(raft_id).unwrap_or(0)
     }
@@ -36,33 +53,94 @@ impl AccountBook {
         self.raft_amounts.insert(raft_id, &amount);
     }
 
+    /// Drops the entry of a delisted raft, so that the per raft loops stay bounded by `max_rafts`.
+    pub(crate) fn remove_raft(&mut self, raft_id: &AccountId) {
+        self.raft_amounts.remove(raft_id);
+    }
+
     pub(crate) fn query_user_raft_amount(&self, user: &AccountId, raft_id: &AccountId) -> Balance {
         self.user_raft_amounts.get(&(user.clone(), raft_id.clone())).unwrap_or(0)
     }
 
+    /// Rafts with their amounts, by index of the map.
+    pub(crate) fn query_raft_amounts(&self, from_index: u64, limit: u64) -> Vec<(AccountId, Balance)> {
+        let keys = self.raft_amounts.keys_as_vector();
+        let values = self.raft_amounts.values_as_vector();
+        (from_index..std::cmp::min(from_index.saturating_add(limit), keys.len()))
+            .map(|index| (keys.get(index).unwrap(), values.get(index).unwrap()))
+            .collect()
+    }
+
+    pub(crate) fn query_user_raft_amounts(&self, user: &AccountId) -> Vec<(AccountId, Balance)> {
+        let mut vec: Vec<(AccountId, Balance)> = Vec::new();
+        for (raft_id, _) in self.raft_amounts.iter() {
+            let amount = self.query_user_raft_amount(user, &raft_id);
+            if amount != 0 {
+                vec.push((raft_id, amount));
+            }
+        }
+
+        vec
+    }
+
     pub(crate) fn insert_user_raft_amount(&mut self, user: &AccountId, raft_id: &AccountId, amount: Balance) {
-        self.user_raft_amounts.insert(&(user.clone(), raft_id.clone()), &amount);
+        let old_amount = self.user_raft_amounts.insert(&(user.clone(), raft_id.clone()), &amount).unwrap_or(0);
+        self.update_holder_count(raft_id, old_amount, amount);
+    }
+
+    pub(crate) fn remove_user_raft_amount(&mut self, user: &AccountId, raft_id: &AccountId) {
+        let old_amount = self.user_raft_amounts.remove(&(user.clone(), raft_id.clone())).unwrap_or(0);
+        self.update_holder_count(raft_id, old_amount, 0);
+    }
+
+    pub(crate) fn query_holder_count(&self, raft_id: &AccountId) -> u64 {
+        self.holder_counts.get(raft_id).unwrap_or(0)
     }
 
-    pub(crate) fn calc_raft_value(&self, price_oracle: &oracle::PriceInfo, raft_id: &AccountId, amount: Balance) -> u128 {
-        price_oracle.get_price(raft_id) * amount
+    pub(crate) fn query_circulating(&self, raft_id: &AccountId) -> Balance {
+        self.circulating_amounts.get(raft_id).unwrap_or(0)
     }
 
-    pub(crate) fn calc_raft_total_value(&self, price_oracle: &oracle::PriceInfo) -> u128 {
+    /// Records rafts minted out as tokens.
+    pub(crate) fn mint_out(&mut self, raft_id: &AccountId, amount: Balance) {
+        let circulating = self.query_circulating(raft_id);
+        self.circulating_amounts.insert(raft_id, &(circulating + amount));
+    }
+
+    /// Records raft tokens burned back, tokens minted out before the tracking started are ignored.
+    pub(crate) fn burn_in(&mut self, raft_id: &AccountId, amount: Balance) {
+        let circulating = self.query_circulating(raft_id);
+        self.circulating_amounts.insert(raft_id, &circulating.saturating_sub(amount));
+    }
+
+    fn update_holder_count(&mut self, raft_id: &AccountId, old_amount: Balance, new_amount: Balance) {
+        let count = self.query_holder_count(raft_id);
+        if old_amount == 0 && new_amount != 0 {
+            self.holder_counts.insert(raft_id, &(count + 1));
+        } else if old_amount != 0 && new_amount == 0 {
+            self.holder_counts.insert(raft_id, &(count - 1));
+        }
+    }
+
+    pub(crate) fn calc_raft_value(&self, valuation: &oracle::Valuation, raft_id: &AccountId, amount: Balance) -> u128 {
+        valuation.calc_value(raft_id, amount)
+    }
+
+    pub(crate) fn calc_raft_total_value(&self, valuation: &oracle::Valuation) -> u128 {
         let mut total: u128 = 0;
         for (raft, amount) in self.raft_amounts.iter() {
-            total += self.calc_raft_value(price_oracle, &raft, amount);
+            total += self.calc_raft_value(valuation, &raft, amount);
         }
 
         total
     }
 
-    pub(crate) fn calc_user_raft_total_value(&self, price_oracle: &oracle::PriceInfo, user: &AccountId) -> u128 {
+    pub(crate) fn calc_user_raft_total_value(&self, valuation: &oracle::Valuation, user: &AccountId) -> u128 {
         let mut total: u128 = 0;
         for (raft, _) in self.raft_amounts.iter() {
             let amount = self.query_user_raft_amount(user, &raft);
             if amount != 0 {
-                total += self.calc_raft_value(price_oracle, &raft, amount);
+                total += self.calc_raft_value(valuation, &raft, amount);
             }
         }
 
@@ -74,15 +152,92 @@ impl AccountBook {
 impl Contract {
     #[private]
     pub fn account_book_callback_deposit(&mut self, sender_id: AccountId, raft_id: AccountId,
-                                         amount: Balance, raft_amount: Balance, user_raft_amount: Balance) {
-        self.account_book.insert_raft_amount(&raft_id, raft_amount + amount);
-        self.account_book.insert_user_raft_amount(&sender_id, &raft_id, user_raft_amount + amount);
+                                         amount: U128, raft_amount: U128, user_raft_amount: U128) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+        self.operation_locks.unlock(&sender_id);
+        if let PromiseResult::Failed = env::promise_result(0) {
+            env::log_str(format!("Failed to burn {} {} of {}, nothing deposited", amount.0, raft_id, sender_id).as_str());
+            return;
+        }
+
+        self.account_book.insert_raft_amount(&raft_id, raft_amount.0 + amount.0);
+        self.account_book.insert_user_raft_amount(&sender_id, &raft_id, user_raft_amount.0 + amount.0);
+        self.account_book.burn_in(&raft_id, amount.0);
+        Event::AccountBookDeposit { account_id: &sender_id, raft_id: &raft_id, amount }.emit();
+        self.internal_record_operation(OperationKind::AccountBookDeposit, &sender_id, vec![raft_id], vec![amount.0]);
     }
 
+    /// Mints the withdrawn rafts out if the sender is registered on the raft token.
+    #[private]
+    pub fn account_book_callback_storage(&mut self, sender_id: AccountId, raft_id: AccountId,
+                                         amount: U128) -> PromiseOrValue<()> {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+
+        let storage_balance: Option<StorageBalance> = match env::promise_result(0) {
+            PromiseResult::Successful(value) => serde_json::from_slice(&value).ok().flatten(),
+            _ => None,
+        };
+        if storage_balance.is_none() {
+            // not a panic, which would keep the sender locked
+            self.operation_locks.unlock(&sender_id);
+            env::log_str(format!("{}: {} on {}", errors::RECEIVER_NOT_REGISTERED, sender_id, raft_id).as_str());
+            return PromiseOrValue::Value(());
+        }
+
+        // amounts are read again as they may have changed while checking the storage
+        let raft_amount = self.account_book.query_raft_amount(&raft_id);
+        let user_raft_amount = self.account_book.query_user_raft_amount(&sender_id, &raft_id);
+        assert!(raft_amount >= amount.0, "{}", errors::NOT_ENOUGH_RAFTS);
+        assert!(user_raft_amount >= amount.0, "{}", errors::NOT_ENOUGH_RAFTS);
+
+        ext_enhanced_fungible_token::mint(
+            sender_id.clone(),
+            amount,
+            raft_id.clone(),
+            utils::ONE_YOCTO,
+            self.gas_config.mint,
+        ).then(ext_self::account_book_callback_withdraw(
+            sender_id,
+            raft_id,
+            amount,
+            U128(raft_amount),
+            U128(user_raft_amount),
+            env::current_account_id(),
+            utils::NO_DEPOSIT,
+            self.gas_config.resolve,
+        )).into()
+    }
+
+    /// Removes the rafts from the account book once they are minted out.
     #[private]
     pub fn account_book_callback_withdraw(&mut self, sender_id: AccountId, raft_id: AccountId,
-                                          amount: Balance, raft_amount: Balance, user_raft_amount: Balance) {
-        self.account_book.insert_raft_amount(&raft_id, raft_amount - amount);
-        self.account_book.insert_user_raft_amount(&sender_id, &raft_id, user_raft_amount - amount);
+                                          amount: U128, raft_amount: U128, user_raft_amount: U128) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+        self.operation_locks.unlock(&sender_id);
+        if let PromiseResult::Failed = env::promise_result(0) {
+            env::log_str(format!("Failed to mint {} {} to {}, kept in account book", amount.0, raft_id, sender_id).as_str());
+            return;
+        }
+
+        self.account_book.insert_raft_amount(&raft_id, raft_amount.0 - amount.0);
+        self.account_book.insert_user_raft_amount(&sender_id, &raft_id, user_raft_amount.0 - amount.0);
+        self.account_book.mint_out(&raft_id, amount.0);
+        Event::AccountBookWithdraw { account_id: &sender_id, raft_id: &raft_id, amount }.emit();
+        self.internal_record_operation(OperationKind::AccountBookWithdraw, &sender_id, vec![raft_id], vec![amount.0]);
     }
 }
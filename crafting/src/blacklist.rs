@@ -0,0 +1,43 @@
+use near_sdk::{env, AccountId};
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Block the accounts from minting, swapping, withdrawing and receiving tokens.
+    /// Only can be called by owner or guardians.
+    pub fn extend_blacklist(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner_or_guardians();
+        let by = env::predecessor_account_id();
+        for account_id in account_ids {
+            if self.blacklist.insert(&account_id) {
+                Event::BlacklistAdd { account_id: &account_id, by: &by }.emit();
+            }
+        }
+    }
+
+    /// Only can be called by owner or guardians.
+    pub fn remove_from_blacklist(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner_or_guardians();
+        let by = env::predecessor_account_id();
+        for account_id in account_ids {
+            if self.blacklist.remove(&account_id) {
+                Event::BlacklistRemove { account_id: &account_id, by: &by }.emit();
+            }
+        }
+    }
+
+    pub fn is_blacklisted(&self, account_id: AccountId) -> bool {
+        self.blacklist.contains(&account_id)
+    }
+
+    pub fn get_blacklist(&self, from_index: u64, limit: u64) -> Vec<AccountId> {
+        self.blacklist.iter().skip(from_index as usize).take(limit as usize).collect()
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_not_blacklisted(&self, account_id: &AccountId) {
+        assert!(!self.blacklist.contains(account_id), "{}", errors::ACCOUNT_BLACKLISTED);
+    }
+}
@@ -0,0 +1,104 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::{AccountId, Balance};
+
+use crate::*;
+
+/// Caps of the debt of each account, in USD with `VALUE_DECIMALS` decimals. A cap of 0 means no cap.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct BorrowLimits {
+    /// Cap of accounts without an override.
+    default_limit: Balance,
+    /// Mapping from account to its own cap, e.g. for institutional accounts.
+    overrides: LookupMap<AccountId, Balance>,
+}
+
+impl BorrowLimits {
+    pub(crate) fn new() -> Self {
+        Self {
+            default_limit: 0,
+            overrides: LookupMap::new(StorageKey::BorrowLimitOverrides),
+        }
+    }
+
+    /// Cap of the account, `None` if its debt isn't capped.
+    pub(crate) fn query_limit(&self, account_id: &AccountId) -> Option<Balance> {
+        let limit = self.overrides.get(account_id).unwrap_or(self.default_limit);
+        if limit == 0 { None } else { Some(limit) }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Change the debt cap in USD of accounts without an override, 0 for no cap. Only can be called by owner.
+    pub fn set_borrow_limit(&mut self, limit: U128) {
+        self.assert_owner();
+        self.borrow_limits.default_limit = limit.into();
+    }
+
+    /// Override the debt cap in USD of an account, 0 for no cap, `None` to remove the override.
+    /// Only can be called by owner.
+    pub fn set_user_borrow_limit(&mut self, account_id: AccountId, limit: Option<U128>) {
+        self.assert_owner();
+        match limit {
+            Some(limit) => self.borrow_limits.overrides.insert(&account_id, &limit.into()),
+            None => self.borrow_limits.overrides.remove(&account_id),
+        };
+    }
+
+    /// Debt cap in USD of the account, `None` if its debt isn't capped.
+    pub fn get_borrow_limit(&self, account_id: AccountId) -> Option<U128> {
+        self.borrow_limits.query_limit(&account_id).map(U128)
+    }
+
+    /// Debt of the account in USD with `VALUE_DECIMALS` decimals, across its collaterals and its share of the debt pool.
+    pub fn get_user_debt(&self, account_id: AccountId) -> U128 {
+        U128(self.calc_user_debt_value(&account_id))
+    }
+}
+
+impl Contract {
+    /// Value of the rafts minted by the account, in USD with `VALUE_DECIMALS` decimals.
+    pub(crate) fn calc_user_debt_value(&self, account_id: &AccountId) -> u128 {
+        let valuation = self.valuation();
+        let raft_value = |raft_id: &AccountId, raft_amount: Balance| valuation.calc_value(raft_id, raft_amount);
+
+        let mut total = math::mul_div(self.debt_pool.calc_raft_total_value(&valuation),
+                                      self.debt_pool.query_debt_ratio(account_id), utils::RATIO_DIVISOR);
+        if let Some(collateral_ids) = self.user_collaterals.get(account_id) {
+            for collateral in collateral_ids.iter().filter_map(|id| self.query_collateral(id)) {
+                if collateral.state == CollateralState::Open && !collateral.join_debtpool {
+                    total += raft_value(&collateral.raft_id, collateral.debt());
+                }
+            }
+        }
+        if let Some(collateral_ids) = self.user_nft_collaterals.get(account_id) {
+            for collateral in collateral_ids.iter().filter_map(|id| self.query_nft_collateral(id)) {
+                total += raft_value(&collateral.raft_id, collateral.raft_amount);
+            }
+        }
+        if let Some(collateral_ids) = self.user_mt_collaterals.get(account_id) {
+            for collateral in collateral_ids.iter().filter_map(|id| self.query_mt_collateral(id)) {
+                total += raft_value(&collateral.raft_id, collateral.raft_amount);
+            }
+        }
+
+        total
+    }
+
+    /// Fails if minting `raft_amount` more of the raft takes the debt of the account above its cap.
+    pub(crate) fn check_borrow_limit(&self, account_id: &AccountId, raft_id: &AccountId,
+                                     raft_amount: Balance) -> Result<(), errors::CraftingError> {
+        if let Some(limit) = self.borrow_limits.query_limit(account_id) {
+            let debt_value = self.calc_user_debt_value(account_id) + self.valuation().calc_value(raft_id, raft_amount);
+            if debt_value > limit {
+                return Err(errors::CraftingError::UserBorrowLimit);
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn assert_borrow_limit(&self, account_id: &AccountId, raft_id: &AccountId, raft_amount: Balance) {
+        self.check_borrow_limit(account_id, raft_id, raft_amount).unwrap_or_else(|error| error.panic());
+    }
+}
@@ -0,0 +1,56 @@
+use near_sdk::AccountId;
+
+use crate::*;
+use crate::errors::CraftingError;
+use crate::timelock::{ChangeId, ParamChange};
+
+#[near_bindgen]
+impl Contract {
+    /// Schedule change of the max share of a single raft in the value of the debt pool, in `BPS_DIVISOR` units,
+    /// above which swaps into the raft are rejected. 0 disables the breaker. Only can be called by owner.
+    pub fn set_max_raft_share(&mut self, share_bps: u32) -> ChangeId {
+        self.assert_owner();
+        assert!(share_bps <= utils::BPS_DIVISOR, "{}", errors::ILLEGAL_FEE_CONFIG);
+        self.internal_schedule_param_change(ParamChange::MaxRaftShare { share_bps })
+    }
+
+    pub fn get_max_raft_share(&self) -> u32 {
+        self.max_raft_share
+    }
+
+    /// Share of the raft in the value of the debt pool, in `BPS_DIVISOR` units.
+    pub fn get_raft_share(&self, raft_id: AccountId) -> u32 {
+        self.calc_raft_share(&raft_id, 0)
+    }
+}
+
+impl Contract {
+    /// Share of the raft in the value of the debt pool once `added_amount` more of it is swapped in.
+    fn calc_raft_share(&self, raft_id: &AccountId, added_amount: Balance) -> u32 {
+        let valuation = self.valuation();
+        let raft_amount = self.debt_pool.query_raft_amount(raft_id);
+        let raft_amount = if raft_amount.is_positive { raft_amount.amount } else { 0 };
+        let added_value = valuation.calc_value(raft_id, added_amount);
+        let total_value = self.debt_pool.calc_raft_total_value(&valuation) + added_value;
+        if total_value == 0 {
+            return 0;
+        }
+        let raft_value = valuation.calc_value(raft_id, raft_amount) + added_value;
+        math::mul_div(raft_value, utils::BPS_DIVISOR as u128, total_value) as u32
+    }
+
+    /// Fails if the raft would weigh more than the max raft share of the debt pool
+    /// once `added_amount` more of it is swapped in.
+    pub(crate) fn check_raft_share(&self, raft_id: &AccountId, added_amount: Balance) -> Result<(), CraftingError> {
+        if self.max_raft_share == 0 || self.calc_raft_share(raft_id, added_amount) <= self.max_raft_share {
+            Ok(())
+        } else {
+            Err(CraftingError::RaftShareTooHigh)
+        }
+    }
+
+    /// Panics if a swap left the raft weighing more than the max raft share of the debt pool.
+    pub(crate) fn assert_raft_share(&self, raft_id: &AccountId) {
+        self.check_raft_share(raft_id, 0).unwrap_or_else(|error| error.panic());
+    }
+}
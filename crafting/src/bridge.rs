@@ -0,0 +1,120 @@
+use near_sdk::{assert_one_yocto, env, serde_json, AccountId, Balance, Promise, PromiseResult};
+
+use crate::*;
+
+/// Length of an Ethereum address in hex, without the `0x` prefix.
+const ETH_ADDRESS_LEN: usize = 40;
+
+#[near_bindgen]
+impl Contract {
+    /// Change the Rainbow Bridge token locker receiving bridged rafts. Only can be called by owner.
+    pub fn set_bridge_locker(&mut self, locker_id: AccountId) {
+        self.assert_owner();
+        self.bridge_locker_id = Some(locker_id);
+    }
+
+    pub fn get_bridge_locker(&self) -> Option<AccountId> {
+        self.bridge_locker_id.clone()
+    }
+
+    /// Withdraws rafts from the caller's account book and locks them in the Rainbow Bridge,
+    /// to be released to `eth_recipient` on Ethereum. Any amount refused by the locker goes back to the account book.
+    /// Resolves to the new operation nonce of the account.
+    #[payable]
+    pub fn withdraw_and_bridge(&mut self, raft_id: AccountId, amount: U128, eth_recipient: String) -> Promise {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let amount: Balance = amount.into();
+        assert!(amount > 0, "{}", errors::ILLEGAL_WITHDRAW_AMOUNT);
+        self.assert_raft_state(&raft_id, AssetState::is_withdrawable);
+
+        let locker_id = self.bridge_locker_id.clone().expect(errors::BRIDGE_NOT_CONFIGURED);
+        let eth_recipient = eth_recipient.strip_prefix("0x").unwrap_or(&eth_recipient).to_lowercase();
+        assert!(
+            eth_recipient.len() == ETH_ADDRESS_LEN && eth_recipient.chars().all(|c| c.is_ascii_hexdigit()),
+            "{}", errors::ILLEGAL_ETH_ADDRESS
+        );
+
+        // the rafts leave the account book now and are given back by the callback if not locked
+        let sender_id = env::predecessor_account_id();
+        self.assert_not_blacklisted(&sender_id);
+        self.operation_locks.lock(&sender_id);
+        self.account_book.burn(&sender_id, &raft_id, amount);
+
+        // the rafts are minted to this contract, which transfers them to the locker
+        let promise = ext_enhanced_fungible_token::mint(
+            env::current_account_id(),
+            U128(amount),
+            raft_id.clone(),
+            utils::ONE_YOCTO,
+            self.gas_config.mint,
+        ).then(ext_fungible_token::ft_transfer_call(
+            locker_id,
+            U128(amount),
+            None,
+            eth_recipient.clone(),
+            raft_id.clone(),
+            utils::ONE_YOCTO,
+            self.gas_config.bridge_transfer_call(),
+        )).then(ext_self::bridge_callback_lock(
+            sender_id.clone(),
+            raft_id,
+            U128(amount),
+            eth_recipient,
+            env::current_account_id(),
+            utils::NO_DEPOSIT,
+            self.gas_config.bridge_callback(),
+        ));
+        self.internal_bump_nonce_then(&sender_id, promise)
+    }
+
+    /// Burns the rafts refused by the locker and gives them back to the sender in the account book.
+    #[private]
+    pub fn bridge_callback_lock(&mut self, sender_id: AccountId, raft_id: AccountId, amount: U128,
+                                eth_recipient: String) {
+        let amount: Balance = amount.into();
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+        self.operation_locks.unlock(&sender_id);
+
+        // a failed transfer may come from a failed mint, a refund means the rafts were minted
+        let (locked_amount, minted) = match env::promise_result(0) {
+            PromiseResult::Successful(value) => (serde_json::from_slice::<U128>(&value).map_or(0, |used| used.0), true),
+            _ => (0, false),
+        };
+        let locked_amount = std::cmp::min(locked_amount, amount);
+
+        let refused_amount = amount - locked_amount;
+        if refused_amount > 0 {
+            if minted {
+                self.internal_burn_held_rafts(&raft_id, refused_amount);
+            } else {
+                // fails without effect if the rafts were never minted
+                ext_enhanced_fungible_token::burn(
+                    env::current_account_id(),
+                    U128(refused_amount),
+                    raft_id.clone(),
+                    utils::ONE_YOCTO,
+                    self.gas_config.burn,
+                );
+            }
+            self.account_book.mint(&sender_id, &raft_id, refused_amount);
+            env::log_str(format!("{} {} not locked, given back to {}", refused_amount, raft_id, sender_id).as_str());
+        }
+
+        if locked_amount > 0 {
+            self.account_book.mint_out(&raft_id, locked_amount);
+            Event::BridgeOut {
+                account_id: &sender_id,
+                raft_id: &raft_id,
+                amount: U128(locked_amount),
+                eth_recipient: &eth_recipient,
+            }.emit();
+            self.internal_record_operation(OperationKind::BridgeOut, &sender_id, vec![raft_id], vec![locked_amount]);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::AccountId;
+
+use crate::*;
+use crate::timelock::{ChangeId, ParamChange};
+
+/// Budget of the buybacks of rUSD with the fees of the treasury.
+#[derive(Clone, Default, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BuybackConfig {
+    /// Share of the accrued treasury fees of a raft spent per buyback, in `BPS_DIVISOR` units, 0 disables buybacks.
+    pub share_bps: u32,
+    /// Max value spent per buyback, in USD with `VALUE_DECIMALS` decimals, 0 for no cap.
+    #[serde(with = "u128_dec_format")]
+    pub max_value: u128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Schedule change of the budget of the rUSD buybacks. Only can be called by owner.
+    pub fn set_buyback_config(&mut self, config: BuybackConfig) -> ChangeId {
+        self.assert_owner();
+        assert!(config.share_bps <= utils::BPS_DIVISOR, "{}", errors::ILLEGAL_FEE_CONFIG);
+        self.internal_schedule_param_change(ParamChange::BuybackConfig { config })
+    }
+
+    pub fn get_buyback_config(&self) -> BuybackConfig {
+        self.buyback_config.clone()
+    }
+
+    /// Spends the budgeted share of the accrued treasury fees of the raft to buy rUSD out of the debt pool
+    /// and burn it, lowering the debt of its participants. Returns the amount of rUSD burned.
+    /// Only can be called by owner, guardians or keepers.
+    pub fn buyback_rusd(&mut self, raft_id: AccountId) -> U128 {
+        self.assert_owner_guardians_or_keepers();
+        let rusd_id = self.query_rusd().expect(errors::ASSET_NOT_FOUND).address;
+
+        let mut amount = math::share(self.fee_ledger.query_accrued(&raft_id), self.buyback_config.share_bps,
+                                     utils::BPS_DIVISOR);
+        let valuation = self.valuation();
+        if self.buyback_config.max_value > 0 {
+            amount = std::cmp::min(amount, valuation.calc_amount(&raft_id, self.buyback_config.max_value));
+        }
+        let mut rusd_amount = valuation.convert(&raft_id, amount, &rusd_id);
+
+        // only the rUSD the debt pool is long of can be bought out of it
+        let pool_rusd_amount = self.debt_pool.query_raft_amount(&rusd_id);
+        let available = if pool_rusd_amount.is_positive { pool_rusd_amount.amount } else { 0 };
+        if rusd_amount > available {
+            rusd_amount = available;
+            amount = valuation.convert(&rusd_id, rusd_amount, &raft_id);
+        }
+        if amount == 0 || rusd_amount == 0 {
+            return U128(0);
+        }
+
+        self.fee_ledger.withdraw(fees::FeeBucket::Treasury, &raft_id, amount);
+        self.debt_pool.calc_sub_raft_amount(&rusd_id, &pool_rusd_amount, rusd_amount);
+        Event::BuybackRusd {
+            raft_id: &raft_id,
+            amount: U128(amount),
+            rusd_amount: U128(rusd_amount),
+        }.emit();
+        U128(rusd_amount)
+    }
+}
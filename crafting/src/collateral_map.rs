@@ -0,0 +1,154 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, Vector};
+use near_sdk::{env, AccountId, Balance, BlockHeight, Timestamp};
+
+use crate::*;
+
+/// Index of a token or raft interned by the collateral map.
+pub type AssetIndex = u16;
+
+/// Collateral as stored, with its token and raft interned as indices instead of full account ids.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct CompactCollateral {
+    issuer: AccountId,
+    token_index: AssetIndex,
+    token_amount: Balance,
+    raft_index: AssetIndex,
+    raft_amount: Balance,
+    join_debtpool: bool,
+    block_index: BlockHeight,
+    create_time: Timestamp,
+    state: CollateralState,
+    stability_fee: Balance,
+    fee_accrued_at: Timestamp,
+}
+
+/// Collaterals keyed by id, ids are handed out by a counter and never reused.
+/// The counter and the records written before the compact encoding keep the layout of the former
+/// `Vector<Collateral>`, such records are read in place and rewritten compact on their next change.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct CollateralMap {
+    /// Id of the next collateral.
+    next_id: CollateralId,
+    records: LookupMap<CollateralId, Collateral>,
+    compact_records: LookupMap<CollateralId, CompactCollateral>,
+    /// Interned tokens and rafts by index, indices are never reused.
+    asset_ids: Vector<AccountId>,
+    asset_indices: LookupMap<AccountId, AssetIndex>,
+}
+
+impl CollateralMap {
+    pub(crate) fn new() -> Self {
+        Self {
+            next_id: 0,
+            records: LookupMap::new(StorageKey::Collaterals),
+            compact_records: LookupMap::new(StorageKey::CompactCollaterals),
+            asset_ids: Vector::new(StorageKey::CollateralAssetIds),
+            asset_indices: LookupMap::new(StorageKey::CollateralAssetIndices),
+        }
+    }
+
+    /// Number of ids handed out, pruned collaterals included.
+    pub(crate) fn len(&self) -> u64 {
+        self.next_id
+    }
+
+    pub(crate) fn get(&self, collateral_id: CollateralId) -> Option<Collateral> {
+        self.compact_records.get(&collateral_id)
+            .map(|record| self.expand(record))
+            .or_else(|| self.records.get(&collateral_id))
+    }
+
+    /// Stores a new collateral under the next id and returns it.
+    pub(crate) fn push(&mut self, collateral: &Collateral) -> CollateralId {
+        let collateral_id = self.next_id;
+        let record = self.compact(collateral);
+        self.compact_records.insert(&collateral_id, &record);
+        self.next_id += 1;
+        collateral_id
+    }
+
+    pub(crate) fn replace(&mut self, collateral_id: CollateralId, collateral: &Collateral) {
+        assert!(collateral_id < self.next_id, "{}", errors::COLLATERAL_NOT_FOUND);
+        let record = self.compact(collateral);
+        if self.compact_records.insert(&collateral_id, &record).is_none() {
+            self.records.remove(&collateral_id);
+        }
+    }
+
+    pub(crate) fn remove(&mut self, collateral_id: CollateralId) -> Option<Collateral> {
+        self.compact_records.remove(&collateral_id)
+            .map(|record| self.expand(record))
+            .or_else(|| self.records.remove(&collateral_id))
+    }
+
+    /// Token or raft interned at the index.
+    fn query_asset_id(&self, asset_index: AssetIndex) -> Option<AccountId> {
+        self.asset_ids.get(asset_index as u64)
+    }
+
+    fn intern(&mut self, asset_id: &AccountId) -> AssetIndex {
+        self.asset_indices.get(asset_id).unwrap_or_else(|| {
+            assert!(self.asset_ids.len() <= AssetIndex::MAX as u64, "{}", errors::TOO_MANY_ASSETS);
+            let asset_index = self.asset_ids.len() as AssetIndex;
+            self.asset_ids.push(asset_id);
+            self.asset_indices.insert(asset_id, &asset_index);
+            asset_index
+        })
+    }
+
+    fn compact(&mut self, collateral: &Collateral) -> CompactCollateral {
+        CompactCollateral {
+            issuer: collateral.issuer.clone(),
+            token_index: self.intern(&collateral.token_id),
+            token_amount: collateral.token_amount,
+            raft_index: self.intern(&collateral.raft_id),
+            raft_amount: collateral.raft_amount,
+            join_debtpool: collateral.join_debtpool,
+            block_index: collateral.block_index,
+            create_time: collateral.create_time,
+            state: collateral.state.clone(),
+            stability_fee: collateral.stability_fee,
+            fee_accrued_at: collateral.fee_accrued_at,
+        }
+    }
+
+    fn expand(&self, record: CompactCollateral) -> Collateral {
+        Collateral {
+            issuer: record.issuer,
+            token_id: self.query_asset_id(record.token_index).expect(errors::ASSET_NOT_FOUND),
+            token_amount: record.token_amount,
+            raft_id: self.query_asset_id(record.raft_index).expect(errors::ASSET_NOT_FOUND),
+            raft_amount: record.raft_amount,
+            join_debtpool: record.join_debtpool,
+            block_index: record.block_index,
+            create_time: record.create_time,
+            state: record.state,
+            stability_fee: record.stability_fee,
+            fee_accrued_at: record.fee_accrued_at,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Deletes the records of closed collaterals, skipping open or already pruned ones. Their ids aren't reused.
+    /// Returns the number of collaterals pruned. Only can be called by owner or guardians.
+    pub fn prune_collaterals(&mut self, collateral_ids: Vec<CollateralId>) -> u64 {
+        self.assert_owner_or_guardians();
+        assert!(collateral_ids.len() as u64 <= utils::KEEPER_BATCH_SIZE, "{}", errors::ILLEGAL_BATCH_SIZE);
+
+        let mut pruned = 0;
+        for collateral_id in collateral_ids {
+            let is_closed = self.query_collateral(collateral_id)
+                .is_some_and(|collateral| collateral.state == CollateralState::Closed);
+            if is_closed {
+                self.collaterals.remove(collateral_id);
+                self.collateral_transfers.remove(&collateral_id);
+                pruned += 1;
+            }
+        }
+        env::log_str(format!("Pruned {} collaterals", pruned).as_str());
+        pruned
+    }
+}
@@ -0,0 +1,64 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::U64;
+use near_sdk::{env, AccountId, Timestamp};
+
+use crate::*;
+use crate::timelock::{ChangeId, ParamChange};
+
+/// Minimum interval between the swaps of an account, so stale oracle prices can't be exploited
+/// by quickly toggling between rafts.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct SwapCooldown {
+    /// Interval in nanoseconds an account has to wait between swaps, 0 disables the cooldown.
+    pub(crate) interval: u64,
+    /// Mapping from account to the timestamp of its last swap.
+    last_swaps: LookupMap<AccountId, Timestamp>,
+}
+
+impl SwapCooldown {
+    pub(crate) fn new() -> Self {
+        Self {
+            interval: 0,
+            last_swaps: LookupMap::new(StorageKey::LastSwaps),
+        }
+    }
+
+    /// Timestamp from which the account can swap again, `None` if it can already.
+    pub(crate) fn query_next_swap(&self, account_id: &AccountId) -> Option<Timestamp> {
+        if self.interval == 0 {
+            return None;
+        }
+        self.last_swaps.get(account_id)
+            .map(|swapped_at| swapped_at + self.interval)
+            .filter(|next_swap| env::block_timestamp() < *next_swap)
+    }
+
+    /// Records a swap of the account, panics if its cooldown isn't over.
+    pub(crate) fn record(&mut self, account_id: &AccountId) {
+        if self.interval == 0 {
+            return;
+        }
+        assert!(self.query_next_swap(account_id).is_none(), "{}", errors::SWAP_COOLDOWN);
+        self.last_swaps.insert(account_id, &env::block_timestamp());
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Schedule change of the minimum interval in nanoseconds between the swaps of an account.
+    /// Only can be called by owner.
+    pub fn set_swap_cooldown(&mut self, interval: U64) -> ChangeId {
+        self.assert_owner();
+        self.internal_schedule_param_change(ParamChange::SwapCooldown { interval: interval.into() })
+    }
+
+    pub fn get_swap_cooldown(&self) -> U64 {
+        U64(self.swap_cooldown.interval)
+    }
+
+    /// Timestamp from which the account can swap again, `None` if it can already.
+    pub fn get_next_swap_time(&self, account_id: AccountId) -> Option<U64> {
+        self.swap_cooldown.query_next_swap(&account_id).map(U64)
+    }
+}
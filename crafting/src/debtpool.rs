@@ -1,4 +1,3 @@
-use std::collections::HashMap;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::{LookupMap, UnorderedMap};
 use near_sdk::{AccountId, Balance};
@@ -8,74 +7,110 @@ use crate::*;
 #[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct WrappedBalance {
+    #[serde(with = "u128_dec_format")]
     pub(crate) amount: Balance,
     pub(crate) is_positive: bool,
 }
 
+/// Value of the rafts of a user in debt pool, kept per raft along with the price round of the raft
+/// it was computed at, so that a feed only makes the value of the raft fed stale.
+#[derive(Default, BorshSerialize, BorshDeserialize)]
+pub struct UserValue {
+    raft_values: Vec<(AccountId, u64, u128)>,
+}
+
 #[derive(BorshSerialize, BorshDeserialize)]
 pub struct DebtPool {
     /// Mapping from raft to amount of raft that is in debt pool.
     raft_amounts: UnorderedMap<AccountId, WrappedBalance>,
     /// Mapping from user and raft to amount of raft that is in debt pool.
     user_raft_amounts: LookupMap<(AccountId, AccountId), Balance>,
-    /// Mapping from user to debt ratio.
-    debt_ratios: HashMap<AccountId, u128>,
+    /// Mapping from raft to number of users holding a non zero amount of it.
+    holder_counts: LookupMap<AccountId, u64>,
+    /// Mapping from user to its generation and debt shares, the debt ratio of a user being its share
+    /// of `total_debt_ratio`. Shares of an older generation were zeroed by a rescale and count as none.
+    debt_shares: LookupMap<AccountId, (u64, u128)>,
+    total_debt_shares: u128,
+    /// Sum of the debt ratios, rescaling it rescales all of them at once.
+    total_debt_ratio: u128,
+    generation: u64,
+    /// Mapping from user to the value of its rafts in debt pool, updated on each change of its raft amounts.
+    user_values: LookupMap<AccountId, UserValue>,
 }
 
 impl DebtPool {
     pub(crate) fn new() -> Self {
         Self {
-            raft_amounts: UnorderedMap::new(b"r".to_vec()),
-            user_raft_amounts: LookupMap::new(b"r".to_vec()),
-            debt_ratios: HashMap::new(),
+            raft_amounts: UnorderedMap::new(StorageKey::DebtPoolRaftAmounts),
+            user_raft_amounts: LookupMap::new(StorageKey::DebtPoolUserRaftAmounts),
+            holder_counts: LookupMap::new(StorageKey::DebtPoolHolderCounts),
+            debt_shares: LookupMap::new(StorageKey::DebtPoolShares),
+            total_debt_shares: 0,
+            total_debt_ratio: 0,
+            generation: 0,
+            user_values: LookupMap::new(StorageKey::DebtPoolUserValues),
         }
     }
 
-    pub(crate) fn join(&mut self, price_oracle: &oracle::PriceInfo, user: &AccountId,
-                       raft_id: &AccountId, raft_amount: Balance) {
+    /// Returns the old and the new total value the other debt ratios were rescaled by, if they were.
+    pub(crate) fn join(&mut self, valuation: &oracle::Valuation, user: &AccountId,
+                       raft_id: &AccountId, raft_amount: Balance) -> Option<(u128, u128)> {
         if self.raft_amounts.is_empty() {
             self.insert_raft_amount(raft_id, &WrappedBalance {
                 amount: raft_amount,
                 is_positive: true,
             });
-            self.insert_user_raft_amount(user, raft_id, raft_amount);
+            self.insert_user_raft_amount(valuation, user, raft_id, raft_amount);
             self.insert_debt_ratio(user.clone(), utils::RATIO_DIVISOR);
+            self.refresh_user_value(valuation, user);
+            None
         } else {
-            let old_total_value = self.calc_raft_total_value(price_oracle);
+            let old_total_value = self.calc_raft_total_value(valuation);
 
             let old_raft_amount = self.query_raft_amount(raft_id);
             self.calc_add_raft_amount(raft_id, &old_raft_amount, raft_amount);
 
             let old_user_raft_amount = self.query_user_raft_amount(user, raft_id);
-            self.insert_user_raft_amount(user, raft_id, old_user_raft_amount + raft_amount);
+            self.insert_user_raft_amount(valuation, user, raft_id, old_user_raft_amount + raft_amount);
 
-            let join_raft_value = self.calc_raft_value(price_oracle, raft_id, raft_amount);
+            let join_raft_value = self.calc_raft_value(valuation, raft_id, raft_amount);
             let new_total_value = old_total_value + join_raft_value;
 
             self.calc_debt_ratio(old_total_value, new_total_value, user.clone());
+            self.refresh_user_value(valuation, user);
+            Some((old_total_value, new_total_value))
         }
     }
 
     pub(crate) fn query_raft_amount(&self, raft_id: &AccountId) -> WrappedBalance {
-        let opt_wbalance = self.raft_amounts.get(raft_id);
-        if opt_wbalance.is_some() {
-            opt_wbalance.unwrap();
-        }
-
-        return WrappedBalance{
+        self.raft_amounts.get(raft_id).unwrap_or(WrappedBalance {
             amount: 0,
             is_positive: true,
-        }
+        })
     }
 
     pub(crate) fn insert_raft_amount(&mut self, raft_id: &AccountId, amount: &WrappedBalance) {
         self.raft_amounts.insert(raft_id, amount);
     }
 
+    /// Drops the entry of a delisted raft, so that the per raft loops stay bounded by `max_rafts`.
+    pub(crate) fn remove_raft(&mut self, raft_id: &AccountId) {
+        self.raft_amounts.remove(raft_id);
+    }
+
     pub(crate) fn query_user_raft_amount(&self, user: &AccountId, raft_id: &AccountId) -> Balance {
         self.user_raft_amounts.get(&(user.clone(), raft_id.clone())).unwrap_or(0)
     }
 
+    /// Rafts with their amounts, by index of the map.
+    pub(crate) fn query_raft_amounts(&self, from_index: u64, limit: u64) -> Vec<(AccountId, WrappedBalance)> {
+        let keys = self.raft_amounts.keys_as_vector();
+        let values = self.raft_amounts.values_as_vector();
+        (from_index..std::cmp::min(from_index.saturating_add(limit), keys.len()))
+            .map(|index| (keys.get(index).unwrap(), values.get(index).unwrap()))
+            .collect()
+    }
+
     pub(crate) fn query_user_raft_amounts(&self, user: &AccountId) -> Vec<(AccountId, Balance)> {
         let mut vec: Vec<(AccountId, Balance)> = Vec::new();
         for (raft_id, _) in self.raft_amounts.iter() {
@@ -88,46 +123,163 @@ impl DebtPool {
         vec
     }
 
-    pub(crate) fn insert_user_raft_amount(&mut self, user: &AccountId, raft_id: &AccountId, amount: Balance) {
-        self.user_raft_amounts.insert(&(user.clone(), raft_id.clone()), &amount);
+    pub(crate) fn insert_user_raft_amount(&mut self, valuation: &oracle::Valuation, user: &AccountId,
+                                          raft_id: &AccountId, amount: Balance) {
+        let old_amount = self.user_raft_amounts.insert(&(user.clone(), raft_id.clone()), &amount).unwrap_or(0);
+        self.update_holder_count(raft_id, old_amount, amount);
+        self.update_user_value(valuation, user, raft_id, old_amount, amount);
     }
 
-    pub(crate) fn remove_user_raft_amount(&mut self, user: &AccountId, raft_id: &AccountId) {
-        self.user_raft_amounts.remove(&(user.clone(), raft_id.clone()));
+    pub(crate) fn remove_user_raft_amount(&mut self, valuation: &oracle::Valuation, user: &AccountId,
+                                          raft_id: &AccountId) {
+        let old_amount = self.user_raft_amounts.remove(&(user.clone(), raft_id.clone())).unwrap_or(0);
+        self.update_holder_count(raft_id, old_amount, 0);
+        self.update_user_value(valuation, user, raft_id, old_amount, 0);
     }
 
-    pub(crate) fn calc_raft_value(&self, price_oracle: &oracle::PriceInfo, raft_id: &AccountId, amount: Balance) -> u128 {
-        price_oracle.get_price(raft_id) * amount
+    /// Replaces the value of the raft in the cached value of the user, if any, by the value of its new amount.
+    fn update_user_value(&mut self, valuation: &oracle::Valuation, user: &AccountId, raft_id: &AccountId,
+                         old_amount: Balance, new_amount: Balance) {
+        if old_amount == new_amount {
+            return;
+        }
+        if let Some(mut user_value) = self.user_values.get(user) {
+            user_value.raft_values.retain(|(id, _, _)| id != raft_id);
+            if new_amount != 0 {
+                user_value.raft_values.push((raft_id.clone(), valuation.round(raft_id),
+                                             self.calc_raft_value(valuation, raft_id, new_amount)));
+            }
+            self.user_values.insert(user, &user_value);
+        }
+    }
+
+    /// Values of the rafts of the user, recomputing only the ones fed since they were cached.
+    /// Returns whether any was recomputed.
+    fn calc_user_raft_values(&self, valuation: &oracle::Valuation, user: &AccountId) -> (UserValue, bool) {
+        let mut user_value = match self.user_values.get(user) {
+            Some(user_value) => user_value,
+            None => {
+                let raft_values = self.query_user_raft_amounts(user).into_iter()
+                    .map(|(raft_id, amount)| {
+                        let round = valuation.round(&raft_id);
+                        let value = self.calc_raft_value(valuation, &raft_id, amount);
+                        (raft_id, round, value)
+                    })
+                    .collect();
+                return (UserValue { raft_values }, true);
+            }
+        };
+        let mut refreshed = false;
+        for (raft_id, round, value) in user_value.raft_values.iter_mut() {
+            if *round != valuation.round(raft_id) {
+                *round = valuation.round(raft_id);
+                *value = self.calc_raft_value(valuation, raft_id, self.query_user_raft_amount(user, raft_id));
+                refreshed = true;
+            }
+        }
+        (user_value, refreshed)
+    }
+
+    /// Value of the rafts of the user in debt pool, from the cache for the rafts not fed since.
+    pub(crate) fn query_user_value(&self, valuation: &oracle::Valuation, user: &AccountId) -> u128 {
+        let (user_value, _) = self.calc_user_raft_values(valuation, user);
+        user_value.raft_values.iter().map(|(_, _, value)| value).sum()
+    }
+
+    /// Same as `query_user_value`, caching the values that had to be computed.
+    /// Amount changes keep the cached values up to date until the next feed of their raft.
+    pub(crate) fn refresh_user_value(&mut self, valuation: &oracle::Valuation, user: &AccountId) -> u128 {
+        let (user_value, refreshed) = self.calc_user_raft_values(valuation, user);
+        if refreshed {
+            self.user_values.insert(user, &user_value);
+        }
+        user_value.raft_values.iter().map(|(_, _, value)| value).sum()
+    }
+
+    pub(crate) fn remove_user_value(&mut self, user: &AccountId) {
+        self.user_values.remove(user);
+    }
+
+    pub(crate) fn query_holder_count(&self, raft_id: &AccountId) -> u64 {
+        self.holder_counts.get(raft_id).unwrap_or(0)
+    }
+
+    fn update_holder_count(&mut self, raft_id: &AccountId, old_amount: Balance, new_amount: Balance) {
+        let count = self.query_holder_count(raft_id);
+        if old_amount == 0 && new_amount != 0 {
+            self.holder_counts.insert(raft_id, &(count + 1));
+        } else if old_amount != 0 && new_amount == 0 {
+            self.holder_counts.insert(raft_id, &(count - 1));
+        }
+    }
+
+    pub(crate) fn calc_raft_value(&self, valuation: &oracle::Valuation, raft_id: &AccountId, amount: Balance) -> u128 {
+        valuation.calc_value(raft_id, amount)
+    }
+
+    fn query_debt_shares(&self, user: &AccountId) -> u128 {
+        match self.debt_shares.get(user) {
+            Some((generation, shares)) if generation == self.generation => shares,
+            _ => 0,
+        }
     }
 
     pub(crate) fn query_debt_ratio(&self, user: &AccountId) -> u128 {
-        self.debt_ratios.get(user).copied().unwrap_or(0)
+        if self.total_debt_shares == 0 {
+            return 0;
+        }
+        math::mul_div(self.query_debt_shares(user), self.total_debt_ratio, self.total_debt_shares)
     }
 
-    fn insert_debt_ratio(&mut self, user: AccountId, debt_ratio: u128) {
-        self.debt_ratios.insert(user, debt_ratio);
+    pub(crate) fn query_total_debt_ratio(&self) -> u128 {
+        self.total_debt_ratio
     }
 
-    pub(crate) fn remove_debt_ratio(&mut self, user: &AccountId) {
-        self.debt_ratios.remove(user);
+    pub(crate) fn insert_debt_ratio(&mut self, user: AccountId, debt_ratio: u128) {
+        self.remove_debt_ratio(&user);
+        self.add_debt_ratio(&user, debt_ratio);
     }
 
-    pub(crate) fn calc_raft_total_value(&self, price_oracle: &oracle::PriceInfo) -> u128 {
-        let mut total: u128 = 0;
-        for (raft, wbalance) in self.raft_amounts.iter() {
-            total += self.calc_raft_value(price_oracle, &raft, wbalance.amount);
+    /// Raises the debt ratio of the user, minting shares at the current ratio per share
+    /// so that the debt ratios of the others don't change.
+    fn add_debt_ratio(&mut self, user: &AccountId, debt_ratio: u128) {
+        if debt_ratio == 0 { return; }
+
+        let shares = if self.total_debt_shares == 0 {
+            debt_ratio
+        } else {
+            math::mul_div(debt_ratio, self.total_debt_shares, self.total_debt_ratio)
+        };
+        self.debt_shares.insert(user, &(self.generation, self.query_debt_shares(user) + shares));
+        self.total_debt_shares += shares;
+        self.total_debt_ratio += debt_ratio;
+    }
+
+    pub(crate) fn remove_debt_ratio(&mut self, user: &AccountId) {
+        let debt_ratio = self.query_debt_ratio(user);
+        let shares = self.query_debt_shares(user);
+        self.debt_shares.remove(user);
+        self.total_debt_shares -= shares;
+        self.total_debt_ratio -= debt_ratio;
+        if self.total_debt_shares == 0 {
+            self.total_debt_ratio = 0;
         }
+    }
 
-        total
+    /// Rescales all the debt ratios by `old_total_value / new_total_value`.
+    /// Once the total ratio is zeroed, the shares left are dropped by starting a new generation.
+    fn rescale_debt_ratios(&mut self, old_total_value: u128, new_total_value: u128) {
+        self.total_debt_ratio = math::mul_div(old_total_value, self.total_debt_ratio, new_total_value);
+        if self.total_debt_ratio == 0 && self.total_debt_shares > 0 {
+            self.total_debt_shares = 0;
+            self.generation += 1;
+        }
     }
 
-    pub(crate) fn calc_user_raft_total_value(&self, price_oracle: &oracle::PriceInfo, user: &AccountId) -> u128 {
+    pub(crate) fn calc_raft_total_value(&self, valuation: &oracle::Valuation) -> u128 {
         let mut total: u128 = 0;
-        for (raft, _) in self.raft_amounts.iter() {
-            let amount = self.query_user_raft_amount(user, &raft);
-            if amount != 0 {
-                total += self.calc_raft_value(price_oracle, &raft, amount);
-            }
+        for (raft, wbalance) in self.raft_amounts.iter() {
+            total += self.calc_raft_value(valuation, &raft, wbalance.amount);
         }
 
         total
@@ -137,28 +289,14 @@ impl DebtPool {
     fn calc_debt_ratio(&mut self, old_total_value: u128, new_total_value: u128, sender_id: AccountId) {
         if new_total_value == 0 { return; }
 
-        let mut is_new_user = true;
-
-        for (user, debt_ratio) in self.debt_ratios.iter_mut() {
-            if *user != sender_id {
-                *debt_ratio = (old_total_value * (*debt_ratio)) / new_total_value;
-            } else {
-                *debt_ratio = (old_total_value * (*debt_ratio) + (new_total_value - old_total_value) * utils::RATIO_DIVISOR) / new_total_value;
-                is_new_user = false;
-            }
-        }
-
-        if is_new_user {
-            self.insert_debt_ratio(sender_id, (new_total_value - old_total_value) * utils::RATIO_DIVISOR / new_total_value);
-        }
+        self.rescale_debt_ratios(old_total_value, new_total_value);
+        self.add_debt_ratio(&sender_id, math::mul_div(new_total_value - old_total_value, utils::RATIO_DIVISOR, new_total_value));
     }
 
     pub (crate) fn calc_all_debt_ratio(&mut self, old_total_value: u128, new_total_value: u128) {
         if new_total_value == 0 { return; }
 
-        for (_, debt_ratio) in self.debt_ratios.iter_mut() {
-            *debt_ratio = (old_total_value * (*debt_ratio)) / new_total_value;
-        }
+        self.rescale_debt_ratios(old_total_value, new_total_value);
     }
 
     pub(crate) fn calc_add_raft_amount(&mut self, raft_id: &AccountId, raft_amount: &WrappedBalance, amount: Balance) {
@@ -203,3 +341,91 @@ impl DebtPool {
         }
     }
 }
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+
+    const RATIO: u128 = utils::RATIO_DIVISOR;
+
+    fn debt_pool() -> DebtPool {
+        testing_env!(VMContextBuilder::new().build());
+        DebtPool::new()
+    }
+
+    #[test]
+    fn test_debt_shares_join() {
+        let mut debt_pool = debt_pool();
+        debt_pool.insert_debt_ratio(accounts(1), RATIO);
+        assert_eq!(debt_pool.query_debt_ratio(&accounts(1)), RATIO);
+
+        // joining with as much value as the pool halves the ratio of the others
+        debt_pool.calc_debt_ratio(100, 200, accounts(2));
+        assert_eq!(debt_pool.query_debt_ratio(&accounts(1)), RATIO / 2);
+        assert_eq!(debt_pool.query_debt_ratio(&accounts(2)), RATIO / 2);
+        assert_eq!(debt_pool.query_total_debt_ratio(), RATIO);
+
+        // joining again adds to the ratio of the user
+        debt_pool.calc_debt_ratio(200, 400, accounts(2));
+        assert_eq!(debt_pool.query_debt_ratio(&accounts(1)), RATIO / 4);
+        assert_eq!(debt_pool.query_debt_ratio(&accounts(2)), RATIO * 3 / 4);
+        assert_eq!(debt_pool.query_total_debt_ratio(), RATIO);
+    }
+
+    #[test]
+    fn test_debt_shares_rescale() {
+        let mut debt_pool = debt_pool();
+        debt_pool.insert_debt_ratio(accounts(1), RATIO);
+        debt_pool.calc_debt_ratio(100, 400, accounts(2));
+        assert_eq!(debt_pool.query_debt_ratio(&accounts(1)), RATIO / 4);
+
+        // a redemption taking half of the value out doubles the ratio of everyone
+        debt_pool.calc_all_debt_ratio(400, 200);
+        assert_eq!(debt_pool.query_debt_ratio(&accounts(1)), RATIO / 2);
+        assert_eq!(debt_pool.query_debt_ratio(&accounts(2)), RATIO * 3 / 2);
+
+        // removing a user drops its shares only
+        debt_pool.remove_debt_ratio(&accounts(2));
+        assert_eq!(debt_pool.query_debt_ratio(&accounts(2)), 0);
+        assert_eq!(debt_pool.query_debt_ratio(&accounts(1)), RATIO / 2);
+        assert_eq!(debt_pool.query_total_debt_ratio(), RATIO / 2);
+
+        // the last user leaving resets the total
+        debt_pool.remove_debt_ratio(&accounts(1));
+        assert_eq!(debt_pool.query_total_debt_ratio(), 0);
+        assert_eq!(debt_pool.total_debt_shares, 0);
+    }
+
+    #[test]
+    fn test_debt_shares_new_generation() {
+        let mut debt_pool = debt_pool();
+        debt_pool.insert_debt_ratio(accounts(1), RATIO);
+
+        // a rescale to zero drops the shares left
+        debt_pool.calc_all_debt_ratio(0, 100);
+        assert_eq!(debt_pool.query_total_debt_ratio(), 0);
+        assert_eq!(debt_pool.query_debt_ratio(&accounts(1)), 0);
+
+        // the old shares don't count against the ones of the new generation
+        debt_pool.insert_debt_ratio(accounts(2), RATIO);
+        assert_eq!(debt_pool.query_debt_ratio(&accounts(2)), RATIO);
+        assert_eq!(debt_pool.query_debt_ratio(&accounts(1)), 0);
+        debt_pool.insert_debt_ratio(accounts(1), RATIO);
+        assert_eq!(debt_pool.query_debt_ratio(&accounts(1)), RATIO);
+        assert_eq!(debt_pool.query_total_debt_ratio(), 2 * RATIO);
+    }
+
+    #[test]
+    fn test_debt_shares_insert_replaces() {
+        let mut debt_pool = debt_pool();
+        debt_pool.insert_debt_ratio(accounts(1), RATIO);
+        debt_pool.insert_debt_ratio(accounts(2), RATIO);
+        debt_pool.insert_debt_ratio(accounts(1), RATIO / 2);
+        assert_eq!(debt_pool.query_debt_ratio(&accounts(1)), RATIO / 2);
+        assert_eq!(debt_pool.query_debt_ratio(&accounts(2)), RATIO);
+        assert_eq!(debt_pool.query_total_debt_ratio(), RATIO * 3 / 2);
+    }
+}
@@ -0,0 +1,98 @@
+use near_sdk::{assert_one_yocto, env, AccountId, Balance};
+
+use crate::*;
+use crate::timelock::{ChangeId, ParamChange};
+
+#[near_bindgen]
+impl Contract {
+    /// Schedule change of the fee paid by deleveraged accounts to the keeper, in `BPS_DIVISOR` units
+    /// of the repaid amount. Only can be called by owner.
+    pub fn set_deleverage_fee(&mut self, fee: u32) -> ChangeId {
+        self.assert_owner();
+        assert!(fee <= utils::BPS_DIVISOR, "{}", errors::ILLEGAL_FEE);
+        self.internal_schedule_param_change(ParamChange::DeleverageFee { fee })
+    }
+
+    pub fn get_deleverage_fee(&self) -> u32 {
+        self.deleverage_fee
+    }
+
+    /// Let keepers repay the rUSD collaterals of the caller from its account book rUSD once their collateral ratio
+    /// falls below their liquidation threshold raised by `buffer_bps`, in `BPS_DIVISOR` units. `None` opts out.
+    #[payable]
+    pub fn set_auto_deleverage(&mut self, buffer_bps: Option<u32>) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        assert!(self.internal_get_account(&account_id).is_some(), "{}", errors::ACC_NOT_REGISTERED);
+        match buffer_bps {
+            Some(buffer_bps) => {
+                assert!(buffer_bps <= utils::BPS_DIVISOR, "{}", errors::ILLEGAL_DELEVERAGE_BUFFER);
+                self.deleverage_buffers.insert(&account_id, &buffer_bps);
+            }
+            None => {
+                self.deleverage_buffers.remove(&account_id);
+            }
+        }
+        Event::SetAutoDeleverage { account_id: &account_id, buffer_bps }.emit();
+    }
+
+    pub fn get_auto_deleverage(&self, account_id: AccountId) -> Option<u32> {
+        self.deleverage_buffers.get(&account_id)
+    }
+
+    /// Repays the rUSD collateral from the account book rUSD of its issuer, who opted in to auto-deleverage,
+    /// just enough to lift its collateral ratio back to the raised threshold. The caller gets the deleverage fee,
+    /// paid by the issuer on top, in its account book. Returns the repaid amount.
+    pub fn deleverage_collateral(&mut self, collateral_id: CollateralId) -> U128 {
+        self.assert_contract_running();
+        let keeper_id = env::predecessor_account_id();
+        let mut collateral = self.query_collateral(collateral_id).expect(errors::COLLATERAL_NOT_FOUND);
+        assert!(collateral.state == CollateralState::Open, "{}", errors::COLLATERAL_CLOSED);
+        assert!(!collateral.join_debtpool, "{}", errors::COLLATERAL_IN_DEBTPOOL);
+        let rusd_id = self.query_rusd().expect(errors::ASSET_NOT_FOUND).address;
+        assert_eq!(collateral.raft_id, rusd_id, "{}", errors::ILLEGAL_COLLATERAL_ASSET);
+        let buffer_bps = self.deleverage_buffers.get(&collateral.issuer).expect(errors::AUTO_DELEVERAGE_DISABLED);
+        self.operation_locks.assert_unlocked(&collateral.issuer);
+
+        self.internal_accrue_stability_fee(&mut collateral);
+        let trigger_ratio = math::ratio(
+            &[self.query_liquidation_threshold(&collateral.token_id, &rusd_id),
+              (utils::BPS_DIVISOR + buffer_bps) as u128],
+            &[utils::BPS_DIVISOR as u128],
+        );
+        let collateral_ratio = self.calc_collateral_ratio(&collateral.token_id, collateral.token_amount,
+                                                          &rusd_id, collateral.debt());
+        assert!(collateral_ratio < trigger_ratio, "{}", errors::DELEVERAGE_NOT_TRIGGERED);
+
+        let valuation = self.valuation();
+        let token_value = valuation.calc_value(&collateral.token_id, collateral.token_amount);
+        let target_debt = valuation.calc_amount(&rusd_id, math::ratio(&[token_value, 100], &[trigger_ratio]));
+        let balance = self.account_book.query_user_raft_amount(&collateral.issuer, &rusd_id);
+        let affordable = math::ratio(&[balance, utils::BPS_DIVISOR as u128],
+                                     &[(utils::BPS_DIVISOR + self.deleverage_fee) as u128]);
+        let repaid_amount: Balance = std::cmp::min(collateral.debt().saturating_sub(target_debt), affordable);
+        assert!(repaid_amount > 0, "{}", errors::NOT_ENOUGH_RAFTS);
+        let fee_amount = math::share(repaid_amount, self.deleverage_fee, utils::BPS_DIVISOR);
+
+        // the accrued stability fee is repaid first
+        let stability_fee_amount = std::cmp::min(repaid_amount, collateral.stability_fee);
+        collateral.stability_fee -= stability_fee_amount;
+        collateral.raft_amount -= repaid_amount - stability_fee_amount;
+        self.collaterals.replace(collateral_id, &collateral);
+        self.internal_accrue_fee(&rusd_id, stability_fee_amount);
+
+        self.account_book.burn(&collateral.issuer, &rusd_id, repaid_amount + fee_amount);
+        self.account_book.mint(&keeper_id, &rusd_id, fee_amount);
+
+        Event::AutoDeleverage {
+            account_id: &collateral.issuer,
+            keeper_id: &keeper_id,
+            collateral_id,
+            amount: U128(repaid_amount),
+            fee: U128(fee_amount),
+        }.emit();
+        self.internal_record_operation(OperationKind::Repay, &collateral.issuer,
+                                       vec![rusd_id], vec![repaid_amount]);
+        U128(repaid_amount)
+    }
+}
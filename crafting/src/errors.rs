@@ -1,6 +1,54 @@
-/// some error code may be unused (future use)
-pub const UNAUTHORIZED: &str = "You are not admin";
-pub const NO_PERMISSION: &str = "You do not have permission";
+use std::fmt;
+
+use near_sdk::env;
+
+/// Declares the errors of the contract, each with a stable code which must never be reused:
+/// the `CraftingError` variant and the `&str` constant panicked with, `ERR{code}: message`.
+macro_rules! crafting_errors {
+    ($($code:literal $name:ident $variant:ident $message:literal,)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum CraftingError {
+            $($variant,)*
+        }
+
+        impl CraftingError {
+            pub const ALL: &'static [CraftingError] = &[$(CraftingError::$variant,)*];
+
+            pub fn code(&self) -> u32 {
+                match self {
+                    $(CraftingError::$variant => $code,)*
+                }
+            }
+
+            pub fn message(&self) -> &'static str {
+                match self {
+                    $(CraftingError::$variant => $message,)*
+                }
+            }
+
+            /// Message panicked with, `ERR{code}: message`.
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $(CraftingError::$variant => $name,)*
+                }
+            }
+
+            pub fn panic(&self) -> ! {
+                env::panic_str(self.as_str())
+            }
+        }
+
+        $(pub const $name: &str = concat!("ERR", $code, ": ", $message);)*
+    };
+}
+
+impl fmt::Display for CraftingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// some error code may be unused (future use)
 // pub const NotSyntheticUsd: &str = "Not synthetic USD asset";
 // pub const OutdatedOracle: &str = "Oracle price is outdated";
 // pub const WithdrawLimit: &str = "Withdraw limit";
@@ -15,7 +63,6 @@ pub const NO_PERMISSION: &str = "You do not have permission";
 // pub const SwapUnavailable: &str = "Swap Unavailable";
 // pub const Uninitialized: &str = "Assets list is not initialized";
 // pub const NoAssetFound: &str = "No asset with such address was found";
-// pub const MaxSupply: &str = "Asset max_supply crossed";
 // pub const NotCollateral: &str = "Asset is not collateral";
 // pub const InsufficientValueTrade: &str = "Insufficient value trade";
 // pub const InsufficientAmountAdminWithdraw: &str = "Insufficient amount admin withdraw";
@@ -27,22 +74,134 @@ pub const NO_PERMISSION: &str = "You do not have permission";
 // pub const MismatchedTokens: &str = "Tokens does not represent same asset";
 // pub const SwaplineLimit: &str = "Limit crossed";
 // pub const CollateralLimitExceeded: &str = "Limit of collateral exceeded";
-// pub const UserBorrowLimit: &str = "User borrow limit";
 // pub const VaultBorrowLimit: &str = "Vault borrow limit";
 // pub const VaultWithdrawLimit: &str = "Vault withdraw limit";
 // pub const InvalidAccount: &str = "Invalid Account";
 // pub const PriceConfidenceOutOfRange: &str = "Price confidence out of range";
 // pub const InvalidOracleProgram: &str = "Invalid oracle program";
 // pub const InvalidExchangeAccount: &str = "Invalid exchange account";
-pub const NO_ATTACHED_DEPOSIT: &str = "Requires positive attached deposit";
-pub const SYNTHETIC_AMOUNT_ERROR: &str = "Invalid synthetic amount";
-pub const CONTRACT_PAUSED: &str = "Contract paused";
-pub const ILLEGAL_FEE: &str = "Illegal fee";
-pub const TOKEN_NOT_REG: &str = "Token not registered";
-pub const NOT_ENOUGH_TOKENS: &str = "Not enough tokens in deposit";
-pub const ACC_NOT_REGISTERED: &str = "Account not registered";
-pub const INSUFFICIENT_STORAGE: &str = "Insufficient $NEAR storage deposit";
-pub const TOKEN_NOT_WHITELISTED: &str = "Token not whitelisted";
-pub const CALLBACK_POST_WITHDRAW_INVALID: &str = "Expected 1 promise result from withdraw";
-pub const ILLEGAL_WITHDRAW_AMOUNT: &str = "Illegal withdraw amount";
-pub const NON_ZERO_TOKEN_BALANCE: &str = "Non-zero token balance";
+crafting_errors! {
+    1 UNAUTHORIZED Unauthorized "You are not admin",
+    2 NO_PERMISSION NoPermission "You do not have permission",
+    3 NO_ATTACHED_DEPOSIT NoAttachedDeposit "Requires positive attached deposit",
+    4 SYNTHETIC_AMOUNT_ERROR SyntheticAmountError "Invalid synthetic amount",
+    5 CONTRACT_PAUSED ContractPaused "Contract paused",
+    6 ILLEGAL_FEE IllegalFee "Illegal fee",
+    7 TOKEN_NOT_REG TokenNotReg "Token not registered",
+    8 NOT_ENOUGH_TOKENS NotEnoughTokens "Not enough tokens in deposit",
+    9 ACC_NOT_REGISTERED AccNotRegistered "Account not registered",
+    10 INSUFFICIENT_STORAGE InsufficientStorage "Insufficient $NEAR storage deposit",
+    11 TOKEN_NOT_WHITELISTED TokenNotWhitelisted "Token not whitelisted",
+    12 CALLBACK_POST_WITHDRAW_INVALID CallbackPostWithdrawInvalid "Expected 1 promise result from withdraw",
+    13 ILLEGAL_WITHDRAW_AMOUNT IllegalWithdrawAmount "Illegal withdraw amount",
+    14 NON_ZERO_TOKEN_BALANCE NonZeroTokenBalance "Non-zero token balance",
+    15 ASSET_NOT_FOUND AssetNotFound "Asset not found",
+    16 CHANGE_NOT_FOUND ChangeNotFound "Parameter change not found",
+    17 TIMELOCK_NOT_EXPIRED TimelockNotExpired "Timelock not expired",
+    18 ILLEGAL_TIMELOCK_DELAY IllegalTimelockDelay "Illegal timelock delay",
+    19 ILLEGAL_GOVERNANCE_ACTION IllegalGovernanceAction "Illegal governance action",
+    20 ILLEGAL_ASSET_PATCH IllegalAssetPatch "Illegal asset patch",
+    21 MAX_RAFTS_REACHED MaxRaftsReached "Max number of listed rafts reached",
+    22 ASSET_IN_USE AssetInUse "Asset still has outstanding collateral or debt",
+    23 ASSET_PAUSED AssetPaused "Asset paused",
+    24 ILLEGAL_FEE_CONFIG IllegalFeeConfig "Fee shares don't sum to the whole fee",
+    25 FEE_RECIPIENT_NOT_SET FeeRecipientNotSet "Fee recipient not set",
+    26 ILLEGAL_REFERRER IllegalReferrer "Illegal referrer",
+    27 REFERRER_ALREADY_SET ReferrerAlreadySet "Referrer already set",
+    28 NOT_ENOUGH_FEES NotEnoughFees "Not enough accrued fees",
+    29 MATH_OVERFLOW MathOverflow "Math overflow",
+    30 DIVISION_BY_ZERO DivisionByZero "Division by zero",
+    31 OPERATION_PENDING OperationPending "Another operation of the account is pending",
+    32 ILLEGAL_PROMO_PERIOD IllegalPromoPeriod "Illegal fee promotion period",
+    33 PROMO_NOT_FOUND PromoNotFound "Fee promotion not found",
+    34 MAX_FEE_PROMOS_REACHED MaxFeePromosReached "Max number of fee promotions reached",
+    35 ACCOUNT_IN_USE AccountInUse "Account still has open collaterals or debt",
+    36 FORCE_UNREGISTER_UNSUPPORTED ForceUnregisterUnsupported "Force unregister is not supported",
+    37 ILLEGAL_TRANSFER_MSG IllegalTransferMsg "Illegal transfer message",
+    38 CONTRACT_NOT_INITIALIZED ContractNotInitialized "Contract not initialized",
+    39 NO_LOSTFOUND NoLostfound "Nothing to claim from lost-found",
+    40 COLLATERAL_NOT_FOUND CollateralNotFound "Collateral not found",
+    41 COLLATERAL_CLOSED CollateralClosed "Collateral closed",
+    42 ILLEGAL_COLLATERAL_ASSET IllegalCollateralAsset "Asset doesn't match the collateral",
+    43 ILLEGAL_LIQUIDATION_THRESHOLD IllegalLiquidationThreshold "Liquidation threshold above collateral ratio",
+    44 COLLATERAL_HEALTHY CollateralHealthy "Collateral ratio above liquidation threshold",
+    45 NOT_ENOUGH_RAFTS NotEnoughRafts "Not enough rafts in account book",
+    46 ILLEGAL_DECIMALS IllegalDecimals "Illegal decimals",
+    47 ILLEGAL_METADATA IllegalMetadata "Illegal token metadata",
+    48 METADATA_UNAVAILABLE MetadataUnavailable "Failed to read the token metadata",
+    49 NO_RAFT_CODE NoRaftCode "Raft code not stored",
+    50 ILLEGAL_RAFT_SYMBOL IllegalRaftSymbol "Raft symbol isn't a valid account prefix",
+    51 ASSET_ALREADY_LISTED AssetAlreadyListed "Asset already listed",
+    52 RECEIVER_NOT_REGISTERED ReceiverNotRegistered "Receiver not registered on the token",
+    53 COLLATERAL_IN_DEBTPOOL CollateralInDebtpool "Collateral backs the debt pool",
+    54 LIQUIDATION_ROUTE_NOT_FOUND LiquidationRouteNotFound "No exchange pool to liquidate the token",
+    55 BRIDGE_NOT_CONFIGURED BridgeNotConfigured "Bridge locker not configured",
+    56 ILLEGAL_ETH_ADDRESS IllegalEthAddress "Illegal Ethereum address",
+    57 MAX_SUPPLY MaxSupply "Asset max_supply crossed",
+    58 USER_BORROW_LIMIT UserBorrowLimit "User borrow limit",
+    59 RAFT_NOT_WHITELISTED RaftNotWhitelisted "Raft not whitelisted",
+    60 ILLEGAL_LEVERAGE_RATIO IllegalLeverageRatio "Leverage ratio out of range",
+    61 COLLATERAL_RATIO_TOO_LOW CollateralRatioTooLow "Collateral ratio below the minimum",
+    62 PRICE_NOT_FOUND PriceNotFound "No oracle price for the asset",
+    63 NO_STORAGE_CAN_WITHDRAW NoStorageCanWithdraw "No storage can be withdrawn",
+    64 STORAGE_WITHDRAW_TOO_MUCH StorageWithdrawTooMuch "Storage withdrawal above the available amount",
+    65 ILLEGAL_SWAP_AMOUNT IllegalSwapAmount "Illegal swap amount",
+    66 NOT_ENOUGH_DEBT_REPAYMENT NotEnoughDebtRepayment "Not enough settlement rafts to repay the debt",
+    67 NO_COLLATERALS NoCollaterals "Account has no collaterals",
+    68 MAX_VIEW_GRANTS_REACHED MaxViewGrantsReached "Max number of view grants reached",
+    69 VIEWER_NOT_GRANTED ViewerNotGranted "Viewer has no view access",
+    70 SWAP_COOLDOWN SwapCooldown "Swap cooldown not over",
+    71 ACCOUNT_BLACKLISTED AccountBlacklisted "Account blacklisted",
+    72 ILLEGAL_BATCH_SIZE IllegalBatchSize "Illegal number of batch actions",
+    73 ILLEGAL_ACTION_ORDER IllegalActionOrder "Withdrawal must be the last action",
+    74 ILLEGAL_PRICE IllegalPrice "Illegal price",
+    75 STALE_PRICE StalePrice "Price is stale",
+    76 RELAYER_NOT_ALLOWED RelayerNotAllowed "Not submitted by an allowed relayer",
+    77 ILLEGAL_NONCE IllegalNonce "Illegal operation nonce",
+    78 RELAYED_SWAP_TOO_LARGE RelayedSwapTooLarge "Swap too large to be relayed",
+    79 PENDING_OPERATION_NOT_FOUND PendingOperationNotFound "Pending operation not found",
+    80 PSM_STABLE_NOT_FOUND PsmStableNotFound "Stablecoin not in the peg stability module",
+    81 PSM_CAP_EXCEEDED PsmCapExceeded "Peg stability module cap exceeded",
+    82 PSM_RESERVE_TOO_LOW PsmReserveTooLow "Not enough stablecoins in the peg stability module",
+    83 ILLEGAL_REDEMPTION_ORDER IllegalRedemptionOrder "Collaterals not in ascending collateral ratio order",
+    84 ILLEGAL_SETTLEMENT_RAFT IllegalSettlementRaft "Raft not a settlement raft",
+    85 ILLEGAL_PEG_GUARD IllegalPegGuard "Illegal peg guard",
+    86 RUSD_OFF_PEG RusdOffPeg "Swaps into rUSD blocked while off peg",
+    87 RAFT_SHARE_TOO_HIGH RaftShareTooHigh "Raft share of the debt pool too high",
+    88 COLLATERAL_HAS_DEBT CollateralHasDebt "Collateral debt not repaid",
+    89 COLLATERAL_TRANSFER_NOT_FOUND CollateralTransferNotFound "Collateral transfer not found",
+    90 CROSS_MARGIN_UNSAFE CrossMarginUnsafe "Collateral liquidatable in isolated margin",
+    91 ILLEGAL_DELEVERAGE_BUFFER IllegalDeleverageBuffer "Illegal deleverage buffer",
+    92 AUTO_DELEVERAGE_DISABLED AutoDeleverageDisabled "Auto-deleverage not enabled by the issuer",
+    93 DELEVERAGE_NOT_TRIGGERED DeleverageNotTriggered "Collateral ratio above the deleverage trigger",
+    94 ILLEGAL_STOP_ORDER IllegalStopOrder "Illegal stop order",
+    95 STOP_ORDER_NOT_FOUND StopOrderNotFound "Stop order not found",
+    96 STOP_NOT_TRIGGERED StopNotTriggered "Stop order price not reached",
+    97 PRICE_ROUND_EXPIRED PriceRoundExpired "Price round unknown or too old",
+    98 PRICE_MOVED PriceMoved "Price moved beyond tolerance since the quoted round",
+    99 ILLEGAL_LABEL IllegalLabel "Illegal collateral label",
+    100 REDEMPTION_PENDING RedemptionPending "Debt pool redemption in progress",
+    101 REDEMPTION_NOT_FOUND RedemptionNotFound "No debt pool redemption in progress",
+    102 TOO_MANY_ASSETS TooManyAssets "Max number of collateral assets reached",
+    103 ILLEGAL_GAS_CONFIG IllegalGasConfig "Illegal gas config",
+    104 REWARDS_NOT_CONFIGURED RewardsNotConfigured "Reward emission not configured",
+    105 REWARD_TOKEN_CHANGED RewardTokenChanged "Reward token can't be changed",
+    106 NO_REWARDS NoRewards "No rewards to claim",
+    107 UNCLAIMED_REWARDS UnclaimedRewards "Rewards not claimed",
+    108 FARM_NOT_FOUND FarmNotFound "Farm not found",
+    109 ILLEGAL_FARM IllegalFarm "Illegal farm",
+    110 TOO_MANY_FARMS TooManyFarms "Max number of farms of the target reached",
+    111 FARM_NOT_ENDED FarmNotEnded "Farm not ended",
+    112 ILLEGAL_STAKING_TOKEN IllegalStakingToken "Illegal staking token",
+    113 NOT_ENOUGH_STAKED NotEnoughStaked "Not enough staked",
+    114 UNSTAKE_COOLING_DOWN UnstakeCoolingDown "Unstaked tokens still cooling down",
+    115 ILLEGAL_VESTING IllegalVesting "Illegal vesting schedule",
+    116 VESTING_NOT_FOUND VestingNotFound "Vesting schedule not found",
+    117 TOO_MANY_VESTINGS TooManyVestings "Max number of vesting schedules of the beneficiary reached",
+    118 NOTHING_VESTED NothingVested "Nothing vested to claim",
+    119 LISTED_INCENTIVE_TOKEN ListedIncentiveToken "Listed assets can't be paid as incentives",
+    120 INSUFFICIENT_BUDGET InsufficientBudget "Not enough tokens funded",
+    121 POSITIONS_NOT_ACCEPTED PositionsNotAccepted "Receiver doesn't accept position transfers",
+    122 STAKERS_FEES_STAKED StakersFeesStaked "Stakers fees are shared by the staking",
+    123 TOO_MANY_MARGIN_COLLATERALS TooManyMarginCollaterals "Max number of collaterals in cross margin reached",
+}
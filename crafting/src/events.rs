@@ -0,0 +1,538 @@
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{env, serde_json, AccountId};
+
+use crate::farm::FarmId;
+use crate::fees::FeeBucket;
+use crate::mt::MtAsset;
+use crate::oplog::OperationKind;
+use crate::promo::{FeePromo, PromoId};
+use crate::retry::{PendingOperation, PendingOperationId};
+use crate::stop::StopOrder;
+use crate::timelock::{ChangeId, ParamChange};
+use crate::vesting::{VestingId, VestingSchedule};
+use crate::{Asset, AssetState, CollateralId, RunningState};
+
+pub const EVENT_STANDARD: &str = "crafting";
+pub const EVENT_STANDARD_VERSION: &str = "1.0.0";
+pub const NFT_EVENT_STANDARD: &str = "nep171";
+pub const NFT_EVENT_STANDARD_VERSION: &str = "1.0.0";
+
+/// Account lists managed by the owner, see `Event::AccountListAdd`.
+#[derive(Serialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub(crate) enum AccountList {
+    Guardians,
+    Keepers,
+    Relayers,
+    SettlementRafts,
+    WhitelistedTokens,
+    WhitelistedRafts,
+}
+
+/// Events following NEP-297, logged as `EVENT_JSON:{"standard":"crafting","version":..,"event":..,"data":..}`.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde", tag = "event", content = "data", rename_all = "snake_case")]
+pub(crate) enum Event<'a> {
+    Deposit {
+        account_id: &'a AccountId,
+        token_id: &'a AccountId,
+        amount: U128,
+    },
+    Withdraw {
+        account_id: &'a AccountId,
+        token_id: &'a AccountId,
+        amount: U128,
+    },
+    ClaimLostfound {
+        account_id: &'a AccountId,
+        token_id: &'a AccountId,
+        amount: U128,
+    },
+    Mint {
+        account_id: &'a AccountId,
+        collateral_id: CollateralId,
+        token_id: &'a AccountId,
+        token_amount: U128,
+        raft_id: &'a AccountId,
+        raft_amount: U128,
+        join_debtpool: bool,
+    },
+    AddCollateral {
+        account_id: &'a AccountId,
+        collateral_id: CollateralId,
+        token_id: &'a AccountId,
+        amount: U128,
+    },
+    BorrowMore {
+        account_id: &'a AccountId,
+        collateral_id: CollateralId,
+        raft_id: &'a AccountId,
+        raft_amount: U128,
+    },
+    Repay {
+        account_id: &'a AccountId,
+        collateral_id: CollateralId,
+        raft_id: &'a AccountId,
+        amount: U128,
+    },
+    Redeem {
+        account_id: &'a AccountId,
+        collateral_id: CollateralId,
+        token_id: &'a AccountId,
+        token_amount: U128,
+    },
+    Swap {
+        account_id: &'a AccountId,
+        old_raft_id: &'a AccountId,
+        old_amount: U128,
+        new_raft_id: &'a AccountId,
+        new_amount: U128,
+        fee: U128,
+        in_debtpool: bool,
+    },
+    AccountBookDeposit {
+        account_id: &'a AccountId,
+        raft_id: &'a AccountId,
+        amount: U128,
+    },
+    AccountBookWithdraw {
+        account_id: &'a AccountId,
+        raft_id: &'a AccountId,
+        amount: U128,
+    },
+    BridgeOut {
+        account_id: &'a AccountId,
+        raft_id: &'a AccountId,
+        amount: U128,
+        eth_recipient: &'a str,
+    },
+    Liquidation {
+        liquidator_id: &'a AccountId,
+        account_id: &'a AccountId,
+        collateral_id: CollateralId,
+        token_id: &'a AccountId,
+        token_amount: U128,
+        /// rUSD received for the collateral and burned.
+        rusd_amount: U128,
+        /// Debt of the collateral, in rUSD, not covered by the swap.
+        shortfall: U128,
+    },
+    NftMint {
+        account_id: &'a AccountId,
+        collateral_id: u64,
+        collection_id: &'a AccountId,
+        nft_token_id: &'a str,
+        raft_id: &'a AccountId,
+        raft_amount: U128,
+    },
+    NftRedeem {
+        account_id: &'a AccountId,
+        collateral_id: u64,
+        collection_id: &'a AccountId,
+        nft_token_id: &'a str,
+    },
+    NftLiquidation {
+        liquidator_id: &'a AccountId,
+        account_id: &'a AccountId,
+        collateral_id: u64,
+        collection_id: &'a AccountId,
+        nft_token_id: &'a str,
+        raft_id: &'a AccountId,
+        raft_amount: U128,
+    },
+    MtMint {
+        account_id: &'a AccountId,
+        collateral_id: u64,
+        contract_id: &'a AccountId,
+        token_id: &'a str,
+        token_amount: U128,
+        raft_id: &'a AccountId,
+        raft_amount: U128,
+    },
+    MtRedeem {
+        account_id: &'a AccountId,
+        collateral_id: u64,
+        contract_id: &'a AccountId,
+        token_id: &'a str,
+        token_amount: U128,
+    },
+    MtLiquidation {
+        liquidator_id: &'a AccountId,
+        account_id: &'a AccountId,
+        collateral_id: u64,
+        contract_id: &'a AccountId,
+        token_id: &'a str,
+        token_amount: U128,
+        raft_id: &'a AccountId,
+        raft_amount: U128,
+    },
+    ClaimFees {
+        raft_id: &'a AccountId,
+        treasury_id: &'a AccountId,
+        amount: U128,
+    },
+    RegisterReferral {
+        account_id: &'a AccountId,
+        referrer_id: &'a AccountId,
+    },
+    GrantViewAccess {
+        account_id: &'a AccountId,
+        viewer_id: &'a AccountId,
+    },
+    RevokeViewAccess {
+        account_id: &'a AccountId,
+        viewer_id: &'a AccountId,
+    },
+    ReferralFee {
+        referrer_id: &'a AccountId,
+        account_id: &'a AccountId,
+        raft_id: &'a AccountId,
+        amount: U128,
+    },
+    RedemptionFee {
+        account_id: &'a AccountId,
+        amount: U128,
+        /// Base rate after the redemption, in `RATIO_DIVISOR` units.
+        base_rate: U128,
+    },
+    MintFee {
+        account_id: &'a AccountId,
+        collateral_id: CollateralId,
+        raft_id: &'a AccountId,
+        amount: U128,
+    },
+    FeePaidInRusd {
+        account_id: &'a AccountId,
+        raft_id: &'a AccountId,
+        fee_amount: U128,
+        rusd_amount: U128,
+    },
+    ScheduleFeePromo {
+        promo_id: PromoId,
+        promo: &'a FeePromo,
+    },
+    CancelFeePromo {
+        promo_id: PromoId,
+    },
+    ClaimFeeShare {
+        bucket: FeeBucket,
+        raft_id: &'a AccountId,
+        account_id: &'a AccountId,
+        amount: U128,
+    },
+    BlacklistAdd {
+        account_id: &'a AccountId,
+        by: &'a AccountId,
+    },
+    BlacklistRemove {
+        account_id: &'a AccountId,
+        by: &'a AccountId,
+    },
+    StalePrice {
+        asset_id: &'a AccountId,
+        feed_address: &'a AccountId,
+    },
+    LabelCollateral {
+        account_id: &'a AccountId,
+        collateral_id: CollateralId,
+        label: Option<&'a str>,
+    },
+    SetStop {
+        account_id: &'a AccountId,
+        raft_id: &'a AccountId,
+        order: &'a StopOrder,
+    },
+    CancelStop {
+        account_id: &'a AccountId,
+        raft_id: &'a AccountId,
+    },
+    ExecuteStop {
+        account_id: &'a AccountId,
+        keeper_id: &'a AccountId,
+        raft_id: &'a AccountId,
+        amount: U128,
+        bounty: U128,
+        rusd_amount: U128,
+    },
+    SetAutoDeleverage {
+        account_id: &'a AccountId,
+        buffer_bps: Option<u32>,
+    },
+    AutoDeleverage {
+        account_id: &'a AccountId,
+        keeper_id: &'a AccountId,
+        collateral_id: CollateralId,
+        amount: U128,
+        fee: U128,
+    },
+    SetCrossMargin {
+        account_id: &'a AccountId,
+        enabled: bool,
+    },
+    AcceptPositions {
+        account_id: &'a AccountId,
+        enabled: bool,
+    },
+    OfferCollateral {
+        collateral_id: CollateralId,
+        issuer: &'a AccountId,
+        new_issuer: Option<&'a AccountId>,
+    },
+    TransferCollateral {
+        collateral_id: CollateralId,
+        old_issuer: &'a AccountId,
+        new_issuer: &'a AccountId,
+    },
+    BuybackRusd {
+        raft_id: &'a AccountId,
+        /// Treasury fees of the raft spent.
+        amount: U128,
+        rusd_amount: U128,
+    },
+    RedeemRusd {
+        account_id: &'a AccountId,
+        collateral_id: CollateralId,
+        rusd_amount: U128,
+        token_id: &'a AccountId,
+        token_amount: U128,
+        /// Collateral tokens left to the issuer.
+        fee: U128,
+    },
+    PsmSwapIn {
+        account_id: &'a AccountId,
+        token_id: &'a AccountId,
+        amount: U128,
+        rusd_amount: U128,
+        fee: U128,
+    },
+    PsmSwapOut {
+        account_id: &'a AccountId,
+        token_id: &'a AccountId,
+        amount: U128,
+        rusd_amount: U128,
+        fee: U128,
+    },
+    QueuePendingOperation {
+        op_id: PendingOperationId,
+        operation: &'a PendingOperation,
+    },
+    ResolvePendingOperation {
+        op_id: PendingOperationId,
+        operation: &'a PendingOperation,
+    },
+    ContractStateChange {
+        old_state: &'a RunningState,
+        new_state: &'a RunningState,
+        by: &'a AccountId,
+    },
+    TokenStateChange {
+        token_id: &'a AccountId,
+        old_state: &'a AssetState,
+        new_state: &'a AssetState,
+        by: &'a AccountId,
+    },
+    NftStateChange {
+        collection_id: &'a AccountId,
+        old_state: &'a AssetState,
+        new_state: &'a AssetState,
+        by: &'a AccountId,
+    },
+    MtStateChange {
+        contract_id: &'a AccountId,
+        token_id: &'a str,
+        old_state: &'a AssetState,
+        new_state: &'a AssetState,
+        by: &'a AccountId,
+    },
+    RaftStateChange {
+        raft_id: &'a AccountId,
+        old_state: &'a AssetState,
+        new_state: &'a AssetState,
+        by: &'a AccountId,
+    },
+    OwnerChange {
+        old_owner_id: &'a AccountId,
+        new_owner_id: &'a AccountId,
+    },
+    TreasuryChange {
+        old_treasury_id: &'a AccountId,
+        new_treasury_id: &'a AccountId,
+    },
+    AccountListAdd {
+        list: AccountList,
+        account_id: &'a AccountId,
+        by: &'a AccountId,
+    },
+    AccountListRemove {
+        list: AccountList,
+        account_id: &'a AccountId,
+        by: &'a AccountId,
+    },
+    TokenRemove {
+        token_id: &'a AccountId,
+        old_asset: &'a Asset,
+        by: &'a AccountId,
+    },
+    RaftRemove {
+        raft_id: &'a AccountId,
+        old_asset: &'a Asset,
+        by: &'a AccountId,
+    },
+    NftRemove {
+        collection_id: &'a AccountId,
+        old_asset: &'a Asset,
+        by: &'a AccountId,
+    },
+    MtRemove {
+        contract_id: &'a AccountId,
+        token_id: &'a str,
+        old_asset: &'a MtAsset,
+        by: &'a AccountId,
+    },
+    ParamChangeScheduled {
+        change_id: ChangeId,
+        change: &'a ParamChange,
+        eta: U64,
+    },
+    /// `old` is the value before the change, see `query_param_value`.
+    ParamChangeApplied {
+        change_id: ChangeId,
+        old: Option<&'a ParamChange>,
+        new: &'a ParamChange,
+    },
+    ParamChangeCancelled {
+        change_id: ChangeId,
+    },
+    Instrumentation {
+        kind: OperationKind,
+        gas_used: U64,
+        storage_delta: i64,
+    },
+    ClaimRewards {
+        account_id: &'a AccountId,
+        token_id: &'a AccountId,
+        amount: U128,
+    },
+    FundRewards {
+        account_id: &'a AccountId,
+        token_id: &'a AccountId,
+        amount: U128,
+    },
+    ClaimFarmRewards {
+        farm_id: FarmId,
+        account_id: &'a AccountId,
+        amount: U128,
+    },
+    FundFarm {
+        farm_id: FarmId,
+        account_id: &'a AccountId,
+        amount: U128,
+    },
+    Stake {
+        account_id: &'a AccountId,
+        amount: U128,
+    },
+    Unstake {
+        account_id: &'a AccountId,
+        amount: U128,
+        unlock_at: U64,
+    },
+    WithdrawUnstaked {
+        account_id: &'a AccountId,
+        amount: U128,
+    },
+    ClaimStakingRevenue {
+        account_id: &'a AccountId,
+        raft_id: &'a AccountId,
+        amount: U128,
+    },
+    CreateVesting {
+        vesting_id: VestingId,
+        schedule: &'a VestingSchedule,
+    },
+    RevokeVesting {
+        vesting_id: VestingId,
+        unvested: U128,
+    },
+    FundVesting {
+        vesting_id: VestingId,
+        account_id: &'a AccountId,
+        amount: U128,
+    },
+    ClaimVested {
+        vesting_id: VestingId,
+        account_id: &'a AccountId,
+        token_id: &'a AccountId,
+        amount: U128,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+impl Event<'_> {
+    pub(crate) fn emit(&self) {
+        let log = EventLog {
+            standard: EVENT_STANDARD,
+            version: EVENT_STANDARD_VERSION,
+            event: self,
+        };
+        env::log_str(&format!("EVENT_JSON:{}", serde_json::to_string(&log).unwrap()));
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub(crate) struct NftMintData<'a> {
+    pub owner_id: &'a AccountId,
+    pub token_ids: [String; 1],
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub(crate) struct NftTransferData<'a> {
+    pub old_owner_id: &'a AccountId,
+    pub new_owner_id: &'a AccountId,
+    pub token_ids: [String; 1],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+/// NEP-171 events of the position tokens of the collaterals, logged with the `nep171` standard.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde", tag = "event", content = "data")]
+pub(crate) enum NftEvent<'a> {
+    #[serde(rename = "nft_mint")]
+    Mint([NftMintData<'a>; 1]),
+    #[serde(rename = "nft_transfer")]
+    Transfer([NftTransferData<'a>; 1]),
+    #[serde(rename = "nft_burn")]
+    Burn([NftMintData<'a>; 1]),
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct NftEventLog<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event: &'a NftEvent<'a>,
+}
+
+impl NftEvent<'_> {
+    pub(crate) fn emit(&self) {
+        let log = NftEventLog {
+            standard: NFT_EVENT_STANDARD,
+            version: NFT_EVENT_STANDARD_VERSION,
+            event: self,
+        };
+        env::log_str(&format!("EVENT_JSON:{}", serde_json::to_string(&log).unwrap()));
+    }
+}
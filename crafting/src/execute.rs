@@ -0,0 +1,97 @@
+use crate::*;
+
+/// Step of `execute`. The actions running a cross-contract call can only be the last one.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Action {
+    /// Locks deposited tokens in an open collateral of the caller.
+    Deposit {
+        collateral_id: CollateralId,
+        amount: U128,
+    },
+    /// Opens a collateral with deposited tokens.
+    Mint(MintAction),
+    /// Swaps rafts of the debt pool or of the account book.
+    Swap {
+        old_raft_id: AccountId,
+        new_raft_id: AccountId,
+        swap_amount: U128,
+        in_debtpool: bool,
+    },
+    /// Burns rafts of the caller to credit them to its account book, only as the last action.
+    MoveToAccountBook {
+        raft_id: AccountId,
+        amount: U128,
+    },
+    /// Withdraws deposited tokens, only as the last action.
+    Withdraw {
+        token_id: AccountId,
+        amount: U128,
+        unwrap: Option<bool>,
+    },
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Runs the actions in order, with the deposits of the caller. Any failing action reverts the
+    /// actions before it, only the last action's cross-contract call, a burn into the account book or the
+    /// transfer of a withdrawal, resolves after the call and reverts its own changes if it fails. Returns the new operation nonce of the account.
+    /// Tokens can be deposited and acted on at once with `ft_transfer_call` and an `Execute` message.
+    #[payable]
+    pub fn execute(&mut self, actions: Vec<Action>) -> u64 {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let sender_id = env::predecessor_account_id();
+        self.internal_execute(&sender_id, actions);
+        self.internal_bump_nonce(&sender_id)
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_execute(&mut self, sender_id: &AccountId, actions: Vec<Action>) {
+        assert!(!actions.is_empty() && actions.len() <= utils::MAX_BATCH_SIZE, "{}", errors::ILLEGAL_BATCH_SIZE);
+        let last_index = actions.len() - 1;
+        for (index, action) in actions.into_iter().enumerate() {
+            match action {
+                Action::Deposit { collateral_id, amount } => {
+                    self.internal_deposit_to_collateral(sender_id, collateral_id, amount.into());
+                }
+                Action::Mint(action) => {
+                    self.internal_mint_from_deposits(sender_id, action);
+                }
+                Action::Swap { old_raft_id, new_raft_id, swap_amount, in_debtpool } => {
+                    if in_debtpool {
+                        self.internal_swap_in_debtpool(sender_id, old_raft_id, new_raft_id, swap_amount.into());
+                    } else {
+                        self.internal_swap_in_accountbook(sender_id, old_raft_id, new_raft_id, swap_amount.into());
+                    }
+                }
+                Action::MoveToAccountBook { raft_id, amount } => {
+                    assert_eq!(index, last_index, "{}", errors::ILLEGAL_ACTION_ORDER);
+                    // the rafts are credited by the callback of the burn
+                    self.internal_deposit_in_accountbook(sender_id, raft_id, amount.into());
+                }
+                Action::Withdraw { token_id, amount, unwrap } => {
+                    assert_eq!(index, last_index, "{}", errors::ILLEGAL_ACTION_ORDER);
+                    // the transfer runs after the call, its callback handles a failure
+                    self.internal_withdraw(sender_id, token_id, amount.into(), None, unwrap);
+                }
+            }
+        }
+    }
+
+    /// Locks deposited tokens of the sender, not locked already, in its open collateral.
+    fn internal_deposit_to_collateral(&mut self, sender_id: &AccountId, collateral_id: CollateralId, amount: Balance) {
+        assert!(amount > 0, "{}", errors::NO_ATTACHED_DEPOSIT);
+        self.assert_not_blacklisted(sender_id);
+        self.operation_locks.assert_unlocked(sender_id);
+        let token_id = self.internal_unwrap_open_collateral(sender_id, collateral_id).token_id;
+
+        let account = self.internal_unwrap_account(sender_id);
+        let balance = account.get_balance(&token_id).expect(errors::TOKEN_NOT_REG);
+        let locked = self.internal_get_locked_collateral(sender_id, &token_id);
+        assert!(balance - locked >= amount, "{}", errors::NOT_ENOUGH_TOKENS);
+
+        self.internal_add_to_collateral(sender_id, collateral_id, &token_id, amount);
+    }
+}
@@ -0,0 +1,104 @@
+use near_contract_standards::fungible_token::metadata::{FungibleTokenMetadata, FT_METADATA_SPEC};
+use near_sdk::json_types::U128;
+use near_sdk::serde_json::json;
+use near_sdk::{env, AccountId, Promise, PromiseResult};
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Stores the raft token contract code deployed by `create_raft`, the code is the raw input of the call.
+    /// Only can be called by owner.
+    pub fn store_raft_code(&mut self) {
+        self.assert_owner();
+        let code = env::input().expect(errors::NO_RAFT_CODE);
+        self.raft_code.set(&code);
+        env::log_str(format!("Stored raft code of {} bytes", code.len()).as_str());
+    }
+
+    /// Deploys a raft token as the `<symbol>.<this contract>` subaccount, with this contract as minter,
+    /// then lists and whitelists it. Only can be called by owner.
+    /// The attached deposit pays for the storage of the new account, `max_supply` is 0 for no cap.
+    #[payable]
+    pub fn create_raft(&mut self, symbol: String, name: String, decimals: u8, feed_address: AccountId,
+                       max_supply: U128) -> Promise {
+        self.assert_owner();
+        assert!(decimals as u32 <= utils::MAX_DECIMALS, "{}", errors::ILLEGAL_DECIMALS);
+        let code = self.raft_code.get().expect(errors::NO_RAFT_CODE);
+        let deposit = env::attached_deposit();
+        assert!(
+            deposit >= (code.len() as u128 + utils::RAFT_ACCOUNT_EXTRA_STORAGE) * env::storage_byte_cost(),
+            "{}", errors::INSUFFICIENT_STORAGE
+        );
+
+        let raft_id: AccountId = format!("{}.{}", symbol.to_lowercase(), env::current_account_id())
+            .parse()
+            .expect(errors::ILLEGAL_RAFT_SYMBOL);
+        assert!(self.query_raft(&raft_id).is_none(), "{}", errors::ASSET_ALREADY_LISTED);
+        self.assert_raft_capacity();
+
+        let metadata = FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.to_string(),
+            name,
+            symbol,
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals,
+        };
+        let args = json!({
+            "owner_id": env::current_account_id(),
+            "total_supply": U128(0),
+            "metadata": metadata,
+            "controller_id": env::current_account_id(),
+        });
+
+        let mut asset = Asset::unnamed("nep141".to_string(), raft_id.clone(), feed_address, 0, AssetState::Active);
+        asset.apply_metadata(metadata);
+        asset.max_supply = max_supply.into();
+
+        Promise::new(raft_id)
+            .create_account()
+            .transfer(deposit)
+            .deploy_contract(code)
+            .function_call(
+                "new".to_string(),
+                args.to_string().into_bytes(),
+                utils::NO_DEPOSIT,
+                utils::GAS_FOR_RAFT_INIT,
+            )
+            .then(ext_self::create_raft_callback(
+                asset,
+                U128(deposit),
+                env::current_account_id(),
+                utils::NO_DEPOSIT,
+                self.gas_config.resolve,
+            ))
+    }
+
+    /// Lists the deployed raft, or refunds the owner if the deployment failed.
+    #[private]
+    pub fn create_raft_callback(&mut self, asset: Asset, deposit: U128) -> bool {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => {
+                self.raft_list.insert(&asset.address, &asset);
+                self.whitelisted_rafts.insert(&asset.address);
+                env::log_str(format!("Raft {} created", asset.address).as_str());
+                true
+            }
+            PromiseResult::Failed => {
+                Promise::new(self.owner_id.clone()).transfer(deposit.into());
+                env::log_str(format!("Failed to create raft {}", asset.address).as_str());
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, Vector};
+use near_sdk::{assert_one_yocto, env, AccountId, Balance, Promise, PromiseResult, Timestamp};
+
+use crate::*;
+
+pub type FarmId = u64;
+
+/// Precision of the reward index of farms.
+const FARM_INDEX_PRECISION: u128 = 1_000_000_000_000_000_000;
+
+/// Behavior rewarded by a farm, the stake of an account being the amount it keeps of it.
+#[derive(Clone, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum FarmTarget {
+    /// Amount of the raft held in the debt pool.
+    DebtPoolRaft { raft_id: AccountId },
+    /// Amount of the stablecoin swapped into the peg stability module by the account and not swapped out by it.
+    PsmDeposit { token_id: AccountId },
+}
+
+/// Reward campaign emitting a token to the stakers of its target, pro rata by stake.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Farm {
+    pub target: FarmTarget,
+    pub reward_token: AccountId,
+    /// Amount emitted per second between `start` and `end`.
+    #[serde(with = "u128_dec_format")]
+    pub rate: Balance,
+    #[serde(with = "u64_dec_format")]
+    pub start: Timestamp,
+    #[serde(with = "u64_dec_format")]
+    pub end: Timestamp,
+    #[serde(with = "u128_dec_format")]
+    pub total_stake: Balance,
+    /// Cumulative reward per unit of stake, in `FARM_INDEX_PRECISION` units.
+    #[serde(with = "u128_dec_format")]
+    pub index: u128,
+    /// Reward tokens funded through `ft_transfer_call` and not claimed yet, claims can't go beyond it.
+    #[serde(with = "u128_dec_format")]
+    pub budget: Balance,
+    #[serde(with = "u64_dec_format")]
+    pub updated_at: Timestamp,
+}
+
+impl Farm {
+    /// Brings the index up to now. Saturates instead of panicking, so that a dust total stake can't block
+    /// the operations syncing the farm.
+    fn accrue(&mut self, now: Timestamp) {
+        let from = std::cmp::max(self.updated_at, self.start);
+        let to = std::cmp::min(now, self.end);
+        if to > from && self.total_stake > 0 {
+            let increment = math::ratio_saturating(&[self.rate, (to - from) as u128, FARM_INDEX_PRECISION],
+                                                   &[self.total_stake, 1_000_000_000]);
+            self.index = self.index.saturating_add(increment);
+        }
+        self.updated_at = std::cmp::max(self.updated_at, now);
+    }
+}
+
+#[derive(Default, BorshSerialize, BorshDeserialize)]
+pub struct FarmAccount {
+    /// Stake of the account as of its last sync.
+    stake: Balance,
+    /// Index of the farm the account was last settled at.
+    index: u128,
+    unclaimed: Balance,
+}
+
+impl FarmAccount {
+    fn query_unclaimed(&self, index: u128) -> Balance {
+        self.unclaimed + math::mul_div(self.stake, index - self.index, FARM_INDEX_PRECISION)
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Farms {
+    farms: Vector<Farm>,
+    /// Mapping from target to its farms synced on stake changes.
+    target_farms: LookupMap<FarmTarget, Vec<FarmId>>,
+    accounts: LookupMap<(FarmId, AccountId), FarmAccount>,
+}
+
+impl Farms {
+    pub(crate) fn new() -> Self {
+        Self {
+            farms: Vector::new(StorageKey::Farms),
+            target_farms: LookupMap::new(StorageKey::TargetFarms),
+            accounts: LookupMap::new(StorageKey::FarmAccounts),
+        }
+    }
+
+    fn unwrap_farm(&self, farm_id: FarmId) -> Farm {
+        self.farms.get(farm_id).expect(errors::FARM_NOT_FOUND)
+    }
+
+    fn query_account(&self, farm_id: FarmId, account_id: &AccountId) -> FarmAccount {
+        self.accounts.get(&(farm_id, account_id.clone())).unwrap_or_default()
+    }
+
+    /// Settles the account at the current index and records its new stake.
+    fn sync(&mut self, farm_id: FarmId, account_id: &AccountId, stake: Balance) {
+        let mut farm = self.unwrap_farm(farm_id);
+        farm.accrue(env::block_timestamp());
+        let mut account = self.query_account(farm_id, account_id);
+        account.unclaimed = account.query_unclaimed(farm.index);
+        account.index = farm.index;
+        farm.total_stake = farm.total_stake - account.stake + stake;
+        account.stake = stake;
+        self.farms.replace(farm_id, &farm);
+        self.accounts.insert(&(farm_id, account_id.clone()), &account);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Add a farm emitting `rate` of the reward token per second to the stakers of the target, from `start`
+    /// to `end`. Only can be called by owner. The tokens emitted are funded with `ft_transfer_call`.
+    /// Stakes held before the farm are only counted once synced, by `sync_farm_stake` or an operation changing them.
+    pub fn add_farm(&mut self, target: FarmTarget, reward_token: AccountId, rate: U128, start: U64, end: U64) -> FarmId {
+        self.assert_owner();
+        let (start, end): (Timestamp, Timestamp) = (start.into(), end.into());
+        assert!(start < end && end > env::block_timestamp(), "{}", errors::ILLEGAL_FARM);
+        match &target {
+            FarmTarget::DebtPoolRaft { raft_id } =>
+                assert!(self.is_in_whitelisted_rafts(raft_id), "{}", errors::RAFT_NOT_WHITELISTED),
+            FarmTarget::PsmDeposit { token_id } =>
+                assert!(self.psm.query_stable(token_id).is_some(), "{}", errors::PSM_STABLE_NOT_FOUND),
+        }
+        self.assert_not_listed(&reward_token);
+
+        let mut farm_ids = self.farms.target_farms.get(&target).unwrap_or_default();
+        assert!(farm_ids.len() < utils::MAX_FARMS_PER_TARGET, "{}", errors::TOO_MANY_FARMS);
+        let farm_id = self.farms.farms.len();
+        self.farms.farms.push(&Farm {
+            target: target.clone(),
+            reward_token,
+            rate: rate.into(),
+            start,
+            end,
+            total_stake: 0,
+            index: 0,
+            budget: 0,
+            updated_at: env::block_timestamp(),
+        });
+        farm_ids.push(farm_id);
+        self.farms.target_farms.insert(&target, &farm_ids);
+        farm_id
+    }
+
+    /// Stop syncing the stakes of an ended farm, its rewards stay claimable. Only can be called by owner.
+    pub fn remove_farm(&mut self, farm_id: FarmId) {
+        self.assert_owner();
+        let farm = self.farms.unwrap_farm(farm_id);
+        assert!(env::block_timestamp() >= farm.end, "{}", errors::FARM_NOT_ENDED);
+        if let Some(mut farm_ids) = self.farms.target_farms.get(&farm.target) {
+            farm_ids.retain(|id| *id != farm_id);
+            self.farms.target_farms.insert(&farm.target, &farm_ids);
+        }
+    }
+
+    /// Record the current stake of the caller in the farm.
+    pub fn sync_farm_stake(&mut self, farm_id: FarmId) {
+        let account_id = env::predecessor_account_id();
+        let target = self.farms.unwrap_farm(farm_id).target;
+        let stake = self.query_farm_stake(&account_id, &target);
+        self.farms.sync(farm_id, &account_id, stake);
+    }
+
+    /// Send the rewards of the farm earned by the caller, who must be registered on the reward token.
+    #[payable]
+    pub fn claim_farm_rewards(&mut self, farm_id: FarmId) -> Promise {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let account_id = env::predecessor_account_id();
+        self.assert_not_blacklisted(&account_id);
+
+        let stake = self.farms.query_account(farm_id, &account_id).stake;
+        self.farms.sync(farm_id, &account_id, stake);
+        let mut farm = self.farms.unwrap_farm(farm_id);
+        let mut account = self.farms.query_account(farm_id, &account_id);
+        let amount = account.unclaimed;
+        assert!(amount > 0, "{}", errors::NO_REWARDS);
+        assert!(amount <= farm.budget, "{}", errors::INSUFFICIENT_BUDGET);
+        account.unclaimed = 0;
+        self.farms.accounts.insert(&(farm_id, account_id.clone()), &account);
+        farm.budget -= amount;
+        self.farms.farms.replace(farm_id, &farm);
+
+        ext_fungible_token::ft_transfer(
+            account_id.clone(),
+            U128(amount),
+            None,
+            farm.reward_token,
+            utils::ONE_YOCTO,
+            self.gas_config.ft_transfer,
+        ).then(ext_self::claim_farm_rewards_callback(
+            farm_id,
+            account_id,
+            U128(amount),
+            env::current_account_id(),
+            utils::NO_DEPOSIT,
+            self.gas_config.resolve,
+        ))
+    }
+
+    pub fn get_farm(&self, farm_id: FarmId) -> Option<Farm> {
+        self.farms.farms.get(farm_id)
+    }
+
+    pub fn get_farms(&self, from_index: u64, limit: u64) -> Vec<(FarmId, Farm)> {
+        (from_index..std::cmp::min(from_index.saturating_add(limit), self.farms.farms.len()))
+            .map(|farm_id| (farm_id, self.farms.farms.get(farm_id).unwrap()))
+            .collect()
+    }
+
+    /// Farms whose target stakes are synced.
+    pub fn get_target_farms(&self, target: FarmTarget) -> Vec<FarmId> {
+        self.farms.target_farms.get(&target).unwrap_or_default()
+    }
+
+    /// Stake of the account recorded in the farm, and its rewards not claimed yet.
+    pub fn get_farm_account(&self, farm_id: FarmId, account_id: AccountId) -> (U128, U128) {
+        let mut farm = self.farms.unwrap_farm(farm_id);
+        farm.accrue(env::block_timestamp());
+        let account = self.farms.query_account(farm_id, &account_id);
+        (U128(account.stake), U128(account.query_unclaimed(farm.index)))
+    }
+
+    #[private]
+    pub fn claim_farm_rewards_callback(&mut self, farm_id: FarmId, account_id: AccountId, amount: U128) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => {
+                Event::ClaimFarmRewards {
+                    farm_id,
+                    account_id: &account_id,
+                    amount,
+                }.emit();
+            }
+            PromiseResult::Failed => {
+                // This reverts the changes from claim_farm_rewards function.
+                let mut account = self.farms.query_account(farm_id, &account_id);
+                account.unclaimed += amount.0;
+                self.farms.accounts.insert(&(farm_id, account_id), &account);
+                let mut farm = self.farms.unwrap_farm(farm_id);
+                farm.budget += amount.0;
+                self.farms.farms.replace(farm_id, &farm);
+                env::log_str(format!("Failed to claim {} rewards of farm {}", amount.0, farm_id).as_str());
+            }
+        };
+    }
+}
+
+impl Contract {
+    /// Adds the reward tokens received to the budget of the farm.
+    pub(crate) fn internal_fund_farm(&mut self, sender_id: &AccountId, token_id: &AccountId,
+                                     farm_id: FarmId, amount: Balance) {
+        let mut farm = self.farms.unwrap_farm(farm_id);
+        assert!(&farm.reward_token == token_id, "{}", errors::ILLEGAL_FARM);
+        farm.budget += amount;
+        self.farms.farms.replace(farm_id, &farm);
+        Event::FundFarm { farm_id, account_id: sender_id, amount: U128(amount) }.emit();
+    }
+
+    fn query_farm_stake(&self, account_id: &AccountId, target: &FarmTarget) -> Balance {
+        match target {
+            FarmTarget::DebtPoolRaft { raft_id } => self.debt_pool.query_user_raft_amount(account_id, raft_id),
+            FarmTarget::PsmDeposit { token_id } => self.psm.query_deposit(account_id, token_id),
+        }
+    }
+
+    /// Records the stake of the account in the farms of the target, to be called after it changed.
+    pub(crate) fn internal_sync_farms(&mut self, account_id: &AccountId, target: FarmTarget) {
+        let farm_ids = match self.farms.target_farms.get(&target) {
+            Some(farm_ids) => farm_ids,
+            None => return,
+        };
+        let stake = self.query_farm_stake(account_id, &target);
+        for farm_id in farm_ids {
+            self.farms.sync(farm_id, account_id, stake);
+        }
+    }
+}
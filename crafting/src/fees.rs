@@ -0,0 +1,401 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, UnorderedMap};
+use near_sdk::{env, AccountId, Balance, PromiseResult};
+
+use crate::*;
+use crate::timelock::{ChangeId, ParamChange};
+
+/// Recipient of a share of the fees.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Eq, PartialEq, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum FeeBucket {
+    Treasury,
+    Insurance,
+    Stakers,
+    /// Fees charged on minting, claimed to the treasury apart from the split fees.
+    Mint,
+}
+
+/// How the interest of an account book collateral is charged on redemption.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Eq, PartialEq, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum InterestMode {
+    /// The stability fee accrued per annum since the collateral was created.
+    PerAnnum,
+    /// The interest fee as a flat share of the minted rafts, however long the collateral was open.
+    Flat,
+}
+
+/// Split of the fees between the buckets, in `BPS_DIVISOR` units summing to `BPS_DIVISOR`.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeConfig {
+    pub treasury_bps: u32,
+    pub insurance_bps: u32,
+    pub stakers_bps: u32,
+}
+
+impl FeeConfig {
+    fn is_valid(&self) -> bool {
+        self.treasury_bps + self.insurance_bps + self.stakers_bps == utils::BPS_DIVISOR
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct FeeLedger {
+    /// Mapping from raft to amount of fees of the treasury settled and not claimed yet.
+    accrued: UnorderedMap<AccountId, Balance>,
+    /// Mapping from bucket other than the treasury and raft to amount of fees settled and not claimed yet.
+    shares: LookupMap<(FeeBucket, AccountId), Balance>,
+    pub(crate) config: FeeConfig,
+    /// Accounts receiving the fees of the insurance fund and of the stakers.
+    insurance_id: Option<AccountId>,
+    stakers_id: Option<AccountId>,
+    /// Mapping from raft to amount of fees settled since the launch of the ledger, claimed or not.
+    revenue: UnorderedMap<AccountId, Balance>,
+    /// Mapping from raft to amount of fees to split accrued since the launch of the index,
+    /// the only write of a fee charge.
+    indices: UnorderedMap<AccountId, Balance>,
+    /// Mapping from raft to the index up to which the fees were split between the buckets.
+    settled_indices: LookupMap<AccountId, Balance>,
+}
+
+impl FeeLedger {
+    pub(crate) fn new() -> Self {
+        Self {
+            accrued: UnorderedMap::new(StorageKey::AccruedFees),
+            shares: LookupMap::new(StorageKey::FeeShares),
+            config: FeeConfig { treasury_bps: utils::BPS_DIVISOR, insurance_bps: 0, stakers_bps: 0 },
+            insurance_id: None,
+            stakers_id: None,
+            revenue: UnorderedMap::new(StorageKey::FeeRevenue),
+            indices: UnorderedMap::new(StorageKey::FeeIndices),
+            settled_indices: LookupMap::new(StorageKey::SettledFeeIndices),
+        }
+    }
+
+    pub(crate) fn query_accrued(&self, raft_id: &AccountId) -> Balance {
+        self.query_share(FeeBucket::Treasury, raft_id)
+    }
+
+    pub(crate) fn query_share(&self, bucket: FeeBucket, raft_id: &AccountId) -> Balance {
+        let pending_amount = self.split(self.query_pending(raft_id)).into_iter()
+            .find(|(split_bucket, _)| *split_bucket == bucket)
+            .map_or(0, |(_, amount)| amount);
+        self.query_settled_share(bucket, raft_id) + pending_amount
+    }
+
+    fn query_settled_share(&self, bucket: FeeBucket, raft_id: &AccountId) -> Balance {
+        match bucket {
+            FeeBucket::Treasury => self.accrued.get(raft_id).unwrap_or(0),
+            _ => self.shares.get(&(bucket, raft_id.clone())).unwrap_or(0),
+        }
+    }
+
+    /// Fees of the raft accrued in all the buckets and not claimed yet.
+    pub(crate) fn query_unclaimed(&self, raft_id: &AccountId) -> Balance {
+        [FeeBucket::Treasury, FeeBucket::Insurance, FeeBucket::Stakers, FeeBucket::Mint].into_iter()
+            .map(|bucket| self.query_share(bucket, raft_id))
+            .sum()
+    }
+
+    pub(crate) fn query_revenue(&self, raft_id: &AccountId) -> Balance {
+        self.revenue.get(raft_id).unwrap_or(0) + self.query_pending(raft_id)
+    }
+
+    pub(crate) fn query_index(&self, raft_id: &AccountId) -> Balance {
+        self.indices.get(raft_id).unwrap_or(0)
+    }
+
+    /// Fees of the raft accrued since the buckets were last settled.
+    fn query_pending(&self, raft_id: &AccountId) -> Balance {
+        self.query_index(raft_id) - self.settled_indices.get(raft_id).unwrap_or(0)
+    }
+
+    /// Splits the fees between the buckets, the rounding goes to the treasury.
+    fn split(&self, amount: Balance) -> [(FeeBucket, Balance); 3] {
+        let insurance_amount = math::share(amount, self.config.insurance_bps, utils::BPS_DIVISOR);
+        let stakers_amount = math::share(amount, self.config.stakers_bps, utils::BPS_DIVISOR);
+        [
+            (FeeBucket::Insurance, insurance_amount),
+            (FeeBucket::Stakers, stakers_amount),
+            (FeeBucket::Treasury, amount - insurance_amount - stakers_amount),
+        ]
+    }
+
+    /// Adds the fees to the index of the raft, they are split between the buckets when settled.
+    pub(crate) fn accrue(&mut self, raft_id: &AccountId, amount: Balance) {
+        if amount == 0 { return; }
+        self.indices.insert(raft_id, &(self.query_index(raft_id) + amount));
+    }
+
+    /// Splits the fees accrued since the last settlement between the buckets.
+    pub(crate) fn settle(&mut self, raft_id: &AccountId) {
+        let pending = self.query_pending(raft_id);
+        if pending == 0 { return; }
+        self.settled_indices.insert(raft_id, &self.query_index(raft_id));
+        self.revenue.insert(raft_id, &(self.revenue.get(raft_id).unwrap_or(0) + pending));
+        for (bucket, amount) in self.split(pending) {
+            self.accrue_share(bucket, raft_id, amount);
+        }
+    }
+
+    /// Settles the fees of every raft, before the split changes.
+    pub(crate) fn settle_all(&mut self) {
+        let raft_ids: Vec<AccountId> = self.indices.keys().collect();
+        for raft_id in raft_ids.iter() {
+            self.settle(raft_id);
+        }
+    }
+
+    /// Accrues fees to a single bucket, without splitting them.
+    pub(crate) fn accrue_unsplit(&mut self, bucket: FeeBucket, raft_id: &AccountId, amount: Balance) {
+        if amount == 0 { return; }
+        self.revenue.insert(raft_id, &(self.revenue.get(raft_id).unwrap_or(0) + amount));
+        self.accrue_share(bucket, raft_id, amount);
+    }
+
+    /// Accrues fees to the bucket without counting them as revenue, e.g. to give back fees failed to be claimed.
+    pub(crate) fn accrue_share(&mut self, bucket: FeeBucket, raft_id: &AccountId, amount: Balance) {
+        if amount == 0 { return; }
+        let accrued = self.query_settled_share(bucket, raft_id);
+        match bucket {
+            FeeBucket::Treasury => self.accrued.insert(raft_id, &(accrued + amount)),
+            _ => self.shares.insert(&(bucket, raft_id.clone()), &(accrued + amount)),
+        };
+    }
+
+    /// Takes back fees accrued by `accrue`, as far as they weren't claimed yet. Returns the amount taken back.
+    pub(crate) fn revert(&mut self, raft_id: &AccountId, amount: Balance) -> Balance {
+        self.settle(raft_id);
+        let reverted = self.split(amount).into_iter().map(|(bucket, amount)| {
+            let reverted = std::cmp::min(amount, self.query_settled_share(bucket, raft_id));
+            self.withdraw(bucket, raft_id, reverted);
+            reverted
+        }).sum::<Balance>();
+        self.revenue.insert(raft_id, &self.revenue.get(raft_id).unwrap_or(0).saturating_sub(reverted));
+        reverted
+    }
+
+    /// Value of the fees accrued since the launch of the ledger, in USD with `VALUE_DECIMALS` decimals.
+    pub(crate) fn calc_revenue_value(&self, valuation: &oracle::Valuation) -> u128 {
+        self.revenue.iter().map(|(raft_id, amount)| valuation.calc_value(&raft_id, amount)).sum::<u128>()
+            + self.indices.keys().map(|raft_id| valuation.calc_value(&raft_id, self.query_pending(&raft_id))).sum::<u128>()
+    }
+
+    /// Panics if `amount` is bigger than the accrued fees of the bucket.
+    pub(crate) fn withdraw(&mut self, bucket: FeeBucket, raft_id: &AccountId, amount: Balance) {
+        self.settle(raft_id);
+        let accrued = self.query_settled_share(bucket, raft_id);
+        assert!(accrued >= amount, "{}", errors::NOT_ENOUGH_FEES);
+        match bucket {
+            FeeBucket::Treasury => self.accrued.insert(raft_id, &(accrued - amount)),
+            _ => self.shares.insert(&(bucket, raft_id.clone()), &(accrued - amount)),
+        };
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Mint accrued fees of the raft to the treasury, all of them by default. Only can be called by owner or treasury.
+    pub fn claim_fees(&mut self, raft_id: AccountId, amount: Option<U128>) -> Promise {
+        let amount = amount.map_or_else(|| self.fee_ledger.query_accrued(&raft_id), Balance::from);
+        self.internal_claim_fees(FeeBucket::Treasury, raft_id, amount)
+    }
+
+    /// Mint all the accrued fees of the raft in the bucket to its recipient. Only can be called by owner or treasury.
+    /// The fees of the `Stakers` bucket can't be claimed once the staking token is set, as they go to the stakers.
+    pub fn claim_fee_share(&mut self, bucket: FeeBucket, raft_id: AccountId) -> Promise {
+        assert!(bucket != FeeBucket::Stakers || self.staking.token_id.is_none(), "{}", errors::STAKERS_FEES_STAKED);
+        let amount = self.fee_ledger.query_share(bucket, &raft_id);
+        self.internal_claim_fees(bucket, raft_id, amount)
+    }
+
+    /// Schedule change of the split of the fees between the buckets. Only can be called by owner.
+    pub fn set_fee_config(&mut self, config: FeeConfig) -> ChangeId {
+        self.assert_owner();
+        assert!(config.is_valid(), "{}", errors::ILLEGAL_FEE_CONFIG);
+        self.internal_schedule_param_change(ParamChange::FeeConfig { config })
+    }
+
+    /// Schedule change of the fee charged on minted rafts, in `BPS_DIVISOR` units. Only can be called by owner.
+    pub fn set_mint_fee(&mut self, mint_fee: u32) -> ChangeId {
+        self.assert_owner();
+        assert!(mint_fee <= utils::BPS_DIVISOR, "{}", errors::ILLEGAL_FEE);
+        self.internal_schedule_param_change(ParamChange::MintFee { mint_fee })
+    }
+
+    /// Schedule change of how the interest of account book collaterals is charged. Only can be called by owner.
+    /// Back in the per annum mode, collaterals untouched in the flat mode accrue since their last accrual.
+    pub fn set_interest_mode(&mut self, interest_mode: InterestMode) -> ChangeId {
+        self.assert_owner();
+        self.internal_schedule_param_change(ParamChange::InterestMode { interest_mode })
+    }
+
+    /// Pay the fees of the caller's swaps and account book redemptions in rUSD from its account book,
+    /// at oracle value, instead of in the swapped or redeemed raft.
+    pub fn set_pay_fees_in_rusd(&mut self, enabled: bool) {
+        let account_id = env::predecessor_account_id();
+        if enabled {
+            self.rusd_fee_payers.insert(&account_id);
+        } else {
+            self.rusd_fee_payers.remove(&account_id);
+        }
+    }
+
+    pub fn get_pays_fees_in_rusd(&self, account_id: AccountId) -> bool {
+        self.rusd_fee_payers.contains(&account_id)
+    }
+
+    /// Change the accounts receiving the fees of the insurance fund and of the stakers. Only can be called by owner.
+    pub fn set_fee_recipients(&mut self, insurance_id: Option<AccountId>, stakers_id: Option<AccountId>) {
+        self.assert_owner();
+        self.fee_ledger.insurance_id = insurance_id;
+        self.fee_ledger.stakers_id = stakers_id;
+    }
+
+    pub fn get_fee_config(&self) -> FeeConfig {
+        self.fee_ledger.config.clone()
+    }
+
+    /// Accounts receiving the fees of the insurance fund and of the stakers.
+    pub fn get_fee_recipients(&self) -> (Option<AccountId>, Option<AccountId>) {
+        (self.fee_ledger.insurance_id.clone(), self.fee_ledger.stakers_id.clone())
+    }
+
+    /// Fees of the raft accrued and not claimed yet.
+    pub fn get_claimable_fees(&self, raft_id: AccountId) -> U128 {
+        U128(self.fee_ledger.query_accrued(&raft_id))
+    }
+
+    /// Fees of the raft in the bucket accrued and not claimed yet.
+    pub fn get_claimable_fee_share(&self, bucket: FeeBucket, raft_id: AccountId) -> U128 {
+        U128(self.fee_ledger.query_share(bucket, &raft_id))
+    }
+
+    /// Fees of the raft accrued in all the buckets and not claimed yet.
+    pub fn get_accrued_fees(&self, raft_id: AccountId) -> U128 {
+        U128(self.fee_ledger.query_unclaimed(&raft_id))
+    }
+
+    /// Fees of the raft accrued since the launch of the fee ledger, claimed or not.
+    pub fn get_fee_revenue(&self, raft_id: AccountId) -> U128 {
+        U128(self.fee_ledger.query_revenue(&raft_id))
+    }
+
+    /// Fees of the raft to split accrued since the launch of the fee index, split between the buckets on claim.
+    pub fn get_fee_index(&self, raft_id: AccountId) -> U128 {
+        U128(self.fee_ledger.query_index(&raft_id))
+    }
+
+    /// Value of the fees of all the rafts accrued since the launch of the fee ledger, at current oracle prices,
+    /// in USD with `VALUE_DECIMALS` decimals.
+    pub fn get_total_fee_revenue(&self) -> U128 {
+        U128(self.fee_ledger.calc_revenue_value(&self.valuation()))
+    }
+
+    #[private]
+    pub fn claim_fees_callback(&mut self, bucket: FeeBucket, raft_id: AccountId, amount: U128,
+                               recipient_id: AccountId) {
+        let amount: Balance = amount.into();
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => {
+                self.account_book.mint_out(&raft_id, amount);
+                match bucket {
+                    FeeBucket::Treasury => Event::ClaimFees {
+                        raft_id: &raft_id,
+                        treasury_id: &recipient_id,
+                        amount: U128(amount),
+                    }.emit(),
+                    _ => Event::ClaimFeeShare {
+                        bucket,
+                        raft_id: &raft_id,
+                        account_id: &recipient_id,
+                        amount: U128(amount),
+                    }.emit(),
+                }
+            }
+            PromiseResult::Failed => {
+                // This reverts the changes from claim_fees function.
+                self.fee_ledger.accrue_share(bucket, &raft_id, amount);
+                env::log_str(format!("Failed to claim {} {} of fees", amount, raft_id).as_str());
+            }
+        };
+    }
+}
+
+impl Contract {
+    fn internal_claim_fees(&mut self, bucket: FeeBucket, raft_id: AccountId, amount: Balance) -> Promise {
+        let predecessor_id = env::predecessor_account_id();
+        assert!(predecessor_id == self.owner_id || predecessor_id == self.treasury_id,
+                "{}", errors::NO_PERMISSION);
+        assert!(amount > 0, "{}", errors::ILLEGAL_WITHDRAW_AMOUNT);
+        let recipient_id = match bucket {
+            FeeBucket::Treasury | FeeBucket::Mint => Some(self.treasury_id.clone()),
+            FeeBucket::Insurance => self.fee_ledger.insurance_id.clone(),
+            FeeBucket::Stakers => self.fee_ledger.stakers_id.clone(),
+        }.expect(errors::FEE_RECIPIENT_NOT_SET);
+        // while the governance token is staked, the fees of the stakers go to the stakes
+        assert!(bucket != FeeBucket::Stakers || self.staking.query_total_staked() == 0, "{}", errors::NO_PERMISSION);
+
+        self.fee_ledger.withdraw(bucket, &raft_id, amount);
+
+        ext_enhanced_fungible_token::mint(
+            recipient_id.clone(),
+            U128(amount),
+            raft_id.clone(),
+            utils::NO_DEPOSIT,
+            self.gas_config.mint,
+        ).then(ext_self::claim_fees_callback(
+            bucket,
+            raft_id,
+            U128(amount),
+            recipient_id,
+            env::current_account_id(),
+            utils::NO_DEPOSIT,
+            self.gas_config.resolve,
+        ))
+    }
+
+    /// Charges a fee in the raft, or in rUSD from the account book of the payer if it pays its fees in rUSD
+    /// and holds enough of them, sharing it with the referrer of the payer.
+    /// Returns the amount of the fee left to charge in the raft by the caller.
+    pub(crate) fn internal_charge_fee(&mut self, payer_id: &AccountId, raft_id: &AccountId,
+                                      fee_amount: Balance) -> Balance {
+        if fee_amount > 0 && self.rusd_fee_payers.contains(payer_id) {
+            if let Some(rusd_id) = self.query_rusd().map(|asset| asset.address).filter(|rusd_id| rusd_id != raft_id) {
+                let rusd_amount = self.valuation().convert(raft_id, fee_amount, &rusd_id);
+                if self.account_book.query_user_raft_amount(payer_id, &rusd_id) >= rusd_amount {
+                    self.account_book.burn(payer_id, &rusd_id, rusd_amount);
+                    let protocol_amount = self.internal_pay_referrer(payer_id, &rusd_id, rusd_amount);
+                    self.internal_accrue_fee(&rusd_id, protocol_amount);
+                    Event::FeePaidInRusd {
+                        account_id: payer_id,
+                        raft_id,
+                        fee_amount: U128(fee_amount),
+                        rusd_amount: U128(rusd_amount),
+                    }.emit();
+                    return 0;
+                }
+            }
+        }
+
+        let protocol_amount = self.internal_pay_referrer(payer_id, raft_id, fee_amount);
+        self.internal_accrue_fee(raft_id, protocol_amount);
+        fee_amount
+    }
+
+    pub(crate) fn internal_accrue_fee(&mut self, raft_id: &AccountId, amount: Balance) {
+        self.fee_ledger.accrue(raft_id, amount);
+    }
+}
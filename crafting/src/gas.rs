@@ -0,0 +1,81 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::Gas;
+
+use crate::*;
+
+/// Gas attached to the calls made to raft and token contracts, tunable for new token contracts without a redeploy.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GasConfig {
+    /// Gas for fungible and multi token transfers.
+    pub ft_transfer: Gas,
+    /// Gas for minting rafts.
+    pub mint: Gas,
+    /// Gas for burning rafts.
+    pub burn: Gas,
+    /// Gas for the callbacks resolving the calls.
+    pub resolve: Gas,
+}
+
+impl Default for GasConfig {
+    fn default() -> Self {
+        Self {
+            ft_transfer: utils::GAS_FOR_FT_TRANSFER,
+            mint: utils::GAS_FOR_FT_TRANSFER,
+            burn: utils::GAS_FOR_FT_TRANSFER,
+            resolve: utils::GAS_FOR_RESOLVE_TRANSFER,
+        }
+    }
+}
+
+impl GasConfig {
+    fn is_valid(&self) -> bool {
+        [self.ft_transfer, self.mint, self.burn, self.resolve].into_iter()
+            .all(|gas| gas >= utils::MIN_CONFIG_GAS && gas <= utils::MAX_CONFIG_GAS)
+    }
+
+    /// Gas for a transfer call to a token, covering its resolution.
+    pub(crate) fn ft_transfer_call(&self) -> Gas {
+        Gas(25_000_000_000_000) + self.resolve
+    }
+
+    /// Gas for minting rafts out of the account book, covering the mint and its callback.
+    pub(crate) fn account_book_mint_out(&self) -> Gas {
+        Gas(10_000_000_000_000) + self.mint + self.resolve
+    }
+
+    /// Gas for handling the rUSD of a liquidation, covering its burn.
+    pub(crate) fn liquidation_proceeds(&self) -> Gas {
+        Gas(10_000_000_000_000) + self.burn
+    }
+
+    /// Gas for handling a liquidation swap, covering the withdrawal and its callback.
+    pub(crate) fn liquidation_callback(&self) -> Gas {
+        Gas(10_000_000_000_000) + utils::GAS_FOR_REF_WITHDRAW + self.liquidation_proceeds()
+    }
+
+    /// Gas for transferring rafts to the bridge locker, which records the lock on `ft_on_transfer`.
+    pub(crate) fn bridge_transfer_call(&self) -> Gas {
+        Gas(30_000_000_000_000) + self.resolve
+    }
+
+    /// Gas for handling a bridge lock, covering the burn of refused rafts.
+    pub(crate) fn bridge_callback(&self) -> Gas {
+        Gas(10_000_000_000_000) + self.burn
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Change the gas attached to token calls, each between `MIN_CONFIG_GAS` and `MAX_CONFIG_GAS`.
+    /// Only can be called by owner.
+    pub fn set_gas_config(&mut self, config: GasConfig) {
+        self.assert_owner();
+        assert!(config.is_valid(), "{}", errors::ILLEGAL_GAS_CONFIG);
+        self.gas_config = config;
+    }
+
+    pub fn get_gas_config(&self) -> GasConfig {
+        self.gas_config.clone()
+    }
+}
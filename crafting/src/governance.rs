@@ -0,0 +1,345 @@
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::json_types::U64;
+use near_sdk::{env, serde_json, AccountId};
+
+use crate::*;
+use crate::buyback::BuybackConfig;
+use crate::fees::{FeeConfig, InterestMode};
+use crate::peg::PegGuard;
+use crate::risk::{RiskClass, RiskParams};
+use crate::timelock::ChangeId;
+
+/// Owner actions that can be submitted as a single proposal payload,
+/// so a DAO (e.g. Sputnik) being the owner doesn't need bespoke proposal code per parameter.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum GovernanceAction {
+    SetOwner { owner_id: AccountId },
+    SetTreasury { treasury_id: AccountId },
+    ChangeState { state: RunningState },
+    ExtendGuardians { guardians: Vec<AccountId> },
+    RemoveGuardians { guardians: Vec<AccountId> },
+    ExtendBlacklist { account_ids: Vec<AccountId> },
+    RemoveFromBlacklist { account_ids: Vec<AccountId> },
+    ExtendRelayers { relayers: Vec<AccountId> },
+    RemoveRelayers { relayers: Vec<AccountId> },
+    ExtendSettlementRafts { raft_ids: Vec<AccountId> },
+    RemoveSettlementRafts { raft_ids: Vec<AccountId> },
+    SetLeverageRatio { leverage_ratio: (u8, u8) },
+    SetInterestFee { interest_fee: u32 },
+    SetExchangeFee { exchange_fee: u32 },
+    SetStabilityFee { stability_fee: u32 },
+    SetMintFee { mint_fee: u32 },
+    SetFeeConfig { config: FeeConfig },
+    SetInterestMode { interest_mode: InterestMode },
+    SetReferralShare { share_bps: u32 },
+    SetCollateralRatio { token_id: AccountId, collateral_ratio: U128 },
+    SetLiquidationThreshold { token_id: AccountId, liquidation_threshold: U128 },
+    SetNftLiquidationThreshold { collection_id: AccountId, liquidation_threshold: U128 },
+    SetPairCollateralRatio { token_id: AccountId, raft_id: AccountId, collateral_ratio: Option<U128> },
+    SetTimelockDelay { delay: U64 },
+    SetGracePeriod { grace_period: U64 },
+    SetSwapCooldown { interval: U64 },
+    SetPsmFee { fee: u32 },
+    SetPsmCap { token_id: AccountId, cap: U128 },
+    SetPegGuard { guard: Option<PegGuard> },
+    SetBuybackConfig { config: BuybackConfig },
+    SetMaxRaftShare { share_bps: u32 },
+    SetDeleverageFee { fee: u32 },
+    SetStopBounty { bounty_bps: u32 },
+    SetPriceRoundTolerance { tolerance_bps: u32 },
+    SetLiquidationSlippage { slippage_bps: u32 },
+    SetRiskParams { risk_class: RiskClass, params: RiskParams },
+    AddTokenList {
+        standard: String,
+        address: AccountId,
+        feed_address: AccountId,
+        collateral_ratio: U128,
+        state: AssetState,
+    },
+    AddTokenListInClass {
+        standard: String,
+        address: AccountId,
+        feed_address: AccountId,
+        risk_class: RiskClass,
+        state: AssetState,
+    },
+    AddRaftList {
+        standard: String,
+        address: AccountId,
+        feed_address: AccountId,
+        state: AssetState,
+    },
+    AddRaftListInClass {
+        standard: String,
+        address: AccountId,
+        feed_address: AccountId,
+        risk_class: RiskClass,
+        state: AssetState,
+    },
+    AddNftList {
+        name: String,
+        symbol: String,
+        address: AccountId,
+        feed_address: AccountId,
+        collateral_ratio: U128,
+        state: AssetState,
+    },
+    AddMtList {
+        contract_id: AccountId,
+        token_id: String,
+        decimals: u32,
+        feed_address: AccountId,
+        collateral_ratio: U128,
+        state: AssetState,
+    },
+    RemoveTokenList { address: AccountId },
+    RemoveRaftList { address: AccountId },
+    RemoveNftList { address: AccountId },
+    RemoveMtList { contract_id: AccountId, token_id: String },
+    SetTokenState { token_id: AccountId, state: AssetState },
+    SetRaftState { raft_id: AccountId, state: AssetState },
+    SetNftState { collection_id: AccountId, state: AssetState },
+    SetMtState { contract_id: AccountId, token_id: String, state: AssetState },
+    UpdateToken { address: AccountId, patch: AssetPatch },
+    UpdateRaft { address: AccountId, patch: AssetPatch },
+    AddWhitelistedTokens { tokens: Vec<AccountId> },
+    RemoveWhitelistedTokens { tokens: Vec<AccountId> },
+    AddWhitelistedRafts { rafts: Vec<AccountId> },
+    RemoveWhitelistedRafts { rafts: Vec<AccountId> },
+    ApplyParamChange { change_id: ChangeId },
+    CancelParamChange { change_id: ChangeId },
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Execute a governance action given as JSON. Only can be called by owner.
+    /// Returns the id of the scheduled change for actions that go through the timelock,
+    /// except token and raft listings which are scheduled once their metadata is read.
+    pub fn execute_governance_action(&mut self, action_json: String) -> Option<ChangeId> {
+        self.assert_owner();
+        let action: GovernanceAction = serde_json::from_str(&action_json)
+            .expect(errors::ILLEGAL_GOVERNANCE_ACTION);
+
+        let change_id = match action {
+            GovernanceAction::SetOwner { owner_id } => {
+                self.set_owner(owner_id);
+                None
+            }
+            GovernanceAction::SetTreasury { treasury_id } => {
+                self.set_treasury(treasury_id);
+                None
+            }
+            GovernanceAction::ChangeState { state } => {
+                self.change_state(state);
+                None
+            }
+            GovernanceAction::ExtendGuardians { guardians } => {
+                self.extend_guardians(guardians);
+                None
+            }
+            GovernanceAction::RemoveGuardians { guardians } => {
+                self.remove_guardians(guardians);
+                None
+            }
+            GovernanceAction::ExtendBlacklist { account_ids } => {
+                self.extend_blacklist(account_ids);
+                None
+            }
+            GovernanceAction::RemoveFromBlacklist { account_ids } => {
+                self.remove_from_blacklist(account_ids);
+                None
+            }
+            GovernanceAction::ExtendRelayers { relayers } => {
+                self.extend_relayers(relayers);
+                None
+            }
+            GovernanceAction::RemoveRelayers { relayers } => {
+                self.remove_relayers(relayers);
+                None
+            }
+            GovernanceAction::ExtendSettlementRafts { raft_ids } => {
+                self.extend_settlement_rafts(raft_ids);
+                None
+            }
+            GovernanceAction::RemoveSettlementRafts { raft_ids } => {
+                self.remove_settlement_rafts(raft_ids);
+                None
+            }
+            GovernanceAction::SetLeverageRatio { leverage_ratio } => {
+                Some(self.set_leverage_ratio(leverage_ratio))
+            }
+            GovernanceAction::SetInterestFee { interest_fee } => {
+                Some(self.set_interest_fee(interest_fee))
+            }
+            GovernanceAction::SetExchangeFee { exchange_fee } => {
+                Some(self.set_exchange_fee(exchange_fee))
+            }
+            GovernanceAction::SetStabilityFee { stability_fee } => {
+                Some(self.set_stability_fee(stability_fee))
+            }
+            GovernanceAction::SetMintFee { mint_fee } => {
+                Some(self.set_mint_fee(mint_fee))
+            }
+            GovernanceAction::SetFeeConfig { config } => {
+                Some(self.set_fee_config(config))
+            }
+            GovernanceAction::SetInterestMode { interest_mode } => {
+                Some(self.set_interest_mode(interest_mode))
+            }
+            GovernanceAction::SetReferralShare { share_bps } => {
+                Some(self.set_referral_share(share_bps))
+            }
+            GovernanceAction::SetCollateralRatio { token_id, collateral_ratio } => {
+                Some(self.set_collateral_ratio(token_id, collateral_ratio))
+            }
+            GovernanceAction::SetLiquidationThreshold { token_id, liquidation_threshold } => {
+                Some(self.set_liquidation_threshold(token_id, liquidation_threshold))
+            }
+            GovernanceAction::SetNftLiquidationThreshold { collection_id, liquidation_threshold } => {
+                Some(self.set_nft_liquidation_threshold(collection_id, liquidation_threshold))
+            }
+            GovernanceAction::SetPairCollateralRatio { token_id, raft_id, collateral_ratio } => {
+                Some(self.set_pair_collateral_ratio(token_id, raft_id, collateral_ratio))
+            }
+            GovernanceAction::SetTimelockDelay { delay } => {
+                Some(self.set_timelock_delay(delay))
+            }
+            GovernanceAction::SetGracePeriod { grace_period } => {
+                Some(self.set_grace_period(grace_period))
+            }
+            GovernanceAction::SetSwapCooldown { interval } => {
+                Some(self.set_swap_cooldown(interval))
+            }
+            GovernanceAction::SetPsmFee { fee } => {
+                Some(self.set_psm_fee(fee))
+            }
+            GovernanceAction::SetPsmCap { token_id, cap } => {
+                Some(self.set_psm_cap(token_id, cap))
+            }
+            GovernanceAction::SetPegGuard { guard } => {
+                Some(self.set_peg_guard(guard))
+            }
+            GovernanceAction::SetBuybackConfig { config } => {
+                Some(self.set_buyback_config(config))
+            }
+            GovernanceAction::SetMaxRaftShare { share_bps } => {
+                Some(self.set_max_raft_share(share_bps))
+            }
+            GovernanceAction::SetDeleverageFee { fee } => {
+                Some(self.set_deleverage_fee(fee))
+            }
+            GovernanceAction::SetStopBounty { bounty_bps } => {
+                Some(self.set_stop_bounty(bounty_bps))
+            }
+            GovernanceAction::SetPriceRoundTolerance { tolerance_bps } => {
+                Some(self.set_price_round_tolerance(tolerance_bps))
+            }
+            GovernanceAction::SetLiquidationSlippage { slippage_bps } => {
+                Some(self.set_liquidation_slippage(slippage_bps))
+            }
+            GovernanceAction::SetRiskParams { risk_class, params } => {
+                Some(self.set_risk_params(risk_class, params))
+            }
+            GovernanceAction::AddTokenList {
+                standard, address, feed_address, collateral_ratio, state
+            } => {
+                self.add_token_list(standard, address, feed_address, collateral_ratio, state);
+                None
+            }
+            GovernanceAction::AddTokenListInClass {
+                standard, address, feed_address, risk_class, state
+            } => {
+                self.add_token_list_in_class(standard, address, feed_address, risk_class, state);
+                None
+            }
+            GovernanceAction::AddRaftList {
+                standard, address, feed_address, state
+            } => {
+                self.add_raft_list(standard, address, feed_address, state);
+                None
+            }
+            GovernanceAction::AddRaftListInClass {
+                standard, address, feed_address, risk_class, state
+            } => {
+                self.add_raft_list_in_class(standard, address, feed_address, risk_class, state);
+                None
+            }
+            GovernanceAction::AddNftList {
+                name, symbol, address, feed_address, collateral_ratio, state
+            } => {
+                Some(self.add_nft_list(name, symbol, address, feed_address, collateral_ratio, state))
+            }
+            GovernanceAction::AddMtList {
+                contract_id, token_id, decimals, feed_address, collateral_ratio, state
+            } => {
+                Some(self.add_mt_list(contract_id, token_id, decimals, feed_address, collateral_ratio, state))
+            }
+            GovernanceAction::RemoveTokenList { address } => {
+                self.remove_token_list(address);
+                None
+            }
+            GovernanceAction::RemoveRaftList { address } => {
+                self.remove_raft_list(address);
+                None
+            }
+            GovernanceAction::RemoveNftList { address } => {
+                self.remove_nft_list(address);
+                None
+            }
+            GovernanceAction::RemoveMtList { contract_id, token_id } => {
+                self.remove_mt_list(contract_id, token_id);
+                None
+            }
+            GovernanceAction::SetTokenState { token_id, state } => {
+                self.set_token_state(token_id, state);
+                None
+            }
+            GovernanceAction::SetRaftState { raft_id, state } => {
+                self.set_raft_state(raft_id, state);
+                None
+            }
+            GovernanceAction::SetNftState { collection_id, state } => {
+                self.set_nft_state(collection_id, state);
+                None
+            }
+            GovernanceAction::SetMtState { contract_id, token_id, state } => {
+                self.set_mt_state(contract_id, token_id, state);
+                None
+            }
+            GovernanceAction::UpdateToken { address, patch } => {
+                Some(self.update_token(address, patch))
+            }
+            GovernanceAction::UpdateRaft { address, patch } => {
+                Some(self.update_raft(address, patch))
+            }
+            GovernanceAction::AddWhitelistedTokens { tokens } => {
+                self.add_whitelisted_tokens(tokens);
+                None
+            }
+            GovernanceAction::RemoveWhitelistedTokens { tokens } => {
+                self.remove_whitelisted_tokens(tokens);
+                None
+            }
+            GovernanceAction::AddWhitelistedRafts { rafts } => {
+                self.add_whitelisted_rafts(rafts);
+                None
+            }
+            GovernanceAction::RemoveWhitelistedRafts { rafts } => {
+                self.remove_whitelisted_rafts(rafts);
+                None
+            }
+            GovernanceAction::ApplyParamChange { change_id } => {
+                self.apply_param_change(change_id);
+                None
+            }
+            GovernanceAction::CancelParamChange { change_id } => {
+                self.cancel_param_change(change_id);
+                None
+            }
+        };
+
+        env::log_str(format!("Governance action applied by {}: {}", env::predecessor_account_id(), action_json).as_str());
+        change_id
+    }
+}
@@ -0,0 +1,75 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::U64;
+use near_sdk::{env, AccountId, Timestamp};
+
+use crate::*;
+use crate::timelock::{ChangeId, ParamChange};
+
+/// Liquidation ratio of an asset before it was raised, in force until the end of the grace period.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RiskGrace {
+    #[serde(with = "u128_dec_format")]
+    pub liquidation_ratio: u128,
+    /// Timestamp from which the raised ratio is used for liquidations.
+    #[serde(with = "u64_dec_format")]
+    pub effective_from: Timestamp,
+}
+
+/// Grace windows given to existing positions when risk parameters of an asset are tightened.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct RiskGraces {
+    /// Delay in nanoseconds before a raised liquidation ratio is used for liquidations.
+    pub(crate) grace_period: u64,
+    /// Mapping from token or NFT collection to its grace window.
+    graces: LookupMap<AccountId, RiskGrace>,
+}
+
+impl RiskGraces {
+    pub(crate) fn new() -> Self {
+        Self {
+            grace_period: 0,
+            graces: LookupMap::new(StorageKey::RiskGraces),
+        }
+    }
+
+    /// Grace window of the asset, `None` if it's over.
+    pub(crate) fn query_grace(&self, asset_id: &AccountId) -> Option<RiskGrace> {
+        self.graces.get(asset_id).filter(|grace| env::block_timestamp() < grace.effective_from)
+    }
+
+    /// Liquidation ratio of the asset used for liquidations, the lower of the current and the grace one.
+    pub(crate) fn query_liquidation_ratio(&self, asset_id: &AccountId, liquidation_ratio: u128) -> u128 {
+        self.query_grace(asset_id).map_or(liquidation_ratio, |grace| grace.liquidation_ratio.min(liquidation_ratio))
+    }
+
+    /// Opens a grace window if the liquidation ratio of the asset is raised.
+    /// A window still open keeps its lower ratio and is extended.
+    pub(crate) fn tighten(&mut self, asset_id: &AccountId, old_ratio: u128, new_ratio: u128) {
+        if new_ratio <= old_ratio || self.grace_period == 0 { return; }
+        let liquidation_ratio = self.query_liquidation_ratio(asset_id, old_ratio);
+        let effective_from = env::block_timestamp() + self.grace_period;
+        self.graces.insert(asset_id, &RiskGrace { liquidation_ratio, effective_from });
+        env::log_str(format!("Liquidation ratio of {} raised from {} to {}, effective from {}",
+                             asset_id, liquidation_ratio, new_ratio, effective_from).as_str());
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Schedule grace period change. Only can be called by owner.
+    pub fn set_grace_period(&mut self, grace_period: U64) -> ChangeId {
+        self.assert_owner();
+        self.internal_schedule_param_change(ParamChange::GracePeriod { grace_period: grace_period.into() })
+    }
+
+    pub fn get_grace_period(&self) -> U64 {
+        U64(self.risk_graces.grace_period)
+    }
+
+    /// Grace window of the token or NFT collection, `None` if it's over.
+    pub fn get_risk_grace(&self, asset_id: AccountId) -> Option<RiskGrace> {
+        self.risk_graces.query_grace(&asset_id)
+    }
+}
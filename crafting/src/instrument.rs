@@ -0,0 +1,46 @@
+use near_sdk::json_types::U64;
+use near_sdk::{env, Gas, StorageUsage};
+
+use crate::*;
+
+/// Gas and storage used by the call when an operation started.
+pub(crate) struct Probe {
+    used_gas: Gas,
+    storage_usage: StorageUsage,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Turn on or off the `Instrumentation` events, reporting the gas and the storage used by each mint,
+    /// swap and redemption. Only can be called by owner.
+    pub fn set_instrumentation(&mut self, enabled: bool) {
+        self.assert_owner();
+        self.instrumentation = enabled;
+    }
+
+    pub fn is_instrumentation_enabled(&self) -> bool {
+        self.instrumentation
+    }
+}
+
+impl Contract {
+    /// Starts measuring an operation, `None` when instrumentation is off.
+    pub(crate) fn internal_start_probe(&self) -> Option<Probe> {
+        self.instrumentation.then(|| Probe {
+            used_gas: env::used_gas(),
+            storage_usage: env::storage_usage(),
+        })
+    }
+
+    /// Emits the gas and the storage used by the operation since its probe started. Writes of the contract
+    /// state itself happen once the call returns and aren't counted.
+    pub(crate) fn internal_end_probe(&self, kind: OperationKind, probe: Option<Probe>) {
+        if let Some(probe) = probe {
+            Event::Instrumentation {
+                kind,
+                gas_used: U64(env::used_gas().0 - probe.used_gas.0),
+                storage_delta: env::storage_usage() as i64 - probe.storage_usage as i64,
+            }.emit();
+        }
+    }
+}
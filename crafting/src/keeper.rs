@@ -0,0 +1,189 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::{env, AccountId, Timestamp};
+
+use crate::*;
+use crate::utils::{u128_dec_format, u64_dec_format};
+
+/// Positions of the maintenance tasks, which process at most `KEEPER_BATCH_SIZE` records per call
+/// and go on from there on the next call, wrapping around at the end.
+#[derive(BorshSerialize, BorshDeserialize, Default)]
+pub struct KeeperCursors {
+    /// Id of the next collateral to accrue the stability fee of.
+    interest: u64,
+    /// Index of the next asset to check the price of, tokens first, then rafts.
+    prices: u64,
+    /// Id of the next collateral to add to the rUSD backing.
+    backing: u64,
+}
+
+/// Value of the rafts of a book, as of the price epoch and timestamp it was computed at.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ValueSnapshot {
+    #[serde(with = "u128_dec_format")]
+    pub value: u128,
+    #[serde(with = "u64_dec_format")]
+    pub epoch: u64,
+    #[serde(with = "u64_dec_format")]
+    pub timestamp: Timestamp,
+}
+
+/// Total values of the debt pool and of the account book, see `refresh_total_values`.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TotalValues {
+    pub debtpool: ValueSnapshot,
+    pub accountbook: ValueSnapshot,
+}
+
+/// Value of the tokens of the open collaterals backing rUSD, in USD with `VALUE_DECIMALS` decimals,
+/// each batch valued at the prices of its call.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BackingValues {
+    /// Value of the account book rUSD collaterals.
+    #[serde(with = "u128_dec_format")]
+    pub accountbook: u128,
+    /// Value of the debt pool collaterals, of which rUSD backs the share of rUSD in the debt pool.
+    #[serde(with = "u128_dec_format")]
+    pub debtpool: u128,
+    /// Timestamp of the call starting the pass over the collaterals.
+    #[serde(with = "u64_dec_format")]
+    pub timestamp: Timestamp,
+}
+
+/// Backing of rUSD summed over the collaterals by `refresh_rusd_backing`.
+#[derive(BorshSerialize, BorshDeserialize, Default)]
+pub struct RusdBacking {
+    /// Sums of the pass in progress, up to the backing cursor.
+    pending: BackingValues,
+    /// Sums of the last complete pass.
+    pub(crate) completed: BackingValues,
+}
+
+/// Maintenance entrypoints meant to be scheduled by a keeper, e.g. a Croncat task. Anyone can call them,
+/// as they only bring state up to date.
+#[near_bindgen]
+impl Contract {
+    /// Accrues the stability fee of the next batch of collaterals. Returns the number of collaterals accrued.
+    pub fn accrue_interest(&mut self) -> u64 {
+        let total = self.collaterals.len();
+        if total == 0 {
+            return 0;
+        }
+
+        let start = self.keeper_cursors.interest % total;
+        let end = std::cmp::min(start + utils::KEEPER_BATCH_SIZE, total);
+        let mut accrued = 0;
+        for collateral_id in start..end {
+            let mut collateral = match self.collaterals.get(collateral_id) {
+                Some(collateral) if !collateral.join_debtpool && collateral.state == CollateralState::Open => collateral,
+                _ => continue,
+            };
+            self.internal_accrue_stability_fee(&mut collateral);
+            self.collaterals.replace(collateral_id, &collateral);
+            accrued += 1;
+        }
+        self.keeper_cursors.interest = end % total;
+
+        env::log_str(format!("Accrued {} collaterals from {} to {}", accrued, start, end).as_str());
+        accrued
+    }
+
+    /// Checks the prices of the next batch of tokens and rafts, returns those older than their max oracle age.
+    pub fn refresh_prices(&mut self) -> Vec<AccountId> {
+        let token_ids = self.token_list.keys_as_vector();
+        let raft_ids = self.raft_list.keys_as_vector();
+        let total = token_ids.len() + raft_ids.len();
+        if total == 0 {
+            return vec![];
+        }
+
+        let start = self.keeper_cursors.prices % total;
+        let end = std::cmp::min(start + utils::KEEPER_BATCH_SIZE, total);
+        let now = env::block_timestamp();
+        let mut stale = vec![];
+        for index in start..end {
+            let asset_id = if index < token_ids.len() {
+                token_ids.get(index)
+            } else {
+                raft_ids.get(index - token_ids.len())
+            }.unwrap();
+            let asset = self.query_token(&asset_id).or_else(|| self.query_raft(&asset_id)).unwrap();
+            let is_stale = self.price_oracle.get_timestamp(&asset_id)
+                .is_none_or(|timestamp| now.saturating_sub(timestamp) > self.query_oracle_max_age(&asset));
+            if is_stale {
+                Event::StalePrice { asset_id: &asset_id, feed_address: &asset.feed_address }.emit();
+                stale.push(asset_id);
+            }
+        }
+        self.keeper_cursors.prices = end % total;
+
+        stale
+    }
+
+    /// Values every raft of the debt pool and of the account book, bounded by `max_rafts`, and stores the totals
+    /// for `get_total_values`, which can be read without pricing them again.
+    pub fn refresh_total_values(&mut self) -> TotalValues {
+        let valuation = self.valuation();
+        let snapshot = |value| ValueSnapshot {
+            value,
+            epoch: valuation.epoch(),
+            timestamp: env::block_timestamp(),
+        };
+        let total_values = TotalValues {
+            debtpool: snapshot(self.debt_pool.calc_raft_total_value(&valuation)),
+            accountbook: snapshot(self.account_book.calc_raft_total_value(&valuation)),
+        };
+        self.total_values = total_values.clone();
+        total_values
+    }
+
+    /// Totals stored by the last `refresh_total_values`, stale once the price epoch moved on
+    /// or rafts entered or left the books.
+    pub fn get_total_values(&self) -> TotalValues {
+        self.total_values.clone()
+    }
+
+    /// Adds the next batch of collaterals to the value of the collaterals backing rUSD, which replaces the one
+    /// of `get_rusd_stats` once every collateral is added. Returns whether the pass over the collaterals completed.
+    pub fn refresh_rusd_backing(&mut self) -> bool {
+        let rusd_id = self.query_rusd().expect(errors::ASSET_NOT_FOUND).address;
+        let total = self.collaterals.len();
+        let start = self.keeper_cursors.backing;
+        if start == 0 || start > total {
+            self.rusd_backing.pending = BackingValues { timestamp: env::block_timestamp(), ..Default::default() };
+        }
+
+        let start = std::cmp::min(start, total);
+        let end = std::cmp::min(start + utils::KEEPER_BATCH_SIZE, total);
+        // built from the fields, the pending sums are borrowed mutably alongside
+        let valuation = oracle::Valuation::new(&self.price_oracle, &self.token_list, &self.raft_list);
+        let pending = &mut self.rusd_backing.pending;
+        for collateral in (start..end).filter_map(|collateral_id| self.collaterals.get(collateral_id)) {
+            if collateral.state != CollateralState::Open {
+                continue;
+            }
+            if collateral.join_debtpool {
+                pending.debtpool += valuation.calc_value(&collateral.token_id, collateral.token_amount);
+            } else if collateral.raft_id == rusd_id {
+                pending.accountbook += valuation.calc_value(&collateral.token_id, collateral.token_amount);
+            }
+        }
+
+        let completed = end == total;
+        if completed {
+            self.rusd_backing.completed = self.rusd_backing.pending.clone();
+            self.keeper_cursors.backing = 0;
+        } else {
+            self.keeper_cursors.backing = end;
+        }
+        completed
+    }
+
+    /// Positions the next `accrue_interest` and `refresh_prices` calls start from.
+    pub fn get_keeper_cursors(&self) -> (U64, U64) {
+        (U64(self.keeper_cursors.interest), U64(self.keeper_cursors.prices))
+    }
+}
@@ -0,0 +1,49 @@
+use near_sdk::{assert_one_yocto, env, AccountId, StorageUsage};
+
+use crate::*;
+
+/// Storage of a label besides its bytes: collection prefix, collateral id key and string length.
+const LABEL_STORAGE: StorageUsage = 1 + 8 + 4;
+
+#[near_bindgen]
+impl Contract {
+    /// Tags an open collateral of the caller with a label of at most `MAX_LABEL_LEN` bytes, `None` removes it.
+    /// The label is charged to the storage deposit of the caller and dropped when the collateral is closed
+    /// or changes hands.
+    #[payable]
+    pub fn set_collateral_label(&mut self, collateral_id: CollateralId, label: Option<String>) {
+        assert_one_yocto();
+        let sender_id = env::predecessor_account_id();
+        let collateral = self.internal_unwrap_open_collateral(&sender_id, collateral_id);
+        if let Some(label) = &label {
+            assert!(!label.is_empty() && label.len() <= utils::MAX_LABEL_LEN, "{}", errors::ILLEGAL_LABEL);
+        }
+
+        self.internal_remove_collateral_label(&collateral.issuer, collateral_id);
+        if let Some(label) = &label {
+            let mut account = self.internal_unwrap_account(&sender_id);
+            account.storage_used += LABEL_STORAGE + label.len() as StorageUsage;
+            account.assert_storage_usage();
+            self.internal_save_account(&sender_id, account);
+            self.collateral_labels.insert(&collateral_id, label);
+        }
+        Event::LabelCollateral { account_id: &sender_id, collateral_id, label: label.as_deref() }.emit();
+    }
+
+    pub fn get_collateral_label(&self, collateral_id: CollateralId) -> Option<String> {
+        self.collateral_labels.get(&collateral_id)
+    }
+}
+
+impl Contract {
+    /// Removes the label of the collateral, releasing its storage to the issuer.
+    pub(crate) fn internal_remove_collateral_label(&mut self, issuer: &AccountId, collateral_id: CollateralId) {
+        if let Some(label) = self.collateral_labels.remove(&collateral_id) {
+            if let Some(mut account) = self.internal_get_account(issuer) {
+                account.storage_used = account.storage_used
+                    .saturating_sub(LABEL_STORAGE + label.len() as StorageUsage);
+                self.internal_save_account(issuer, account);
+            }
+        }
+    }
+}
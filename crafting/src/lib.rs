@@ -2,32 +2,168 @@ use std::fmt;
 
 use near_sdk::{
     assert_one_yocto, env, near_bindgen, ext_contract, AccountId, Balance, BlockHeight, Timestamp,
-    PanicOnDefault, Promise, PromiseOrValue, BorshStorageKey,
+    serde_json, PanicOnDefault, Promise, BorshStorageKey,
 };
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet, Vector};
-use near_sdk::json_types::U128;
+use near_sdk::collections::{LazyOption, LookupMap, LookupSet, UnorderedMap, UnorderedSet, Vector};
+use near_sdk::json_types::{U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_contract_standards::fungible_token::core_impl::ext_fungible_token;
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
 
-use crate::account::VAccount;
+use crate::account::{VAccount, WithNonce};
+use crate::events::{Event, NftEvent, NftMintData};
+use crate::oplog::OperationKind;
+use crate::utils::{u128_dec_format, u64_dec_format};
 
 mod account;
 mod accountbook;
+mod blacklist;
+mod borrow_limit;
+mod breaker;
+mod cooldown;
+mod bridge;
+mod buyback;
+mod collateral_map;
 mod debtpool;
+mod deleverage;
 mod errors;
+mod events;
+mod execute;
+mod factory;
+mod farm;
+mod fees;
+mod gas;
+mod governance;
+mod grace;
+mod instrument;
+mod keeper;
+mod label;
+mod lock;
+mod margin;
+mod math;
+mod migration;
+mod mt;
+mod nft;
+mod oplog;
 mod oracle;
 mod owner;
+mod position;
+mod peg;
+mod promo;
+mod psm;
+mod ref_finance;
+mod redemption;
+mod redeem_step;
+mod relay;
+mod retry;
+mod rewards;
+mod referral;
+mod risk;
+mod simulate;
+mod staking;
+mod stop;
+mod storage_impl;
+mod timelock;
+mod token_receiver;
 mod utils;
+mod vesting;
+mod view_access;
 mod views;
 
 pub type CollateralId = u64;
 
+/// Prefixes of all the collections, every collection must have its own variant.
+/// New variants must be appended, as the borsh discriminant is the prefix.
 #[derive(BorshStorageKey, BorshSerialize)]
 pub(crate) enum StorageKey {
     Accounts,
+    /// Legacy prefix shared by both whitelists, kept to preserve the discriminants.
+    #[allow(dead_code)]
     Whitelist,
     AccountTokens { account_id: AccountId },
+    Guardians,
+    PendingChanges,
+    AccruedFees,
+    UserCollateralIds { account_id: AccountId },
+    AccountIds,
+    WhitelistedTokens,
+    WhitelistedRafts,
+    TokenList,
+    RaftList,
+    Collaterals,
+    UserCollaterals,
+    DebtPoolRaftAmounts,
+    DebtPoolUserRaftAmounts,
+    AccountBookRaftAmounts,
+    AccountBookUserRaftAmounts,
+    Prices,
+    LostFound,
+    Operations,
+    DebtPoolHolderCounts,
+    AccountBookHolderCounts,
+    NftList,
+    NftCollaterals,
+    UserNftCollaterals,
+    UserNftCollateralIds { account_id: AccountId },
+    MtList,
+    MtPrices,
+    MtCollaterals,
+    UserMtCollaterals,
+    UserMtCollateralIds { account_id: AccountId },
+    RaftCode,
+    LiquidationPools,
+    AccountBookCirculating,
+    BorrowLimitOverrides,
+    PairCollateralRatios,
+    RiskGraces,
+    RiskParams,
+    FeeShares,
+    RusdFeePayers,
+    Referrers,
+    ReferralEarnings,
+    FeeRevenue,
+    FeePromos,
+    OperationLocks,
+    ViewGrants,
+    LastSwaps,
+    Blacklist,
+    PriceTimestamps,
+    Relayers,
+    PendingOperations,
+    PsmStables,
+    SettlementRafts,
+    CollateralTransfers,
+    CrossMarginAccounts,
+    DeleverageBuffers,
+    StopOrders,
+    PriceRounds,
+    PriceRoundHistory,
+    CollateralLabels,
+    PendingRedemptions,
+    DebtPoolUserValues,
+    FeeIndices,
+    SettledFeeIndices,
+    CompactCollaterals,
+    CollateralAssetIds,
+    CollateralAssetIndices,
+    RewardAccounts,
+    Farms,
+    TargetFarms,
+    FarmAccounts,
+    PsmDeposits,
+    StakingAccounts,
+    StakingIndices,
+    StakerRevenues,
+    VestingSchedules,
+    BeneficiarySchedules,
+    StakingRafts,
+    DebtPoolShares,
+    LockedCollaterals,
+    OpenCollateralCounts,
+    OpenMtCollateralCounts,
+    PositionReceivers,
+    Keepers,
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Eq, PartialEq, Clone)]
@@ -47,18 +183,114 @@ impl fmt::Display for RunningState {
     }
 }
 
+/// State of a listed asset, allowing to freeze a single asset without pausing the whole contract.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum AssetState {
+    /// Can be used everywhere.
+    Active,
+    /// Can't be used for new mints, everything else works.
+    MintPaused,
+    /// Frozen, can't be minted, swapped, deposited or withdrawn.
+    FullyPaused,
+    /// Being phased out, positions can only be unwound.
+    Delisted,
+}
+
+impl AssetState {
+    pub(crate) fn is_mintable(&self) -> bool {
+        matches!(self, AssetState::Active)
+    }
+
+    pub(crate) fn is_tradable(&self) -> bool {
+        matches!(self, AssetState::Active | AssetState::MintPaused)
+    }
+
+    pub(crate) fn is_withdrawable(&self) -> bool {
+        !matches!(self, AssetState::FullyPaused)
+    }
+
+    /// Whether moving to the state only restricts the asset further, from active to mint paused to fully paused.
+    pub(crate) fn is_tightened_to(&self, state: &AssetState) -> bool {
+        matches!((self, state),
+                 (AssetState::Active, AssetState::MintPaused | AssetState::FullyPaused)
+                 | (AssetState::MintPaused, AssetState::FullyPaused))
+    }
+}
+
+impl fmt::Display for AssetState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssetState::Active => write!(f, "Active"),
+            AssetState::MintPaused => write!(f, "MintPaused"),
+            AssetState::FullyPaused => write!(f, "FullyPaused"),
+            AssetState::Delisted => write!(f, "Delisted"),
+        }
+    }
+}
+
+/// State of a collateral record, borsh compatible with the former `0`/`1` flag.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum CollateralState {
+    /// Backing a debt, can be redeemed.
+    Open,
+    /// Fully redeemed or liquidated.
+    Closed,
+}
+
 #[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Collateral {
     issuer: AccountId,
     token_id: AccountId,
+    #[serde(with = "u128_dec_format")]
     token_amount: Balance,
     raft_id: AccountId,
+    #[serde(with = "u128_dec_format")]
     raft_amount: Balance,
     join_debtpool: bool,
+    #[serde(with = "u64_dec_format")]
     block_index: BlockHeight,
+    #[serde(with = "u64_dec_format")]
     create_time: Timestamp,
-    state: u8,
+    state: CollateralState,
+    /// Stability fee accrued and not paid yet, in raft.
+    #[serde(with = "u128_dec_format")]
+    stability_fee: Balance,
+    /// Timestamp up to which the stability fee is accrued.
+    #[serde(with = "u64_dec_format")]
+    fee_accrued_at: Timestamp,
+}
+
+impl Collateral {
+    /// Accrues the stability fee since the last accrual, at `rate` per annum in `FEE_DIVISOR` units.
+    pub(crate) fn accrue_stability_fee(&mut self, rate: u32) {
+        let now = env::block_timestamp();
+        let elapsed = (now.saturating_sub(self.fee_accrued_at) / 1_000_000_000) as u128;
+        let yearly_fee = math::share(self.raft_amount, rate, utils::FEE_DIVISOR);
+        let year = utils::SECONDS_PER_YEAR as u128;
+        self.stability_fee += math::mul(yearly_fee, elapsed / year) + math::mul_div(yearly_fee, elapsed % year, year);
+        self.fee_accrued_at = now;
+    }
+
+    /// Rafts owed by the collateral, including the accrued stability fee.
+    pub(crate) fn debt(&self) -> Balance {
+        self.raft_amount + self.stability_fee
+    }
+}
+
+/// Collateral to open from deposited tokens, see `batch_mint`.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintAction {
+    pub token_id: AccountId,
+    pub token_amount: U128,
+    pub raft_id: AccountId,
+    pub raft_amount: U128,
+    pub join_debtpool: bool,
 }
 
 #[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
@@ -70,8 +302,92 @@ pub struct Asset {
     decimals: u32,
     address: AccountId,
     feed_address: AccountId,
+    #[serde(with = "u128_dec_format")]
     collateral_ratio: u128,
-    state: u8,
+    /// Collateral ratio under which a collateral of the asset can be liquidated, 0 for the collateral ratio.
+    #[serde(with = "u128_dec_format")]
+    liquidation_threshold: u128,
+    /// Max supply of a raft, 0 for no cap. Unused for tokens.
+    #[serde(with = "u128_dec_format")]
+    max_supply: u128,
+    /// Risk class the asset was listed in, `None` if its parameters were set by hand.
+    risk_class: Option<risk::RiskClass>,
+    state: AssetState,
+}
+
+/// Fields of a listed asset that can be updated, `None` keeps the current value.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AssetPatch {
+    decimals: Option<u32>,
+    feed_address: Option<AccountId>,
+    collateral_ratio: Option<U128>,
+    max_supply: Option<U128>,
+    risk_class: Option<risk::RiskClass>,
+    state: Option<AssetState>,
+}
+
+impl Asset {
+    /// Asset waiting for its name, symbol and decimals to be read from its metadata.
+    pub(crate) fn unnamed(standard: String, address: AccountId, feed_address: AccountId,
+                          collateral_ratio: u128, state: AssetState) -> Self {
+        Self {
+            name: String::new(),
+            symbol: String::new(),
+            standard,
+            decimals: 0,
+            address,
+            feed_address,
+            collateral_ratio,
+            liquidation_threshold: 0,
+            max_supply: 0,
+            risk_class: None,
+            state,
+        }
+    }
+
+    pub(crate) fn apply_metadata(&mut self, metadata: FungibleTokenMetadata) {
+        assert!(metadata.decimals as u32 <= utils::MAX_DECIMALS, "{}", errors::ILLEGAL_DECIMALS);
+        self.name = metadata.name;
+        self.symbol = metadata.symbol;
+        self.decimals = metadata.decimals as u32;
+    }
+
+    /// Collateral ratio under which a collateral of the asset can be liquidated.
+    pub(crate) fn liquidation_ratio(&self) -> u128 {
+        if self.liquidation_threshold == 0 { self.collateral_ratio } else { self.liquidation_threshold }
+    }
+
+    pub(crate) fn assert_liquidation_threshold(&self) {
+        assert!(self.liquidation_threshold <= self.collateral_ratio, "{}", errors::ILLEGAL_LIQUIDATION_THRESHOLD);
+    }
+
+    /// Updates the fields set by the patch, its risk class being applied beforehand with `apply_risk_class`.
+    pub(crate) fn apply_patch(&mut self, patch: AssetPatch) {
+        if let Some(decimals) = patch.decimals {
+            self.decimals = decimals;
+        }
+        if let Some(feed_address) = patch.feed_address {
+            self.feed_address = feed_address;
+        }
+        if let Some(collateral_ratio) = patch.collateral_ratio {
+            self.collateral_ratio = collateral_ratio.into();
+        }
+        if let Some(max_supply) = patch.max_supply {
+            self.max_supply = max_supply.into();
+        }
+        if let Some(state) = patch.state {
+            self.state = state;
+        }
+    }
+
+    /// Sets the parameters of the risk class on the asset.
+    pub(crate) fn apply_risk_class(&mut self, risk_class: risk::RiskClass, params: &risk::RiskParams) {
+        self.risk_class = Some(risk_class);
+        self.collateral_ratio = params.collateral_ratio;
+        self.liquidation_threshold = params.liquidation_threshold;
+        self.max_supply = params.debt_ceiling;
+    }
 }
 
 #[ext_contract(ext_enhanced_fungible_token)]
@@ -81,23 +397,93 @@ pub trait EnhancedFungibleTokenContract {
     fn burn(&mut self, account_id: AccountId, amount: U128);
 }
 
-#[ext_contract(ext_self)]
-pub trait ExtSelf {
-    fn account_book_callback_deposit(&mut self, sender_id: AccountId, raft_id: AccountId,
-                                     amount: Balance, raft_amount: Balance, user_raft_amount: Balance);
+#[ext_contract(ext_storage_management)]
+pub trait StorageManagementContract {
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<near_contract_standards::storage_management::StorageBalance>;
+}
+
+#[ext_contract(ext_wrap_near)]
+pub trait WrapNearContract {
+    fn near_withdraw(&mut self, amount: U128);
+}
+
+#[ext_contract(ext_ft_metadata)]
+pub trait FungibleTokenMetadataContract {
+    fn ft_metadata(&self) -> FungibleTokenMetadata;
+}
+
+#[ext_contract(ext_non_fungible_token)]
+pub trait NonFungibleTokenContract {
+    fn nft_transfer(&mut self, receiver_id: AccountId, token_id: String,
+                    approval_id: Option<u64>, memo: Option<String>);
+}
+
+#[ext_contract(ext_multi_token)]
+pub trait MultiTokenContract {
+    fn mt_transfer(&mut self, receiver_id: AccountId, token_id: String, amount: U128,
+                   approval: Option<(AccountId, u64)>);
+}
+
+/// The functions generated by `ext_contract` take the receiver, deposit and gas on top of the callback arguments.
+#[allow(clippy::too_many_arguments)]
+mod callbacks {
+    use super::*;
+
+    #[ext_contract(ext_self)]
+    pub trait ExtSelf {
+        fn account_book_callback_deposit(&mut self, sender_id: AccountId, raft_id: AccountId,
+                                         amount: U128, raft_amount: U128, user_raft_amount: U128);
+
+        fn account_book_callback_storage(&mut self, sender_id: AccountId, raft_id: AccountId, amount: U128);
+
+        fn account_book_callback_withdraw(&mut self, sender_id: AccountId, raft_id: AccountId,
+                                          amount: U128, raft_amount: U128, user_raft_amount: U128);
+
+        fn claim_fees_callback(&mut self, bucket: fees::FeeBucket, raft_id: AccountId, amount: U128,
+                               recipient_id: AccountId);
+
+        fn list_asset_callback_metadata(&mut self, change: timelock::ParamChange);
+
+        fn nft_collateral_callback_send(&mut self, collateral_id: u64, payer_id: AccountId, fee_amount: U128);
+
+        fn mt_collateral_callback_send(&mut self, collateral_id: u64, payer_id: AccountId, fee_amount: U128);
+
+        fn create_raft_callback(&mut self, asset: Asset, deposit: U128);
 
-    fn account_book_callback_withdraw(&mut self, sender_id: AccountId, raft_id: AccountId,
-                                      amount: Balance, raft_amount: Balance, user_raft_amount: Balance);
+        fn liquidation_callback_swap(&mut self, collateral_id: u64, liquidator_id: AccountId);
 
-    fn mint_callback(&mut self, sender_id: AccountId, token_id: AccountId, token_amount: Balance,
-                     raft_id: AccountId, raft_amount: Balance, join_debtpool: bool);
+        fn liquidation_callback_proceeds(&mut self, collateral_id: u64, liquidator_id: AccountId, amount_out: U128);
+
+        fn liquidation_callback_refund(&mut self, collateral_id: u64);
+
+        fn bridge_callback_lock(&mut self, sender_id: AccountId, raft_id: AccountId, amount: U128, eth_recipient: String);
+
+        fn claim_rewards_callback(&mut self, account_id: AccountId, token_id: AccountId, amount: U128);
+
+        fn claim_farm_rewards_callback(&mut self, farm_id: u64, account_id: AccountId, amount: U128);
+
+        fn withdraw_unstaked_callback(&mut self, account_id: AccountId, amount: U128);
+
+        fn claim_vested_callback(&mut self, vesting_id: u64, amount: U128);
+
+        fn pending_operation_callback(&mut self, op_id: Option<U64>, operation: retry::PendingOperation);
+
+        fn nft_resolve_transfer(&mut self, previous_owner_id: AccountId, receiver_id: AccountId, token_id: String,
+                                approved_account_ids: Option<std::collections::HashMap<AccountId, u64>>) -> bool;
+    }
 }
 
+pub use callbacks::ext_self;
+
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Contract {
     /// Account of the owner.
     owner_id: AccountId,
+    /// Set of guardians, who can only pause the contract or assets.
+    guardians: UnorderedSet<AccountId>,
+    /// Account receiving the protocol fees.
+    treasury_id: AccountId,
     /// Running state
     state: RunningState,
     /// Leverage ratio (managed by governance).
@@ -108,6 +494,10 @@ pub struct Contract {
     exchange_fee: u32,
     /// Accounts registered, keeping track all the amounts deposited, storage and more.
     accounts: LookupMap<AccountId, VAccount>,
+    /// Registry of the registered accounts, allowing to enumerate them.
+    account_ids: UnorderedSet<AccountId>,
+    /// Mapping from account and token to amount of tokens that failed to be sent back to the account.
+    lostfound: LookupMap<(AccountId, AccountId), Balance>,
     /// Set of whitelisted tokens by "owner".
     whitelisted_tokens: UnorderedSet<AccountId>,
     token_list: UnorderedMap<AccountId, Asset>,
@@ -115,14 +505,131 @@ pub struct Contract {
     whitelisted_rafts: UnorderedSet<AccountId>,
     raft_list: UnorderedMap<AccountId, Asset>,
     /// Collateral
-    collaterals: Vector<Collateral>,
-    user_collaterals: LookupMap<AccountId, Vector<CollateralId>>,
+    collaterals: collateral_map::CollateralMap,
+    /// Mapping from user to the ids of its open collaterals.
+    user_collaterals: LookupMap<AccountId, UnorderedSet<CollateralId>>,
     /// Debt pool
     debt_pool: debtpool::DebtPool,
     /// Account book
     account_book: accountbook::AccountBook,
     /// Oracle
     price_oracle: oracle::PriceInfo,
+    /// Timelock for sensitive parameter changes
+    timelock: timelock::Timelock,
+    /// Protocol fees accrued per raft
+    fee_ledger: fees::FeeLedger,
+    /// Last operations, for indexers to backfill missed events
+    oplog: oplog::OpLog,
+    /// Listed NFT collections, valued at their floor price.
+    nft_list: UnorderedMap<AccountId, Asset>,
+    /// NFT collateral
+    nft_collaterals: Vector<nft::NftCollateral>,
+    user_nft_collaterals: LookupMap<AccountId, UnorderedSet<nft::NftCollateralId>>,
+    /// Listed multi-tokens, keyed by contract and token id.
+    mt_list: UnorderedMap<mt::MtTokenKey, mt::MtAsset>,
+    /// Multi-token collateral
+    mt_collaterals: Vector<mt::MtCollateral>,
+    user_mt_collaterals: LookupMap<AccountId, UnorderedSet<mt::MtCollateralId>>,
+    /// Code of the raft token contract deployed by the raft factory.
+    raft_code: LazyOption<Vec<u8>>,
+    /// Exchange and pools swapping liquidated collateral into rUSD.
+    liquidation_router: ref_finance::LiquidationRouter,
+    /// Rainbow Bridge token locker receiving bridged rafts.
+    bridge_locker_id: Option<AccountId>,
+    /// wNEAR contract, whose tokens can be unwrapped on withdrawal.
+    wnear_id: Option<AccountId>,
+    /// Caps of the debt of each account.
+    borrow_limits: borrow_limit::BorrowLimits,
+    /// Mapping from (collateral token, raft) to collateral ratio overriding the one of the token.
+    pair_collateral_ratios: LookupMap<(AccountId, AccountId), u128>,
+    /// Grace windows of assets whose liquidation ratio was raised.
+    risk_graces: grace::RiskGraces,
+    /// Mapping from risk class to its parameters, when changed from the defaults.
+    risk_params: LookupMap<risk::RiskClass, risk::RiskParams>,
+    /// Stability fee per annum of account book collaterals of unclassified tokens (managed by governance).
+    stability_fee: u32,
+    /// Max number of listed rafts, bounding the loops over all rafts.
+    max_rafts: u64,
+    /// How the interest of account book collaterals is charged on redemption (managed by governance).
+    interest_mode: fees::InterestMode,
+    /// Accounts paying their fees in rUSD.
+    rusd_fee_payers: LookupSet<AccountId>,
+    /// Referrers of accounts and their earnings.
+    referrals: referral::Referrals,
+    /// Fee charged on minted rafts, in `BPS_DIVISOR` units (managed by governance).
+    mint_fee: u32,
+    /// Decaying base rate of the fee on redemptions from the debt pool.
+    redemption_rate: redemption::RedemptionRate,
+    /// Temporary fee overrides scheduled by the owner.
+    fee_promos: promo::FeePromos,
+    /// Accounts with an operation waiting for its callback.
+    operation_locks: lock::OperationLocks,
+    /// Viewers allowed by accounts to read their user-scoped views.
+    view_access: view_access::ViewAccess,
+    /// Minimum interval between the swaps of an account (managed by governance).
+    swap_cooldown: cooldown::SwapCooldown,
+    /// Accounts blocked from minting, swapping, withdrawing and receiving tokens.
+    blacklist: UnorderedSet<AccountId>,
+    /// Relayers allowed to submit gasless calls of users.
+    relayers: UnorderedSet<AccountId>,
+    /// Positions of the maintenance tasks run by keepers.
+    keeper_cursors: keeper::KeeperCursors,
+    /// Failed mints and burns of rafts, to be retried.
+    retry_queue: retry::RetryQueue,
+    /// Raft redeemed from the debt pool and received for liquidated collateral.
+    rusd_id: Option<AccountId>,
+    psm: psm::Psm,
+    /// Rafts other than rUSD the debt of the debt pool can be settled in (managed by governance).
+    settlement_rafts: UnorderedSet<AccountId>,
+    peg_guard: Option<peg::PegGuard>,
+    buyback_config: buyback::BuybackConfig,
+    /// Max share of a single raft in the value of the debt pool, in `BPS_DIVISOR` units, 0 for no max.
+    max_raft_share: u32,
+    /// Mapping from collateral id to the account its issuer offered it to, pending acceptance.
+    collateral_transfers: LookupMap<CollateralId, AccountId>,
+    /// Accounts whose account book collaterals are liquidated as one portfolio.
+    cross_margin_accounts: LookupSet<AccountId>,
+    /// Mapping from account opted in to auto-deleverage to its buffer above the liquidation threshold,
+    /// in `BPS_DIVISOR` units.
+    deleverage_buffers: LookupMap<AccountId, u32>,
+    /// Fee paid to the keeper on auto-deleverage, in `BPS_DIVISOR` units of the repaid amount.
+    deleverage_fee: u32,
+    /// Mapping from account and raft to the stop order on its debt pool position.
+    stop_orders: UnorderedMap<(AccountId, AccountId), stop::StopOrder>,
+    /// Bounty paid to keepers out of executed stop orders, in `BPS_DIVISOR` units.
+    stop_bounty: u32,
+    /// Max move of a price since the round a call was quoted against, in `BPS_DIVISOR` units.
+    price_round_tolerance: u32,
+    /// Mapping from collateral id to the label set by its issuer.
+    collateral_labels: LookupMap<CollateralId, String>,
+    /// Debt pool redemptions whose collaterals aren't all returned yet.
+    pending_redemptions: LookupMap<AccountId, redeem_step::PendingRedemption>,
+    /// Gas attached to the calls to raft and token contracts.
+    gas_config: gas::GasConfig,
+    /// Totals of the books stored by keepers.
+    total_values: keeper::TotalValues,
+    /// Whether mints, swaps and redemptions report the gas and the storage they use.
+    instrumentation: bool,
+    /// Emission of the governance token to the debt pool participants.
+    debtpool_rewards: rewards::DebtPoolRewards,
+    /// Reward campaigns of the owner on rafts held in the debt pool and deposits in the peg stability module.
+    farms: farm::Farms,
+    /// Stakes of the governance token sharing the fees of the stakers.
+    staking: staking::Staking,
+    /// Allocations of tokens held by the contract released over time to their beneficiaries.
+    vesting: vesting::Vesting,
+    /// Mapping from user and token to the amount of the token backing its open collaterals.
+    locked_collaterals: LookupMap<(AccountId, AccountId), Balance>,
+    /// Mapping from token, raft or NFT collection to the number of open collaterals of any kind using it.
+    open_collateral_counts: LookupMap<AccountId, u64>,
+    /// Mapping from multi-token to the number of open collaterals of it.
+    open_mt_collateral_counts: LookupMap<mt::MtTokenKey, u64>,
+    /// Accounts accepting position tokens sent with `nft_transfer`.
+    position_receivers: LookupSet<AccountId>,
+    /// Accounts allowed to run the maintenance tasks spending fees, on top of the owner and guardians.
+    keepers: UnorderedSet<AccountId>,
+    /// Value of the collaterals backing rUSD summed by keepers.
+    rusd_backing: keeper::RusdBacking,
 }
 
 #[near_bindgen]
@@ -131,232 +638,224 @@ impl Contract {
     pub fn new(owner_id: AccountId) -> Self {
         Self {
             owner_id: owner_id.clone(),
+            guardians: UnorderedSet::new(StorageKey::Guardians),
+            treasury_id: owner_id.clone(),
             state: RunningState::Running,
             leverage_ratio: (1, 10),
             interest_fee: 0,
             exchange_fee: 3,
             accounts: LookupMap::new(StorageKey::Accounts),
-            whitelisted_tokens: UnorderedSet::new(StorageKey::Whitelist),
-            token_list: UnorderedMap::new(b"r".to_vec()),
-            whitelisted_rafts: UnorderedSet::new(StorageKey::Whitelist),
-            raft_list: UnorderedMap::new(b"r".to_vec()),
-            collaterals: Vector::new(b"r".to_vec()),
-            user_collaterals: LookupMap::new(b"r".to_vec()),
+            account_ids: UnorderedSet::new(StorageKey::AccountIds),
+            lostfound: LookupMap::new(StorageKey::LostFound),
+            whitelisted_tokens: UnorderedSet::new(StorageKey::WhitelistedTokens),
+            token_list: UnorderedMap::new(StorageKey::TokenList),
+            whitelisted_rafts: UnorderedSet::new(StorageKey::WhitelistedRafts),
+            raft_list: UnorderedMap::new(StorageKey::RaftList),
+            collaterals: collateral_map::CollateralMap::new(),
+            user_collaterals: LookupMap::new(StorageKey::UserCollaterals),
             debt_pool: debtpool::DebtPool::new(),
             account_book: accountbook::AccountBook::new(),
             price_oracle: oracle::PriceInfo::new(),
+            timelock: timelock::Timelock::new(utils::DEFAULT_TIMELOCK_DELAY),
+            fee_ledger: fees::FeeLedger::new(),
+            oplog: oplog::OpLog::new(),
+            nft_list: UnorderedMap::new(StorageKey::NftList),
+            nft_collaterals: Vector::new(StorageKey::NftCollaterals),
+            user_nft_collaterals: LookupMap::new(StorageKey::UserNftCollaterals),
+            mt_list: UnorderedMap::new(StorageKey::MtList),
+            mt_collaterals: Vector::new(StorageKey::MtCollaterals),
+            user_mt_collaterals: LookupMap::new(StorageKey::UserMtCollaterals),
+            raft_code: LazyOption::new(StorageKey::RaftCode, None),
+            liquidation_router: ref_finance::LiquidationRouter::new(),
+            bridge_locker_id: None,
+            wnear_id: None,
+            borrow_limits: borrow_limit::BorrowLimits::new(),
+            pair_collateral_ratios: LookupMap::new(StorageKey::PairCollateralRatios),
+            risk_graces: grace::RiskGraces::new(),
+            risk_params: LookupMap::new(StorageKey::RiskParams),
+            stability_fee: 0,
+            max_rafts: utils::DEFAULT_MAX_RAFTS,
+            interest_mode: fees::InterestMode::PerAnnum,
+            rusd_fee_payers: LookupSet::new(StorageKey::RusdFeePayers),
+            referrals: referral::Referrals::new(),
+            mint_fee: 0,
+            redemption_rate: redemption::RedemptionRate::new(),
+            fee_promos: promo::FeePromos::new(),
+            operation_locks: lock::OperationLocks::new(),
+            view_access: view_access::ViewAccess::new(),
+            swap_cooldown: cooldown::SwapCooldown::new(),
+            blacklist: UnorderedSet::new(StorageKey::Blacklist),
+            relayers: UnorderedSet::new(StorageKey::Relayers),
+            keeper_cursors: keeper::KeeperCursors::default(),
+            retry_queue: retry::RetryQueue::new(),
+            rusd_id: None,
+            psm: psm::Psm::new(),
+            settlement_rafts: UnorderedSet::new(StorageKey::SettlementRafts),
+            peg_guard: None,
+            buyback_config: buyback::BuybackConfig::default(),
+            max_raft_share: 0,
+            collateral_transfers: LookupMap::new(StorageKey::CollateralTransfers),
+            cross_margin_accounts: LookupSet::new(StorageKey::CrossMarginAccounts),
+            deleverage_buffers: LookupMap::new(StorageKey::DeleverageBuffers),
+            deleverage_fee: 0,
+            stop_orders: UnorderedMap::new(StorageKey::StopOrders),
+            stop_bounty: 0,
+            price_round_tolerance: 0,
+            collateral_labels: LookupMap::new(StorageKey::CollateralLabels),
+            pending_redemptions: LookupMap::new(StorageKey::PendingRedemptions),
+            gas_config: gas::GasConfig::default(),
+            total_values: keeper::TotalValues::default(),
+            instrumentation: false,
+            debtpool_rewards: rewards::DebtPoolRewards::new(),
+            farms: farm::Farms::new(),
+            staking: staking::Staking::new(),
+            vesting: vesting::Vesting::new(),
+            locked_collaterals: LookupMap::new(StorageKey::LockedCollaterals),
+            open_collateral_counts: LookupMap::new(StorageKey::OpenCollateralCounts),
+            open_mt_collateral_counts: LookupMap::new(StorageKey::OpenMtCollateralCounts),
+            position_receivers: LookupSet::new(StorageKey::PositionReceivers),
+            keepers: UnorderedSet::new(StorageKey::Keepers),
+            rusd_backing: keeper::RusdBacking::default(),
         }
     }
 
+    /// Opens a collateral from tokens deposited by the caller. Returns the id of the collateral.
+    /// `price_rounds` binds the mint to the price rounds it was quoted against, see `get_price_round`.
     #[payable]
-    pub fn mint(&mut self, token_id: AccountId, token_amount: Balance,
-                raft_id: AccountId, raft_amount: Balance, join_debtpool: bool) -> Promise {
+    pub fn mint(&mut self, token_id: AccountId, token_amount: U128, raft_id: AccountId, raft_amount: U128,
+                join_debtpool: bool, price_rounds: Option<Vec<oracle::PriceRound>>) -> WithNonce<CollateralId> {
         assert_one_yocto();
         self.assert_contract_running();
-
-        assert!(self.is_in_whitelisted_tokens(&token_id));
-        assert!(self.is_in_whitelisted_rafts(&raft_id));
-
-        assert!(token_amount > 0, "{}", errors::NO_ATTACHED_DEPOSIT);
-        assert!(raft_amount > 0, "{}", errors::SYNTHETIC_AMOUNT_ERROR);
+        self.assert_price_rounds(&price_rounds);
 
         let sender_id = env::predecessor_account_id();
-        ext_fungible_token::ft_transfer_call(
-            env::current_account_id(),
-            U128(token_amount),
-            None,
-            "".to_string(),
-            token_id.clone(),
-            utils::ONE_YOCTO,
-            utils::GAS_FOR_FT_TRANSFER,
-        ).then(ext_self::mint_callback(
-            sender_id,
+        let collateral_id = self.internal_mint_from_deposits(&sender_id, MintAction {
             token_id,
             token_amount,
             raft_id,
             raft_amount,
             join_debtpool,
-            env::current_account_id(),
-            utils::NO_DEPOSIT,
-            utils::GAS_FOR_FT_TRANSFER,
-        ))
+        });
+        WithNonce { value: collateral_id, nonce: self.internal_bump_nonce(&sender_id) }
     }
 
-    #[private]
-    fn mint_callback(&mut self, sender_id: AccountId, token_id: AccountId, token_amount: Balance,
-                     raft_id: AccountId, raft_amount: Balance, join_debtpool: bool) {
-        if join_debtpool {
-            let token_decimals = self.query_token(&token_id).unwrap().decimals;
-            let raft_decimals = self.query_raft(&raft_id).unwrap().decimals;
-
-            let leverage_ratio = (self.price_oracle.get_price(&raft_id) * raft_amount * 10u128.pow(token_decimals))
-                / (self.price_oracle.get_price(&token_id) * token_amount * 10u128.pow(raft_decimals));
-
-            let (min, max) = self.leverage_ratio;
-            assert!(leverage_ratio >= min.into());
-            assert!(leverage_ratio <= max.into());
-
-            self.debt_pool.join(&self.price_oracle, &sender_id, &raft_id, raft_amount);
-        } else {
-            let token_asset = self.query_token(&token_id).unwrap();
-            let raft_asset = self.query_token(&raft_id).unwrap();
-
-            let token_decimals = token_asset.decimals;
-            let raft_decimals = raft_asset.decimals;
-
-            let collateral_ratio = (self.price_oracle.get_price(&token_id) * token_amount * 10u128.pow(raft_decimals) * 100)
-                / (self.price_oracle.get_price(&raft_id) * raft_amount * 10u128.pow(token_decimals));
-
-            assert!(collateral_ratio >= token_asset.collateral_ratio);
-
-            self.account_book.mint(&sender_id, &raft_id, raft_amount);
-        }
-
-        let collateral = Collateral {
-            issuer: sender_id,
-            token_id: token_id.clone(),
-            token_amount,
-            raft_id: raft_id.clone(),
-            raft_amount,
-            join_debtpool,
-            block_index: env::block_height(),
-            create_time: env::block_timestamp(),
-            state: 0,
-        };
+    /// Opens several collaterals at once from tokens deposited by the caller, each checked as a single mint.
+    /// Either all of them are opened or none. Returns the ids of the collaterals.
+    #[payable]
+    pub fn batch_mint(&mut self, actions: Vec<MintAction>) -> WithNonce<Vec<CollateralId>> {
+        assert_one_yocto();
+        self.assert_contract_running();
+        assert!(!actions.is_empty() && actions.len() <= utils::MAX_BATCH_SIZE, "{}", errors::ILLEGAL_BATCH_SIZE);
 
-        self.collaterals.push(&collateral);
+        let sender_id = env::predecessor_account_id();
+        let collateral_ids = actions.into_iter()
+            .map(|action| self.internal_mint_from_deposits(&sender_id, action))
+            .collect();
+        WithNonce { value: collateral_ids, nonce: self.internal_bump_nonce(&sender_id) }
     }
 
-    pub fn swap_in_debtpool(&mut self, old_raft_id: AccountId, new_raft_id: AccountId, swap_amount: Balance) {
+    /// Returns the new operation nonce of the account.
+    /// `price_rounds` binds the swap to the price rounds it was quoted against, see `get_price_round`.
+    pub fn swap_in_debtpool(&mut self, old_raft_id: AccountId, new_raft_id: AccountId, swap_amount: U128,
+                            price_rounds: Option<Vec<oracle::PriceRound>>) -> u64 {
         self.assert_contract_running();
-
-        assert!(self.is_in_whitelisted_rafts(&old_raft_id));
-        assert!(self.is_in_whitelisted_rafts(&new_raft_id));
-        assert!(swap_amount > 0);
-
+        self.assert_price_rounds(&price_rounds);
         let sender_id = env::predecessor_account_id();
-
-        let old_raft_amount = self.debt_pool.query_raft_amount(&old_raft_id);
-        let old_user_raft_amount = self.debt_pool.query_user_raft_amount(&sender_id, &old_raft_id);
-        assert!(old_user_raft_amount >= swap_amount);
-
-        // charge transaction fee
-        let exchange_fee_amount = swap_amount * self.exchange_fee as u128 / utils::FEE_DIVISOR as u128;
-        let owner_raft_amount = self.debt_pool.query_user_raft_amount(&self.owner_id, &old_raft_id);
-        self.debt_pool.insert_user_raft_amount(&self.owner_id, &old_raft_id, owner_raft_amount + exchange_fee_amount);
-
-        self.debt_pool.calc_sub_raft_amount(&old_raft_id, &old_raft_amount, swap_amount - exchange_fee_amount);
-        self.debt_pool.insert_user_raft_amount(&sender_id, &old_raft_id, old_user_raft_amount - swap_amount);
-
-        let new_swap_amount = self.debt_pool.calc_raft_value(&self.price_oracle, &old_raft_id, swap_amount - exchange_fee_amount)
-            / self.price_oracle.get_price(&new_raft_id);
-        let new_raft_amount = self.debt_pool.query_raft_amount(&new_raft_id);
-        self.debt_pool.calc_add_raft_amount(&new_raft_id, &new_raft_amount, new_swap_amount);
-
-        let new_user_raft_amount = self.debt_pool.query_user_raft_amount(&sender_id, &new_raft_id);
-        self.debt_pool.insert_user_raft_amount(&sender_id, &new_raft_id, new_user_raft_amount + new_swap_amount);
+        self.internal_swap_in_debtpool(&sender_id, old_raft_id, new_raft_id, swap_amount.into());
+        self.internal_bump_nonce(&sender_id)
     }
 
-    pub fn swap_in_accountbook(&mut self, old_raft_id: AccountId, new_raft_id: AccountId, swap_amount: Balance) {
+    /// Returns the new operation nonce of the account.
+    /// `price_rounds` binds the swap to the price rounds it was quoted against, see `get_price_round`.
+    pub fn swap_in_accountbook(&mut self, old_raft_id: AccountId, new_raft_id: AccountId, swap_amount: U128,
+                               price_rounds: Option<Vec<oracle::PriceRound>>) -> u64 {
         self.assert_contract_running();
-
-        assert!(self.is_in_whitelisted_rafts(&old_raft_id));
-        assert!(self.is_in_whitelisted_rafts(&new_raft_id));
-        assert!(swap_amount > 0);
-
+        self.assert_price_rounds(&price_rounds);
         let sender_id = env::predecessor_account_id();
-
-        let old_raft_amount = self.account_book.query_raft_amount(&old_raft_id);
-        assert!(old_raft_amount >= swap_amount);
-        let old_user_raft_amount = self.account_book.query_user_raft_amount(&sender_id, &old_raft_id);
-        assert!(old_user_raft_amount >= swap_amount);
-
-        // charge transaction fee
-        let exchange_fee_amount = swap_amount * self.exchange_fee as u128 / utils::FEE_DIVISOR as u128;
-        let owner_raft_amount = self.account_book.query_user_raft_amount(&self.owner_id, &old_raft_id);
-        self.account_book.insert_user_raft_amount(&self.owner_id, &old_raft_id, owner_raft_amount + exchange_fee_amount);
-
-        // processing in the account book
-        self.account_book.insert_raft_amount(&old_raft_id, old_raft_amount - swap_amount + exchange_fee_amount);
-        self.account_book.insert_user_raft_amount(&sender_id, &old_raft_id, old_user_raft_amount - swap_amount);
-
-        let new_swap_amount = self.price_oracle.get_price(&old_raft_id) * (swap_amount - exchange_fee_amount)
-            / self.price_oracle.get_price(&new_raft_id);
-        let new_raft_amount = self.account_book.query_raft_amount(&new_raft_id);
-        self.account_book.insert_raft_amount(&new_raft_id, new_raft_amount + new_swap_amount);
-
-        let new_user_raft_amount = self.account_book.query_user_raft_amount(&sender_id, &new_raft_id);
-        self.account_book.insert_user_raft_amount(&sender_id, &new_raft_id, new_user_raft_amount + new_swap_amount);
-
-        // processing in the debt pool
-        let old_raft_amount = self.debt_pool.query_raft_amount(&old_raft_id);
-        self.debt_pool.calc_sub_raft_amount(&old_raft_id, &old_raft_amount, new_swap_amount);
-
-        let new_raft_amount = self.debt_pool.query_raft_amount(&new_raft_id);
-        self.debt_pool.calc_add_raft_amount(&new_raft_id, &new_raft_amount, new_swap_amount);
+        self.internal_swap_in_accountbook(&sender_id, old_raft_id, new_raft_id, swap_amount.into());
+        self.internal_bump_nonce(&sender_id)
     }
 
+    /// `unwrap` sends returned wNEAR collateral as native NEAR.
+    /// The debt is settled in `settlement_id`, one of the settlement rafts, or in rUSD by default.
+    /// The debt pool collaterals are returned by `redeem_step`, the first step runs right away.
+    /// Returns the new operation nonce of the account.
     #[payable]
-    pub fn redeem_in_debtpool(&mut self) -> PromiseOrValue<U128> {
+    pub fn redeem_in_debtpool(&mut self, unwrap: Option<bool>, settlement_id: Option<AccountId>) -> u64 {
+        let probe = self.internal_start_probe();
         assert_one_yocto();
         self.assert_contract_running();
 
-        let opt_rusd = self.query_rusd();
-        assert!(opt_rusd.is_some());
-        let rusd_asset = opt_rusd.unwrap();
+        let settlement_asset = self.query_settlement_raft(settlement_id);
+        assert!(settlement_asset.state.is_withdrawable(), "{}", errors::ASSET_PAUSED);
 
         let sender_id = env::predecessor_account_id();
-        let collateral_ids: Option<Vector<CollateralId>> = self.user_collaterals.get(&sender_id);
-        assert!(collateral_ids.is_some());
+        self.operation_locks.assert_unlocked(&sender_id);
+        self.assert_no_pending_redemption(&sender_id);
+        let collateral_count = self.user_collaterals.get(&sender_id).map(|collateral_ids| collateral_ids.len());
+        assert!(collateral_count.is_some(), "{}", errors::NO_COLLATERALS);
+        // credit the rewards earned with the debt ratio before it's removed
+        self.internal_settle_rewards(&sender_id);
 
         // calculate user debt
         let user_debt_ratio = self.debt_pool.query_debt_ratio(&sender_id);
-        let raft_total_value = self.debt_pool.calc_raft_total_value(&self.price_oracle);
-        let user_debt = raft_total_value * user_debt_ratio / utils::RATIO_DIVISOR;
+        let raft_total_value = self.debt_pool.calc_raft_total_value(&self.valuation());
+        let user_debt = math::mul_div(raft_total_value, user_debt_ratio, utils::RATIO_DIVISOR);
 
+        let mut redemption_fee_amount = 0;
         if user_debt > 0 {
-            let user_rusd_amount_in_debtpool = self.debt_pool.query_user_raft_amount(&sender_id, &rusd_asset.address);
-            let user_debt_amount = user_debt / utils::PRICE_PRECISION as u128;
-            if user_debt <= user_rusd_amount_in_debtpool * utils::PRICE_PRECISION as u128 {
+            let user_settlement_amount_in_debtpool = self.debt_pool.query_user_raft_amount(&sender_id, &settlement_asset.address);
+            let user_debt_amount = self.valuation().calc_amount(&settlement_asset.address, user_debt);
+            let fee_rate = self.redemption_rate.redeem(user_debt, raft_total_value);
+            redemption_fee_amount = math::mul_div(user_debt_amount, fee_rate, utils::RATIO_DIVISOR);
+            if user_debt_amount <= user_settlement_amount_in_debtpool {
                 // subtract user raft amount
-                self.debt_pool.insert_user_raft_amount(&sender_id, &rusd_asset.address, user_rusd_amount_in_debtpool - user_debt_amount);
+                let valuation = oracle::Valuation::new(&self.price_oracle, &self.token_list, &self.raft_list);
+                self.debt_pool.insert_user_raft_amount(&valuation, &sender_id, &settlement_asset.address, user_settlement_amount_in_debtpool - user_debt_amount);
 
                 // subtract total raft amount
-                let rusd_amount = self.debt_pool.query_raft_amount(&rusd_asset.address);
-                self.debt_pool.calc_sub_raft_amount(&rusd_asset.address, &rusd_amount, user_debt_amount);
+                let settlement_amount = self.debt_pool.query_raft_amount(&settlement_asset.address);
+                self.debt_pool.calc_sub_raft_amount(&settlement_asset.address, &settlement_amount, user_debt_amount);
 
                 // remove user debt ratio
                 self.debt_pool.remove_debt_ratio(&sender_id);
             } else {
-                let user_rusd_amount_in_accountbook = self.account_book.query_user_raft_amount(&sender_id, &rusd_asset.address);
-                assert!(user_debt_amount <= user_rusd_amount_in_debtpool + user_rusd_amount_in_accountbook);
+                let user_settlement_amount_in_accountbook = self.account_book.query_user_raft_amount(&sender_id, &settlement_asset.address);
+                assert!(
+                    user_debt_amount <= user_settlement_amount_in_debtpool + user_settlement_amount_in_accountbook,
+                    "{}",
+                    errors::NOT_ENOUGH_DEBT_REPAYMENT
+                );
 
                 // remove user raft amount in debt pool
-                self.debt_pool.remove_user_raft_amount(&sender_id, &rusd_asset.address);
+                let valuation = oracle::Valuation::new(&self.price_oracle, &self.token_list, &self.raft_list);
+                self.debt_pool.remove_user_raft_amount(&valuation, &sender_id, &settlement_asset.address);
 
                 // subtract total raft amount in debt pool
-                let rusd_amount_in_debtpool = self.debt_pool.query_raft_amount(&rusd_asset.address);
-                self.debt_pool.calc_sub_raft_amount(&rusd_asset.address, &rusd_amount_in_debtpool,
-                                                    user_rusd_amount_in_debtpool);
+                let settlement_amount_in_debtpool = self.debt_pool.query_raft_amount(&settlement_asset.address);
+                self.debt_pool.calc_sub_raft_amount(&settlement_asset.address, &settlement_amount_in_debtpool,
+                                                    user_settlement_amount_in_debtpool);
 
                 // remove user debt ratio
                 self.debt_pool.remove_debt_ratio(&sender_id);
 
-                let remaining_debt_amount = user_debt_amount - user_rusd_amount_in_debtpool;
+                let remaining_debt_amount = user_debt_amount - user_settlement_amount_in_debtpool;
                 // subtract user raft amount in account book
-                self.account_book.insert_user_raft_amount(&sender_id, &rusd_asset.address, user_rusd_amount_in_accountbook - remaining_debt_amount);
+                self.account_book.insert_user_raft_amount(&sender_id, &settlement_asset.address, user_settlement_amount_in_accountbook - remaining_debt_amount);
 
                 // subtract total raft amount in account book
-                let rusd_amount_in_accountbook = self.account_book.query_raft_amount(&rusd_asset.address);
-                self.account_book.insert_raft_amount(&rusd_asset.address, rusd_amount_in_accountbook - remaining_debt_amount);
+                let settlement_amount_in_accountbook = self.account_book.query_raft_amount(&settlement_asset.address);
+                self.account_book.insert_raft_amount(&settlement_asset.address, settlement_amount_in_accountbook - remaining_debt_amount);
             }
         }
 
         // transfer debt pool assets to account book
-        for (raft, amount) in self.debt_pool.query_user_raft_amounts(&sender_id).iter() {
+        let valuation = oracle::Valuation::new(&self.price_oracle, &self.token_list, &self.raft_list);
+        let user_raft_amounts = self.debt_pool.query_user_raft_amounts(&sender_id);
+        for (raft, amount) in user_raft_amounts.iter() {
             let debtpool_raft_amount = self.debt_pool.query_raft_amount(raft);
             self.debt_pool.calc_sub_raft_amount(raft, &debtpool_raft_amount, *amount);
-            self.debt_pool.remove_user_raft_amount(&sender_id, raft);
+            self.debt_pool.remove_user_raft_amount(&valuation, &sender_id, raft);
 
             let accountbook_raft_amount = self.account_book.query_raft_amount(raft);
             self.account_book.insert_raft_amount(raft, accountbook_raft_amount + amount);
@@ -365,128 +864,229 @@ impl Contract {
             self.account_book.insert_user_raft_amount(&sender_id, raft, accountbook_user_raft_amount + amount);
         }
 
+        self.debt_pool.remove_user_value(&sender_id);
+        self.internal_sync_farms(&sender_id, farm::FarmTarget::DebtPoolRaft { raft_id: settlement_asset.address.clone() });
+        for (raft_id, _) in user_raft_amounts.into_iter().filter(|(raft_id, _)| *raft_id != settlement_asset.address) {
+            self.internal_sync_farms(&sender_id, farm::FarmTarget::DebtPoolRaft { raft_id });
+        }
+
         // recalculating debt ratio
-        let new_raft_total_value = self.debt_pool.calc_raft_total_value(&self.price_oracle);
+        let new_raft_total_value = self.debt_pool.calc_raft_total_value(&self.valuation());
         self.debt_pool.calc_all_debt_ratio(raft_total_value, new_raft_total_value);
-
-        // return of collateral assets
-        for collateral_id in collateral_ids.unwrap().iter() {
-            let opt_collateral = self.query_collateral(collateral_id);
-            if opt_collateral.is_none() { continue; }
-            let mut collateral = opt_collateral.unwrap();
-            if collateral.issuer != sender_id { continue; }
-            if collateral.join_debtpool == false { continue; }
-            if collateral.state != 0  { continue; }
-
-            // update collateral state
-            collateral.state = 1;
-            self.collaterals.replace(collateral_id, &collateral);
-            
-            let mut account = self.internal_unwrap_account(&sender_id);
-            account.withdraw(&collateral.token_id, collateral.token_amount);
-            self.internal_save_account(&sender_id, account);
-            self.internal_send_tokens(&sender_id, &collateral.token_id, collateral.token_amount);
+        self.debtpool_rewards.rescale(raft_total_value, new_raft_total_value);
+
+        // charge redemption fee on the settlement rafts now in the account book
+        if redemption_fee_amount > 0 {
+            let fee_amount = self.internal_charge_fee(&sender_id, &settlement_asset.address, redemption_fee_amount);
+            self.account_book.burn(&sender_id, &settlement_asset.address, fee_amount);
+            Event::RedemptionFee {
+                account_id: &sender_id,
+                amount: U128(redemption_fee_amount),
+                base_rate: U128(self.redemption_rate.query_base_rate()),
+            }.emit();
         }
 
-        PromiseOrValue::Value(U128(0))
+        // collateral assets are returned in steps, see `redeem_step`
+        self.pending_redemptions.insert(&sender_id, &redeem_step::PendingRedemption {
+            remaining: collateral_count.unwrap(),
+            unwrap: unwrap == Some(true),
+        });
+        self.internal_redeem_step(&sender_id);
+        self.internal_end_probe(OperationKind::Redeem, probe);
+        self.internal_bump_nonce(&sender_id)
     }
 
+    /// `unwrap` sends returned wNEAR collateral as native NEAR.
+    /// Resolves to the new operation nonce of the account.
     #[payable]
-    pub fn redeem_in_accountbook(&mut self, collateral_id: CollateralId) -> Promise {
+    pub fn redeem_in_accountbook(&mut self, collateral_id: CollateralId, unwrap: Option<bool>) -> Promise {
         assert_one_yocto();
         self.assert_contract_running();
 
-        let opt_collateral = self.query_collateral(collateral_id);
-        assert!(opt_collateral.is_some());
-
         let sender_id = env::predecessor_account_id();
-        let mut collateral = opt_collateral.unwrap();
-        assert_eq!(collateral.issuer, sender_id);
-        assert_eq!(collateral.join_debtpool, false);
-        assert_eq!(collateral.state, 0);
+        let (token_id, token_amount) = self.internal_redeem_in_accountbook(&sender_id, collateral_id);
 
-        let raft_amount = self.account_book.query_raft_amount(&collateral.raft_id);
-        let user_raft_amount = self.account_book.query_user_raft_amount(&sender_id, &collateral.raft_id);
-        let interest_fee_amount = collateral.raft_amount * self.interest_fee as u128 / utils::FEE_DIVISOR as u128;
-        assert!(raft_amount > collateral.raft_amount + interest_fee_amount);
-        assert!(user_raft_amount > collateral.raft_amount + interest_fee_amount);
+        let mut account = self.internal_unwrap_account(&sender_id);
+        account.withdraw(&token_id, token_amount);
+        self.internal_save_account(&sender_id, account);
+        let promise = self.internal_send_tokens_or_near(&sender_id, &token_id, token_amount, unwrap == Some(true));
+        self.internal_bump_nonce_then(&sender_id, promise)
+    }
 
-        // charge interest fee
-        let owner_raft_amount = self.account_book.query_user_raft_amount(&self.owner_id, &collateral.raft_id);
-        self.account_book.insert_user_raft_amount(&self.owner_id, &collateral.raft_id, owner_raft_amount + interest_fee_amount);
+    /// Closes an account book collateral of the caller whose debt was fully repaid, dropping it from the caller's
+    /// collaterals. Its tokens left, if any, are unlocked in the caller's deposits.
+    /// Returns the new operation nonce of the account.
+    pub fn close_collateral(&mut self, collateral_id: CollateralId) -> u64 {
+        self.assert_contract_running();
+        let sender_id = env::predecessor_account_id();
+        self.operation_locks.assert_unlocked(&sender_id);
+        self.assert_no_pending_redemption(&sender_id);
+        let mut collateral = self.internal_unwrap_open_collateral(&sender_id, collateral_id);
+        assert!(!collateral.join_debtpool, "{}", errors::COLLATERAL_IN_DEBTPOOL);
+        self.internal_accrue_stability_fee(&mut collateral);
+        assert_eq!(collateral.debt(), 0, "{}", errors::COLLATERAL_HAS_DEBT);
+
+        self.internal_close_collateral(collateral_id, collateral.clone());
+        Event::Redeem {
+            account_id: &sender_id,
+            collateral_id,
+            token_id: &collateral.token_id,
+            token_amount: U128(collateral.token_amount),
+        }.emit();
+        self.internal_record_operation(OperationKind::Redeem, &sender_id,
+                                       vec![collateral.token_id], vec![collateral.token_amount]);
+        self.internal_bump_nonce(&sender_id)
+    }
 
-        // subtract user raft amount
-        self.account_book.insert_user_raft_amount(&sender_id, &collateral.raft_id, user_raft_amount - collateral.raft_amount - interest_fee_amount);
+    /// Redeems several account book collaterals of the caller at once, either all of them or none.
+    /// The returned tokens are summed up per token and sent in one transfer each.
+    /// Resolves to the new operation nonce of the account.
+    #[payable]
+    pub fn batch_redeem_in_accountbook(&mut self, collateral_ids: Vec<CollateralId>, unwrap: Option<bool>) -> Promise {
+        assert_one_yocto();
+        self.assert_contract_running();
+        assert!(!collateral_ids.is_empty() && collateral_ids.len() <= utils::MAX_BATCH_SIZE,
+                "{}", errors::ILLEGAL_BATCH_SIZE);
 
-        // subtract total raft amount
-        self.account_book.insert_raft_amount(&collateral.raft_id, raft_amount - collateral.raft_amount);
+        let sender_id = env::predecessor_account_id();
+        // a repeated id fails as the collateral is closed by its first redemption
+        let mut returns: Vec<(AccountId, Balance)> = vec![];
+        for collateral_id in collateral_ids {
+            let (token_id, token_amount) = self.internal_redeem_in_accountbook(&sender_id, collateral_id);
+            match returns.iter_mut().find(|(returned_id, _)| returned_id == &token_id) {
+                Some((_, amount)) => *amount += token_amount,
+                None => returns.push((token_id, token_amount)),
+            }
+        }
 
-        // update collateral state
-        collateral.state = 1;
-        self.collaterals.replace(collateral_id, &collateral);
-        
         let mut account = self.internal_unwrap_account(&sender_id);
-        account.withdraw(&collateral.token_id, collateral.token_amount);
+        for (token_id, amount) in returns.iter() {
+            account.withdraw(token_id, *amount);
+        }
         self.internal_save_account(&sender_id, account);
-        self.internal_send_tokens(&sender_id, &collateral.token_id, collateral.token_amount)
+        let promise = returns.iter()
+            .map(|(token_id, amount)| self.internal_send_tokens_or_near(&sender_id, token_id, *amount, unwrap == Some(true)))
+            .reduce(|promise, next| promise.and(next))
+            .unwrap();
+        self.internal_bump_nonce_then(&sender_id, promise)
     }
 
+    /// Mints more rafts in the account book against an open account book collateral of the caller,
+    /// which must stay above the collateral ratio of its token. Returns the new operation nonce of the account.
     #[payable]
-    pub fn deposit_in_accountbook(&mut self, raft_id: AccountId, amount: Balance) -> Promise {
+    pub fn borrow_more(&mut self, collateral_id: CollateralId, raft_amount: U128) -> u64 {
         assert_one_yocto();
         self.assert_contract_running();
+        let raft_amount: Balance = raft_amount.into();
+        assert!(raft_amount > 0, "{}", errors::SYNTHETIC_AMOUNT_ERROR);
 
         let sender_id = env::predecessor_account_id();
-        let raft_amount = self.account_book.query_raft_amount(&raft_id);
-        let user_raft_amount = self.account_book.query_user_raft_amount(&sender_id, &raft_id);
+        self.operation_locks.assert_unlocked(&sender_id);
+        let mut collateral = self.query_collateral(collateral_id).expect(errors::COLLATERAL_NOT_FOUND);
+        assert_eq!(collateral.issuer, sender_id, "{}", errors::NO_PERMISSION);
+        assert!(collateral.state == CollateralState::Open, "{}", errors::COLLATERAL_CLOSED);
+        assert!(!collateral.join_debtpool, "{}", errors::COLLATERAL_IN_DEBTPOOL);
+        self.assert_token_state(&collateral.token_id, AssetState::is_mintable);
+        self.assert_raft_state(&collateral.raft_id, AssetState::is_mintable);
+        self.assert_max_supply(&collateral.raft_id, raft_amount);
+        self.assert_borrow_limit(&sender_id, &collateral.raft_id, raft_amount);
+
+        self.internal_accrue_stability_fee(&mut collateral);
+        collateral.raft_amount += raft_amount;
+        let collateral_ratio = self.calc_collateral_ratio(&collateral.token_id, collateral.token_amount,
+                                                          &collateral.raft_id, collateral.debt());
+        assert!(
+            collateral_ratio >= self.query_min_collateral_ratio(&collateral.token_id, &collateral.raft_id),
+            "{}",
+            errors::COLLATERAL_RATIO_TOO_LOW
+        );
 
-        ext_enhanced_fungible_token::burn(
-            sender_id.clone(),
-            U128(amount),
-            raft_id.clone(),
-            utils::ONE_YOCTO,
-            utils::GAS_FOR_FT_TRANSFER,
-        ).then(ext_self::account_book_callback_deposit(
-            sender_id.clone(),
-            raft_id.clone(),
-            amount,
-            raft_amount,
-            user_raft_amount,
-            env::current_account_id(),
-            utils::NO_DEPOSIT,
-            utils::GAS_FOR_FT_TRANSFER,
-        ))
+        self.collaterals.replace(collateral_id, &collateral);
+        self.account_book.mint(&sender_id, &collateral.raft_id, raft_amount);
+
+        Event::BorrowMore {
+            account_id: &sender_id,
+            collateral_id,
+            raft_id: &collateral.raft_id,
+            raft_amount: U128(raft_amount),
+        }.emit();
+        self.internal_record_operation(OperationKind::BorrowMore, &sender_id,
+                                       vec![collateral.raft_id.clone()], vec![raft_amount]);
+        self.internal_bump_nonce(&sender_id)
+    }
+
+    /// Remove ids of closed collaterals from the user's collateral list, returns how many were removed.
+    /// Anyone can call it, as it only drops references to records that can't be used anymore.
+    /// Checks the ids from `from_index`, at most `KEEPER_BATCH_SIZE` of them.
+    pub fn compact_user_collaterals(&mut self, user: AccountId, from_index: Option<u64>) -> u64 {
+        self.assert_no_pending_redemption(&user);
+        let opt_collateral_ids = self.user_collaterals.get(&user);
+        if opt_collateral_ids.is_none() {
+            return 0;
+        }
+
+        let mut collateral_ids = opt_collateral_ids.unwrap();
+        let mut removed = 0;
+        let mut index = from_index.unwrap_or(0);
+        let end = index.saturating_add(utils::KEEPER_BATCH_SIZE);
+        while index < std::cmp::min(end - removed, collateral_ids.len()) {
+            let collateral_id = collateral_ids.as_vector().get(index).unwrap();
+            let is_open = self.query_collateral(collateral_id)
+                .is_some_and(|collateral| collateral.state == CollateralState::Open);
+            if is_open {
+                index += 1;
+            } else {
+                // the last id is swapped in its place
+                collateral_ids.remove(&collateral_id);
+                removed += 1;
+            }
+        }
+        self.user_collaterals.insert(&user, &collateral_ids);
+
+        env::log_str(format!("Removed {} closed collaterals of {}", removed, user).as_str());
+        removed
     }
 
+    /// Resolves to the new operation nonce of the account.
     #[payable]
-    pub fn withdraw_in_accountbook(&mut self, raft_id: AccountId, amount: Balance) -> Promise {
+    pub fn deposit_in_accountbook(&mut self, raft_id: AccountId, amount: U128) -> Promise {
         assert_one_yocto();
         self.assert_contract_running();
+        let sender_id = env::predecessor_account_id();
+        let promise = self.internal_deposit_in_accountbook(&sender_id, raft_id, amount.into());
+        self.internal_bump_nonce_then(&sender_id, promise)
+    }
+
+    /// Resolves to the new operation nonce of the account.
+    #[payable]
+    pub fn withdraw_in_accountbook(&mut self, raft_id: AccountId, amount: U128) -> Promise {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let amount: Balance = amount.into();
 
         assert!(amount > 0, "{}", errors::ILLEGAL_WITHDRAW_AMOUNT);
 
         let sender_id = env::predecessor_account_id();
-        let raft_amount = self.account_book.query_raft_amount(&raft_id);
-        let user_raft_amount = self.account_book.query_user_raft_amount(&sender_id, &raft_id);
-        assert!(raft_amount >= amount);
-        assert!(user_raft_amount >= amount);
+        self.assert_not_blacklisted(&sender_id);
+        self.assert_accountbook_params(&sender_id, &raft_id);
+        assert!(self.account_book.query_user_raft_amount(&sender_id, &raft_id) >= amount, "{}", errors::NOT_ENOUGH_RAFTS);
+        self.operation_locks.lock(&sender_id);
 
-        ext_enhanced_fungible_token::mint(
+        // the rafts are only minted out once the sender is known to be registered on the raft token
+        let promise = ext_storage_management::storage_balance_of(
             sender_id.clone(),
-            U128(amount),
             raft_id.clone(),
-            utils::ONE_YOCTO,
-            utils::GAS_FOR_FT_TRANSFER,
-        ).then(ext_self::account_book_callback_withdraw(
+            utils::NO_DEPOSIT,
+            utils::GAS_FOR_STORAGE_BALANCE_OF,
+        ).then(ext_self::account_book_callback_storage(
             sender_id.clone(),
-            raft_id.clone(),
-            amount,
-            raft_amount,
-            user_raft_amount,
+            raft_id,
+            U128(amount),
             env::current_account_id(),
             utils::NO_DEPOSIT,
-            utils::GAS_FOR_FT_TRANSFER,
-        ))
+            self.gas_config.account_book_mint_out(),
+        ));
+        self.internal_bump_nonce_then(&sender_id, promise)
     }
 }
 
@@ -511,6 +1111,34 @@ impl Contract {
         self.token_list.get(token_id)
     }
 
+    /// Collateral ratio required to mint the raft against the token, the one of the pair if set,
+    /// else the one of the token.
+    fn query_min_collateral_ratio(&self, token_id: &AccountId, raft_id: &AccountId) -> u128 {
+        self.pair_collateral_ratios.get(&(token_id.clone(), raft_id.clone()))
+            .or_else(|| self.query_token(token_id).map(|asset| asset.collateral_ratio))
+            .unwrap_or(0)
+    }
+
+    /// Collateral ratio required to mint the raft with the token, panics if either isn't listed.
+    fn query_token_collateral_ratio(&self, token_id: &AccountId, raft_id: &AccountId) -> u128 {
+        self.query_token(token_id).expect(errors::ASSET_NOT_FOUND);
+        self.query_raft(raft_id).expect(errors::ASSET_NOT_FOUND);
+        self.query_min_collateral_ratio(token_id, raft_id)
+    }
+
+    /// Liquidation ratio of the token, never above the collateral ratio required to mint the raft with it.
+    pub(crate) fn query_pair_liquidation_ratio(&self, token_id: &AccountId, raft_id: &AccountId) -> u128 {
+        let min_collateral_ratio = self.query_min_collateral_ratio(token_id, raft_id);
+        self.query_token(token_id)
+            .map_or(min_collateral_ratio, |asset| asset.liquidation_ratio().min(min_collateral_ratio))
+    }
+
+    /// Collateral ratio under which a collateral of the token minting the raft can be liquidated,
+    /// never above the collateral ratio required to mint nor the ratio in grace.
+    fn query_liquidation_threshold(&self, token_id: &AccountId, raft_id: &AccountId) -> u128 {
+        self.risk_graces.query_liquidation_ratio(token_id, self.query_pair_liquidation_ratio(token_id, raft_id))
+    }
+
     fn is_in_whitelisted_rafts(&self, raft_id: &AccountId) -> bool {
         if self.whitelisted_rafts.contains(raft_id) {
             return true;
@@ -523,25 +1151,605 @@ impl Contract {
         self.raft_list.get(raft_id)
     }
 
+    /// Asserts the token is neither a listed token nor a listed raft, whose balances back the deposits.
+    pub(crate) fn assert_not_listed(&self, token_id: &AccountId) {
+        assert!(self.query_token(token_id).is_none() && self.query_raft(token_id).is_none(),
+                "{}", errors::LISTED_INCENTIVE_TOKEN);
+    }
+
+    /// Values listed tokens and rafts at oracle prices.
+    pub(crate) fn valuation(&self) -> oracle::Valuation<'_> {
+        oracle::Valuation::new(&self.price_oracle, &self.token_list, &self.raft_list)
+    }
+
+    fn assert_token_state<F: Fn(&AssetState) -> bool>(&self, token_id: &AccountId, allowed: F) {
+        let asset = self.query_token(token_id).expect(errors::ASSET_NOT_FOUND);
+        assert!(allowed(&asset.state), "{}", errors::ASSET_PAUSED);
+    }
+
+    fn assert_raft_state<F: Fn(&AssetState) -> bool>(&self, raft_id: &AccountId, allowed: F) {
+        let asset = self.query_raft(raft_id).expect(errors::ASSET_NOT_FOUND);
+        assert!(allowed(&asset.state), "{}", errors::ASSET_PAUSED);
+    }
+
+    /// Panics if no more raft can be listed.
+    fn assert_raft_capacity(&self) {
+        assert!(self.raft_list.len() < self.max_rafts, "{}", errors::MAX_RAFTS_REACHED);
+    }
+
     fn query_rusd(&self) -> Option<Asset> {
-        for (_, asset) in self.raft_list.iter() {
-            if asset.symbol == "rUSD" {
-                return Some(asset);
+        self.rusd_id.as_ref().and_then(|rusd_id| self.query_raft(rusd_id))
+    }
+
+    /// Raft the debt pool debt is settled in, rUSD if `None`.
+    fn query_settlement_raft(&self, settlement_id: Option<AccountId>) -> Asset {
+        match settlement_id {
+            Some(settlement_id) => {
+                assert!(self.settlement_rafts.contains(&settlement_id), "{}", errors::ILLEGAL_SETTLEMENT_RAFT);
+                self.query_raft(&settlement_id).expect(errors::ASSET_NOT_FOUND)
+            }
+            None => self.query_rusd().expect(errors::ASSET_NOT_FOUND),
+        }
+    }
+
+    /// Records a collateral backed by tokens already held by the contract and accounts the minted rafts.
+    fn internal_mint(&mut self, sender_id: AccountId, token_id: AccountId, token_amount: Balance,
+                     raft_id: AccountId, raft_amount: Balance, join_debtpool: bool) -> CollateralId {
+        let probe = self.internal_start_probe();
+        self.assert_no_pending_redemption(&sender_id);
+        self.assert_max_supply(&raft_id, raft_amount);
+        self.assert_borrow_limit(&sender_id, &raft_id, raft_amount);
+        if join_debtpool {
+            self.internal_settle_rewards(&sender_id);
+            // built from the fields, the debt pool is borrowed mutably alongside
+            let valuation = oracle::Valuation::new(&self.price_oracle, &self.token_list, &self.raft_list);
+            let leverage_ratio = math::ratio(&[valuation.calc_value(&raft_id, raft_amount)],
+                                             &[valuation.calc_value(&token_id, token_amount)]);
+
+            let (min, max) = self.leverage_ratio;
+            assert!(leverage_ratio >= min.into(), "{}", errors::ILLEGAL_LEVERAGE_RATIO);
+            assert!(leverage_ratio <= max.into(), "{}", errors::ILLEGAL_LEVERAGE_RATIO);
+
+            if let Some((old_total_value, new_total_value)) = self.debt_pool.join(&valuation, &sender_id, &raft_id, raft_amount) {
+                self.debtpool_rewards.rescale(old_total_value, new_total_value);
+            }
+        } else {
+            let collateral_ratio = self.calc_collateral_ratio(&token_id, token_amount, &raft_id, raft_amount);
+
+            assert!(collateral_ratio >= self.query_min_collateral_ratio(&token_id, &raft_id), "{}", errors::COLLATERAL_RATIO_TOO_LOW);
+
+            self.account_book.mint(&sender_id, &raft_id, raft_amount);
+        }
+
+        // charge mint fee, the collateral still owes the whole raft amount
+        let mint_fee_amount = math::share(raft_amount, self.query_mint_fee(&raft_id), utils::BPS_DIVISOR);
+        if mint_fee_amount > 0 {
+            if join_debtpool {
+                let pool_raft_amount = self.debt_pool.query_raft_amount(&raft_id);
+                let user_raft_amount = self.debt_pool.query_user_raft_amount(&sender_id, &raft_id);
+                self.debt_pool.calc_sub_raft_amount(&raft_id, &pool_raft_amount, mint_fee_amount);
+                let valuation = oracle::Valuation::new(&self.price_oracle, &self.token_list, &self.raft_list);
+                self.debt_pool.insert_user_raft_amount(&valuation, &sender_id, &raft_id, user_raft_amount - mint_fee_amount);
+            } else {
+                self.account_book.burn(&sender_id, &raft_id, mint_fee_amount);
             }
+            self.fee_ledger.accrue_unsplit(fees::FeeBucket::Mint, &raft_id, mint_fee_amount);
+        }
+        if join_debtpool {
+            self.internal_sync_farms(&sender_id, farm::FarmTarget::DebtPoolRaft { raft_id: raft_id.clone() });
         }
 
-        None
+        let collateral = Collateral {
+            issuer: sender_id,
+            token_id: token_id.clone(),
+            token_amount,
+            raft_id: raft_id.clone(),
+            raft_amount,
+            join_debtpool,
+            block_index: env::block_height(),
+            create_time: env::block_timestamp(),
+            state: CollateralState::Open,
+            stability_fee: 0,
+            fee_accrued_at: env::block_timestamp(),
+        };
+
+        let collateral_id = self.internal_add_collateral(&collateral);
+        Event::Mint {
+            account_id: &collateral.issuer,
+            collateral_id,
+            token_id: &token_id,
+            token_amount: U128(token_amount),
+            raft_id: &raft_id,
+            raft_amount: U128(raft_amount),
+            join_debtpool,
+        }.emit();
+        if mint_fee_amount > 0 {
+            Event::MintFee {
+                account_id: &collateral.issuer,
+                collateral_id,
+                raft_id: &collateral.raft_id,
+                amount: U128(mint_fee_amount),
+            }.emit();
+        }
+        self.internal_record_operation(OperationKind::Mint, &collateral.issuer,
+                                       vec![token_id, raft_id], vec![token_amount, raft_amount]);
+        self.internal_end_probe(OperationKind::Mint, probe);
+        collateral_id
+    }
+
+    /// Closes an account book collateral of the user, burning its debt and charging its interest fee.
+    /// Returns the token and amount locked by the collateral, still to be withdrawn from the deposits.
+    fn internal_redeem_in_accountbook(&mut self, sender_id: &AccountId, collateral_id: CollateralId) -> (AccountId, Balance) {
+        let probe = self.internal_start_probe();
+        let opt_collateral = self.query_collateral(collateral_id);
+        assert!(opt_collateral.is_some(), "{}", errors::COLLATERAL_NOT_FOUND);
+
+        self.operation_locks.assert_unlocked(sender_id);
+        self.assert_no_pending_redemption(sender_id);
+        let mut collateral = opt_collateral.unwrap();
+        assert_eq!(&collateral.issuer, sender_id, "{}", errors::NO_PERMISSION);
+        assert!(!collateral.join_debtpool, "{}", errors::COLLATERAL_IN_DEBTPOOL);
+        assert_eq!(collateral.state, CollateralState::Open, "{}", errors::COLLATERAL_CLOSED);
+        self.assert_token_state(&collateral.token_id, AssetState::is_withdrawable);
+        self.assert_raft_state(&collateral.raft_id, AssetState::is_withdrawable);
+
+        self.internal_accrue_stability_fee(&mut collateral);
+        let interest_fee_amount = match self.interest_mode {
+            fees::InterestMode::PerAnnum => collateral.stability_fee,
+            fees::InterestMode::Flat => {
+                let risk_class = self.query_token(&collateral.token_id).and_then(|asset| asset.risk_class);
+                math::share(collateral.raft_amount, self.query_interest_fee(risk_class), utils::FEE_DIVISOR)
+            }
+        };
+
+        // charge interest fee
+        let interest_fee_amount = self.internal_charge_fee(sender_id, &collateral.raft_id, interest_fee_amount);
+        let raft_amount = self.account_book.query_raft_amount(&collateral.raft_id);
+        let user_raft_amount = self.account_book.query_user_raft_amount(sender_id, &collateral.raft_id);
+        assert!(raft_amount > collateral.raft_amount + interest_fee_amount, "{}", errors::NOT_ENOUGH_RAFTS);
+        assert!(user_raft_amount > collateral.raft_amount + interest_fee_amount, "{}", errors::NOT_ENOUGH_RAFTS);
+
+        // subtract user raft amount
+        self.account_book.insert_user_raft_amount(sender_id, &collateral.raft_id, user_raft_amount - collateral.raft_amount - interest_fee_amount);
+
+        // subtract total raft amount
+        self.account_book.insert_raft_amount(&collateral.raft_id, raft_amount - collateral.raft_amount - interest_fee_amount);
+
+        // close collateral
+        self.internal_close_collateral(collateral_id, collateral.clone());
+        Event::Redeem {
+            account_id: sender_id,
+            collateral_id,
+            token_id: &collateral.token_id,
+            token_amount: U128(collateral.token_amount),
+        }.emit();
+        self.internal_record_operation(OperationKind::Redeem, sender_id,
+                                       vec![collateral.token_id.clone()], vec![collateral.token_amount]);
+        self.internal_end_probe(OperationKind::Redeem, probe);
+        (collateral.token_id, collateral.token_amount)
+    }
+
+    fn internal_swap_in_debtpool(&mut self, sender_id: &AccountId, old_raft_id: AccountId,
+                                 new_raft_id: AccountId, swap_amount: Balance) {
+        let probe = self.internal_start_probe();
+        assert!(self.is_in_whitelisted_rafts(&old_raft_id), "{}", errors::RAFT_NOT_WHITELISTED);
+        assert!(self.is_in_whitelisted_rafts(&new_raft_id), "{}", errors::RAFT_NOT_WHITELISTED);
+        self.assert_raft_state(&old_raft_id, AssetState::is_tradable);
+        self.assert_raft_state(&new_raft_id, AssetState::is_tradable);
+        assert!(swap_amount > 0, "{}", errors::ILLEGAL_SWAP_AMOUNT);
+
+        self.assert_not_blacklisted(sender_id);
+        self.operation_locks.assert_unlocked(sender_id);
+        self.swap_cooldown.record(sender_id);
+
+        let old_raft_amount = self.debt_pool.query_raft_amount(&old_raft_id);
+        let old_user_raft_amount = self.debt_pool.query_user_raft_amount(sender_id, &old_raft_id);
+        assert!(old_user_raft_amount >= swap_amount, "{}", errors::NOT_ENOUGH_RAFTS);
+
+        // charge transaction fee
+        let exchange_fee_amount = math::share(swap_amount, self.query_swap_fee(&old_raft_id, &new_raft_id), utils::FEE_DIVISOR);
+        let exchange_fee_amount = self.internal_charge_fee(sender_id, &old_raft_id, exchange_fee_amount);
+
+        // built from the fields, the debt pool is borrowed mutably alongside
+        let valuation = oracle::Valuation::new(&self.price_oracle, &self.token_list, &self.raft_list);
+        self.debt_pool.calc_sub_raft_amount(&old_raft_id, &old_raft_amount, swap_amount);
+        self.debt_pool.insert_user_raft_amount(&valuation, sender_id, &old_raft_id, old_user_raft_amount - swap_amount);
+
+        let new_swap_amount = self.valuation().convert(&old_raft_id, swap_amount - exchange_fee_amount, &new_raft_id);
+        self.assert_max_supply(&new_raft_id, new_swap_amount);
+        let new_raft_amount = self.debt_pool.query_raft_amount(&new_raft_id);
+        self.debt_pool.calc_add_raft_amount(&new_raft_id, &new_raft_amount, new_swap_amount);
+
+        let new_user_raft_amount = self.debt_pool.query_user_raft_amount(sender_id, &new_raft_id);
+        self.debt_pool.insert_user_raft_amount(&valuation, sender_id, &new_raft_id, new_user_raft_amount + new_swap_amount);
+        self.assert_raft_share(&new_raft_id);
+        self.internal_sync_farms(sender_id, farm::FarmTarget::DebtPoolRaft { raft_id: old_raft_id.clone() });
+        self.internal_sync_farms(sender_id, farm::FarmTarget::DebtPoolRaft { raft_id: new_raft_id.clone() });
+
+        Event::Swap {
+            account_id: sender_id,
+            old_raft_id: &old_raft_id,
+            old_amount: U128(swap_amount),
+            new_raft_id: &new_raft_id,
+            new_amount: U128(new_swap_amount),
+            fee: U128(exchange_fee_amount),
+            in_debtpool: true,
+        }.emit();
+        self.internal_record_operation(OperationKind::Swap, sender_id,
+                                       vec![old_raft_id, new_raft_id], vec![swap_amount, new_swap_amount]);
+        self.internal_end_probe(OperationKind::Swap, probe);
+    }
+
+    fn internal_swap_in_accountbook(&mut self, sender_id: &AccountId, old_raft_id: AccountId,
+                                    new_raft_id: AccountId, swap_amount: Balance) {
+        let probe = self.internal_start_probe();
+        assert!(self.is_in_whitelisted_rafts(&old_raft_id), "{}", errors::RAFT_NOT_WHITELISTED);
+        assert!(self.is_in_whitelisted_rafts(&new_raft_id), "{}", errors::RAFT_NOT_WHITELISTED);
+        self.assert_raft_state(&old_raft_id, AssetState::is_tradable);
+        self.assert_raft_state(&new_raft_id, AssetState::is_tradable);
+        assert!(swap_amount > 0, "{}", errors::ILLEGAL_SWAP_AMOUNT);
+
+        self.assert_not_blacklisted(sender_id);
+        self.operation_locks.assert_unlocked(sender_id);
+        self.swap_cooldown.record(sender_id);
+
+        let old_raft_amount = self.account_book.query_raft_amount(&old_raft_id);
+        assert!(old_raft_amount >= swap_amount, "{}", errors::NOT_ENOUGH_RAFTS);
+        let old_user_raft_amount = self.account_book.query_user_raft_amount(sender_id, &old_raft_id);
+        assert!(old_user_raft_amount >= swap_amount, "{}", errors::NOT_ENOUGH_RAFTS);
+
+        // charge transaction fee
+        let exchange_fee_amount = math::share(swap_amount, self.query_swap_fee(&old_raft_id, &new_raft_id), utils::FEE_DIVISOR);
+        let exchange_fee_amount = self.internal_charge_fee(sender_id, &old_raft_id, exchange_fee_amount);
+
+        // processing in the account book
+        self.account_book.insert_raft_amount(&old_raft_id, old_raft_amount - swap_amount);
+        self.account_book.insert_user_raft_amount(sender_id, &old_raft_id, old_user_raft_amount - swap_amount);
+
+        let new_swap_amount = self.valuation().convert(&old_raft_id, swap_amount - exchange_fee_amount, &new_raft_id);
+        self.assert_max_supply(&new_raft_id, new_swap_amount);
+        let new_raft_amount = self.account_book.query_raft_amount(&new_raft_id);
+        self.account_book.insert_raft_amount(&new_raft_id, new_raft_amount + new_swap_amount);
+
+        let new_user_raft_amount = self.account_book.query_user_raft_amount(sender_id, &new_raft_id);
+        self.account_book.insert_user_raft_amount(sender_id, &new_raft_id, new_user_raft_amount + new_swap_amount);
+
+        // processing in the debt pool
+        let old_raft_amount = self.debt_pool.query_raft_amount(&old_raft_id);
+        self.debt_pool.calc_sub_raft_amount(&old_raft_id, &old_raft_amount, new_swap_amount);
+
+        let new_raft_amount = self.debt_pool.query_raft_amount(&new_raft_id);
+        self.debt_pool.calc_add_raft_amount(&new_raft_id, &new_raft_amount, new_swap_amount);
+        self.assert_raft_share(&new_raft_id);
+
+        Event::Swap {
+            account_id: sender_id,
+            old_raft_id: &old_raft_id,
+            old_amount: U128(swap_amount),
+            new_raft_id: &new_raft_id,
+            new_amount: U128(new_swap_amount),
+            fee: U128(exchange_fee_amount),
+            in_debtpool: false,
+        }.emit();
+        self.internal_record_operation(OperationKind::Swap, sender_id,
+                                       vec![old_raft_id, new_raft_id], vec![swap_amount, new_swap_amount]);
+        self.internal_end_probe(OperationKind::Swap, probe);
+    }
+
+    /// Opens a collateral with unlocked tokens deposited by the user.
+    fn internal_mint_from_deposits(&mut self, sender_id: &AccountId, action: MintAction) -> CollateralId {
+        let token_amount: Balance = action.token_amount.into();
+        let raft_amount: Balance = action.raft_amount.into();
+        self.assert_mint_params(&action.token_id, token_amount, &action.raft_id, raft_amount);
+        self.assert_not_blacklisted(sender_id);
+        self.operation_locks.assert_unlocked(sender_id);
+
+        let account = self.internal_unwrap_account(sender_id);
+        let balance = account.get_balance(&action.token_id).expect(errors::TOKEN_NOT_REG);
+        let locked = self.internal_get_locked_collateral(sender_id, &action.token_id);
+        assert!(balance - locked >= token_amount, "{}", errors::NOT_ENOUGH_TOKENS);
+
+        self.internal_mint(sender_id.clone(), action.token_id, token_amount,
+                           action.raft_id, raft_amount, action.join_debtpool)
+    }
+
+    fn assert_mint_params(&self, token_id: &AccountId, token_amount: Balance,
+                          raft_id: &AccountId, raft_amount: Balance) {
+        assert!(self.is_in_whitelisted_tokens(token_id), "{}", errors::TOKEN_NOT_WHITELISTED);
+        assert!(self.is_in_whitelisted_rafts(raft_id), "{}", errors::RAFT_NOT_WHITELISTED);
+        self.assert_token_state(token_id, AssetState::is_mintable);
+        self.assert_raft_state(raft_id, AssetState::is_mintable);
+
+        assert!(token_amount > 0, "{}", errors::NO_ATTACHED_DEPOSIT);
+        assert!(raft_amount > 0, "{}", errors::SYNTHETIC_AMOUNT_ERROR);
+    }
+
+    /// Only registered accounts may move whitelisted rafts in or out of the account book.
+    fn assert_accountbook_params(&self, sender_id: &AccountId, raft_id: &AccountId) {
+        assert!(self.internal_get_account(sender_id).is_some(), "{}", errors::ACC_NOT_REGISTERED);
+        assert!(self.is_in_whitelisted_rafts(raft_id), "{}", errors::RAFT_NOT_WHITELISTED);
+        self.assert_raft_state(raft_id, AssetState::is_withdrawable);
+    }
+
+    /// Burns the rafts of the sender, credited to its account book once burnt.
+    pub(crate) fn internal_deposit_in_accountbook(&mut self, sender_id: &AccountId, raft_id: AccountId,
+                                                  amount: Balance) -> Promise {
+        assert!(amount > 0, "{}", errors::SYNTHETIC_AMOUNT_ERROR);
+        self.assert_accountbook_params(sender_id, &raft_id);
+        self.operation_locks.lock(sender_id);
+        let raft_amount = self.account_book.query_raft_amount(&raft_id);
+        let user_raft_amount = self.account_book.query_user_raft_amount(sender_id, &raft_id);
+
+        ext_enhanced_fungible_token::burn(
+            sender_id.clone(),
+            U128(amount),
+            raft_id.clone(),
+            utils::ONE_YOCTO,
+            self.gas_config.burn,
+        ).then(ext_self::account_book_callback_deposit(
+            sender_id.clone(),
+            raft_id.clone(),
+            U128(amount),
+            U128(raft_amount),
+            U128(user_raft_amount),
+            env::current_account_id(),
+            utils::NO_DEPOSIT,
+            self.gas_config.resolve,
+        ))
+    }
+
+    /// Supply of the raft counted against its max supply: the amounts held in the account book,
+    /// the net long exposure of the debt pool and the tokens minted out.
+    fn calc_raft_supply(&self, raft_id: &AccountId) -> Balance {
+        let debtpool_amount = self.debt_pool.query_raft_amount(raft_id);
+        let debtpool_amount = if debtpool_amount.is_positive { debtpool_amount.amount } else { 0 };
+
+        self.account_book.query_raft_amount(raft_id) + debtpool_amount + self.account_book.query_circulating(raft_id)
+    }
+
+    /// Amount of the raft that can still be minted, `None` if the raft has no max supply.
+    fn calc_remaining_supply(&self, raft_id: &AccountId) -> Option<Balance> {
+        let max_supply = self.query_raft(raft_id).expect(errors::ASSET_NOT_FOUND).max_supply;
+        if max_supply == 0 {
+            return None;
+        }
+
+        Some(max_supply.saturating_sub(self.calc_raft_supply(raft_id)))
+    }
+
+    fn assert_max_supply(&self, raft_id: &AccountId, raft_amount: Balance) {
+        if let Some(remaining) = self.calc_remaining_supply(raft_id) {
+            assert!(raft_amount <= remaining, "{}", errors::MAX_SUPPLY);
+        }
     }
 
     fn query_collateral(&self, collateral_id: CollateralId) -> Option<Collateral> {
         self.collaterals.get(collateral_id)
     }
 
+    /// Ratio in percent between the value of the collateral tokens and the value of the minted rafts.
+    fn calc_collateral_ratio(&self, token_id: &AccountId, token_amount: Balance,
+                             raft_id: &AccountId, raft_amount: Balance) -> u128 {
+        let valuation = self.valuation();
+        math::ratio(&[valuation.calc_value(token_id, token_amount), 100],
+                    &[valuation.calc_value(raft_id, raft_amount)])
+    }
+
+    /// Price of the collateral token, in oracle precision, at which the collateral ratio
+    /// falls to the liquidation threshold of the pair. Rounded up.
+    fn calc_liquidation_price(&self, collateral: &Collateral) -> u128 {
+        let token_asset = self.query_token(&collateral.token_id).expect(errors::ASSET_NOT_FOUND);
+        let raft_decimals = self.query_raft(&collateral.raft_id).expect(errors::ASSET_NOT_FOUND).decimals;
+
+        let liquidation_threshold = self.query_liquidation_threshold(&collateral.token_id, &collateral.raft_id);
+        math::ratio_ceil(
+            &[liquidation_threshold, self.price_oracle.get_price(&collateral.raft_id),
+              collateral.debt(), math::pow10(token_asset.decimals)],
+            &[collateral.token_amount, math::pow10(raft_decimals), 100],
+        )
+    }
+
+    /// Accrues the stability fee of an account book collateral, at the rate of the risk class of its token.
+    fn internal_accrue_stability_fee(&self, collateral: &mut Collateral) {
+        if collateral.join_debtpool || collateral.state != CollateralState::Open { return; }
+        let risk_class = self.query_token(&collateral.token_id).and_then(|asset| asset.risk_class);
+        let rate = match self.interest_mode {
+            fees::InterestMode::PerAnnum => self.query_stability_fee(risk_class),
+            fees::InterestMode::Flat => 0,
+        };
+        collateral.accrue_stability_fee(rate);
+    }
+
+    /// Collateral with its stability fee accrued up to now, without saving it.
+    fn query_accrued_collateral(&self, collateral_id: CollateralId) -> Option<Collateral> {
+        let mut collateral = self.query_collateral(collateral_id)?;
+        self.internal_accrue_stability_fee(&mut collateral);
+        Some(collateral)
+    }
+
+    /// Stores a new collateral and adds its id to the issuer's collateral list.
+    /// Account book collaterals are minted a position token.
+    fn internal_add_collateral(&mut self, collateral: &Collateral) -> CollateralId {
+        self.assert_no_pending_redemption(&collateral.issuer);
+        self.check_margin_capacity(&collateral.issuer).unwrap_or_else(|error| error.panic());
+        let collateral_id = self.collaterals.push(collateral);
+        self.internal_push_user_collateral(&collateral.issuer, collateral_id, collateral);
+        self.internal_count_open_collateral(&collateral.token_id, true);
+        self.internal_count_open_collateral(&collateral.raft_id, true);
+
+        if !collateral.join_debtpool {
+            NftEvent::Mint([NftMintData {
+                owner_id: &collateral.issuer,
+                token_ids: [collateral_id.to_string()],
+            }]).emit();
+        }
+        collateral_id
+    }
+
+    /// Marks the collateral as closed and removes its id from the issuer's collateral list,
+    /// burning its position token.
+    fn internal_close_collateral(&mut self, collateral_id: CollateralId, mut collateral: Collateral) {
+        collateral.state = CollateralState::Closed;
+        self.collaterals.replace(collateral_id, &collateral);
+        self.internal_remove_user_collateral(&collateral.issuer, collateral_id, &collateral);
+        self.internal_count_open_collateral(&collateral.token_id, false);
+        self.internal_count_open_collateral(&collateral.raft_id, false);
+        self.internal_remove_collateral_label(&collateral.issuer, collateral_id);
+
+        if !collateral.join_debtpool {
+            NftEvent::Burn([NftMintData {
+                owner_id: &collateral.issuer,
+                token_ids: [collateral_id.to_string()],
+            }]).emit();
+        }
+    }
+
+    /// Adds the id to the user's collateral list and locks the collateral tokens of the user.
+    fn internal_push_user_collateral(&mut self, account_id: &AccountId, collateral_id: CollateralId,
+                                     collateral: &Collateral) {
+        let mut collateral_ids = self.user_collaterals.get(account_id).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::UserCollateralIds { account_id: account_id.clone() })
+        });
+        collateral_ids.insert(&collateral_id);
+        self.user_collaterals.insert(account_id, &collateral_ids);
+        self.internal_lock_collateral(account_id, &collateral.token_id, collateral.token_amount);
+    }
+
+    /// Drops the list once empty to free its storage.
+    fn internal_remove_user_collateral(&mut self, account_id: &AccountId, collateral_id: CollateralId,
+                                       collateral: &Collateral) {
+        if let Some(mut collateral_ids) = self.user_collaterals.get(account_id) {
+            if collateral_ids.remove(&collateral_id) {
+                if collateral_ids.is_empty() {
+                    self.user_collaterals.remove(account_id);
+                } else {
+                    self.user_collaterals.insert(account_id, &collateral_ids);
+                }
+                self.internal_unlock_collateral(account_id, &collateral.token_id, collateral.token_amount);
+            }
+        }
+    }
+
+    pub(crate) fn internal_lock_collateral(&mut self, account_id: &AccountId, token_id: &AccountId, amount: Balance) {
+        let key = (account_id.clone(), token_id.clone());
+        let locked = self.locked_collaterals.get(&key).unwrap_or(0);
+        self.locked_collaterals.insert(&key, &(locked + amount));
+    }
+
+    pub(crate) fn internal_unlock_collateral(&mut self, account_id: &AccountId, token_id: &AccountId, amount: Balance) {
+        let key = (account_id.clone(), token_id.clone());
+        let locked = self.locked_collaterals.get(&key).unwrap_or(0) - amount;
+        if locked == 0 {
+            self.locked_collaterals.remove(&key);
+        } else {
+            self.locked_collaterals.insert(&key, &locked);
+        }
+    }
+
+    pub(crate) fn query_open_collateral_count(&self, asset_id: &AccountId) -> u64 {
+        self.open_collateral_counts.get(asset_id).unwrap_or(0)
+    }
+
+    /// Counts one more, or one less once closed, open collateral using the asset.
+    pub(crate) fn internal_count_open_collateral(&mut self, asset_id: &AccountId, opened: bool) {
+        let count = self.query_open_collateral_count(asset_id);
+        let count = if opened { count + 1 } else { count - 1 };
+        if count == 0 {
+            self.open_collateral_counts.remove(asset_id);
+        } else {
+            self.open_collateral_counts.insert(asset_id, &count);
+        }
+    }
+
     fn assert_query_authority(&self, user: AccountId) {
-        if self.owner_id == env::predecessor_account_id() {
+        let viewer_id = env::predecessor_account_id();
+        if self.owner_id == viewer_id || self.view_access.is_granted(&user, &viewer_id) {
             return;
         }
 
-        assert_eq!(user, env::predecessor_account_id(), "{}", errors::NO_PERMISSION);
+        assert_eq!(user, viewer_id, "{}", errors::NO_PERMISSION);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+    const YEAR: u64 = utils::SECONDS_PER_YEAR * SECOND;
+
+    fn collateral(raft_amount: Balance) -> Collateral {
+        Collateral {
+            issuer: accounts(1),
+            token_id: accounts(2),
+            token_amount: 0,
+            raft_id: accounts(3),
+            raft_amount,
+            join_debtpool: false,
+            block_index: 0,
+            create_time: 0,
+            state: CollateralState::Open,
+            stability_fee: 0,
+            fee_accrued_at: 0,
+        }
+    }
+
+    fn set_timestamp(timestamp: u64) {
+        testing_env!(VMContextBuilder::new().block_timestamp(timestamp).build());
+    }
+
+    #[test]
+    fn test_stability_fee_per_annum() {
+        // 10% per annum
+        let mut collateral = collateral(1_000_000);
+        set_timestamp(YEAR / 2);
+        collateral.accrue_stability_fee(100);
+        assert_eq!(collateral.stability_fee, 50_000);
+        assert_eq!(collateral.fee_accrued_at, YEAR / 2);
+
+        set_timestamp(YEAR);
+        collateral.accrue_stability_fee(100);
+        assert_eq!(collateral.stability_fee, 100_000);
+        // the fee is not compounded
+        assert_eq!(collateral.debt(), 1_100_000);
+    }
+
+    #[test]
+    fn test_stability_fee_over_years() {
+        let mut collateral = collateral(1_000_000);
+        set_timestamp(5 * YEAR / 2);
+        collateral.accrue_stability_fee(100);
+        assert_eq!(collateral.stability_fee, 250_000);
+    }
+
+    #[test]
+    fn test_stability_fee_rounding() {
+        // rounded down, the elapsed nanoseconds below a second don't accrue
+        let mut collateral = collateral(1_000_000);
+        set_timestamp(SECOND - 1);
+        collateral.accrue_stability_fee(100);
+        assert_eq!(collateral.stability_fee, 0);
+
+        set_timestamp(SECOND - 1 + YEAR / 1_000);
+        collateral.accrue_stability_fee(1);
+        assert_eq!(collateral.stability_fee, 1);
+    }
+
+    #[test]
+    fn test_stability_fee_without_time() {
+        let mut collateral = collateral(1_000_000);
+        collateral.fee_accrued_at = YEAR;
+        // a timestamp before the last accrual accrues nothing
+        set_timestamp(YEAR / 2);
+        collateral.accrue_stability_fee(100);
+        assert_eq!(collateral.stability_fee, 0);
+
+        set_timestamp(2 * YEAR);
+        collateral.accrue_stability_fee(0);
+        assert_eq!(collateral.stability_fee, 0);
+        assert_eq!(collateral.fee_accrued_at, 2 * YEAR);
     }
 }
@@ -0,0 +1,52 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::U64;
+use near_sdk::{env, AccountId, Timestamp};
+
+use crate::*;
+
+/// Accounts with an operation waiting for its callback. Other operations of such an account
+/// which move its balances are blocked until the callback ran, or the lock expired
+/// in case the callback failed before releasing it.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct OperationLocks {
+    /// Mapping from account to the timestamp its lock was taken at.
+    locks: LookupMap<AccountId, Timestamp>,
+}
+
+impl OperationLocks {
+    pub(crate) fn new() -> Self {
+        Self {
+            locks: LookupMap::new(StorageKey::OperationLocks),
+        }
+    }
+
+    /// Timestamp the lock of the account was taken at, `None` if it isn't locked or the lock expired.
+    pub(crate) fn query_lock(&self, account_id: &AccountId) -> Option<Timestamp> {
+        self.locks.get(account_id)
+            .filter(|locked_at| env::block_timestamp() < locked_at + utils::OPERATION_LOCK_TIMEOUT)
+    }
+
+    /// Panics if the account has a pending operation.
+    pub(crate) fn assert_unlocked(&self, account_id: &AccountId) {
+        assert!(self.query_lock(account_id).is_none(), "{}", errors::OPERATION_PENDING);
+    }
+
+    /// Locks the account until `unlock`, panics if it has a pending operation.
+    pub(crate) fn lock(&mut self, account_id: &AccountId) {
+        self.assert_unlocked(account_id);
+        self.locks.insert(account_id, &env::block_timestamp());
+    }
+
+    pub(crate) fn unlock(&mut self, account_id: &AccountId) {
+        self.locks.remove(account_id);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Timestamp the pending operation of the account was dispatched at, `None` if it has none.
+    pub fn get_pending_operation(&self, account_id: AccountId) -> Option<U64> {
+        self.operation_locks.query_lock(&account_id).map(U64)
+    }
+}
@@ -0,0 +1,117 @@
+use near_sdk::{assert_one_yocto, env, AccountId};
+
+use crate::*;
+
+/// Health of the account book collaterals of an account taken as one portfolio.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MarginHealth {
+    pub cross_margin: bool,
+    /// Value of the collateral tokens, in USD with `VALUE_DECIMALS` decimals.
+    pub collateral_value: U128,
+    /// Value of the collateral tokens discounted by their liquidation thresholds.
+    pub liquidation_value: U128,
+    /// Value of the debt of the collaterals, stability fees included.
+    pub debt_value: U128,
+    /// Whether the liquidation value is below the debt value.
+    pub liquidatable: bool,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Switch the caller between isolated and cross margin. In cross margin the account book collaterals
+    /// of the caller are liquidatable only once their liquidation value taken together falls below their debt,
+    /// and then any of them can be liquidated. Leaving cross margin requires every collateral to be healthy alone.
+    /// An account in cross margin has at most `MAX_MARGIN_COLLATERALS` open collaterals.
+    #[payable]
+    pub fn set_cross_margin(&mut self, enabled: bool) {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let account_id = env::predecessor_account_id();
+        assert!(self.internal_get_account(&account_id).is_some(), "{}", errors::ACC_NOT_REGISTERED);
+        self.operation_locks.assert_unlocked(&account_id);
+
+        if enabled {
+            assert!(self.query_collateral_count(&account_id) <= utils::MAX_MARGIN_COLLATERALS,
+                    "{}", errors::TOO_MANY_MARGIN_COLLATERALS);
+            self.cross_margin_accounts.insert(&account_id);
+        } else {
+            for collateral in self.query_margin_collaterals(&account_id) {
+                assert!(!self.is_collateral_liquidatable(&collateral), "{}", errors::CROSS_MARGIN_UNSAFE);
+            }
+            self.cross_margin_accounts.remove(&account_id);
+        }
+        Event::SetCrossMargin { account_id: &account_id, enabled }.emit();
+    }
+
+    pub fn is_cross_margin(&self, account_id: AccountId) -> bool {
+        self.cross_margin_accounts.contains(&account_id)
+    }
+
+    pub fn get_margin_health(&self, account_id: AccountId) -> MarginHealth {
+        self.calc_margin_health(&account_id)
+    }
+}
+
+impl Contract {
+    fn query_collateral_count(&self, account_id: &AccountId) -> u64 {
+        self.user_collaterals.get(account_id).map_or(0, |collateral_ids| collateral_ids.len())
+    }
+
+    /// Fails if the account is in cross margin and can't open another collateral.
+    pub(crate) fn check_margin_capacity(&self, account_id: &AccountId) -> Result<(), errors::CraftingError> {
+        if self.cross_margin_accounts.contains(account_id)
+            && self.query_collateral_count(account_id) >= utils::MAX_MARGIN_COLLATERALS {
+            return Err(errors::CraftingError::TooManyMarginCollaterals);
+        }
+        Ok(())
+    }
+
+    /// Open account book collaterals of the account, with their stability fees accrued.
+    fn query_margin_collaterals(&self, account_id: &AccountId) -> Vec<Collateral> {
+        self.user_collaterals.get(account_id)
+            .map(|collateral_ids| collateral_ids.iter()
+                .filter_map(|collateral_id| self.query_accrued_collateral(collateral_id))
+                .filter(|collateral| collateral.state == CollateralState::Open && !collateral.join_debtpool)
+                .collect())
+            .unwrap_or_default()
+    }
+
+    fn is_collateral_liquidatable(&self, collateral: &Collateral) -> bool {
+        let collateral_ratio = self.calc_collateral_ratio(&collateral.token_id, collateral.token_amount,
+                                                          &collateral.raft_id, collateral.debt());
+        collateral_ratio < self.query_liquidation_threshold(&collateral.token_id, &collateral.raft_id)
+    }
+
+    pub(crate) fn calc_margin_health(&self, account_id: &AccountId) -> MarginHealth {
+        let valuation = self.valuation();
+        let (mut collateral_value, mut liquidation_value, mut debt_value) = (0, 0, 0);
+        for collateral in self.query_margin_collaterals(account_id) {
+            let value = valuation.calc_value(&collateral.token_id, collateral.token_amount);
+            let liquidation_threshold = self.query_liquidation_threshold(&collateral.token_id, &collateral.raft_id);
+            collateral_value += value;
+            liquidation_value += if liquidation_threshold == 0 { value } else {
+                math::ratio(&[value, 100], &[liquidation_threshold])
+            };
+            debt_value += valuation.calc_value(&collateral.raft_id, collateral.debt());
+        }
+        MarginHealth {
+            cross_margin: self.cross_margin_accounts.contains(account_id),
+            collateral_value: U128(collateral_value),
+            liquidation_value: U128(liquidation_value),
+            debt_value: U128(debt_value),
+            liquidatable: liquidation_value < debt_value,
+        }
+    }
+
+    /// Panics unless the accrued account book collateral can be liquidated, alone in isolated margin
+    /// or with the other collaterals of its issuer in cross margin.
+    pub(crate) fn assert_collateral_liquidatable(&self, collateral: &Collateral) {
+        let liquidatable = if self.cross_margin_accounts.contains(&collateral.issuer) {
+            self.calc_margin_health(&collateral.issuer).liquidatable
+        } else {
+            self.is_collateral_liquidatable(collateral)
+        };
+        assert!(liquidatable, "{}", errors::COLLATERAL_HEALTHY);
+    }
+}
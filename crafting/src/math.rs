@@ -0,0 +1,148 @@
+//! Checked arithmetic for valuations, fees and ratios, panicking on overflow or division by zero
+//! instead of wrapping in release builds.
+use crate::errors;
+
+pub(crate) use uint256::U256;
+
+// lints of the code generated by the macro
+#[allow(clippy::manual_div_ceil, clippy::assign_op_pattern)]
+mod uint256 {
+    uint::construct_uint! {
+        /// 256 bits integer for intermediate products.
+        pub struct U256(4);
+    }
+}
+
+/// `a * b`, panics on overflow.
+pub(crate) fn mul(a: u128, b: u128) -> u128 {
+    a.checked_mul(b).expect(errors::MATH_OVERFLOW)
+}
+
+/// `a * b / c` with a 256 bits intermediate product, panics if `c` is 0 or the result overflows.
+pub(crate) fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+    ratio(&[a, b], &[c])
+}
+
+/// Share of `amount` at `rate` out of `divisor`, e.g. a fee, rounded down.
+pub(crate) fn share(amount: u128, rate: u32, divisor: u32) -> u128 {
+    mul_div(amount, rate as u128, divisor as u128)
+}
+
+/// `10^exponent`, panics on overflow.
+pub(crate) fn pow10(exponent: u32) -> u128 {
+    10u128.checked_pow(exponent).expect(errors::MATH_OVERFLOW)
+}
+
+/// Product of `numerators` divided by product of `denominators`, with 256 bits intermediate products.
+/// Rounded down. Panics if a denominator is 0 or a product or the result overflows.
+pub(crate) fn ratio(numerators: &[u128], denominators: &[u128]) -> u128 {
+    div(numerators, denominators, false)
+}
+
+/// Same as `ratio`, rounded up.
+pub(crate) fn ratio_ceil(numerators: &[u128], denominators: &[u128]) -> u128 {
+    div(numerators, denominators, true)
+}
+
+/// Same as `ratio`, saturating at `u128::MAX` instead of panicking if the result overflows.
+pub(crate) fn ratio_saturating(numerators: &[u128], denominators: &[u128]) -> u128 {
+    std::cmp::min(quotient(numerators, denominators, false), U256::from(u128::MAX)).as_u128()
+}
+
+fn div(numerators: &[u128], denominators: &[u128], round_up: bool) -> u128 {
+    let result = quotient(numerators, denominators, round_up);
+    assert!(result <= U256::from(u128::MAX), "{}", errors::MATH_OVERFLOW);
+    result.as_u128()
+}
+
+fn quotient(numerators: &[u128], denominators: &[u128], round_up: bool) -> U256 {
+    let product = |factors: &[u128]| factors.iter().try_fold(U256::one(), |product, &factor| {
+        product.checked_mul(U256::from(factor))
+    }).expect(errors::MATH_OVERFLOW);
+    let denominator = product(denominators);
+    assert!(!denominator.is_zero(), "{}", errors::DIVISION_BY_ZERO);
+    let (mut result, remainder) = product(numerators).div_mod(denominator);
+    if round_up && !remainder.is_zero() {
+        result += U256::one();
+    }
+    result
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::utils;
+
+    /// Factors of `2^129 - 1`, which is `2 * u128::MAX + 1`.
+    const FACTORS: [u128; 2] = [(1 << 43) - 1, (1 << 86) + (1 << 43) + 1];
+
+    #[test]
+    fn test_ratio_rounding() {
+        assert_eq!(ratio(&[10, 3], &[4]), 7);
+        assert_eq!(ratio_ceil(&[10, 3], &[4]), 8);
+        // no remainder, nothing to round up
+        assert_eq!(ratio_ceil(&[10, 4], &[5]), 8);
+        assert_eq!(ratio_ceil(&[0], &[3]), 0);
+        assert_eq!(ratio(&[], &[]), 1);
+    }
+
+    #[test]
+    fn test_ratio_wide_product() {
+        // the product only fits in 256 bits, the result fits in 128
+        assert_eq!(ratio(&[u128::MAX, 4], &[8]), u128::MAX / 2);
+        assert_eq!(ratio_ceil(&[u128::MAX, 3], &[2, 3]), u128::MAX / 2 + 1);
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX), u128::MAX);
+        assert_eq!(ratio(&FACTORS, &[2]), u128::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR29: Math overflow")]
+    fn test_ratio_result_overflow() {
+        ratio(&[u128::MAX, 2], &[1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR29: Math overflow")]
+    fn test_ratio_ceil_result_overflow() {
+        // rounding up pushes the result past u128::MAX
+        ratio_ceil(&FACTORS, &[2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR29: Math overflow")]
+    fn test_ratio_product_overflow() {
+        ratio(&[u128::MAX, u128::MAX, u128::MAX], &[u128::MAX, u128::MAX]);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR30: Division by zero")]
+    fn test_ratio_division_by_zero() {
+        ratio(&[1], &[2, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR30: Division by zero")]
+    fn test_ratio_saturating_division_by_zero() {
+        ratio_saturating(&[1], &[0]);
+    }
+
+    #[test]
+    fn test_ratio_saturating() {
+        assert_eq!(ratio_saturating(&[10, 3], &[4]), 7);
+        assert_eq!(ratio_saturating(&[u128::MAX, 2], &[1]), u128::MAX);
+    }
+
+    #[test]
+    fn test_quotient() {
+        assert_eq!(quotient(&[u128::MAX, 2], &[1], false), U256::from(u128::MAX) * 2);
+        assert_eq!(quotient(&[7], &[2], false), U256::from(3));
+        assert_eq!(quotient(&[7], &[2], true), U256::from(4));
+    }
+
+    #[test]
+    fn test_share() {
+        assert_eq!(share(1_000, 3, utils::FEE_DIVISOR), 3);
+        assert_eq!(share(999, 3, utils::FEE_DIVISOR), 2);
+        assert_eq!(share(u128::MAX, utils::FEE_DIVISOR, utils::FEE_DIVISOR), u128::MAX);
+    }
+}
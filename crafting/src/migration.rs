@@ -0,0 +1,397 @@
+use std::collections::{HashMap, HashSet};
+
+use near_sdk::borsh::{self, BorshDeserialize};
+use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet, Vector};
+use near_sdk::{env, AccountId, Balance, BlockHeight, Timestamp};
+
+use crate::*;
+use crate::debtpool::WrappedBalance;
+
+/// Layout of the maps sharing the `b"r"` prefix, values are kept raw as they may belong to any of them.
+type LegacyMap = UnorderedMap<AccountId, Vec<u8>>;
+
+/// Listed asset before `AssetState` was introduced.
+#[derive(BorshDeserialize)]
+#[cfg_attr(test, derive(borsh::BorshSerialize))]
+struct AssetV0 {
+    name: String,
+    symbol: String,
+    standard: String,
+    decimals: u32,
+    address: AccountId,
+    feed_address: AccountId,
+    collateral_ratio: u128,
+    state: u8,
+}
+
+impl AssetV0 {
+    fn into_current(self) -> Asset {
+        Asset {
+            name: self.name,
+            symbol: self.symbol,
+            standard: self.standard,
+            decimals: self.decimals,
+            address: self.address,
+            feed_address: self.feed_address,
+            collateral_ratio: self.collateral_ratio,
+            liquidation_threshold: 0,
+            max_supply: 0,
+            risk_class: None,
+            // the old flag had no meaning yet, anything but 0 is kept frozen
+            state: if self.state == 0 { AssetState::Active } else { AssetState::FullyPaused },
+        }
+    }
+}
+
+/// Collateral before the stability fee was introduced.
+#[derive(BorshDeserialize)]
+#[cfg_attr(test, derive(borsh::BorshSerialize))]
+struct CollateralV0 {
+    issuer: AccountId,
+    token_id: AccountId,
+    token_amount: Balance,
+    raft_id: AccountId,
+    raft_amount: Balance,
+    join_debtpool: bool,
+    block_index: BlockHeight,
+    create_time: Timestamp,
+    state: CollateralState,
+}
+
+impl CollateralV0 {
+    fn into_current(self) -> Collateral {
+        Collateral {
+            issuer: self.issuer,
+            token_id: self.token_id,
+            token_amount: self.token_amount,
+            raft_id: self.raft_id,
+            raft_amount: self.raft_amount,
+            join_debtpool: self.join_debtpool,
+            block_index: self.block_index,
+            create_time: self.create_time,
+            state: self.state,
+            stability_fee: 0,
+            // the fee accrues from the migration
+            fee_accrued_at: env::block_timestamp(),
+        }
+    }
+}
+
+#[derive(BorshDeserialize)]
+#[cfg_attr(test, derive(borsh::BorshSerialize))]
+struct DebtPoolV0 {
+    raft_amounts: LegacyMap,
+    user_raft_amounts: LookupMap<(AccountId, AccountId), Balance>,
+    debt_ratios: HashMap<AccountId, u128>,
+}
+
+#[derive(BorshDeserialize)]
+#[cfg_attr(test, derive(borsh::BorshSerialize))]
+struct AccountBookV0 {
+    raft_amounts: LegacyMap,
+    /// Same prefix as the debt pool user raft amounts, read through the debt pool.
+    _user_raft_amounts: LookupMap<(AccountId, AccountId), Balance>,
+}
+
+#[derive(BorshDeserialize)]
+#[cfg_attr(test, derive(borsh::BorshSerialize))]
+struct PriceInfoV0 {
+    prices: LookupMap<AccountId, u128>,
+}
+
+/// Contract layout before the storage redesign.
+#[derive(BorshDeserialize)]
+#[cfg_attr(test, derive(borsh::BorshSerialize))]
+struct ContractV0 {
+    owner_id: AccountId,
+    state: RunningState,
+    leverage_ratio: (u8, u8),
+    interest_fee: u32,
+    exchange_fee: u32,
+    accounts: LookupMap<AccountId, VAccount>,
+    whitelisted_tokens: UnorderedSet<AccountId>,
+    token_list: LegacyMap,
+    whitelisted_rafts: UnorderedSet<AccountId>,
+    raft_list: LegacyMap,
+    collaterals: Vector<CollateralV0>,
+    /// Never written before the storage redesign, rebuilt from the collaterals.
+    _user_collaterals: LookupMap<AccountId, Vector<CollateralId>>,
+    debt_pool: DebtPoolV0,
+    account_book: AccountBookV0,
+    price_oracle: PriceInfoV0,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Moves the state written before the storage redesign under distinct prefixes.
+    /// The old collections shared prefixes and overwrote each other, so data is restored on a best effort basis:
+    /// - entries of the shared map are told apart by their value: assets with a collateral ratio are tokens,
+    ///   other assets are rafts, signed balances belong to the debt pool and plain balances to the account book;
+    /// - user raft amounts go to the debt pool for users holding a debt ratio, to the account book otherwise;
+    /// - only collateral issuers and the owner are added to the account registry,
+    ///   other accounts are added on their next action.
+    #[init(ignore_state)]
+    #[private]
+    pub fn migrate() -> Self {
+        let mut old: ContractV0 = env::state_read().expect(errors::CONTRACT_NOT_INITIALIZED);
+
+        let mut contract = Self::new(old.owner_id.clone());
+        contract.state = old.state.clone();
+        contract.leverage_ratio = old.leverage_ratio;
+        contract.interest_fee = old.interest_fee;
+        contract.exchange_fee = old.exchange_fee;
+        contract.accounts = old.accounts;
+
+        // shared map, the longest view covers every slot written
+        let shared = [
+            &mut old.token_list,
+            &mut old.raft_list,
+            &mut old.debt_pool.raft_amounts,
+            &mut old.account_book.raft_amounts,
+        ].into_iter().max_by_key(|map| map.len()).unwrap();
+        let keys = shared.keys_as_vector();
+        let values = shared.values_as_vector();
+        for index in 0..keys.len() {
+            let key = keys.get(index).unwrap();
+            let raw_value = values.get_raw(index).unwrap();
+            if let Ok(asset) = AssetV0::try_from_slice(&raw_value) {
+                if asset.collateral_ratio > 0 {
+                    contract.token_list.insert(&key, &asset.into_current());
+                } else {
+                    contract.raft_list.insert(&key, &asset.into_current());
+                }
+            } else if let Ok(amount) = WrappedBalance::try_from_slice(&raw_value) {
+                contract.debt_pool.insert_raft_amount(&key, &amount);
+            } else if let Ok(amount) = Balance::try_from_slice(&raw_value) {
+                contract.account_book.insert_raft_amount(&key, amount);
+            } else {
+                env::log_str(format!("Migration skipped unknown entry of {}", key).as_str());
+            }
+        }
+        shared.clear();
+        // rUSD used to be found by its symbol
+        contract.rusd_id = contract.raft_list.iter()
+            .find(|(_, asset)| asset.symbol == "rUSD")
+            .map(|(raft_id, _)| raft_id);
+
+        // shared whitelist
+        let whitelist = [&mut old.whitelisted_tokens, &mut old.whitelisted_rafts]
+            .into_iter().max_by_key(|set| set.len()).unwrap();
+        for asset_id in whitelist.iter() {
+            if contract.token_list.get(&asset_id).is_some() {
+                contract.whitelisted_tokens.insert(&asset_id);
+            } else if contract.raft_list.get(&asset_id).is_some() {
+                contract.whitelisted_rafts.insert(&asset_id);
+            } else {
+                env::log_str(format!("Migration skipped unlisted whitelist entry {}", asset_id).as_str());
+            }
+        }
+        whitelist.clear();
+
+        // collaterals keep their ids
+        let mut users: HashSet<AccountId> = HashSet::new();
+        users.insert(contract.owner_id.clone());
+        for collateral in old.collaterals.iter().map(CollateralV0::into_current) {
+            users.insert(collateral.issuer.clone());
+            if collateral.state == CollateralState::Open {
+                contract.internal_add_collateral(&collateral);
+            } else {
+                contract.collaterals.push(&collateral);
+            }
+        }
+        old.collaterals.clear();
+
+        for (user, debt_ratio) in old.debt_pool.debt_ratios.into_iter() {
+            contract.debt_pool.insert_debt_ratio(user, debt_ratio);
+        }
+
+        let raft_ids: Vec<AccountId> = contract.raft_list.keys().collect();
+        for user in users.iter() {
+            for raft_id in raft_ids.iter() {
+                let key = (user.clone(), raft_id.clone());
+                if let Some(amount) = old.debt_pool.user_raft_amounts.remove(&key) {
+                    if contract.debt_pool.query_debt_ratio(user) > 0 {
+                        let valuation = oracle::Valuation::new(&contract.price_oracle, &contract.token_list,
+                                                               &contract.raft_list);
+                        contract.debt_pool.insert_user_raft_amount(&valuation, user, raft_id, amount);
+                    } else {
+                        contract.account_book.insert_user_raft_amount(user, raft_id, amount);
+                    }
+                }
+            }
+            if contract.accounts.contains_key(user) {
+                contract.account_ids.insert(user);
+            }
+        }
+
+        let asset_ids: Vec<AccountId> = contract.token_list.keys().chain(raft_ids).collect();
+        for asset_id in asset_ids.iter() {
+            if let Some(price) = old.price_oracle.prices.remove(asset_id) {
+                contract.price_oracle.feed_price(asset_id, price, env::block_timestamp());
+            }
+        }
+
+        env::log_str(format!("Migrated {} tokens, {} rafts, {} collaterals",
+                             contract.token_list.len(), contract.raft_list.len(),
+                             contract.collaterals.len()).as_str());
+        contract
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::borsh::BorshSerialize;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::account::Account;
+
+    /// Value stored as is, like the typed collections sharing the prefix wrote it.
+    struct Raw(Vec<u8>);
+
+    impl BorshSerialize for Raw {
+        fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+            writer.write_all(&self.0)
+        }
+    }
+
+    impl BorshDeserialize for Raw {
+        fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+            let raw = buf.to_vec();
+            *buf = &[];
+            Ok(Raw(raw))
+        }
+    }
+
+    /// Another view of the same collection, as the old fields sharing a prefix were.
+    fn view<T: BorshSerialize + BorshDeserialize>(collection: &T) -> T {
+        T::try_from_slice(&collection.try_to_vec().unwrap()).unwrap()
+    }
+
+    fn asset(address: AccountId, symbol: &str, collateral_ratio: u128) -> AssetV0 {
+        AssetV0 {
+            name: symbol.to_string(),
+            symbol: symbol.to_string(),
+            standard: "nep-141".to_string(),
+            decimals: 24,
+            address: address.clone(),
+            feed_address: address,
+            collateral_ratio,
+            state: 0,
+        }
+    }
+
+    fn collateral(issuer: AccountId, raft_id: &str, join_debtpool: bool, state: CollateralState) -> CollateralV0 {
+        CollateralV0 {
+            issuer,
+            token_id: "wnear.near".parse().unwrap(),
+            token_amount: 1_000,
+            raft_id: raft_id.parse().unwrap(),
+            raft_amount: 100,
+            join_debtpool,
+            block_index: 1,
+            create_time: 1,
+            state,
+        }
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut context = VMContextBuilder::new();
+        context.current_account_id(accounts(0)).predecessor_account_id(accounts(0)).block_timestamp(42);
+        testing_env!(context.build());
+        let wnear_id: AccountId = "wnear.near".parse().unwrap();
+        let rusd_id: AccountId = "rusd.near".parse().unwrap();
+        let rbtc_id: AccountId = "rbtc.near".parse().unwrap();
+        let (owner, alice, bob) = (accounts(1), accounts(2), accounts(3));
+
+        let mut shared: UnorderedMap<AccountId, Raw> = UnorderedMap::new(b"r".to_vec());
+        for asset in [asset(wnear_id.clone(), "wNEAR", 150), asset(rusd_id.clone(), "rUSD", 0),
+                      asset(rbtc_id.clone(), "rBTC", 0)] {
+            shared.insert(&asset.address.clone(), &Raw(asset.try_to_vec().unwrap()));
+        }
+        let mut whitelist: UnorderedSet<AccountId> = UnorderedSet::new(StorageKey::Whitelist);
+        for asset_id in [&wnear_id, &rusd_id, &rbtc_id] {
+            whitelist.insert(asset_id);
+        }
+        let mut accounts_map: LookupMap<AccountId, VAccount> = LookupMap::new(StorageKey::Accounts);
+        for account_id in [&alice, &bob] {
+            accounts_map.insert(account_id, &VAccount::Current(Account::new(account_id)));
+        }
+        let mut collaterals: Vector<CollateralV0> = Vector::new(b"r".to_vec());
+        collaterals.push(&collateral(alice.clone(), "rbtc.near", true, CollateralState::Open));
+        collaterals.push(&collateral(bob.clone(), "rusd.near", false, CollateralState::Closed));
+        collaterals.push(&collateral(bob.clone(), "rusd.near", false, CollateralState::Open));
+        let mut user_raft_amounts: LookupMap<(AccountId, AccountId), Balance> = LookupMap::new(b"r".to_vec());
+        user_raft_amounts.insert(&(alice.clone(), rbtc_id.clone()), &100);
+        user_raft_amounts.insert(&(bob.clone(), rusd_id.clone()), &100);
+        let mut prices: LookupMap<AccountId, u128> = LookupMap::new(b"r".to_vec());
+        for asset_id in [&wnear_id, &rusd_id, &rbtc_id] {
+            prices.insert(asset_id, &1_000_000);
+        }
+
+        let shared: LegacyMap = LegacyMap::try_from_slice(&shared.try_to_vec().unwrap()).unwrap();
+        env::state_write(&ContractV0 {
+            owner_id: owner.clone(),
+            state: RunningState::Running,
+            leverage_ratio: (1, 10),
+            interest_fee: 5,
+            exchange_fee: 3,
+            accounts: accounts_map,
+            whitelisted_tokens: view(&whitelist),
+            token_list: view(&shared),
+            whitelisted_rafts: whitelist,
+            raft_list: view(&shared),
+            collaterals,
+            _user_collaterals: LookupMap::new(b"r".to_vec()),
+            debt_pool: DebtPoolV0 {
+                raft_amounts: view(&shared),
+                user_raft_amounts: view(&user_raft_amounts),
+                debt_ratios: HashMap::from([(alice.clone(), utils::RATIO_DIVISOR)]),
+            },
+            account_book: AccountBookV0 {
+                raft_amounts: shared,
+                _user_raft_amounts: user_raft_amounts,
+            },
+            price_oracle: PriceInfoV0 { prices },
+        });
+
+        let contract = Contract::migrate();
+        assert_eq!(contract.owner_id, owner);
+        assert_eq!(contract.interest_fee, 5);
+
+        // assets are told apart by their collateral ratio, rUSD by its symbol
+        assert!(contract.token_list.get(&wnear_id).is_some());
+        assert!(contract.raft_list.get(&rusd_id).is_some());
+        assert!(contract.raft_list.get(&rbtc_id).is_some());
+        assert!(contract.whitelisted_tokens.contains(&wnear_id));
+        assert!(contract.whitelisted_rafts.contains(&rbtc_id));
+        assert_eq!(contract.rusd_id, Some(rusd_id.clone()));
+
+        // registered issuers are in the registry, the unregistered owner only keeps its ownership
+        assert!(contract.accounts.get(&alice).is_some());
+        assert!(contract.account_ids.contains(&alice));
+        assert!(contract.account_ids.contains(&bob));
+        assert!(!contract.account_ids.contains(&owner));
+
+        // collaterals keep their ids, only open ones are in the lists of their issuers
+        assert_eq!(contract.collaterals.len(), 3);
+        let closed = contract.query_collateral(1).unwrap();
+        assert_eq!(closed.issuer, bob);
+        assert!(closed.state == CollateralState::Closed);
+        let open = contract.query_collateral(2).unwrap();
+        assert!(open.state == CollateralState::Open);
+        assert_eq!(open.fee_accrued_at, 42);
+        let bob_ids: Vec<CollateralId> = contract.user_collaterals.get(&bob).unwrap().to_vec();
+        assert_eq!(bob_ids, vec![2]);
+        let alice_ids: Vec<CollateralId> = contract.user_collaterals.get(&alice).unwrap().to_vec();
+        assert_eq!(alice_ids, vec![0]);
+
+        // user raft amounts go to the debt pool for holders of a debt ratio
+        assert_eq!(contract.debt_pool.query_debt_ratio(&alice), utils::RATIO_DIVISOR);
+        assert_eq!(contract.debt_pool.query_user_raft_amount(&alice, &rbtc_id), 100);
+        assert_eq!(contract.account_book.query_user_raft_amount(&bob, &rusd_id), 100);
+        assert_eq!(contract.debt_pool.query_user_raft_amount(&bob, &rusd_id), 0);
+    }
+}
@@ -0,0 +1,307 @@
+use near_sdk::{assert_one_yocto, env, serde_json, AccountId, Balance, Promise, PromiseOrValue, PromiseResult};
+
+use crate::*;
+
+pub type MtCollateralId = u64;
+
+/// Token of a multi-token (NEP-245) contract, e.g. the shares of a Ref Finance pool.
+pub type MtTokenKey = (AccountId, String);
+
+/// Listed multi-token, valued with the price fed for `(contract_id, token_id)`.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MtAsset {
+    pub(crate) contract_id: AccountId,
+    pub(crate) token_id: String,
+    pub(crate) decimals: u32,
+    pub(crate) feed_address: AccountId,
+    #[serde(with = "u128_dec_format")]
+    pub(crate) collateral_ratio: u128,
+    pub(crate) state: AssetState,
+}
+
+/// Multi-token amount backing rafts minted in the account book.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MtCollateral {
+    pub(crate) issuer: AccountId,
+    pub(crate) contract_id: AccountId,
+    pub(crate) token_id: String,
+    #[serde(with = "u128_dec_format")]
+    pub(crate) token_amount: Balance,
+    pub(crate) raft_id: AccountId,
+    #[serde(with = "u128_dec_format")]
+    pub(crate) raft_amount: Balance,
+    #[serde(with = "u64_dec_format")]
+    pub(crate) block_index: BlockHeight,
+    #[serde(with = "u64_dec_format")]
+    pub(crate) create_time: Timestamp,
+    pub(crate) state: CollateralState,
+}
+
+/// Passed as `msg` of `mt_transfer_call`.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct MtMintMessage {
+    raft_id: AccountId,
+    raft_amount: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// NEP-245 callback on receiving multi-tokens, a single listed token is locked to mint rafts in the account book.
+    pub fn mt_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_ids: Vec<AccountId>,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+        msg: String,
+    ) -> PromiseOrValue<Vec<U128>> {
+        self.assert_contract_running();
+        assert!(token_ids.len() == 1 && amounts.len() == 1 && previous_owner_ids.len() == 1,
+                "{}", errors::ILLEGAL_TRANSFER_MSG);
+        // only the owner can lock the tokens, not an approved account
+        assert_eq!(sender_id, previous_owner_ids[0], "{}", errors::NO_PERMISSION);
+        self.assert_not_blacklisted(&sender_id);
+
+        let message: MtMintMessage = serde_json::from_str(&msg).expect(errors::ILLEGAL_TRANSFER_MSG);
+        let raft_amount: Balance = message.raft_amount.into();
+        let token_amount: Balance = amounts[0].into();
+        let key = (env::predecessor_account_id(), token_ids[0].clone());
+
+        let asset = self.query_mt(&key).expect(errors::ASSET_NOT_FOUND);
+        assert!(asset.state.is_mintable(), "{}", errors::ASSET_PAUSED);
+        assert!(self.is_in_whitelisted_rafts(&message.raft_id), "{}", errors::RAFT_NOT_WHITELISTED);
+        self.assert_raft_state(&message.raft_id, AssetState::is_mintable);
+        assert!(token_amount > 0, "{}", errors::NO_ATTACHED_DEPOSIT);
+        assert!(raft_amount > 0, "{}", errors::SYNTHETIC_AMOUNT_ERROR);
+
+        let collateral_ratio = self.calc_mt_collateral_ratio(&asset, token_amount, &message.raft_id, raft_amount);
+        assert!(collateral_ratio >= asset.collateral_ratio, "{}", errors::COLLATERAL_RATIO_TOO_LOW);
+        self.assert_max_supply(&message.raft_id, raft_amount);
+        self.assert_borrow_limit(&sender_id, &message.raft_id, raft_amount);
+
+        self.account_book.mint(&sender_id, &message.raft_id, raft_amount);
+
+        let (contract_id, token_id) = key;
+        let collateral = MtCollateral {
+            issuer: sender_id.clone(),
+            contract_id,
+            token_id,
+            token_amount,
+            raft_id: message.raft_id,
+            raft_amount,
+            block_index: env::block_height(),
+            create_time: env::block_timestamp(),
+            state: CollateralState::Open,
+        };
+        let collateral_id = self.internal_add_mt_collateral(&collateral);
+
+        Event::MtMint {
+            account_id: &sender_id,
+            collateral_id,
+            contract_id: &collateral.contract_id,
+            token_id: &collateral.token_id,
+            token_amount: U128(token_amount),
+            raft_id: &collateral.raft_id,
+            raft_amount: U128(raft_amount),
+        }.emit();
+        self.internal_record_operation(OperationKind::MtMint, &sender_id,
+                                       vec![collateral.contract_id.clone(), collateral.raft_id.clone()],
+                                       vec![token_amount, raft_amount]);
+        self.internal_bump_nonce(&sender_id);
+
+        PromiseOrValue::Value(vec![U128(0)])
+    }
+
+    /// Repays the rafts of a multi-token collateral from the caller's account book, plus the interest fee,
+    /// and sends the tokens back. Resolves to the new operation nonce of the account.
+    #[payable]
+    pub fn redeem_mt_collateral(&mut self, collateral_id: MtCollateralId) -> Promise {
+        assert_one_yocto();
+        self.assert_contract_running();
+
+        let sender_id = env::predecessor_account_id();
+        let collateral = self.query_mt_collateral(collateral_id).expect(errors::COLLATERAL_NOT_FOUND);
+        assert_eq!(collateral.issuer, sender_id, "{}", errors::NO_PERMISSION);
+        assert!(collateral.state == CollateralState::Open, "{}", errors::COLLATERAL_CLOSED);
+        self.assert_raft_state(&collateral.raft_id, AssetState::is_withdrawable);
+
+        // charge interest fee
+        let interest_fee_amount = math::share(collateral.raft_amount, self.interest_fee, utils::FEE_DIVISOR);
+        self.account_book.burn(&sender_id, &collateral.raft_id, collateral.raft_amount + interest_fee_amount);
+        self.internal_accrue_fee(&collateral.raft_id, interest_fee_amount);
+
+        self.internal_close_mt_collateral(collateral_id, collateral.clone());
+
+        Event::MtRedeem {
+            account_id: &sender_id,
+            collateral_id,
+            contract_id: &collateral.contract_id,
+            token_id: &collateral.token_id,
+            token_amount: U128(collateral.token_amount),
+        }.emit();
+        self.internal_record_operation(OperationKind::MtRedeem, &sender_id,
+                                       vec![collateral.contract_id.clone(), collateral.raft_id.clone()],
+                                       vec![collateral.token_amount, collateral.raft_amount]);
+
+        let promise = self.internal_send_mt(collateral_id, &collateral, &sender_id, interest_fee_amount);
+        self.internal_bump_nonce_then(&sender_id, promise)
+    }
+
+    /// Repays the rafts of a multi-token collateral under its liquidation threshold from the caller's account book,
+    /// and sends the tokens to the caller. Resolves to the new operation nonce of the caller.
+    #[payable]
+    pub fn liquidate_mt_collateral(&mut self, collateral_id: MtCollateralId) -> Promise {
+        assert_one_yocto();
+        self.assert_contract_running();
+
+        let liquidator_id = env::predecessor_account_id();
+        let collateral = self.query_mt_collateral(collateral_id).expect(errors::COLLATERAL_NOT_FOUND);
+        assert!(collateral.state == CollateralState::Open, "{}", errors::COLLATERAL_CLOSED);
+        self.assert_raft_state(&collateral.raft_id, AssetState::is_withdrawable);
+
+        let asset = self.query_mt(&(collateral.contract_id.clone(), collateral.token_id.clone()))
+            .expect(errors::ASSET_NOT_FOUND);
+        let collateral_ratio = self.calc_mt_collateral_ratio(&asset, collateral.token_amount,
+                                                             &collateral.raft_id, collateral.raft_amount);
+        assert!(collateral_ratio < asset.collateral_ratio, "{}", errors::COLLATERAL_HEALTHY);
+
+        self.account_book.burn(&liquidator_id, &collateral.raft_id, collateral.raft_amount);
+        self.internal_close_mt_collateral(collateral_id, collateral.clone());
+
+        Event::MtLiquidation {
+            liquidator_id: &liquidator_id,
+            account_id: &collateral.issuer,
+            collateral_id,
+            contract_id: &collateral.contract_id,
+            token_id: &collateral.token_id,
+            token_amount: U128(collateral.token_amount),
+            raft_id: &collateral.raft_id,
+            raft_amount: U128(collateral.raft_amount),
+        }.emit();
+        self.internal_record_operation(OperationKind::MtLiquidation, &liquidator_id,
+                                       vec![collateral.contract_id.clone(), collateral.raft_id.clone()],
+                                       vec![collateral.token_amount, collateral.raft_amount]);
+
+        let promise = self.internal_send_mt(collateral_id, &collateral, &liquidator_id, 0);
+        self.internal_bump_nonce_then(&liquidator_id, promise)
+    }
+
+    /// Reopens the collateral and gives the rafts back to the payer if the tokens couldn't be sent.
+    #[private]
+    pub fn mt_collateral_callback_send(&mut self, collateral_id: MtCollateralId, payer_id: AccountId,
+                                       fee_amount: U128) {
+        let fee_amount: Balance = fee_amount.into();
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => {}
+            PromiseResult::Failed => {
+                let mut collateral = self.query_mt_collateral(collateral_id).unwrap();
+                collateral.state = CollateralState::Open;
+                self.mt_collaterals.replace(collateral_id, &collateral);
+                self.internal_index_mt_collateral(collateral_id, &collateral);
+
+                // the fee is only given back if it wasn't claimed yet
+                let refunded_fee = self.fee_ledger.revert(&collateral.raft_id, fee_amount);
+                self.account_book.mint(&payer_id, &collateral.raft_id, collateral.raft_amount + refunded_fee);
+                env::log_str(format!("Multi-token collateral {} reopened, the transfer failed", collateral_id).as_str());
+            }
+        };
+    }
+}
+
+impl Contract {
+    pub(crate) fn query_mt(&self, key: &MtTokenKey) -> Option<MtAsset> {
+        self.mt_list.get(key)
+    }
+
+    pub(crate) fn query_mt_collateral(&self, collateral_id: MtCollateralId) -> Option<MtCollateral> {
+        self.mt_collaterals.get(collateral_id)
+    }
+
+    pub(crate) fn query_open_mt_collateral_count(&self, key: &MtTokenKey) -> u64 {
+        self.open_mt_collateral_counts.get(key).unwrap_or(0)
+    }
+
+    /// Counts one more, or one less once closed, open collateral of the multi-token and its raft.
+    fn internal_count_open_mt_collateral(&mut self, collateral: &MtCollateral, opened: bool) {
+        let key = (collateral.contract_id.clone(), collateral.token_id.clone());
+        let count = self.query_open_mt_collateral_count(&key);
+        let count = if opened { count + 1 } else { count - 1 };
+        if count == 0 {
+            self.open_mt_collateral_counts.remove(&key);
+        } else {
+            self.open_mt_collateral_counts.insert(&key, &count);
+        }
+        self.internal_count_open_collateral(&collateral.raft_id, opened);
+    }
+
+    /// Ratio in percent between the value of the multi-tokens and the value of the minted rafts.
+    fn calc_mt_collateral_ratio(&self, asset: &MtAsset, token_amount: Balance,
+                                raft_id: &AccountId, raft_amount: Balance) -> u128 {
+        let token_price = self.price_oracle.get_mt_price(&asset.contract_id, &asset.token_id);
+
+        math::ratio(&[oracle::calc_value(token_price, token_amount, asset.decimals), 100],
+                    &[self.valuation().calc_value(raft_id, raft_amount)])
+    }
+
+    fn internal_add_mt_collateral(&mut self, collateral: &MtCollateral) -> MtCollateralId {
+        let collateral_id = self.mt_collaterals.len();
+        self.mt_collaterals.push(collateral);
+        self.internal_index_mt_collateral(collateral_id, collateral);
+
+        collateral_id
+    }
+
+    /// Adds the id of an open collateral to the issuer's multi-token collateral list and counts it.
+    fn internal_index_mt_collateral(&mut self, collateral_id: MtCollateralId, collateral: &MtCollateral) {
+        let mut collateral_ids = self.user_mt_collaterals.get(&collateral.issuer).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::UserMtCollateralIds { account_id: collateral.issuer.clone() })
+        });
+        collateral_ids.insert(&collateral_id);
+        self.user_mt_collaterals.insert(&collateral.issuer, &collateral_ids);
+        self.internal_count_open_mt_collateral(collateral, true);
+    }
+
+    /// Marks the collateral as closed and removes its id from the issuer's multi-token collateral list.
+    fn internal_close_mt_collateral(&mut self, collateral_id: MtCollateralId, mut collateral: MtCollateral) {
+        collateral.state = CollateralState::Closed;
+        self.mt_collaterals.replace(collateral_id, &collateral);
+
+        if let Some(mut collateral_ids) = self.user_mt_collaterals.get(&collateral.issuer) {
+            if collateral_ids.remove(&collateral_id) {
+                self.user_mt_collaterals.insert(&collateral.issuer, &collateral_ids);
+                self.internal_count_open_mt_collateral(&collateral, false);
+            }
+        }
+    }
+
+    fn internal_send_mt(&self, collateral_id: MtCollateralId, collateral: &MtCollateral,
+                        receiver_id: &AccountId, fee_amount: Balance) -> Promise {
+        ext_multi_token::mt_transfer(
+            receiver_id.clone(),
+            collateral.token_id.clone(),
+            U128(collateral.token_amount),
+            None,
+            collateral.contract_id.clone(),
+            utils::ONE_YOCTO,
+            self.gas_config.ft_transfer,
+        ).then(ext_self::mt_collateral_callback_send(
+            collateral_id,
+            receiver_id.clone(),
+            U128(fee_amount),
+            env::current_account_id(),
+            utils::NO_DEPOSIT,
+            self.gas_config.resolve,
+        ))
+    }
+}
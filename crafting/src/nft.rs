@@ -0,0 +1,270 @@
+use near_contract_standards::non_fungible_token::core::NonFungibleTokenReceiver;
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::{assert_one_yocto, env, serde_json, AccountId, Balance, Promise, PromiseOrValue, PromiseResult};
+
+use crate::*;
+
+pub type NftCollateralId = u64;
+
+/// NFT of a listed collection backing rafts minted in the account book.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftCollateral {
+    pub(crate) issuer: AccountId,
+    pub(crate) collection_id: AccountId,
+    pub(crate) nft_token_id: TokenId,
+    pub(crate) raft_id: AccountId,
+    #[serde(with = "u128_dec_format")]
+    pub(crate) raft_amount: Balance,
+    #[serde(with = "u64_dec_format")]
+    pub(crate) block_index: BlockHeight,
+    #[serde(with = "u64_dec_format")]
+    pub(crate) create_time: Timestamp,
+    pub(crate) state: CollateralState,
+}
+
+/// Passed as `msg` of `nft_transfer_call`.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct NftMintMessage {
+    raft_id: AccountId,
+    raft_amount: U128,
+}
+
+#[near_bindgen]
+impl NonFungibleTokenReceiver for Contract {
+    /// Callback on receiving an NFT of a listed collection, which is locked to mint rafts in the account book.
+    /// The collateral ratio is valued with the floor price of the collection.
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: TokenId,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        self.assert_contract_running();
+        // only the owner can lock the NFT, not an approved account
+        assert_eq!(sender_id, previous_owner_id, "{}", errors::NO_PERMISSION);
+        self.assert_not_blacklisted(&sender_id);
+
+        let message: NftMintMessage = serde_json::from_str(&msg).expect(errors::ILLEGAL_TRANSFER_MSG);
+        let raft_amount: Balance = message.raft_amount.into();
+        let collection_id = env::predecessor_account_id();
+
+        let collection = self.query_nft_collection(&collection_id).expect(errors::ASSET_NOT_FOUND);
+        assert!(collection.state.is_mintable(), "{}", errors::ASSET_PAUSED);
+        assert!(self.is_in_whitelisted_rafts(&message.raft_id), "{}", errors::RAFT_NOT_WHITELISTED);
+        self.assert_raft_state(&message.raft_id, AssetState::is_mintable);
+        assert!(raft_amount > 0, "{}", errors::SYNTHETIC_AMOUNT_ERROR);
+
+        let collateral_ratio = self.calc_nft_collateral_ratio(&collection_id, &message.raft_id, raft_amount);
+        assert!(collateral_ratio >= collection.collateral_ratio, "{}", errors::COLLATERAL_RATIO_TOO_LOW);
+        self.assert_max_supply(&message.raft_id, raft_amount);
+        self.assert_borrow_limit(&previous_owner_id, &message.raft_id, raft_amount);
+
+        self.account_book.mint(&previous_owner_id, &message.raft_id, raft_amount);
+
+        let collateral = NftCollateral {
+            issuer: previous_owner_id.clone(),
+            collection_id,
+            nft_token_id: token_id,
+            raft_id: message.raft_id,
+            raft_amount,
+            block_index: env::block_height(),
+            create_time: env::block_timestamp(),
+            state: CollateralState::Open,
+        };
+        let collateral_id = self.internal_add_nft_collateral(&collateral);
+
+        Event::NftMint {
+            account_id: &collateral.issuer,
+            collateral_id,
+            collection_id: &collateral.collection_id,
+            nft_token_id: &collateral.nft_token_id,
+            raft_id: &collateral.raft_id,
+            raft_amount: U128(raft_amount),
+        }.emit();
+        self.internal_record_operation(OperationKind::NftMint, &collateral.issuer,
+                                       vec![collateral.collection_id.clone(), collateral.raft_id.clone()],
+                                       vec![1, raft_amount]);
+        self.internal_bump_nonce(&previous_owner_id);
+
+        PromiseOrValue::Value(false)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Repays the rafts of an NFT collateral from the caller's account book, plus the interest fee,
+    /// and sends the NFT back. Resolves to the new operation nonce of the account.
+    #[payable]
+    pub fn redeem_nft_collateral(&mut self, collateral_id: NftCollateralId) -> Promise {
+        assert_one_yocto();
+        self.assert_contract_running();
+
+        let sender_id = env::predecessor_account_id();
+        let collateral = self.query_nft_collateral(collateral_id).expect(errors::COLLATERAL_NOT_FOUND);
+        assert_eq!(collateral.issuer, sender_id, "{}", errors::NO_PERMISSION);
+        assert!(collateral.state == CollateralState::Open, "{}", errors::COLLATERAL_CLOSED);
+        self.assert_raft_state(&collateral.raft_id, AssetState::is_withdrawable);
+
+        // charge interest fee
+        let interest_fee = self.query_interest_fee(self.query_nft_collection(&collateral.collection_id)
+            .and_then(|asset| asset.risk_class));
+        let interest_fee_amount = math::share(collateral.raft_amount, interest_fee, utils::FEE_DIVISOR);
+        self.account_book.burn(&sender_id, &collateral.raft_id, collateral.raft_amount + interest_fee_amount);
+        self.internal_accrue_fee(&collateral.raft_id, interest_fee_amount);
+
+        self.internal_close_nft_collateral(collateral_id, collateral.clone());
+
+        Event::NftRedeem {
+            account_id: &sender_id,
+            collateral_id,
+            collection_id: &collateral.collection_id,
+            nft_token_id: &collateral.nft_token_id,
+        }.emit();
+        self.internal_record_operation(OperationKind::NftRedeem, &sender_id,
+                                       vec![collateral.collection_id.clone(), collateral.raft_id.clone()],
+                                       vec![1, collateral.raft_amount]);
+
+        let promise = self.internal_send_nft(collateral_id, &collateral, &sender_id, interest_fee_amount);
+        self.internal_bump_nonce_then(&sender_id, promise)
+    }
+
+    /// Repays the rafts of an NFT collateral under its liquidation threshold from the caller's account book,
+    /// and sends the NFT to the caller. Resolves to the new operation nonce of the caller.
+    #[payable]
+    pub fn liquidate_nft_collateral(&mut self, collateral_id: NftCollateralId) -> Promise {
+        assert_one_yocto();
+        self.assert_contract_running();
+
+        let liquidator_id = env::predecessor_account_id();
+        let collateral = self.query_nft_collateral(collateral_id).expect(errors::COLLATERAL_NOT_FOUND);
+        assert!(collateral.state == CollateralState::Open, "{}", errors::COLLATERAL_CLOSED);
+        self.assert_raft_state(&collateral.raft_id, AssetState::is_withdrawable);
+
+        let collection = self.query_nft_collection(&collateral.collection_id).expect(errors::ASSET_NOT_FOUND);
+        let collateral_ratio = self.calc_nft_collateral_ratio(&collateral.collection_id, &collateral.raft_id,
+                                                              collateral.raft_amount);
+        let liquidation_ratio = self.risk_graces.query_liquidation_ratio(&collateral.collection_id,
+                                                                         collection.liquidation_ratio());
+        assert!(collateral_ratio < liquidation_ratio, "{}", errors::COLLATERAL_HEALTHY);
+
+        self.account_book.burn(&liquidator_id, &collateral.raft_id, collateral.raft_amount);
+        self.internal_close_nft_collateral(collateral_id, collateral.clone());
+
+        Event::NftLiquidation {
+            liquidator_id: &liquidator_id,
+            account_id: &collateral.issuer,
+            collateral_id,
+            collection_id: &collateral.collection_id,
+            nft_token_id: &collateral.nft_token_id,
+            raft_id: &collateral.raft_id,
+            raft_amount: U128(collateral.raft_amount),
+        }.emit();
+        self.internal_record_operation(OperationKind::NftLiquidation, &liquidator_id,
+                                       vec![collateral.collection_id.clone(), collateral.raft_id.clone()],
+                                       vec![1, collateral.raft_amount]);
+
+        let promise = self.internal_send_nft(collateral_id, &collateral, &liquidator_id, 0);
+        self.internal_bump_nonce_then(&liquidator_id, promise)
+    }
+
+    /// Reopens the collateral and gives the rafts back to the payer if the NFT couldn't be sent.
+    #[private]
+    pub fn nft_collateral_callback_send(&mut self, collateral_id: NftCollateralId, payer_id: AccountId,
+                                        fee_amount: U128) {
+        let fee_amount: Balance = fee_amount.into();
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => {}
+            PromiseResult::Failed => {
+                let mut collateral = self.query_nft_collateral(collateral_id).unwrap();
+                collateral.state = CollateralState::Open;
+                self.nft_collaterals.replace(collateral_id, &collateral);
+                self.internal_index_nft_collateral(collateral_id, &collateral);
+
+                // the fee is only given back if it wasn't claimed yet
+                let refunded_fee = self.fee_ledger.revert(&collateral.raft_id, fee_amount);
+                self.account_book.mint(&payer_id, &collateral.raft_id, collateral.raft_amount + refunded_fee);
+                env::log_str(format!("NFT collateral {} reopened, the transfer failed", collateral_id).as_str());
+            }
+        };
+    }
+}
+
+impl Contract {
+    pub(crate) fn query_nft_collection(&self, collection_id: &AccountId) -> Option<Asset> {
+        self.nft_list.get(collection_id)
+    }
+
+    pub(crate) fn query_nft_collateral(&self, collateral_id: NftCollateralId) -> Option<NftCollateral> {
+        self.nft_collaterals.get(collateral_id)
+    }
+
+    /// Ratio in percent between the floor price of the collection and the value of the minted rafts.
+    fn calc_nft_collateral_ratio(&self, collection_id: &AccountId, raft_id: &AccountId, raft_amount: Balance) -> u128 {
+        math::ratio(&[oracle::calc_value(self.price_oracle.get_price(collection_id), 1, 0), 100],
+                    &[self.valuation().calc_value(raft_id, raft_amount)])
+    }
+
+    fn internal_add_nft_collateral(&mut self, collateral: &NftCollateral) -> NftCollateralId {
+        let collateral_id = self.nft_collaterals.len();
+        self.nft_collaterals.push(collateral);
+        self.internal_index_nft_collateral(collateral_id, collateral);
+
+        collateral_id
+    }
+
+    /// Adds the id of an open collateral to the issuer's NFT collateral list and counts it.
+    fn internal_index_nft_collateral(&mut self, collateral_id: NftCollateralId, collateral: &NftCollateral) {
+        let mut collateral_ids = self.user_nft_collaterals.get(&collateral.issuer).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::UserNftCollateralIds { account_id: collateral.issuer.clone() })
+        });
+        collateral_ids.insert(&collateral_id);
+        self.user_nft_collaterals.insert(&collateral.issuer, &collateral_ids);
+        self.internal_count_open_collateral(&collateral.collection_id, true);
+        self.internal_count_open_collateral(&collateral.raft_id, true);
+    }
+
+    /// Marks the collateral as closed and removes its id from the issuer's NFT collateral list.
+    fn internal_close_nft_collateral(&mut self, collateral_id: NftCollateralId, mut collateral: NftCollateral) {
+        collateral.state = CollateralState::Closed;
+        self.nft_collaterals.replace(collateral_id, &collateral);
+
+        if let Some(mut collateral_ids) = self.user_nft_collaterals.get(&collateral.issuer) {
+            if collateral_ids.remove(&collateral_id) {
+                self.user_nft_collaterals.insert(&collateral.issuer, &collateral_ids);
+                self.internal_count_open_collateral(&collateral.collection_id, false);
+                self.internal_count_open_collateral(&collateral.raft_id, false);
+            }
+        }
+    }
+
+    fn internal_send_nft(&self, collateral_id: NftCollateralId, collateral: &NftCollateral,
+                         receiver_id: &AccountId, fee_amount: Balance) -> Promise {
+        ext_non_fungible_token::nft_transfer(
+            receiver_id.clone(),
+            collateral.nft_token_id.clone(),
+            None,
+            None,
+            collateral.collection_id.clone(),
+            utils::ONE_YOCTO,
+            utils::GAS_FOR_NFT_TRANSFER,
+        ).then(ext_self::nft_collateral_callback_send(
+            collateral_id,
+            receiver_id.clone(),
+            U128(fee_amount),
+            env::current_account_id(),
+            utils::NO_DEPOSIT,
+            self.gas_config.resolve,
+        ))
+    }
+}
@@ -0,0 +1,103 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, AccountId, Balance, BlockHeight};
+
+use crate::*;
+
+pub type OperationId = u64;
+
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    Deposit,
+    Withdraw,
+    ClaimLostfound,
+    Mint,
+    AddCollateral,
+    Repay,
+    Redeem,
+    Swap,
+    AccountBookDeposit,
+    AccountBookWithdraw,
+    NftMint,
+    NftRedeem,
+    NftLiquidation,
+    MtMint,
+    MtRedeem,
+    MtLiquidation,
+    Liquidation,
+    BridgeOut,
+    BorrowMore,
+}
+
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Operation {
+    pub kind: OperationKind,
+    pub account_id: AccountId,
+    /// Assets involved, in the same order as `amounts`.
+    pub assets: Vec<AccountId>,
+    pub amounts: Vec<U128>,
+    #[serde(with = "u64_dec_format")]
+    pub block_height: BlockHeight,
+}
+
+/// Log of the last `utils::MAX_OPERATIONS` operations, older ones are dropped as new ones are recorded.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct OpLog {
+    /// Id of the oldest operation still kept.
+    first_id: OperationId,
+    /// Id of the next recorded operation.
+    next_id: OperationId,
+    operations: LookupMap<OperationId, Operation>,
+}
+
+impl OpLog {
+    pub(crate) fn new() -> Self {
+        Self {
+            first_id: 0,
+            next_id: 0,
+            operations: LookupMap::new(StorageKey::Operations),
+        }
+    }
+
+    pub(crate) fn record(&mut self, operation: &Operation) -> OperationId {
+        let id = self.next_id;
+        self.operations.insert(&id, operation);
+        self.next_id += 1;
+
+        if self.next_id - self.first_id > utils::MAX_OPERATIONS {
+            self.operations.remove(&self.first_id);
+            self.first_id += 1;
+        }
+
+        id
+    }
+
+    /// Operations with id in `[from_id, from_id + limit)` which are still kept.
+    pub(crate) fn query_range(&self, from_id: OperationId, limit: u64) -> Vec<(OperationId, Operation)> {
+        let from_id = std::cmp::max(from_id, self.first_id);
+        (from_id..std::cmp::min(from_id.saturating_add(limit), self.next_id))
+            .map(|id| (id, self.operations.get(&id).unwrap()))
+            .collect()
+    }
+
+    pub(crate) fn query_bounds(&self) -> (OperationId, OperationId) {
+        (self.first_id, self.next_id)
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_record_operation(&mut self, kind: OperationKind, account_id: &AccountId,
+                                            assets: Vec<AccountId>, amounts: Vec<Balance>) {
+        self.oplog.record(&Operation {
+            kind,
+            account_id: account_id.clone(),
+            assets,
+            amounts: amounts.into_iter().map(U128).collect(),
+            block_height: env::block_height(),
+        });
+    }
+}
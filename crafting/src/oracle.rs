@@ -1,29 +1,248 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::LookupMap;
-use near_sdk::AccountId;
+use near_sdk::collections::{LookupMap, UnorderedMap};
+use near_sdk::json_types::U64;
+use near_sdk::{env, AccountId, Balance, Timestamp};
+
+use crate::*;
+use crate::errors::CraftingError;
+use crate::timelock::{ChangeId, ParamChange};
+
+/// Value of `amount` of an asset with `decimals` at `price` per whole unit, in USD with `VALUE_DECIMALS` decimals.
+pub(crate) fn calc_value(price: u128, amount: Balance, decimals: u32) -> u128 {
+    math::ratio(&[price, amount, math::pow10(utils::VALUE_DECIMALS)],
+                &[math::pow10(decimals), utils::PRICE_PRECISION as u128])
+}
 
 #[derive(BorshSerialize, BorshDeserialize)]
 pub struct PriceInfo {
     /// Mapping from assets to price of assets.
     prices: LookupMap<AccountId, u128>,
+    /// Mapping from multi-token contract and token id to price of the token.
+    mt_prices: LookupMap<(AccountId, String), u128>,
+    /// Mapping from assets to the timestamp their price was observed at by the feeder.
+    timestamps: LookupMap<AccountId, Timestamp>,
+    /// Mapping from assets to the id of their last price round, incremented on each feed.
+    rounds: LookupMap<AccountId, u64>,
+    /// Mapping from asset and slot to the round and price of the last `PRICE_ROUND_HISTORY` rounds.
+    round_prices: LookupMap<(AccountId, u64), (u64, u128)>,
+    /// Incremented on each feed of any asset, values cached at an earlier epoch are stale.
+    epoch: u64,
+}
+
+/// Price round of an asset a call was quoted against, see `get_price_round`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceRound {
+    pub asset: AccountId,
+    pub round_id: U64,
+}
+
+/// Price of an asset observed by its feeder at `timestamp`, see `feed_prices`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceEntry {
+    pub asset: AccountId,
+    pub price: U128,
+    pub timestamp: U64,
 }
 
 impl PriceInfo {
     pub fn new() -> Self {
         Self {
-            prices: LookupMap::new(b"r".to_vec()),
+            prices: LookupMap::new(StorageKey::Prices),
+            mt_prices: LookupMap::new(StorageKey::MtPrices),
+            timestamps: LookupMap::new(StorageKey::PriceTimestamps),
+            rounds: LookupMap::new(StorageKey::PriceRounds),
+            round_prices: LookupMap::new(StorageKey::PriceRoundHistory),
+            epoch: 0,
         }
     }
 
+    /// Price of the asset, `None` if it was never fed.
+    pub fn query_price(&self, asset: &AccountId) -> Option<u128> {
+        self.prices.get(asset)
+    }
+
     /// Returns the price of assets.
     pub fn get_price(&self, asset: &AccountId) -> u128 {
         let opt = self.prices.get(asset);
-        assert!(opt.is_some());
+        assert!(opt.is_some(), "{}", errors::PRICE_NOT_FOUND);
+        opt.unwrap()
+    }
+
+    /// Returns the price of a multi-token.
+    pub fn get_mt_price(&self, contract_id: &AccountId, token_id: &str) -> u128 {
+        let opt = self.mt_prices.get(&(contract_id.clone(), token_id.to_string()));
+        assert!(opt.is_some(), "{}", errors::PRICE_NOT_FOUND);
         opt.unwrap()
     }
 
-    /// Feed the price of assets.
-    pub fn feed_price(&mut self, asset: &AccountId, price: u128) {
+    /// Timestamp the price of the asset was observed at, `None` if it was never fed.
+    pub fn get_timestamp(&self, asset: &AccountId) -> Option<Timestamp> {
+        self.timestamps.get(asset)
+    }
+
+    /// Feed the price of assets, observed at `timestamp`.
+    pub fn feed_price(&mut self, asset: &AccountId, price: u128, timestamp: Timestamp) {
         self.prices.insert(asset, &price);
+        self.timestamps.insert(asset, &timestamp);
+        let round_id = self.query_round(asset) + 1;
+        self.rounds.insert(asset, &round_id);
+        self.epoch += 1;
+        self.round_prices.insert(&(asset.clone(), round_id % utils::PRICE_ROUND_HISTORY), &(round_id, price));
+    }
+
+    pub fn query_epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Id of the last price round of the asset, 0 if it was never fed.
+    pub fn query_round(&self, asset: &AccountId) -> u64 {
+        self.rounds.get(asset).unwrap_or(0)
+    }
+
+    /// Price of the asset in the round, `None` if the round is unknown or older than the kept history.
+    pub fn query_round_price(&self, asset: &AccountId, round_id: u64) -> Option<u128> {
+        self.round_prices.get(&(asset.clone(), round_id % utils::PRICE_ROUND_HISTORY))
+            .filter(|(slot_round_id, _)| *slot_round_id == round_id)
+            .map(|(_, price)| price)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Feed the prices of several tokens and rafts at once. Only can be called by the feeder of each asset.
+    /// Each entry is checked on its own: an entry from the future, older than the max oracle age
+    /// of the asset or than its current price is skipped. Returns the number of prices updated.
+    pub fn feed_prices(&mut self, entries: Vec<PriceEntry>) -> u32 {
+        let feeder_id = env::predecessor_account_id();
+        let now = env::block_timestamp();
+        let mut updated = 0;
+        for entry in entries {
+            let asset = self.query_token(&entry.asset).or_else(|| self.query_raft(&entry.asset))
+                .expect(errors::ASSET_NOT_FOUND);
+            assert_eq!(asset.feed_address, feeder_id, "{}", errors::NO_PERMISSION);
+            assert!(entry.price.0 > 0, "{}", errors::ILLEGAL_PRICE);
+
+            let timestamp = entry.timestamp.0;
+            let max_age = self.query_oracle_max_age(&asset);
+            let is_newer = self.price_oracle.get_timestamp(&entry.asset).is_none_or(|last| timestamp > last);
+            if timestamp > now || now - timestamp > max_age || !is_newer {
+                env::log_str(format!("{}: {} at {}", errors::STALE_PRICE, entry.asset, timestamp).as_str());
+                continue;
+            }
+
+            self.price_oracle.feed_price(&entry.asset, entry.price.0, timestamp);
+            updated += 1;
+        }
+
+        updated
+    }
+
+    /// Price of the token or raft with the timestamp it was observed at.
+    pub fn get_price(&self, asset: AccountId) -> Option<PriceEntry> {
+        let price = self.price_oracle.prices.get(&asset)?;
+        let timestamp = self.price_oracle.get_timestamp(&asset).unwrap_or(0);
+        Some(PriceEntry { asset, price: U128(price), timestamp: U64(timestamp) })
+    }
+
+    /// Schedule change of how far, in `BPS_DIVISOR` units, a price can move from the round a call was quoted
+    /// against before the call is rejected. Only can be called by owner.
+    pub fn set_price_round_tolerance(&mut self, tolerance_bps: u32) -> ChangeId {
+        self.assert_owner();
+        assert!(tolerance_bps <= utils::BPS_DIVISOR, "{}", errors::ILLEGAL_FEE_CONFIG);
+        self.internal_schedule_param_change(ParamChange::PriceRoundTolerance { tolerance_bps })
+    }
+
+    pub fn get_price_round_tolerance(&self) -> u32 {
+        self.price_round_tolerance
+    }
+
+    /// Id of the last price round of the token or raft, to bind a mint or swap to the price it was quoted at.
+    pub fn get_price_round(&self, asset: AccountId) -> Option<U64> {
+        Some(self.price_oracle.query_round(&asset)).filter(|round_id| *round_id > 0).map(U64)
+    }
+}
+
+/// Values listed tokens and rafts at oracle prices, taking their decimals into account.
+pub(crate) struct Valuation<'a> {
+    price_oracle: &'a PriceInfo,
+    token_list: &'a UnorderedMap<AccountId, Asset>,
+    raft_list: &'a UnorderedMap<AccountId, Asset>,
+}
+
+impl Contract {
+    /// Fails if a price left the kept history or moved beyond the tolerance since the round it was quoted in.
+    pub(crate) fn check_price_rounds(&self, price_rounds: &[PriceRound]) -> Result<(), CraftingError> {
+        for PriceRound { asset, round_id } in price_rounds {
+            let quoted_price = self.price_oracle.query_round_price(asset, round_id.0)
+                .ok_or(CraftingError::PriceRoundExpired)?;
+            let price = self.price_oracle.query_price(asset).ok_or(CraftingError::PriceNotFound)?;
+            let deviation = math::mul_div(price.abs_diff(quoted_price), utils::BPS_DIVISOR as u128, quoted_price);
+            if deviation > self.price_round_tolerance as u128 {
+                return Err(CraftingError::PriceMoved);
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn assert_price_rounds(&self, price_rounds: &Option<Vec<PriceRound>>) {
+        if let Some(price_rounds) = price_rounds {
+            self.check_price_rounds(price_rounds).unwrap_or_else(|error| error.panic());
+        }
+    }
+}
+
+impl<'a> Valuation<'a> {
+    pub(crate) fn new(price_oracle: &'a PriceInfo, token_list: &'a UnorderedMap<AccountId, Asset>,
+                      raft_list: &'a UnorderedMap<AccountId, Asset>) -> Self {
+        Self { price_oracle, token_list, raft_list }
+    }
+
+    fn query_decimals(&self, asset_id: &AccountId) -> u32 {
+        self.raft_list.get(asset_id).or_else(|| self.token_list.get(asset_id))
+            .expect(errors::ASSET_NOT_FOUND)
+            .decimals
+    }
+
+    /// Price epoch the values are computed at.
+    pub(crate) fn epoch(&self) -> u64 {
+        self.price_oracle.query_epoch()
+    }
+
+    /// Price round of the asset the values are computed at.
+    pub(crate) fn round(&self, asset_id: &AccountId) -> u64 {
+        self.price_oracle.query_round(asset_id)
+    }
+
+    /// Value of `amount` of the token or raft, in USD with `VALUE_DECIMALS` decimals.
+    pub(crate) fn calc_value(&self, asset_id: &AccountId, amount: Balance) -> u128 {
+        calc_value(self.price_oracle.get_price(asset_id), amount, self.query_decimals(asset_id))
+    }
+
+    /// Amount of the token or raft worth `value`, rounded down.
+    pub(crate) fn calc_amount(&self, asset_id: &AccountId, value: u128) -> Balance {
+        math::ratio(&[value, math::pow10(self.query_decimals(asset_id)), utils::PRICE_PRECISION as u128],
+                    &[self.price_oracle.get_price(asset_id), math::pow10(utils::VALUE_DECIMALS)])
+    }
+
+    /// Amount of the token or raft worth at least `value`, rounded up.
+    pub(crate) fn calc_amount_ceil(&self, asset_id: &AccountId, value: u128) -> Balance {
+        math::ratio_ceil(&[value, math::pow10(self.query_decimals(asset_id)), utils::PRICE_PRECISION as u128],
+                         &[self.price_oracle.get_price(asset_id), math::pow10(utils::VALUE_DECIMALS)])
+    }
+
+    /// Amount of `to_id` worth `amount` of `from_id`, rounded down.
+    pub(crate) fn convert(&self, from_id: &AccountId, amount: Balance, to_id: &AccountId) -> Balance {
+        math::ratio(&[self.price_oracle.get_price(from_id), amount, math::pow10(self.query_decimals(to_id))],
+                    &[self.price_oracle.get_price(to_id), math::pow10(self.query_decimals(from_id))])
+    }
+}
+
+impl Contract {
+    /// Max age in nanoseconds of the price of the asset, from its risk class.
+    pub(crate) fn query_oracle_max_age(&self, asset: &Asset) -> u64 {
+        asset.risk_class
+            .map_or(utils::ORACLE_MAX_AGE, |risk_class| self.query_risk_params(risk_class).oracle_max_age)
     }
 }
@@ -1,57 +1,211 @@
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
+use near_sdk::json_types::U64;
+use near_sdk::{serde_json, PromiseResult};
+
 use crate::*;
+use crate::events::AccountList;
+use crate::mt::MtAsset;
+use crate::timelock::{ChangeId, ParamChange};
 
 #[near_bindgen]
 impl Contract {
     /// Change owner. Only can be called by owner.
     pub fn set_owner(&mut self, owner_id: AccountId) {
         self.assert_owner();
+        Event::OwnerChange { old_owner_id: &self.owner_id, new_owner_id: &owner_id }.emit();
         self.owner_id = owner_id;
     }
 
+    /// Change treasury, which receives the claimed fees. Only can be called by owner.
+    pub fn set_treasury(&mut self, treasury_id: AccountId) {
+        self.assert_owner();
+        Event::TreasuryChange { old_treasury_id: &self.treasury_id, new_treasury_id: &treasury_id }.emit();
+        self.treasury_id = treasury_id;
+    }
+
+    /// Change wNEAR contract, whose tokens can be unwrapped on withdrawal. Only can be called by owner.
+    pub fn set_wnear(&mut self, wnear_id: AccountId) {
+        self.assert_owner();
+        self.wnear_id = Some(wnear_id);
+    }
+
+    /// Change the rUSD raft, which must be listed. Only can be called by owner.
+    pub fn set_rusd(&mut self, rusd_id: AccountId) {
+        self.assert_owner();
+        assert!(self.query_raft(&rusd_id).is_some(), "{}", errors::ASSET_NOT_FOUND);
+        self.rusd_id = Some(rusd_id);
+    }
+
+    /// Extend listed rafts the debt of the debt pool can be settled in besides rUSD. Only can be called by owner.
+    pub fn extend_settlement_rafts(&mut self, raft_ids: Vec<AccountId>) {
+        self.assert_owner();
+        let by = env::predecessor_account_id();
+        for raft_id in raft_ids {
+            assert!(self.query_raft(&raft_id).is_some(), "{}", errors::ASSET_NOT_FOUND);
+            if self.settlement_rafts.insert(&raft_id) {
+                Event::AccountListAdd { list: AccountList::SettlementRafts, account_id: &raft_id, by: &by }.emit();
+            }
+        }
+    }
+
+    /// Remove settlement rafts. Only can be called by owner.
+    pub fn remove_settlement_rafts(&mut self, raft_ids: Vec<AccountId>) {
+        self.assert_owner();
+        let by = env::predecessor_account_id();
+        for raft_id in raft_ids {
+            if self.settlement_rafts.remove(&raft_id) {
+                Event::AccountListRemove { list: AccountList::SettlementRafts, account_id: &raft_id, by: &by }.emit();
+            }
+        }
+    }
+
+    /// Extend guardians. Only can be called by owner.
+    pub fn extend_guardians(&mut self, guardians: Vec<AccountId>) {
+        self.assert_owner();
+        let by = env::predecessor_account_id();
+        for guardian in guardians {
+            if self.guardians.insert(&guardian) {
+                Event::AccountListAdd { list: AccountList::Guardians, account_id: &guardian, by: &by }.emit();
+            }
+        }
+    }
+
+    /// Remove guardians. Only can be called by owner.
+    pub fn remove_guardians(&mut self, guardians: Vec<AccountId>) {
+        self.assert_owner();
+        let by = env::predecessor_account_id();
+        for guardian in guardians {
+            if self.guardians.remove(&guardian) {
+                Event::AccountListRemove { list: AccountList::Guardians, account_id: &guardian, by: &by }.emit();
+            }
+        }
+    }
+
+    /// Extend keepers. Only can be called by owner.
+    pub fn extend_keepers(&mut self, keepers: Vec<AccountId>) {
+        self.assert_owner();
+        let by = env::predecessor_account_id();
+        for keeper in keepers {
+            if self.keepers.insert(&keeper) {
+                Event::AccountListAdd { list: AccountList::Keepers, account_id: &keeper, by: &by }.emit();
+            }
+        }
+    }
+
+    /// Remove keepers. Only can be called by owner.
+    pub fn remove_keepers(&mut self, keepers: Vec<AccountId>) {
+        self.assert_owner();
+        let by = env::predecessor_account_id();
+        for keeper in keepers {
+            if self.keepers.remove(&keeper) {
+                Event::AccountListRemove { list: AccountList::Keepers, account_id: &keeper, by: &by }.emit();
+            }
+        }
+    }
+
     /// Change state of contract, Only can be called by owner or guardians.
+    /// Guardians can only pause the contract, resuming requires the owner.
     pub fn change_state(&mut self, state: RunningState) {
-        self.assert_owner();
+        self.assert_owner_or_guardians();
         if self.state != state {
-            env::log_str(
-                format!(
-                    "Contract state changed from {} to {} by {}",
-                    self.state, state, env::predecessor_account_id()
-                ).as_str(),
-            );
+            if state == RunningState::Running {
+                // only owner can resume the contract
+                self.assert_owner();
+            }
+            Event::ContractStateChange {
+                old_state: &self.state,
+                new_state: &state,
+                by: &env::predecessor_account_id(),
+            }.emit();
             self.state = state;
         }
     }
 
-    /// Set leverage ratio. Only can be called by owner.
-    pub fn set_leverage_ratio(&mut self, leverage_ratio: (u8, u8)) {
+    /// Schedule leverage ratio change. Only can be called by owner.
+    pub fn set_leverage_ratio(&mut self, leverage_ratio: (u8, u8)) -> ChangeId {
         self.assert_owner();
         let (min, max) = leverage_ratio;
-        assert!(min >= 1);
-        assert!(max <= 100);
-        self.leverage_ratio = leverage_ratio;
+        assert!(min >= 1, "{}", errors::ILLEGAL_LEVERAGE_RATIO);
+        assert!(max <= 100, "{}", errors::ILLEGAL_LEVERAGE_RATIO);
+        self.internal_schedule_param_change(ParamChange::LeverageRatio { leverage_ratio })
     }
 
-    /// Set interest fee. Only can be called by owner.
-    pub fn set_interest_fee(&mut self, interest_fee: u32) {
+    /// Schedule interest fee change. Only can be called by owner.
+    pub fn set_interest_fee(&mut self, interest_fee: u32) -> ChangeId {
         self.assert_owner();
         assert!(interest_fee <= utils::FEE_DIVISOR, "{}", errors::ILLEGAL_FEE);
-        self.interest_fee = interest_fee;
+        self.internal_schedule_param_change(ParamChange::InterestFee { interest_fee })
     }
 
-    /// Set exchange fee. Only can be called by owner.
-    pub fn set_exchange_fee(&mut self, exchange_fee: u32) {
+    /// Schedule exchange fee change. Only can be called by owner.
+    pub fn set_exchange_fee(&mut self, exchange_fee: u32) -> ChangeId {
         self.assert_owner();
         assert!(exchange_fee <= utils::FEE_DIVISOR, "{}", errors::ILLEGAL_FEE);
-        self.exchange_fee = exchange_fee;
+        self.internal_schedule_param_change(ParamChange::ExchangeFee { exchange_fee })
+    }
+
+    /// Schedule change of the stability fee per annum of collaterals of unclassified tokens.
+    /// Only can be called by owner.
+    pub fn set_stability_fee(&mut self, stability_fee: u32) -> ChangeId {
+        self.assert_owner();
+        assert!(stability_fee <= utils::FEE_DIVISOR, "{}", errors::ILLEGAL_FEE);
+        self.internal_schedule_param_change(ParamChange::StabilityFee { stability_fee })
+    }
+
+    /// Schedule collateral ratio change of a listed token. Only can be called by owner.
+    pub fn set_collateral_ratio(&mut self, token_id: AccountId, collateral_ratio: U128) -> ChangeId {
+        self.assert_owner();
+        let collateral_ratio: u128 = collateral_ratio.into();
+        assert!(self.query_token(&token_id).is_some(), "{}", errors::ASSET_NOT_FOUND);
+        self.internal_schedule_param_change(ParamChange::CollateralRatio { token_id, collateral_ratio })
+    }
+
+    /// Schedule liquidation threshold change of a listed token, 0 to liquidate under the collateral ratio.
+    /// Only can be called by owner.
+    pub fn set_liquidation_threshold(&mut self, token_id: AccountId, liquidation_threshold: U128) -> ChangeId {
+        self.assert_owner();
+        let liquidation_threshold: u128 = liquidation_threshold.into();
+        let asset = self.query_token(&token_id).expect(errors::ASSET_NOT_FOUND);
+        assert!(liquidation_threshold <= asset.collateral_ratio, "{}", errors::ILLEGAL_LIQUIDATION_THRESHOLD);
+        self.internal_schedule_param_change(ParamChange::LiquidationThreshold { token_id, liquidation_threshold })
+    }
+
+    /// Schedule liquidation threshold change of a listed NFT collection, 0 to liquidate under the collateral ratio.
+    /// Only can be called by owner.
+    pub fn set_nft_liquidation_threshold(&mut self, collection_id: AccountId, liquidation_threshold: U128) -> ChangeId {
+        self.assert_owner();
+        let liquidation_threshold: u128 = liquidation_threshold.into();
+        let asset = self.query_nft_collection(&collection_id).expect(errors::ASSET_NOT_FOUND);
+        assert!(liquidation_threshold <= asset.collateral_ratio, "{}", errors::ILLEGAL_LIQUIDATION_THRESHOLD);
+        self.internal_schedule_param_change(ParamChange::NftLiquidationThreshold { collection_id, liquidation_threshold })
+    }
+
+    /// Schedule collateral ratio change of a (token, raft) pair, overriding the ratio of the token.
+    /// `None` falls back to the ratio of the token. Only can be called by owner.
+    pub fn set_pair_collateral_ratio(&mut self, token_id: AccountId, raft_id: AccountId,
+                                     collateral_ratio: Option<U128>) -> ChangeId {
+        self.assert_owner();
+        assert!(self.query_token(&token_id).is_some(), "{}", errors::ASSET_NOT_FOUND);
+        assert!(self.query_raft(&raft_id).is_some(), "{}", errors::ASSET_NOT_FOUND);
+        self.internal_schedule_param_change(ParamChange::PairCollateralRatio { token_id, raft_id, collateral_ratio })
+    }
+
+    /// Schedule timelock delay change. Only can be called by owner.
+    pub fn set_timelock_delay(&mut self, delay: U64) -> ChangeId {
+        self.assert_owner();
+        let delay: u64 = delay.into();
+        assert!(delay <= utils::MAX_TIMELOCK_DELAY, "{}", errors::ILLEGAL_TIMELOCK_DELAY);
+        self.internal_schedule_param_change(ParamChange::TimelockDelay { delay })
     }
 
     /// Add whitelisted tokens with new tokens. Only can be called by owner.
     pub fn add_whitelisted_tokens(&mut self, tokens: Vec<AccountId>) {
         self.assert_owner();
+        let by = env::predecessor_account_id();
         for token in tokens {
             let opt = self.token_list.get(&token);
-            if opt.is_some() {
-                self.whitelisted_tokens.insert(&token);
+            if opt.is_some() && self.whitelisted_tokens.insert(&token) {
+                Event::AccountListAdd { list: AccountList::WhitelistedTokens, account_id: &token, by: &by }.emit();
             }
         }
     }
@@ -59,36 +213,95 @@ impl Contract {
     /// Remove whitelisted token. Only can be called by owner.
     pub fn remove_whitelisted_tokens(&mut self, tokens: Vec<AccountId>) {
         self.assert_owner();
+        let by = env::predecessor_account_id();
         for token in tokens {
-            self.whitelisted_tokens.remove(&token);
+            if self.whitelisted_tokens.remove(&token) {
+                Event::AccountListRemove { list: AccountList::WhitelistedTokens, account_id: &token, by: &by }.emit();
+            }
         }
     }
 
-    /// Add token. Only can be called by owner.
-    pub fn add_token_list(&mut self, name: String, symbol: String, standard: String,
-                          decimals: u32, address: AccountId, feed_address: AccountId,
-                          collateral_ratio: u128, state: u8) {
+    /// Schedule token listing. Only can be called by owner.
+    /// Name, symbol and decimals are read from the `ft_metadata` of the token, the change id is returned by the callback.
+    pub fn add_token_list(&mut self, standard: String, address: AccountId, feed_address: AccountId,
+                          collateral_ratio: U128, state: AssetState) -> Promise {
         self.assert_owner();
-        let asset = Asset {
-            name,
-            symbol,
-            standard,
-            decimals,
-            address: address.clone(),
-            feed_address,
-            collateral_ratio,
-            state,
-        };
-        self.token_list.insert(&address, &asset);
+        let collateral_ratio: u128 = collateral_ratio.into();
+        let asset = Asset::unnamed(standard, address, feed_address, collateral_ratio, state);
+        self.internal_fetch_metadata(ParamChange::TokenListing { asset })
+    }
+
+    /// Schedule update of a listed token. Only can be called by owner.
+    pub fn update_token(&mut self, address: AccountId, patch: AssetPatch) -> ChangeId {
+        self.assert_owner();
+        assert!(self.query_token(&address).is_some(), "{}", errors::ASSET_NOT_FOUND);
+        assert!(patch.max_supply.is_none(), "{}", errors::ILLEGAL_ASSET_PATCH);
+        Self::assert_valid_patch(&patch);
+        self.internal_schedule_param_change(ParamChange::TokenUpdate { token_id: address, patch })
+    }
+
+    /// Change state of a listed token. Only can be called by owner or guardians,
+    /// guardians can only pause the token further.
+    pub fn set_token_state(&mut self, token_id: AccountId, state: AssetState) {
+        let mut asset = self.query_token(&token_id).expect(errors::ASSET_NOT_FOUND);
+        self.assert_asset_state_change(&asset.state, &state);
+        if asset.state != state {
+            Event::TokenStateChange {
+                token_id: &token_id,
+                old_state: &asset.state,
+                new_state: &state,
+                by: &env::predecessor_account_id(),
+            }.emit();
+            asset.state = state;
+            self.token_list.insert(&token_id, &asset);
+        }
+    }
+
+    /// Change state of a listed raft. Only can be called by owner or guardians,
+    /// guardians can only pause the raft further.
+    pub fn set_raft_state(&mut self, raft_id: AccountId, state: AssetState) {
+        let mut asset = self.query_raft(&raft_id).expect(errors::ASSET_NOT_FOUND);
+        self.assert_asset_state_change(&asset.state, &state);
+        if asset.state != state {
+            Event::RaftStateChange {
+                raft_id: &raft_id,
+                old_state: &asset.state,
+                new_state: &state,
+                by: &env::predecessor_account_id(),
+            }.emit();
+            asset.state = state;
+            self.raft_list.insert(&raft_id, &asset);
+        }
+    }
+
+    /// Remove token from token list and whitelist. Only can be called by owner.
+    /// Fails if any open collateral or the peg stability module still uses the token.
+    pub fn remove_token_list(&mut self, address: AccountId) {
+        self.assert_owner();
+        let old_asset = self.token_list.remove(&address).expect(errors::ASSET_NOT_FOUND);
+        assert_eq!(self.query_open_collateral_count(&address), 0, "{}", errors::ASSET_IN_USE);
+        assert!(self.psm.query_stable(&address).is_none_or(|stable| stable.reserve == 0),
+                "{}", errors::ASSET_IN_USE);
+        self.whitelisted_tokens.remove(&address);
+        Event::TokenRemove { token_id: &address, old_asset: &old_asset, by: &env::predecessor_account_id() }.emit();
+    }
+
+    /// Change the max number of listed rafts, not below the number of rafts already listed.
+    /// Only can be called by owner.
+    pub fn set_max_rafts(&mut self, max_rafts: u64) {
+        self.assert_owner();
+        assert!(max_rafts >= self.raft_list.len(), "{}", errors::MAX_RAFTS_REACHED);
+        self.max_rafts = max_rafts;
     }
 
     /// Add whitelisted tokens with new rafts. Only can be called by owner.
     pub fn add_whitelisted_rafts(&mut self, rafts: Vec<AccountId>) {
         self.assert_owner();
+        let by = env::predecessor_account_id();
         for raft in rafts {
             let opt = self.raft_list.get(&raft);
-            if opt.is_some() {
-                self.whitelisted_rafts.insert(&raft);
+            if opt.is_some() && self.whitelisted_rafts.insert(&raft) {
+                Event::AccountListAdd { list: AccountList::WhitelistedRafts, account_id: &raft, by: &by }.emit();
             }
         }
     }
@@ -96,30 +309,229 @@ impl Contract {
     /// Remove whitelisted raft. Only can be called by owner.
     pub fn remove_whitelisted_rafts(&mut self, rafts: Vec<AccountId>) {
         self.assert_owner();
+        let by = env::predecessor_account_id();
         for raft in rafts {
-            self.whitelisted_rafts.remove(&raft);
+            if self.whitelisted_rafts.remove(&raft) {
+                Event::AccountListRemove { list: AccountList::WhitelistedRafts, account_id: &raft, by: &by }.emit();
+            }
+        }
+    }
+
+    /// Schedule raft listing. Only can be called by owner.
+    /// Name, symbol and decimals are read from the `ft_metadata` of the raft, the change id is returned by the callback.
+    pub fn add_raft_list(&mut self, standard: String, address: AccountId, feed_address: AccountId,
+                         state: AssetState) -> Promise {
+        self.assert_owner();
+        self.assert_raft_capacity();
+        let asset = Asset::unnamed(standard, address, feed_address, 0, state);
+        self.internal_fetch_metadata(ParamChange::RaftListing { asset })
+    }
+
+    /// Schedules the listing with the metadata returned by the asset.
+    #[private]
+    pub fn list_asset_callback_metadata(&mut self, change: ParamChange) -> ChangeId {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+
+        let metadata: FungibleTokenMetadata = match env::promise_result(0) {
+            PromiseResult::Successful(value) => serde_json::from_slice(&value).expect(errors::ILLEGAL_METADATA),
+            _ => env::panic_str(errors::METADATA_UNAVAILABLE),
+        };
+        let change = match change {
+            ParamChange::TokenListing { mut asset } => {
+                asset.apply_metadata(metadata);
+                ParamChange::TokenListing { asset }
+            }
+            ParamChange::RaftListing { mut asset } => {
+                asset.apply_metadata(metadata);
+                ParamChange::RaftListing { asset }
+            }
+            _ => env::panic_str(errors::ILLEGAL_GOVERNANCE_ACTION),
+        };
+        self.internal_schedule_param_change(change)
+    }
+
+    /// Remove raft from raft list and whitelist. Only can be called by owner.
+    /// Fails if the raft is still minted in the account book, debt pool or any open collateral.
+    pub fn remove_raft_list(&mut self, address: AccountId) {
+        self.assert_owner();
+        let old_asset = self.raft_list.remove(&address).expect(errors::ASSET_NOT_FOUND);
+        if self.rusd_id.as_ref() == Some(&address) {
+            self.rusd_id = None;
         }
+        self.settlement_rafts.remove(&address);
+        assert_eq!(self.debt_pool.query_raft_amount(&address).amount, 0, "{}", errors::ASSET_IN_USE);
+        assert_eq!(self.account_book.query_raft_amount(&address), 0, "{}", errors::ASSET_IN_USE);
+        assert_eq!(self.query_open_collateral_count(&address), 0, "{}", errors::ASSET_IN_USE);
+        self.whitelisted_rafts.remove(&address);
+        self.debt_pool.remove_raft(&address);
+        self.account_book.remove_raft(&address);
+        Event::RaftRemove { raft_id: &address, old_asset: &old_asset, by: &env::predecessor_account_id() }.emit();
     }
 
-    /// Add raft. Only can be called by owner.
-    pub fn add_raft_list(&mut self, name: String, symbol: String, standard: String,
-                          decimals: u32, address: AccountId, feed_address: AccountId,
-                          state: u8) {
+    /// Schedule NFT collection listing. Only can be called by owner.
+    /// `feed_address` feeds the floor price of the collection, `collateral_ratio` is also the liquidation threshold.
+    pub fn add_nft_list(&mut self, name: String, symbol: String, address: AccountId, feed_address: AccountId,
+                        collateral_ratio: U128, state: AssetState) -> ChangeId {
         self.assert_owner();
+        let collateral_ratio: u128 = collateral_ratio.into();
         let asset = Asset {
             name,
             symbol,
-            standard,
+            standard: "nep171".to_string(),
+            decimals: 0,
+            address,
+            feed_address,
+            collateral_ratio,
+            liquidation_threshold: 0,
+            max_supply: 0,
+            risk_class: None,
+            state,
+        };
+        self.internal_schedule_param_change(ParamChange::NftListing { asset })
+    }
+
+    /// Remove NFT collection from the NFT list. Only can be called by owner.
+    /// Fails if any open NFT collateral still uses the collection.
+    pub fn remove_nft_list(&mut self, address: AccountId) {
+        self.assert_owner();
+        let old_asset = self.nft_list.remove(&address).expect(errors::ASSET_NOT_FOUND);
+        assert_eq!(self.query_open_collateral_count(&address), 0, "{}", errors::ASSET_IN_USE);
+        Event::NftRemove {
+            collection_id: &address,
+            old_asset: &old_asset,
+            by: &env::predecessor_account_id(),
+        }.emit();
+    }
+
+    /// Change state of a listed NFT collection. Only can be called by owner or guardians,
+    /// guardians can only pause the collection further.
+    pub fn set_nft_state(&mut self, collection_id: AccountId, state: AssetState) {
+        let mut asset = self.query_nft_collection(&collection_id).expect(errors::ASSET_NOT_FOUND);
+        self.assert_asset_state_change(&asset.state, &state);
+        if asset.state != state {
+            Event::NftStateChange {
+                collection_id: &collection_id,
+                old_state: &asset.state,
+                new_state: &state,
+                by: &env::predecessor_account_id(),
+            }.emit();
+            asset.state = state;
+            self.nft_list.insert(&collection_id, &asset);
+        }
+    }
+
+    /// Schedule multi-token listing, e.g. pool shares. Only can be called by owner.
+    /// `feed_address` feeds the price of the token, `collateral_ratio` is also the liquidation threshold.
+    pub fn add_mt_list(&mut self, contract_id: AccountId, token_id: String, decimals: u32,
+                       feed_address: AccountId, collateral_ratio: U128, state: AssetState) -> ChangeId {
+        self.assert_owner();
+        assert!(decimals <= utils::MAX_DECIMALS, "{}", errors::ILLEGAL_DECIMALS);
+        let collateral_ratio: u128 = collateral_ratio.into();
+        let asset = MtAsset {
+            contract_id,
+            token_id,
             decimals,
-            address: address.clone(),
             feed_address,
-            collateral_ratio: 0,
+            collateral_ratio,
             state,
         };
-        self.raft_list.insert(&address, &asset);
+        self.internal_schedule_param_change(ParamChange::MtListing { asset })
+    }
+
+    /// Remove multi-token from the multi-token list. Only can be called by owner.
+    /// Fails if any open multi-token collateral still uses the token.
+    pub fn remove_mt_list(&mut self, contract_id: AccountId, token_id: String) {
+        self.assert_owner();
+        let key = (contract_id, token_id);
+        let old_asset = self.mt_list.remove(&key).expect(errors::ASSET_NOT_FOUND);
+        assert_eq!(self.query_open_mt_collateral_count(&key), 0, "{}", errors::ASSET_IN_USE);
+        Event::MtRemove {
+            contract_id: &key.0,
+            token_id: &key.1,
+            old_asset: &old_asset,
+            by: &env::predecessor_account_id(),
+        }.emit();
+    }
+
+    /// Change state of a listed multi-token. Only can be called by owner or guardians,
+    /// guardians can only pause the token further.
+    pub fn set_mt_state(&mut self, contract_id: AccountId, token_id: String, state: AssetState) {
+        let key = (contract_id, token_id);
+        let mut asset = self.query_mt(&key).expect(errors::ASSET_NOT_FOUND);
+        self.assert_asset_state_change(&asset.state, &state);
+        if asset.state != state {
+            Event::MtStateChange {
+                contract_id: &key.0,
+                token_id: &key.1,
+                old_state: &asset.state,
+                new_state: &state,
+                by: &env::predecessor_account_id(),
+            }.emit();
+            asset.state = state;
+            self.mt_list.insert(&key, &asset);
+        }
+    }
+
+    /// Schedule update of a listed raft. Only can be called by owner.
+    pub fn update_raft(&mut self, address: AccountId, patch: AssetPatch) -> ChangeId {
+        self.assert_owner();
+        assert!(self.query_raft(&address).is_some(), "{}", errors::ASSET_NOT_FOUND);
+        assert!(patch.collateral_ratio.is_none(), "{}", errors::ILLEGAL_ASSET_PATCH);
+        Self::assert_valid_patch(&patch);
+        self.internal_schedule_param_change(ParamChange::RaftUpdate { raft_id: address, patch })
+    }
+
+    fn assert_asset_state_change(&self, old_state: &AssetState, new_state: &AssetState) {
+        self.assert_owner_or_guardians();
+        if !old_state.is_tightened_to(new_state) {
+            // only owner can resume, loosen or delist the asset
+            self.assert_owner();
+        }
+    }
+
+    pub(crate) fn internal_fetch_metadata(&self, change: ParamChange) -> Promise {
+        let address = match &change {
+            ParamChange::TokenListing { asset } | ParamChange::RaftListing { asset } => asset.address.clone(),
+            _ => env::panic_str(errors::ILLEGAL_GOVERNANCE_ACTION),
+        };
+        ext_ft_metadata::ft_metadata(
+            address,
+            utils::NO_DEPOSIT,
+            utils::GAS_FOR_FT_METADATA,
+        ).then(ext_self::list_asset_callback_metadata(
+            change,
+            env::current_account_id(),
+            utils::NO_DEPOSIT,
+            self.gas_config.resolve,
+        ))
+    }
+
+    fn assert_valid_patch(patch: &AssetPatch) {
+        if let Some(decimals) = patch.decimals {
+            assert!(decimals <= utils::MAX_DECIMALS, "{}", errors::ILLEGAL_ASSET_PATCH);
+        }
     }
 
     pub(crate) fn assert_owner(&self) {
         assert_eq!(env::predecessor_account_id(), self.owner_id, "{}", errors::UNAUTHORIZED);
     }
+
+    pub(crate) fn is_owner_or_guardians(&self) -> bool {
+        let predecessor_id = env::predecessor_account_id();
+        predecessor_id == self.owner_id || self.guardians.contains(&predecessor_id)
+    }
+
+    pub(crate) fn assert_owner_or_guardians(&self) {
+        assert!(self.is_owner_or_guardians(), "{}", errors::UNAUTHORIZED);
+    }
+
+    pub(crate) fn assert_owner_guardians_or_keepers(&self) {
+        assert!(self.is_owner_or_guardians() || self.keepers.contains(&env::predecessor_account_id()),
+                "{}", errors::UNAUTHORIZED);
+    }
 }
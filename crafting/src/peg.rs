@@ -0,0 +1,95 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::AccountId;
+
+use crate::*;
+use crate::errors::CraftingError;
+use crate::timelock::{ChangeId, ParamChange};
+
+/// What happens to swaps into rUSD while its price is off the peg.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum PegGuardMode {
+    Block,
+    /// Exchange fee added on top of the usual one, in `FEE_DIVISOR` units.
+    Fee { fee: u32 },
+}
+
+/// Guard of the swaps into rUSD, so a de-pegged rUSD can't be dumped on the debt pool at its oracle price.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PegGuard {
+    /// Deviation of the price from $1 above which the guard applies, in `BPS_DIVISOR` units.
+    pub threshold: u32,
+    pub mode: PegGuardMode,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PegStatus {
+    /// Oracle price of rUSD, in `PRICE_PRECISION` units.
+    pub price: U128,
+    /// Deviation of the price from $1, in `BPS_DIVISOR` units.
+    pub deviation: u32,
+    /// Whether the guard applies to swaps into rUSD.
+    pub guarded: bool,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Schedule change of the guard of swaps into rUSD, `None` removes it. Only can be called by owner.
+    pub fn set_peg_guard(&mut self, guard: Option<PegGuard>) -> ChangeId {
+        self.assert_owner();
+        if let Some(PegGuard { threshold, mode }) = &guard {
+            assert!(*threshold <= utils::BPS_DIVISOR, "{}", errors::ILLEGAL_PEG_GUARD);
+            if let PegGuardMode::Fee { fee } = mode {
+                assert!(*fee <= utils::FEE_DIVISOR, "{}", errors::ILLEGAL_FEE);
+            }
+        }
+        self.internal_schedule_param_change(ParamChange::PegGuard { guard })
+    }
+
+    pub fn get_peg_guard(&self) -> Option<PegGuard> {
+        self.peg_guard.clone()
+    }
+
+    /// Oracle price of rUSD against $1, `None` if rUSD or its price isn't set.
+    pub fn get_rusd_peg(&self) -> Option<PegStatus> {
+        let price = self.query_rusd().and_then(|rusd| self.price_oracle.query_price(&rusd.address))?;
+        let deviation = self.calc_peg_deviation(price);
+        Some(PegStatus {
+            price: U128(price),
+            deviation,
+            guarded: self.peg_guard.as_ref().is_some_and(|guard| deviation > guard.threshold),
+        })
+    }
+}
+
+impl Contract {
+    fn calc_peg_deviation(&self, price: u128) -> u32 {
+        let peg = utils::PRICE_PRECISION as u128;
+        let deviation = math::mul_div(price.abs_diff(peg), utils::BPS_DIVISOR as u128, peg);
+        std::cmp::min(deviation, utils::BPS_DIVISOR as u128) as u32
+    }
+
+    /// Exchange fee of a swap into `new_raft_id`, raised by the peg guard on swaps into an off peg rUSD.
+    /// Fails if the guard blocks the swap.
+    pub(crate) fn check_swap_fee(&self, old_raft_id: &AccountId, new_raft_id: &AccountId) -> Result<u32, CraftingError> {
+        let exchange_fee = self.query_exchange_fee(old_raft_id);
+        let guard = match &self.peg_guard {
+            Some(guard) if self.rusd_id.as_ref() == Some(new_raft_id) => guard,
+            _ => return Ok(exchange_fee),
+        };
+        let price = self.price_oracle.query_price(new_raft_id).ok_or(CraftingError::PriceNotFound)?;
+        if self.calc_peg_deviation(price) <= guard.threshold {
+            return Ok(exchange_fee);
+        }
+        match guard.mode {
+            PegGuardMode::Block => Err(CraftingError::RusdOffPeg),
+            PegGuardMode::Fee { fee } => Ok(std::cmp::min(exchange_fee + fee, utils::FEE_DIVISOR)),
+        }
+    }
+
+    pub(crate) fn query_swap_fee(&self, old_raft_id: &AccountId, new_raft_id: &AccountId) -> u32 {
+        self.check_swap_fee(old_raft_id, new_raft_id).unwrap_or_else(|error| error.panic())
+    }
+}
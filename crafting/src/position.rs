@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+
+use near_contract_standards::non_fungible_token::core::{NonFungibleTokenCore, NonFungibleTokenResolver};
+use near_contract_standards::non_fungible_token::metadata::{NFTContractMetadata, NFT_METADATA_SPEC};
+use near_contract_standards::non_fungible_token::{Token, TokenId};
+use near_sdk::{assert_one_yocto, env, AccountId, PromiseOrValue, PromiseResult};
+
+use crate::*;
+use crate::events::NftTransferData;
+
+#[ext_contract(ext_nft_receiver)]
+pub trait NftReceiverContract {
+    fn nft_on_transfer(&mut self, sender_id: AccountId, previous_owner_id: AccountId,
+                       token_id: TokenId, msg: String) -> PromiseOrValue<bool>;
+}
+
+/// Open account book collaterals are NEP-171 position tokens of their issuer, with the collateral id as token id.
+/// Transferring the token transfers the collateral, its locked tokens and its debt, so the receiver must
+/// accept position transfers with `set_accept_positions` beforehand. Approvals aren't supported.
+#[near_bindgen]
+impl NonFungibleTokenCore for Contract {
+    #[payable]
+    fn nft_transfer(&mut self, receiver_id: AccountId, token_id: TokenId,
+                    approval_id: Option<u64>, memo: Option<String>) {
+        assert_one_yocto();
+        self.assert_contract_running();
+        assert!(approval_id.is_none(), "{}", errors::NO_PERMISSION);
+        let sender_id = env::predecessor_account_id();
+        self.assert_accepts_positions(&receiver_id);
+        self.internal_transfer_position(&sender_id, &token_id, &receiver_id, memo.as_deref());
+    }
+
+    /// The position is transferred back if the receiver fails or returns `true`.
+    #[payable]
+    fn nft_transfer_call(&mut self, receiver_id: AccountId, token_id: TokenId, approval_id: Option<u64>,
+                         memo: Option<String>, msg: String) -> PromiseOrValue<bool> {
+        assert_one_yocto();
+        self.assert_contract_running();
+        assert!(approval_id.is_none(), "{}", errors::NO_PERMISSION);
+        let sender_id = env::predecessor_account_id();
+        self.assert_accepts_positions(&receiver_id);
+        self.internal_transfer_position(&sender_id, &token_id, &receiver_id, memo.as_deref());
+
+        ext_nft_receiver::nft_on_transfer(
+            sender_id.clone(),
+            sender_id.clone(),
+            token_id.clone(),
+            msg,
+            receiver_id.clone(),
+            utils::NO_DEPOSIT,
+            utils::GAS_FOR_NFT_ON_TRANSFER,
+        ).then(ext_self::nft_resolve_transfer(
+            sender_id,
+            receiver_id,
+            token_id,
+            None,
+            env::current_account_id(),
+            utils::NO_DEPOSIT,
+            self.gas_config.resolve,
+        )).into()
+    }
+
+    fn nft_token(&self, token_id: TokenId) -> Option<Token> {
+        let collateral_id: CollateralId = token_id.parse().ok()?;
+        self.query_collateral(collateral_id)
+            .filter(|collateral| collateral.state == CollateralState::Open && !collateral.join_debtpool)
+            .map(|collateral| Token {
+                token_id,
+                owner_id: collateral.issuer,
+                metadata: None,
+                approved_account_ids: None,
+            })
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenResolver for Contract {
+    /// Returns whether the position stayed with the receiver.
+    #[private]
+    fn nft_resolve_transfer(&mut self, previous_owner_id: AccountId, receiver_id: AccountId, token_id: TokenId,
+                            approved_account_ids: Option<HashMap<AccountId, u64>>) -> bool {
+        let _ = approved_account_ids;
+        let keep = match env::promise_result(0) {
+            PromiseResult::Successful(value) => !serde_json::from_slice::<bool>(&value).unwrap_or(true),
+            _ => false,
+        };
+        if keep {
+            return true;
+        }
+
+        // the receiver may have moved the position on already
+        let still_owned = self.nft_token(token_id.clone()).is_some_and(|token| token.owner_id == receiver_id);
+        if still_owned && self.internal_try_transfer_position(&receiver_id, &token_id, &previous_owner_id) {
+            return false;
+        }
+        env::log_str(format!("Position {} not returned to {}", token_id, previous_owner_id).as_str());
+        true
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Offers the open account book collateral of the caller to `new_owner`, who takes it over with
+    /// `accept_collateral`. `None` withdraws the offer.
+    #[payable]
+    pub fn transfer_collateral(&mut self, collateral_id: CollateralId, new_owner: Option<AccountId>) {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let sender_id = env::predecessor_account_id();
+        let collateral = self.query_collateral(collateral_id).expect(errors::COLLATERAL_NOT_FOUND);
+        assert_eq!(collateral.issuer, sender_id, "{}", errors::NO_PERMISSION);
+        assert!(collateral.state == CollateralState::Open, "{}", errors::COLLATERAL_CLOSED);
+        assert!(!collateral.join_debtpool, "{}", errors::COLLATERAL_IN_DEBTPOOL);
+
+        match &new_owner {
+            Some(new_owner) => {
+                assert_ne!(new_owner, &sender_id, "{}", errors::NO_PERMISSION);
+                self.collateral_transfers.insert(&collateral_id, new_owner);
+            }
+            None => {
+                self.collateral_transfers.remove(&collateral_id).expect(errors::COLLATERAL_TRANSFER_NOT_FOUND);
+            }
+        }
+        Event::OfferCollateral {
+            collateral_id,
+            issuer: &sender_id,
+            new_issuer: new_owner.as_ref(),
+        }.emit();
+    }
+
+    /// Takes over the collateral offered to the caller, with its locked tokens and its debt.
+    /// The caller must be registered with the collateral token.
+    #[payable]
+    pub fn accept_collateral(&mut self, collateral_id: CollateralId) {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let receiver_id = env::predecessor_account_id();
+        assert!(self.collateral_transfers.get(&collateral_id).as_ref() == Some(&receiver_id),
+                "{}", errors::COLLATERAL_TRANSFER_NOT_FOUND);
+        let issuer = self.query_collateral(collateral_id).expect(errors::COLLATERAL_NOT_FOUND).issuer;
+        self.internal_transfer_position(&issuer, &collateral_id.to_string(), &receiver_id, None);
+    }
+
+    /// Let anyone send positions to the caller with `nft_transfer`, or only through offers
+    /// accepted with `accept_collateral`.
+    #[payable]
+    pub fn set_accept_positions(&mut self, enabled: bool) {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let account_id = env::predecessor_account_id();
+        assert!(self.internal_get_account(&account_id).is_some(), "{}", errors::ACC_NOT_REGISTERED);
+
+        if enabled {
+            self.position_receivers.insert(&account_id);
+        } else {
+            self.position_receivers.remove(&account_id);
+        }
+        Event::AcceptPositions { account_id: &account_id, enabled }.emit();
+    }
+
+    pub fn is_accepting_positions(&self, account_id: AccountId) -> bool {
+        self.position_receivers.contains(&account_id)
+    }
+
+    /// Account the collateral is offered to, if any.
+    pub fn get_collateral_transfer(&self, collateral_id: CollateralId) -> Option<AccountId> {
+        self.collateral_transfers.get(&collateral_id)
+    }
+
+    pub fn nft_metadata(&self) -> NFTContractMetadata {
+        NFTContractMetadata {
+            spec: NFT_METADATA_SPEC.to_string(),
+            name: "Crafting Positions".to_string(),
+            symbol: "CPOS".to_string(),
+            icon: None,
+            base_uri: None,
+            reference: None,
+            reference_hash: None,
+        }
+    }
+}
+
+impl Contract {
+    fn assert_accepts_positions(&self, receiver_id: &AccountId) {
+        assert!(self.position_receivers.contains(receiver_id), "{}", errors::POSITIONS_NOT_ACCEPTED);
+    }
+
+    fn internal_transfer_position(&mut self, sender_id: &AccountId, token_id: &TokenId,
+                                  receiver_id: &AccountId, memo: Option<&str>) {
+        let collateral_id: CollateralId = token_id.parse().expect(errors::COLLATERAL_NOT_FOUND);
+        self.internal_transfer_collateral(sender_id, collateral_id, receiver_id);
+        NftEvent::Transfer([NftTransferData {
+            old_owner_id: sender_id,
+            new_owner_id: receiver_id,
+            token_ids: [token_id.clone()],
+            memo,
+        }]).emit();
+    }
+
+    /// Transfers the position back without panicking, returns whether it could.
+    fn internal_try_transfer_position(&mut self, sender_id: &AccountId, token_id: &TokenId,
+                                      receiver_id: &AccountId) -> bool {
+        let collateral_id: CollateralId = match token_id.parse() {
+            Ok(collateral_id) => collateral_id,
+            Err(_) => return false,
+        };
+        if self.check_collateral_transfer(sender_id, collateral_id, receiver_id).is_err() {
+            return false;
+        }
+        self.internal_transfer_position(sender_id, token_id, receiver_id, None);
+        true
+    }
+
+    /// Fails if the open account book collateral of the sender can't move to the receiver,
+    /// which must be registered with the collateral token and stay within its borrow limit with the debt.
+    pub(crate) fn check_collateral_transfer(&self, sender_id: &AccountId, collateral_id: CollateralId,
+                                            receiver_id: &AccountId) -> Result<Collateral, errors::CraftingError> {
+        use errors::CraftingError;
+
+        let collateral = self.query_collateral(collateral_id).ok_or(CraftingError::CollateralNotFound)?;
+        if &collateral.issuer != sender_id || receiver_id == sender_id {
+            return Err(CraftingError::NoPermission);
+        }
+        if collateral.state != CollateralState::Open {
+            return Err(CraftingError::CollateralClosed);
+        }
+        if collateral.join_debtpool {
+            return Err(CraftingError::CollateralInDebtpool);
+        }
+        if self.blacklist.contains(sender_id) || self.blacklist.contains(receiver_id) {
+            return Err(CraftingError::AccountBlacklisted);
+        }
+        if self.operation_locks.query_lock(sender_id).is_some() || self.operation_locks.query_lock(receiver_id).is_some() {
+            return Err(CraftingError::OperationPending);
+        }
+        if self.pending_redemptions.get(sender_id).is_some() || self.pending_redemptions.get(receiver_id).is_some() {
+            return Err(CraftingError::RedemptionPending);
+        }
+        let receiver = self.internal_get_account(receiver_id).ok_or(CraftingError::AccNotRegistered)?;
+        if receiver.get_balance(&collateral.token_id).is_none() {
+            return Err(CraftingError::TokenNotReg);
+        }
+        self.check_borrow_limit(receiver_id, &collateral.raft_id, collateral.debt())?;
+        self.check_margin_capacity(receiver_id)?;
+        Ok(collateral)
+    }
+
+    /// Reassigns the collateral to the receiver along with its locked tokens, dropping any pending offer of it.
+    pub(crate) fn internal_transfer_collateral(&mut self, sender_id: &AccountId, collateral_id: CollateralId,
+                                               receiver_id: &AccountId) {
+        let mut collateral = self.check_collateral_transfer(sender_id, collateral_id, receiver_id)
+            .unwrap_or_else(|error| error.panic());
+        self.collateral_transfers.remove(&collateral_id);
+        self.internal_remove_collateral_label(sender_id, collateral_id);
+
+        self.internal_accrue_stability_fee(&mut collateral);
+        let mut sender = self.internal_unwrap_account(sender_id);
+        sender.withdraw(&collateral.token_id, collateral.token_amount);
+        self.internal_save_account(sender_id, sender);
+        let mut receiver = self.internal_unwrap_account(receiver_id);
+        receiver.deposit(&collateral.token_id, collateral.token_amount);
+        self.internal_save_account(receiver_id, receiver);
+
+        collateral.issuer = receiver_id.clone();
+        self.collaterals.replace(collateral_id, &collateral);
+        self.internal_remove_user_collateral(sender_id, collateral_id, &collateral);
+        self.internal_push_user_collateral(receiver_id, collateral_id, &collateral);
+
+        Event::TransferCollateral {
+            collateral_id,
+            old_issuer: sender_id,
+            new_issuer: receiver_id,
+        }.emit();
+    }
+}
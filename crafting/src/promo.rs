@@ -0,0 +1,153 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::UnorderedMap;
+use near_sdk::json_types::U64;
+use near_sdk::{env, AccountId, Timestamp};
+
+use crate::*;
+
+pub type PromoId = u64;
+
+/// Fee lowered by a promotion.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Eq, PartialEq, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum PromoFee {
+    /// Exchange fee of swaps, in `FEE_DIVISOR` units.
+    Exchange,
+    /// Mint fee, in `BPS_DIVISOR` units.
+    Mint,
+}
+
+impl PromoFee {
+    fn divisor(&self) -> u32 {
+        match self {
+            PromoFee::Exchange => utils::FEE_DIVISOR,
+            PromoFee::Mint => utils::BPS_DIVISOR,
+        }
+    }
+}
+
+/// Temporary fee override, in force from `starts_at` until `ends_at`.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeePromo {
+    pub fee: PromoFee,
+    /// Raft the promotion applies to, all rafts if `None`.
+    pub raft_id: Option<AccountId>,
+    pub rate: u32,
+    #[serde(with = "u64_dec_format")]
+    pub starts_at: Timestamp,
+    #[serde(with = "u64_dec_format")]
+    pub ends_at: Timestamp,
+}
+
+impl FeePromo {
+    fn is_active(&self, now: Timestamp) -> bool {
+        self.starts_at <= now && now < self.ends_at
+    }
+
+    fn applies_to(&self, fee: PromoFee, raft_id: &AccountId) -> bool {
+        self.fee == fee && self.raft_id.as_ref().is_none_or(|promo_raft_id| promo_raft_id == raft_id)
+    }
+}
+
+/// Fee promotions scheduled by the owner. Promotions can only lower fees.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct FeePromos {
+    next_id: PromoId,
+    promos: UnorderedMap<PromoId, FeePromo>,
+}
+
+impl FeePromos {
+    pub(crate) fn new() -> Self {
+        Self {
+            next_id: 0,
+            promos: UnorderedMap::new(StorageKey::FeePromos),
+        }
+    }
+
+    /// Rate of the fee of the raft, the lowest of `rate` and the rates of the active promotions.
+    pub(crate) fn query_rate(&self, fee: PromoFee, raft_id: &AccountId, rate: u32) -> u32 {
+        let now = env::block_timestamp();
+        self.promos.values()
+            .filter(|promo| promo.is_active(now) && promo.applies_to(fee, raft_id))
+            .map(|promo| promo.rate)
+            .fold(rate, std::cmp::min)
+    }
+
+    /// Removes the promotions which are over.
+    fn prune(&mut self) {
+        let now = env::block_timestamp();
+        let ended: Vec<PromoId> = self.promos.iter()
+            .filter(|(_, promo)| promo.ends_at <= now)
+            .map(|(promo_id, _)| promo_id)
+            .collect();
+        for promo_id in ended {
+            self.promos.remove(&promo_id);
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Schedule a fee promotion between two timestamps, for one raft or for all of them if `raft_id` is `None`.
+    /// Promotions which are over are removed. Only can be called by owner.
+    pub fn schedule_fee_promo(&mut self, fee: PromoFee, raft_id: Option<AccountId>, rate: u32,
+                              starts_at: U64, ends_at: U64) -> PromoId {
+        self.assert_owner();
+        let (starts_at, ends_at): (Timestamp, Timestamp) = (starts_at.into(), ends_at.into());
+        assert!(rate <= fee.divisor(), "{}", errors::ILLEGAL_FEE);
+        assert!(starts_at < ends_at && env::block_timestamp() < ends_at, "{}", errors::ILLEGAL_PROMO_PERIOD);
+        if let Some(raft_id) = &raft_id {
+            assert!(self.query_raft(raft_id).is_some(), "{}", errors::ASSET_NOT_FOUND);
+        }
+
+        self.fee_promos.prune();
+        assert!(self.fee_promos.promos.len() < utils::MAX_FEE_PROMOS, "{}", errors::MAX_FEE_PROMOS_REACHED);
+        let promo_id = self.fee_promos.next_id;
+        self.fee_promos.next_id += 1;
+        let promo = FeePromo { fee, raft_id, rate, starts_at, ends_at };
+        self.fee_promos.promos.insert(&promo_id, &promo);
+        Event::ScheduleFeePromo { promo_id, promo: &promo }.emit();
+        promo_id
+    }
+
+    /// Cancel a fee promotion, whether it started or not. Only can be called by owner.
+    pub fn cancel_fee_promo(&mut self, promo_id: PromoId) {
+        self.assert_owner();
+        self.fee_promos.promos.remove(&promo_id).expect(errors::PROMO_NOT_FOUND);
+        Event::CancelFeePromo { promo_id }.emit();
+    }
+
+    /// Fee promotions in force now.
+    pub fn get_active_fee_promos(&self) -> Vec<(PromoId, FeePromo)> {
+        let now = env::block_timestamp();
+        self.fee_promos.promos.iter().filter(|(_, promo)| promo.is_active(now)).collect()
+    }
+
+    /// Fee promotions not over yet, including the ones which didn't start.
+    pub fn get_fee_promos(&self) -> Vec<(PromoId, FeePromo)> {
+        let now = env::block_timestamp();
+        self.fee_promos.promos.iter().filter(|(_, promo)| now < promo.ends_at).collect()
+    }
+
+    /// Exchange fee of swaps from the raft now, in `FEE_DIVISOR` units.
+    pub fn get_exchange_fee(&self, raft_id: AccountId) -> u32 {
+        self.query_exchange_fee(&raft_id)
+    }
+
+    /// Mint fee of the raft now, in `BPS_DIVISOR` units.
+    pub fn get_mint_fee(&self, raft_id: AccountId) -> u32 {
+        self.query_mint_fee(&raft_id)
+    }
+}
+
+impl Contract {
+    pub(crate) fn query_exchange_fee(&self, raft_id: &AccountId) -> u32 {
+        self.fee_promos.query_rate(PromoFee::Exchange, raft_id, self.exchange_fee)
+    }
+
+    pub(crate) fn query_mint_fee(&self, raft_id: &AccountId) -> u32 {
+        self.fee_promos.query_rate(PromoFee::Mint, raft_id, self.mint_fee)
+    }
+}
@@ -0,0 +1,174 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, UnorderedMap};
+use near_sdk::{AccountId, Balance};
+
+use crate::*;
+use crate::farm::FarmTarget;
+use crate::timelock::{ChangeId, ParamChange};
+
+/// Stablecoin swapped at par for rUSD by the peg stability module.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PsmStable {
+    /// Max amount of the stablecoin held, 0 stops swapping it in.
+    #[serde(with = "u128_dec_format")]
+    pub cap: Balance,
+    /// Amount of the stablecoin held, backing the rUSD minted for it.
+    #[serde(with = "u128_dec_format")]
+    pub reserve: Balance,
+}
+
+/// Peg stability module, keeping rUSD near $1 by swapping it 1:1 for whitelisted stablecoins.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Psm {
+    /// Fee charged in rUSD on both directions, in `BPS_DIVISOR` units.
+    pub(crate) fee: u32,
+    /// Mapping from stablecoin to its cap and reserve.
+    stables: UnorderedMap<AccountId, PsmStable>,
+    /// Mapping from account and stablecoin to the amount it swapped in and didn't swap out, staked in farms.
+    deposits: LookupMap<(AccountId, AccountId), Balance>,
+}
+
+impl Psm {
+    pub(crate) fn new() -> Self {
+        Self {
+            fee: 0,
+            stables: UnorderedMap::new(StorageKey::PsmStables),
+            deposits: LookupMap::new(StorageKey::PsmDeposits),
+        }
+    }
+
+    pub(crate) fn query_stable(&self, token_id: &AccountId) -> Option<PsmStable> {
+        self.stables.get(token_id)
+    }
+
+    pub(crate) fn query_deposit(&self, account_id: &AccountId, token_id: &AccountId) -> Balance {
+        self.deposits.get(&(account_id.clone(), token_id.clone())).unwrap_or(0)
+    }
+
+    fn insert_deposit(&mut self, account_id: &AccountId, token_id: &AccountId, amount: Balance) {
+        let key = (account_id.clone(), token_id.clone());
+        if amount > 0 {
+            self.deposits.insert(&key, &amount);
+        } else {
+            self.deposits.remove(&key);
+        }
+    }
+
+    pub(crate) fn set_cap(&mut self, token_id: &AccountId, cap: Balance) {
+        let mut stable = self.query_stable(token_id).unwrap_or(PsmStable { cap: 0, reserve: 0 });
+        stable.cap = cap;
+        self.stables.insert(token_id, &stable);
+    }
+
+    /// Adds the swapped in stablecoins to the reserve, panics above the cap.
+    fn add_reserve(&mut self, token_id: &AccountId, amount: Balance) {
+        let mut stable = self.query_stable(token_id).expect(errors::PSM_STABLE_NOT_FOUND);
+        stable.reserve += amount;
+        assert!(stable.reserve <= stable.cap, "{}", errors::PSM_CAP_EXCEEDED);
+        self.stables.insert(token_id, &stable);
+    }
+
+    fn sub_reserve(&mut self, token_id: &AccountId, amount: Balance) {
+        let mut stable = self.query_stable(token_id).expect(errors::PSM_STABLE_NOT_FOUND);
+        assert!(stable.reserve >= amount, "{}", errors::PSM_RESERVE_TOO_LOW);
+        stable.reserve -= amount;
+        self.stables.insert(token_id, &stable);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Schedule change of the fee charged on swaps with the peg stability module, in `BPS_DIVISOR` units.
+    /// Only can be called by owner.
+    pub fn set_psm_fee(&mut self, fee: u32) -> ChangeId {
+        self.assert_owner();
+        assert!(fee <= utils::BPS_DIVISOR, "{}", errors::ILLEGAL_FEE);
+        self.internal_schedule_param_change(ParamChange::PsmFee { fee })
+    }
+
+    /// Schedule change of the max amount of a listed token held by the peg stability module,
+    /// 0 stops swapping it in. Only can be called by owner.
+    pub fn set_psm_cap(&mut self, token_id: AccountId, cap: U128) -> ChangeId {
+        self.assert_owner();
+        assert!(self.query_token(&token_id).is_some(), "{}", errors::ASSET_NOT_FOUND);
+        self.internal_schedule_param_change(ParamChange::PsmCap { token_id, cap: cap.into() })
+    }
+
+    pub fn get_psm_fee(&self) -> u32 {
+        self.psm.fee
+    }
+
+    pub fn get_psm_stable(&self, token_id: AccountId) -> Option<PsmStable> {
+        self.psm.query_stable(&token_id)
+    }
+
+    pub fn get_psm_stables(&self) -> Vec<(AccountId, PsmStable)> {
+        self.psm.stables.to_vec()
+    }
+}
+
+impl Contract {
+    /// Takes the stablecoins received into the reserve and mints the rUSD at par, less the fee, to the sender.
+    pub(crate) fn internal_psm_swap_in(&mut self, sender_id: &AccountId, token_id: &AccountId, amount: Balance) {
+        self.assert_not_blacklisted(sender_id);
+        assert!(self.is_in_whitelisted_tokens(token_id), "{}", errors::TOKEN_NOT_WHITELISTED);
+        self.assert_token_state(token_id, AssetState::is_mintable);
+        let rusd = self.query_rusd().expect(errors::ASSET_NOT_FOUND);
+        assert!(rusd.state.is_mintable(), "{}", errors::ASSET_PAUSED);
+        let token = self.query_token(token_id).expect(errors::ASSET_NOT_FOUND);
+
+        self.psm.add_reserve(token_id, amount);
+        let deposit = self.psm.query_deposit(sender_id, token_id);
+        self.psm.insert_deposit(sender_id, token_id, deposit + amount);
+        self.internal_sync_farms(sender_id, FarmTarget::PsmDeposit { token_id: token_id.clone() });
+        let rusd_amount = math::ratio(&[amount, math::pow10(rusd.decimals)], &[math::pow10(token.decimals)]);
+        let fee_amount = math::share(rusd_amount, self.psm.fee, utils::BPS_DIVISOR);
+        assert!(rusd_amount > fee_amount, "{}", errors::ILLEGAL_SWAP_AMOUNT);
+
+        self.internal_accrue_fee(&rusd.address, fee_amount);
+        self.account_book.mint_out(&rusd.address, rusd_amount - fee_amount);
+        self.internal_mint_rafts(&rusd.address, sender_id, rusd_amount - fee_amount);
+
+        Event::PsmSwapIn {
+            account_id: sender_id,
+            token_id,
+            amount: U128(amount),
+            rusd_amount: U128(rusd_amount - fee_amount),
+            fee: U128(fee_amount),
+        }.emit();
+    }
+
+    /// Burns the rUSD received and credits the stablecoins at par, less the fee, to the deposits of the sender.
+    pub(crate) fn internal_psm_swap_out(&mut self, sender_id: &AccountId, rusd_id: &AccountId,
+                                        token_id: &AccountId, amount: Balance) {
+        self.assert_not_blacklisted(sender_id);
+        let rusd = self.query_rusd().expect(errors::ASSET_NOT_FOUND);
+        assert_eq!(&rusd.address, rusd_id, "{}", errors::ILLEGAL_TRANSFER_MSG);
+        assert!(rusd.state.is_withdrawable(), "{}", errors::ASSET_PAUSED);
+        let token = self.query_token(token_id).expect(errors::ASSET_NOT_FOUND);
+
+        let fee_amount = math::share(amount, self.psm.fee, utils::BPS_DIVISOR);
+        let token_amount = math::ratio(&[amount - fee_amount, math::pow10(token.decimals)], &[math::pow10(rusd.decimals)]);
+        assert!(token_amount > 0, "{}", errors::ILLEGAL_SWAP_AMOUNT);
+        self.psm.sub_reserve(token_id, token_amount);
+        // stablecoins swapped in by others aren't deposits of the sender
+        let deposit = self.psm.query_deposit(sender_id, token_id);
+        self.psm.insert_deposit(sender_id, token_id, deposit.saturating_sub(token_amount));
+        self.internal_sync_farms(sender_id, FarmTarget::PsmDeposit { token_id: token_id.clone() });
+
+        // the fee is minted again when claimed
+        self.internal_accrue_fee(rusd_id, fee_amount);
+        self.account_book.burn_in(rusd_id, amount);
+        self.internal_burn_held_rafts(rusd_id, amount);
+        self.internal_deposit(sender_id, token_id, token_amount);
+
+        Event::PsmSwapOut {
+            account_id: sender_id,
+            token_id,
+            amount: U128(token_amount),
+            rusd_amount: U128(amount),
+            fee: U128(fee_amount),
+        }.emit();
+    }
+}
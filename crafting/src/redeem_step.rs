@@ -0,0 +1,90 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::{assert_one_yocto, env, AccountId};
+
+use crate::*;
+
+/// Debt pool redemption whose collaterals are returned in steps.
+/// The collateral list of the account is visited backwards, so the swap-removal of closed collaterals
+/// only moves already visited ones. The account can't add or drop collaterals until the redemption completes,
+/// see `assert_no_pending_redemption`, so no collateral is moved into the part left to visit.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct PendingRedemption {
+    /// Number of positions of the collateral list of the account not visited yet.
+    pub remaining: u64,
+    pub unwrap: bool,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Returns the next debt pool collaterals of the pending debt pool redemption of the caller.
+    /// Returns the number of collaterals left to visit, 0 once the redemption is complete.
+    #[payable]
+    pub fn redeem_step(&mut self) -> WithNonce<U64> {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let sender_id = env::predecessor_account_id();
+        assert!(self.pending_redemptions.get(&sender_id).is_some(), "{}", errors::REDEMPTION_NOT_FOUND);
+        let probe = self.internal_start_probe();
+        let remaining = self.internal_redeem_step(&sender_id);
+        self.internal_end_probe(OperationKind::Redeem, probe);
+        WithNonce { value: U64(remaining), nonce: self.internal_bump_nonce(&sender_id) }
+    }
+
+    /// Number of collaterals left to visit by the debt pool redemption of the account, if one is pending.
+    pub fn get_pending_redemption(&self, account_id: AccountId) -> Option<U64> {
+        self.pending_redemptions.get(&account_id).map(|redemption| U64(redemption.remaining))
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_no_pending_redemption(&self, account_id: &AccountId) {
+        assert!(self.pending_redemptions.get(account_id).is_none(), "{}", errors::REDEMPTION_PENDING);
+    }
+
+    pub(crate) fn internal_redeem_step(&mut self, sender_id: &AccountId) -> u64 {
+        let mut redemption = match self.pending_redemptions.get(sender_id) {
+            Some(redemption) => redemption,
+            None => return 0,
+        };
+
+        let end = redemption.remaining.saturating_sub(utils::REDEEM_STEP_SIZE);
+        while redemption.remaining > end {
+            redemption.remaining -= 1;
+            let collateral_id = match self.user_collaterals.get(sender_id)
+                .and_then(|collateral_ids| collateral_ids.as_vector().get(redemption.remaining)) {
+                Some(collateral_id) => collateral_id,
+                None => continue,
+            };
+            let collateral = match self.query_collateral(collateral_id) {
+                Some(collateral) if collateral.join_debtpool && collateral.state == CollateralState::Open => collateral,
+                _ => continue,
+            };
+            self.assert_token_state(&collateral.token_id, AssetState::is_withdrawable);
+
+            // close collateral
+            self.internal_close_collateral(collateral_id, collateral.clone());
+            Event::Redeem {
+                account_id: sender_id,
+                collateral_id,
+                token_id: &collateral.token_id,
+                token_amount: U128(collateral.token_amount),
+            }.emit();
+            self.internal_record_operation(OperationKind::Redeem, sender_id,
+                                           vec![collateral.token_id.clone()], vec![collateral.token_amount]);
+
+            let mut account = self.internal_unwrap_account(sender_id);
+            account.withdraw(&collateral.token_id, collateral.token_amount);
+            self.internal_save_account(sender_id, account);
+            self.internal_send_tokens_or_near(sender_id, &collateral.token_id, collateral.token_amount,
+                                              redemption.unwrap);
+        }
+
+        if redemption.remaining == 0 {
+            self.pending_redemptions.remove(sender_id);
+        } else {
+            self.pending_redemptions.insert(sender_id, &redemption);
+        }
+        redemption.remaining
+    }
+}
@@ -0,0 +1,168 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::{env, AccountId, Balance, Timestamp};
+
+use crate::*;
+
+/// Base rate of the fee on rUSD redemptions from the debt pool, in `RATIO_DIVISOR` units.
+/// Redemptions raise it by half the share of the debt pool they redeem and it halves every
+/// `REDEMPTION_HALF_LIFE`, so runs of redemptions get more and more expensive.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct RedemptionRate {
+    base_rate: u128,
+    /// Timestamp of the last redemption, from which the base rate decays.
+    updated_at: Timestamp,
+}
+
+impl RedemptionRate {
+    pub(crate) fn new() -> Self {
+        Self {
+            base_rate: 0,
+            updated_at: env::block_timestamp(),
+        }
+    }
+
+    /// Base rate decayed since the last redemption, halving per half life
+    /// and linearly approximated within a half life.
+    pub(crate) fn query_base_rate(&self) -> u128 {
+        let elapsed = env::block_timestamp().saturating_sub(self.updated_at);
+        let half_lives = elapsed / utils::REDEMPTION_HALF_LIFE;
+        if half_lives >= u128::BITS as u64 { return 0; }
+        let rate = self.base_rate >> half_lives;
+        let remainder = (elapsed % utils::REDEMPTION_HALF_LIFE) as u128;
+        rate - math::mul_div(rate, remainder, 2 * utils::REDEMPTION_HALF_LIFE as u128)
+    }
+
+    /// Fee rate of a redemption, the floor plus the base rate, capped at 100%.
+    pub(crate) fn query_fee_rate(&self) -> u128 {
+        std::cmp::min(utils::REDEMPTION_FEE_FLOOR + self.query_base_rate(), utils::RATIO_DIVISOR)
+    }
+
+    /// Raises the base rate by a redemption of `redeemed_value` out of the `total_value` of the debt pool.
+    /// Returns the fee rate of the redemption.
+    pub(crate) fn redeem(&mut self, redeemed_value: u128, total_value: u128) -> u128 {
+        let redeemed_rate = if total_value == 0 { utils::RATIO_DIVISOR } else {
+            math::mul_div(redeemed_value, utils::RATIO_DIVISOR, total_value)
+        };
+        self.base_rate = std::cmp::min(self.query_base_rate() + redeemed_rate / 2, utils::RATIO_DIVISOR);
+        self.updated_at = env::block_timestamp();
+        self.query_fee_rate()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Base rate of the redemption fee, decayed to now, in `RATIO_DIVISOR` units.
+    pub fn get_redemption_base_rate(&self) -> U128 {
+        U128(self.redemption_rate.query_base_rate())
+    }
+
+    /// Fee rate a redemption from the debt pool would pay before raising the base rate, in `RATIO_DIVISOR` units.
+    pub fn get_redemption_fee_rate(&self) -> U128 {
+        U128(self.redemption_rate.query_fee_rate())
+    }
+
+    /// Timestamp of the last redemption from the debt pool.
+    pub fn get_last_redemption_time(&self) -> U64 {
+        U64(self.redemption_rate.updated_at)
+    }
+
+    /// Open rUSD collaterals of the account book redeemable with rUSD among the collaterals with ids
+    /// from `from_index`, at most `limit` of them, by ascending collateral ratio.
+    /// The pages are merged by collateral ratio to pass to the `RedeemRusd` transfer message.
+    pub fn get_rusd_redemption_hints(&self, from_index: u64, limit: u64) -> Vec<CollateralId> {
+        let rusd_id = match self.rusd_id.as_ref() {
+            Some(rusd_id) => rusd_id,
+            None => return vec![],
+        };
+        let mut hints: Vec<(u128, CollateralId)> = (from_index..std::cmp::min(from_index.saturating_add(limit),
+                                                                              self.collaterals.len()))
+            .filter_map(|collateral_id| self.collaterals.get(collateral_id).map(|collateral| (collateral_id, collateral)))
+            .filter(|(_, collateral)| collateral.state == CollateralState::Open && !collateral.join_debtpool
+                && &collateral.raft_id == rusd_id && collateral.debt() > 0)
+            .map(|(collateral_id, collateral)| {
+                let collateral_ratio = self.calc_collateral_ratio(&collateral.token_id, collateral.token_amount,
+                                                                  rusd_id, collateral.debt());
+                (collateral_ratio, collateral_id)
+            })
+            .filter(|(collateral_ratio, _)| *collateral_ratio >= 100)
+            .collect();
+        hints.sort();
+        hints.into_iter().map(|(_, collateral_id)| collateral_id).collect()
+    }
+}
+
+impl Contract {
+    /// Burns the rUSD received against the debts of the collaterals, in ascending collateral ratio order,
+    /// and credits their tokens worth the burned rUSD, less the redemption fee left to the issuers,
+    /// to the deposits of the sender. Collaterals under 100% or of locked issuers are skipped.
+    /// Returns the amount of rUSD not used.
+    pub(crate) fn internal_redeem_rusd(&mut self, sender_id: &AccountId, rusd_id: &AccountId, amount: Balance,
+                                       collateral_ids: Vec<CollateralId>) -> Balance {
+        self.assert_not_blacklisted(sender_id);
+        let rusd = self.query_rusd().expect(errors::ASSET_NOT_FOUND);
+        assert_eq!(&rusd.address, rusd_id, "{}", errors::ILLEGAL_TRANSFER_MSG);
+        assert!(rusd.state.is_withdrawable(), "{}", errors::ASSET_PAUSED);
+        assert!(!collateral_ids.is_empty() && collateral_ids.len() <= utils::MAX_BATCH_SIZE,
+                "{}", errors::ILLEGAL_BATCH_SIZE);
+
+        let fee_rate = self.redemption_rate.query_fee_rate();
+        let supply = self.calc_raft_supply(rusd_id);
+        let mut remaining = amount;
+        let mut last_ratio = 0;
+        for collateral_id in collateral_ids {
+            if remaining == 0 { break; }
+            let mut collateral = self.query_collateral(collateral_id).expect(errors::COLLATERAL_NOT_FOUND);
+            assert_eq!(collateral.state, CollateralState::Open, "{}", errors::COLLATERAL_CLOSED);
+            assert!(!collateral.join_debtpool, "{}", errors::COLLATERAL_IN_DEBTPOOL);
+            assert_eq!(&collateral.raft_id, rusd_id, "{}", errors::ILLEGAL_COLLATERAL_ASSET);
+            self.assert_token_state(&collateral.token_id, AssetState::is_withdrawable);
+
+            self.internal_accrue_stability_fee(&mut collateral);
+            let collateral_ratio = self.calc_collateral_ratio(&collateral.token_id, collateral.token_amount,
+                                                              rusd_id, collateral.debt());
+            assert!(collateral_ratio >= last_ratio, "{}", errors::ILLEGAL_REDEMPTION_ORDER);
+            last_ratio = collateral_ratio;
+            if collateral_ratio < 100 || self.operation_locks.query_lock(&collateral.issuer).is_some() {
+                env::log_str(format!("Collateral {} skipped", collateral_id).as_str());
+                continue;
+            }
+
+            // the accrued stability fee is repaid first
+            let redeemed_amount = std::cmp::min(remaining, collateral.debt());
+            let fee_amount = std::cmp::min(redeemed_amount, collateral.stability_fee);
+            collateral.stability_fee -= fee_amount;
+            collateral.raft_amount -= redeemed_amount - fee_amount;
+            self.internal_accrue_fee(rusd_id, fee_amount);
+
+            let token_amount = self.valuation().convert(rusd_id, redeemed_amount, &collateral.token_id);
+            let redemption_fee_amount = math::mul_div(token_amount, fee_rate, utils::RATIO_DIVISOR);
+            collateral.token_amount -= token_amount - redemption_fee_amount;
+            self.collaterals.replace(collateral_id, &collateral);
+            self.internal_unlock_collateral(&collateral.issuer, &collateral.token_id, token_amount - redemption_fee_amount);
+
+            let mut account = self.internal_unwrap_account(&collateral.issuer);
+            account.withdraw(&collateral.token_id, token_amount - redemption_fee_amount);
+            self.internal_save_account(&collateral.issuer, account);
+            self.internal_deposit(sender_id, &collateral.token_id, token_amount - redemption_fee_amount);
+            remaining -= redeemed_amount;
+
+            Event::RedeemRusd {
+                account_id: sender_id,
+                collateral_id,
+                rusd_amount: U128(redeemed_amount),
+                token_id: &collateral.token_id,
+                token_amount: U128(token_amount - redemption_fee_amount),
+                fee: U128(redemption_fee_amount),
+            }.emit();
+        }
+
+        let redeemed_amount = amount - remaining;
+        if redeemed_amount > 0 {
+            self.redemption_rate.redeem(redeemed_amount, supply);
+            self.account_book.burn_in(rusd_id, redeemed_amount);
+            self.internal_burn_held_rafts(rusd_id, redeemed_amount);
+        }
+        remaining
+    }
+}
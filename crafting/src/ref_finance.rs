@@ -0,0 +1,305 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::UnorderedMap;
+use near_sdk::{assert_one_yocto, env, serde_json, AccountId, Balance, Promise, PromiseResult};
+
+use crate::*;
+use crate::timelock::{ChangeId, ParamChange};
+
+/// Swap action of the Ref Finance exchange.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SwapAction {
+    pub pool_id: u64,
+    pub token_in: AccountId,
+    pub amount_in: Option<U128>,
+    pub token_out: AccountId,
+    pub min_amount_out: U128,
+}
+
+#[ext_contract(ext_ref_exchange)]
+pub trait RefExchange {
+    fn swap(&mut self, actions: Vec<SwapAction>, referral_id: Option<AccountId>) -> U128;
+
+    fn withdraw(&mut self, token_id: AccountId, amount: U128, unregister: Option<bool>);
+}
+
+/// Ref Finance exchange and pools used to swap seized collateral into rUSD.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct LiquidationRouter {
+    exchange_id: Option<AccountId>,
+    /// Mapping from collateral token to id of its pool with rUSD.
+    pool_ids: UnorderedMap<AccountId, u64>,
+    /// Max slippage of the swap below the oracle value of the seized tokens, in `BPS_DIVISOR` units.
+    pub(crate) max_slippage: u32,
+}
+
+impl LiquidationRouter {
+    pub(crate) fn new() -> Self {
+        Self {
+            exchange_id: None,
+            pool_ids: UnorderedMap::new(StorageKey::LiquidationPools),
+            max_slippage: utils::DEFAULT_LIQUIDATION_SLIPPAGE,
+        }
+    }
+
+    /// Exchange and pool swapping the token, if both are configured.
+    pub(crate) fn query_route(&self, token_id: &AccountId) -> Option<(AccountId, u64)> {
+        Some((self.exchange_id.clone()?, self.pool_ids.get(token_id)?))
+    }
+
+    fn unwrap_exchange(&self) -> AccountId {
+        self.exchange_id.clone().expect(errors::LIQUIDATION_ROUTE_NOT_FOUND)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Change the Ref Finance exchange used for liquidations. Only can be called by owner.
+    /// This contract must be registered on the exchange, and on the tokens swapped through it.
+    pub fn set_liquidation_exchange(&mut self, exchange_id: AccountId) {
+        self.assert_owner();
+        self.liquidation_router.exchange_id = Some(exchange_id);
+    }
+
+    /// Set the pool between the token and rUSD used to liquidate collaterals of the token.
+    /// Only can be called by owner.
+    pub fn set_liquidation_pool(&mut self, token_id: AccountId, pool_id: u64) {
+        self.assert_owner();
+        self.query_token(&token_id).expect(errors::ASSET_NOT_FOUND);
+        self.liquidation_router.pool_ids.insert(&token_id, &pool_id);
+    }
+
+    /// Remove the liquidation pool of the token. Only can be called by owner.
+    pub fn remove_liquidation_pool(&mut self, token_id: AccountId) {
+        self.assert_owner();
+        self.liquidation_router.pool_ids.remove(&token_id);
+    }
+
+    /// Schedule change of the max slippage of liquidation swaps below the oracle value of the seized tokens,
+    /// in `BPS_DIVISOR` units. Only can be called by owner.
+    pub fn set_liquidation_slippage(&mut self, slippage_bps: u32) -> ChangeId {
+        self.assert_owner();
+        assert!(slippage_bps <= utils::BPS_DIVISOR, "{}", errors::ILLEGAL_FEE);
+        self.internal_schedule_param_change(ParamChange::LiquidationSlippage { slippage_bps })
+    }
+
+    pub fn get_liquidation_slippage(&self) -> u32 {
+        self.liquidation_router.max_slippage
+    }
+
+    pub fn get_liquidation_exchange(&self) -> Option<AccountId> {
+        self.liquidation_router.exchange_id.clone()
+    }
+
+    pub fn get_liquidation_pools(&self) -> Vec<(AccountId, u64)> {
+        self.liquidation_router.pool_ids.to_vec()
+    }
+
+    /// Seizes an account book collateral under its liquidation threshold, or any one of an issuer in cross margin
+    /// whose collaterals together are, and swaps it into rUSD on Ref Finance, failing if less than `min_rusd_out` is
+    /// received or the oracle value of the tokens minus the max slippage, whichever is higher. The rUSD is burned against the debt of the collateral,
+    /// the amount above the debt is credited to the issuer in the account book.
+    /// Resolves to the new operation nonce of the caller.
+    #[payable]
+    pub fn liquidate_collateral(&mut self, collateral_id: CollateralId, min_rusd_out: U128) -> Promise {
+        assert_one_yocto();
+        self.assert_contract_running();
+
+        let liquidator_id = env::predecessor_account_id();
+        let mut collateral = self.query_collateral(collateral_id).expect(errors::COLLATERAL_NOT_FOUND);
+        assert!(collateral.state == CollateralState::Open, "{}", errors::COLLATERAL_CLOSED);
+        assert!(!collateral.join_debtpool, "{}", errors::COLLATERAL_IN_DEBTPOOL);
+        self.assert_token_state(&collateral.token_id, AssetState::is_withdrawable);
+
+        let rusd_asset = self.query_rusd().expect(errors::ASSET_NOT_FOUND);
+        let (exchange_id, pool_id) = self.liquidation_router.query_route(&collateral.token_id)
+            .expect(errors::LIQUIDATION_ROUTE_NOT_FOUND);
+
+        self.internal_accrue_stability_fee(&mut collateral);
+        self.assert_collateral_liquidatable(&collateral);
+
+        // the collateral is reopened if the swap fails
+        self.internal_close_collateral(collateral_id, collateral.clone());
+        let mut account = self.internal_unwrap_account(&collateral.issuer);
+        account.withdraw(&collateral.token_id, collateral.token_amount);
+        self.internal_save_account(&collateral.issuer, account);
+
+        let oracle_rusd_out = self.valuation().convert(&collateral.token_id, collateral.token_amount,
+                                                       &rusd_asset.address);
+        let min_oracle_rusd_out = math::share(oracle_rusd_out, utils::BPS_DIVISOR - self.liquidation_router.max_slippage,
+                                              utils::BPS_DIVISOR);
+        let action = SwapAction {
+            pool_id,
+            token_in: collateral.token_id.clone(),
+            amount_in: Some(U128(collateral.token_amount)),
+            token_out: rusd_asset.address,
+            min_amount_out: U128(std::cmp::max(min_rusd_out.0, min_oracle_rusd_out)),
+        };
+        let promise = ext_fungible_token::ft_transfer_call(
+            exchange_id.clone(),
+            U128(collateral.token_amount),
+            None,
+            "".to_string(),
+            collateral.token_id,
+            utils::ONE_YOCTO,
+            self.gas_config.ft_transfer_call(),
+        ).then(ext_ref_exchange::swap(
+            vec![action],
+            None,
+            exchange_id,
+            utils::ONE_YOCTO,
+            utils::GAS_FOR_REF_SWAP,
+        )).then(ext_self::liquidation_callback_swap(
+            collateral_id,
+            liquidator_id.clone(),
+            env::current_account_id(),
+            utils::NO_DEPOSIT,
+            self.gas_config.liquidation_callback(),
+        ));
+        self.internal_bump_nonce_then(&liquidator_id, promise)
+    }
+
+    /// Withdraws the rUSD out of the exchange, or the collateral tokens back if the swap failed.
+    #[private]
+    pub fn liquidation_callback_swap(&mut self, collateral_id: CollateralId, liquidator_id: AccountId) -> Promise {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+
+        let collateral = self.query_collateral(collateral_id).unwrap();
+        let exchange_id = self.liquidation_router.unwrap_exchange();
+        let amount_out: Option<U128> = match env::promise_result(0) {
+            PromiseResult::Successful(value) => serde_json::from_slice(&value).ok(),
+            _ => None,
+        };
+
+        match amount_out {
+            Some(amount_out) => {
+                let rusd_id = self.query_rusd().expect(errors::ASSET_NOT_FOUND).address;
+                ext_ref_exchange::withdraw(
+                    rusd_id,
+                    amount_out,
+                    None,
+                    exchange_id,
+                    utils::ONE_YOCTO,
+                    utils::GAS_FOR_REF_WITHDRAW,
+                ).then(ext_self::liquidation_callback_proceeds(
+                    collateral_id,
+                    liquidator_id,
+                    amount_out,
+                    env::current_account_id(),
+                    utils::NO_DEPOSIT,
+                    self.gas_config.liquidation_proceeds(),
+                ))
+            }
+            None => {
+                // if the deposit itself failed the tokens were already refunded and this withdraw fails too
+                ext_ref_exchange::withdraw(
+                    collateral.token_id,
+                    U128(collateral.token_amount),
+                    None,
+                    exchange_id,
+                    utils::ONE_YOCTO,
+                    utils::GAS_FOR_REF_WITHDRAW,
+                ).then(ext_self::liquidation_callback_refund(
+                    collateral_id,
+                    env::current_account_id(),
+                    utils::NO_DEPOSIT,
+                    self.gas_config.resolve,
+                ))
+            }
+        }
+    }
+
+    /// Burns the rUSD received for the collateral and credits the surplus over its debt,
+    /// including its stability fee, to the issuer.
+    #[private]
+    pub fn liquidation_callback_proceeds(&mut self, collateral_id: CollateralId, liquidator_id: AccountId,
+                                         amount_out: U128) {
+        let amount_out: Balance = amount_out.into();
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+
+        if let PromiseResult::Failed = env::promise_result(0) {
+            env::log_str(format!("Failed to withdraw {} rUSD of collateral {} from the exchange",
+                                 amount_out, collateral_id).as_str());
+            return;
+        }
+
+        let collateral = self.query_collateral(collateral_id).unwrap();
+        let rusd_asset = self.query_rusd().expect(errors::ASSET_NOT_FOUND);
+        let debt = self.valuation().convert(&collateral.raft_id, collateral.debt(), &rusd_asset.address);
+        let surplus = amount_out.saturating_sub(debt);
+
+        self.internal_burn_held_rafts(&rusd_asset.address, amount_out);
+        self.account_book.burn_in(&rusd_asset.address, amount_out);
+        if surplus > 0 {
+            self.account_book.mint(&collateral.issuer, &rusd_asset.address, surplus);
+        }
+        // the stability fee is only collected if the swap covered the whole debt
+        if amount_out >= debt {
+            self.internal_accrue_fee(&collateral.raft_id, collateral.stability_fee);
+        }
+
+        Event::Liquidation {
+            liquidator_id: &liquidator_id,
+            account_id: &collateral.issuer,
+            collateral_id,
+            token_id: &collateral.token_id,
+            token_amount: U128(collateral.token_amount),
+            rusd_amount: U128(amount_out),
+            shortfall: U128(debt.saturating_sub(amount_out)),
+        }.emit();
+        self.internal_record_operation(OperationKind::Liquidation, &liquidator_id,
+                                       vec![collateral.token_id, rusd_asset.address],
+                                       vec![collateral.token_amount, amount_out]);
+    }
+
+    /// Gives the tokens back to the issuer and reopens the collateral after a failed swap.
+    #[private]
+    pub fn liquidation_callback_refund(&mut self, collateral_id: CollateralId) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+        if let PromiseResult::Failed = env::promise_result(0) {
+            env::log_str(format!("Tokens of collateral {} not withdrawn from the exchange", collateral_id).as_str());
+        }
+
+        let mut collateral = self.query_collateral(collateral_id).unwrap();
+        let restored = match self.internal_get_account(&collateral.issuer) {
+            Some(mut account) => {
+                let restored = account.deposit_with_storage_check(&collateral.token_id, collateral.token_amount);
+                if restored {
+                    self.accounts.insert(&collateral.issuer, &account.into());
+                }
+                restored
+            }
+            None => false,
+        };
+        if !restored {
+            self.internal_lostfound(&collateral.issuer, &collateral.token_id, collateral.token_amount);
+            return;
+        }
+
+        collateral.state = CollateralState::Open;
+        self.collaterals.replace(collateral_id, &collateral);
+        self.internal_push_user_collateral(&collateral.issuer, collateral_id, &collateral);
+        self.internal_count_open_collateral(&collateral.token_id, true);
+        self.internal_count_open_collateral(&collateral.raft_id, true);
+        NftEvent::Mint([NftMintData {
+            owner_id: &collateral.issuer,
+            token_ids: [collateral_id.to_string()],
+        }]).emit();
+        env::log_str(format!("Collateral {} reopened, the swap failed", collateral_id).as_str());
+    }
+}
@@ -0,0 +1,99 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::{env, AccountId, Balance};
+
+use crate::*;
+use crate::timelock::{ChangeId, ParamChange};
+
+/// Referrers of accounts, earning a share of the fees paid by the accounts they referred.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Referrals {
+    /// Share of the fees going to the referrer, in `BPS_DIVISOR` units.
+    pub(crate) share_bps: u32,
+    /// Mapping from account to its referrer.
+    referrers: LookupMap<AccountId, AccountId>,
+    /// Mapping from referrer and raft to amount earned since the referrer's first referral.
+    earnings: LookupMap<(AccountId, AccountId), Balance>,
+}
+
+impl Referrals {
+    pub(crate) fn new() -> Self {
+        Self {
+            share_bps: 0,
+            referrers: LookupMap::new(StorageKey::Referrers),
+            earnings: LookupMap::new(StorageKey::ReferralEarnings),
+        }
+    }
+
+    pub(crate) fn query_earnings(&self, referrer_id: &AccountId, raft_id: &AccountId) -> Balance {
+        self.earnings.get(&(referrer_id.clone(), raft_id.clone())).unwrap_or(0)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Register the account which referred the caller, only once. The referrer must be registered.
+    pub fn register_referral(&mut self, referrer_id: AccountId) {
+        self.assert_contract_running();
+        let account_id = env::predecessor_account_id();
+        assert_ne!(account_id, referrer_id, "{}", errors::ILLEGAL_REFERRER);
+        assert!(self.internal_get_account(&referrer_id).is_some(), "{}", errors::ACC_NOT_REGISTERED);
+        assert!(self.referrals.referrers.get(&account_id).is_none(), "{}", errors::REFERRER_ALREADY_SET);
+
+        self.referrals.referrers.insert(&account_id, &referrer_id);
+        Event::RegisterReferral { account_id: &account_id, referrer_id: &referrer_id }.emit();
+    }
+
+    /// Schedule change of the share of the fees going to referrers. Only can be called by owner.
+    pub fn set_referral_share(&mut self, share_bps: u32) -> ChangeId {
+        self.assert_owner();
+        assert!(share_bps <= utils::BPS_DIVISOR, "{}", errors::ILLEGAL_FEE);
+        self.internal_schedule_param_change(ParamChange::ReferralShare { share_bps })
+    }
+
+    pub fn get_referral_share(&self) -> u32 {
+        self.referrals.share_bps
+    }
+
+    pub fn get_referrer(&self, account_id: AccountId) -> Option<AccountId> {
+        self.referrals.referrers.get(&account_id)
+    }
+
+    /// Amounts of each raft earned by the referrer.
+    pub fn get_referral_earnings(&self, referrer_id: AccountId) -> Vec<(AccountId, U128)> {
+        self.raft_list.keys()
+            .map(|raft_id| {
+                let amount = self.referrals.query_earnings(&referrer_id, &raft_id);
+                (raft_id, U128(amount))
+            })
+            .filter(|(_, amount)| amount.0 > 0)
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Credits the share of the referrer of the payer in its account book. Returns the amount left for the protocol.
+    pub(crate) fn internal_pay_referrer(&mut self, payer_id: &AccountId, raft_id: &AccountId,
+                                        fee_amount: Balance) -> Balance {
+        // blacklisted referrers don't receive their share, which stays with the protocol
+        let referrer_id = match self.referrals.referrers.get(payer_id) {
+            Some(referrer_id) if !self.blacklist.contains(&referrer_id) => referrer_id,
+            _ => return fee_amount,
+        };
+        let amount = math::share(fee_amount, self.referrals.share_bps, utils::BPS_DIVISOR);
+        if amount == 0 {
+            return fee_amount;
+        }
+
+        self.account_book.mint(&referrer_id, raft_id, amount);
+        let earnings = self.referrals.query_earnings(&referrer_id, raft_id);
+        self.referrals.earnings.insert(&(referrer_id.clone(), raft_id.clone()), &(earnings + amount));
+        Event::ReferralFee {
+            referrer_id: &referrer_id,
+            account_id: payer_id,
+            raft_id,
+            amount: U128(amount),
+        }.emit();
+        fee_amount - amount
+    }
+}
@@ -0,0 +1,75 @@
+use near_sdk::{env, AccountId};
+
+use crate::*;
+use crate::events::AccountList;
+
+/// Gasless flows, called by users through NEP-366 delegate actions submitted by an allowed relayer.
+/// The user is the predecessor and the relayer the signer; the operation nonce of the user,
+/// which must be passed, keeps the relayer from replaying or reordering the calls.
+#[near_bindgen]
+impl Contract {
+    /// Extend relayers allowed to submit gasless calls. Only can be called by owner.
+    pub fn extend_relayers(&mut self, relayers: Vec<AccountId>) {
+        self.assert_owner();
+        let by = env::predecessor_account_id();
+        for relayer in relayers {
+            if self.relayers.insert(&relayer) {
+                Event::AccountListAdd { list: AccountList::Relayers, account_id: &relayer, by: &by }.emit();
+            }
+        }
+    }
+
+    /// Remove relayers. Only can be called by owner.
+    pub fn remove_relayers(&mut self, relayers: Vec<AccountId>) {
+        self.assert_owner();
+        let by = env::predecessor_account_id();
+        for relayer in relayers {
+            if self.relayers.remove(&relayer) {
+                Event::AccountListRemove { list: AccountList::Relayers, account_id: &relayer, by: &by }.emit();
+            }
+        }
+    }
+
+    pub fn get_relayers(&self) -> Vec<AccountId> {
+        self.relayers.to_vec()
+    }
+
+    /// Swap worth up to `MAX_RELAYED_SWAP_VALUE` of the caller, submitted by a relayer.
+    /// Returns the new operation nonce of the account.
+    pub fn relayed_swap(&mut self, old_raft_id: AccountId, new_raft_id: AccountId, swap_amount: U128,
+                        in_debtpool: bool, nonce: u64) -> u64 {
+        self.assert_contract_running();
+        let sender_id = env::predecessor_account_id();
+        self.assert_relayed(&sender_id, nonce);
+        let value = self.valuation().calc_value(&old_raft_id, swap_amount.0);
+        assert!(value <= utils::MAX_RELAYED_SWAP_VALUE, "{}", errors::RELAYED_SWAP_TOO_LARGE);
+
+        if in_debtpool {
+            self.internal_swap_in_debtpool(&sender_id, old_raft_id, new_raft_id, swap_amount.into());
+        } else {
+            self.internal_swap_in_accountbook(&sender_id, old_raft_id, new_raft_id, swap_amount.into());
+        }
+        self.internal_bump_nonce(&sender_id)
+    }
+
+    /// Claim of the caller's lost-found, submitted by a relayer. Returns the amount claimed.
+    pub fn relayed_claim_lostfound(&mut self, token_id: AccountId, nonce: u64) -> WithNonce<U128> {
+        self.assert_contract_running();
+        let sender_id = env::predecessor_account_id();
+        self.assert_relayed(&sender_id, nonce);
+
+        let amount = self.internal_claim_lostfound(&sender_id, token_id);
+        WithNonce { value: U128(amount), nonce: self.internal_bump_nonce(&sender_id) }
+    }
+}
+
+impl Contract {
+    /// Panics unless the call is a delegate action of the sender submitted by an allowed relayer,
+    /// carrying the current operation nonce of the sender.
+    fn assert_relayed(&self, sender_id: &AccountId, nonce: u64) {
+        let relayer_id = env::signer_account_id();
+        assert!(&relayer_id != sender_id && self.relayers.contains(&relayer_id), "{}", errors::RELAYER_NOT_ALLOWED);
+        let account = self.internal_unwrap_account(sender_id);
+        assert_eq!(account.nonce, nonce, "{}", errors::ILLEGAL_NONCE);
+    }
+}
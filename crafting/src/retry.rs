@@ -0,0 +1,151 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::UnorderedMap;
+use near_sdk::json_types::U64;
+use near_sdk::{env, AccountId, Promise, PromiseResult, Timestamp};
+
+use crate::*;
+
+pub type PendingOperationId = u64;
+
+/// Mint or burn of rafts on the raft token, for internal state which already changed.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum PendingKind {
+    Mint,
+    Burn,
+}
+
+/// Mint or burn which failed, kept until it's retried successfully or resolved by the owner.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingOperation {
+    pub kind: PendingKind,
+    pub raft_id: AccountId,
+    /// Account minted to or burnt from on the raft token.
+    pub account_id: AccountId,
+    #[serde(with = "u128_dec_format")]
+    pub amount: Balance,
+    /// Timestamp of the first failure.
+    #[serde(with = "u64_dec_format")]
+    pub failed_at: Timestamp,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct RetryQueue {
+    next_id: PendingOperationId,
+    operations: UnorderedMap<PendingOperationId, PendingOperation>,
+}
+
+impl RetryQueue {
+    pub(crate) fn new() -> Self {
+        Self {
+            next_id: 0,
+            operations: UnorderedMap::new(StorageKey::PendingOperations),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Dispatch a failed operation again. Anyone can call it.
+    /// The operation leaves the queue while in flight and is queued back under the same id if it fails again.
+    pub fn retry(&mut self, op_id: U64) -> Promise {
+        let operation = self.retry_queue.operations.remove(&op_id.0).expect(errors::PENDING_OPERATION_NOT_FOUND);
+        self.internal_dispatch_pending(Some(op_id.0), operation)
+    }
+
+    /// Drop a failed operation settled out of the contract. Only can be called by owner.
+    pub fn resolve_manually(&mut self, op_id: U64) {
+        self.assert_owner();
+        let operation = self.retry_queue.operations.remove(&op_id.0).expect(errors::PENDING_OPERATION_NOT_FOUND);
+        Event::ResolvePendingOperation { op_id: op_id.0, operation: &operation }.emit();
+    }
+
+    pub fn get_pending_operations(&self, from_index: u64, limit: u64) -> Vec<(U64, PendingOperation)> {
+        self.retry_queue.operations.iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .map(|(op_id, operation)| (U64(op_id), operation))
+            .collect()
+    }
+
+    /// Queues the operation if it failed, a failed retry keeping its id.
+    #[private]
+    pub fn pending_operation_callback(&mut self, op_id: Option<U64>, operation: PendingOperation) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+
+        match (env::promise_result(0), op_id) {
+            (PromiseResult::Successful(_), Some(op_id)) => {
+                env::log_str(format!("Pending operation {} settled", op_id.0).as_str());
+            }
+            (PromiseResult::Failed, None) => {
+                let op_id = self.retry_queue.next_id;
+                self.retry_queue.next_id += 1;
+                self.retry_queue.operations.insert(&op_id, &operation);
+                Event::QueuePendingOperation { op_id, operation: &operation }.emit();
+            }
+            (PromiseResult::Failed, Some(op_id)) => {
+                self.retry_queue.operations.insert(&op_id.0, &operation);
+                env::log_str(format!("Retry of pending operation {} failed", op_id.0).as_str());
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Contract {
+    /// Burns rafts held by this contract, queuing the burn if it fails.
+    pub(crate) fn internal_burn_held_rafts(&mut self, raft_id: &AccountId, amount: Balance) -> Promise {
+        let operation = PendingOperation {
+            kind: PendingKind::Burn,
+            raft_id: raft_id.clone(),
+            account_id: env::current_account_id(),
+            amount,
+            failed_at: env::block_timestamp(),
+        };
+        self.internal_dispatch_pending(None, operation)
+    }
+
+    /// Mints rafts to the account, queued for retry if the mint fails.
+    pub(crate) fn internal_mint_rafts(&mut self, raft_id: &AccountId, account_id: &AccountId, amount: Balance) -> Promise {
+        let operation = PendingOperation {
+            kind: PendingKind::Mint,
+            raft_id: raft_id.clone(),
+            account_id: account_id.clone(),
+            amount,
+            failed_at: env::block_timestamp(),
+        };
+        self.internal_dispatch_pending(None, operation)
+    }
+
+    fn internal_dispatch_pending(&mut self, op_id: Option<PendingOperationId>, operation: PendingOperation) -> Promise {
+        let promise = match operation.kind {
+            PendingKind::Mint => ext_enhanced_fungible_token::mint(
+                operation.account_id.clone(),
+                U128(operation.amount),
+                operation.raft_id.clone(),
+                utils::ONE_YOCTO,
+                self.gas_config.mint,
+            ),
+            PendingKind::Burn => ext_enhanced_fungible_token::burn(
+                operation.account_id.clone(),
+                U128(operation.amount),
+                operation.raft_id.clone(),
+                utils::ONE_YOCTO,
+                self.gas_config.burn,
+            ),
+        };
+        promise.then(ext_self::pending_operation_callback(
+            op_id.map(U64),
+            operation,
+            env::current_account_id(),
+            utils::NO_DEPOSIT,
+            self.gas_config.resolve,
+        ))
+    }
+}
@@ -0,0 +1,226 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::{assert_one_yocto, env, AccountId, Balance, Promise, PromiseResult, Timestamp};
+
+use crate::*;
+
+/// Precision of the scale of the debt ratios.
+const SCALE_PRECISION: u128 = 1_000_000_000;
+
+/// Emission of the governance token to the debt pool participants.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RewardEmission {
+    pub token_id: AccountId,
+    /// Amount emitted per second, shared by the participants pro rata by debt ratio.
+    #[serde(with = "u128_dec_format")]
+    pub rate: Balance,
+    /// Timestamp the emission stops at.
+    #[serde(with = "u64_dec_format")]
+    pub end_time: Timestamp,
+}
+
+#[derive(Default, BorshSerialize, BorshDeserialize)]
+pub struct RewardAccount {
+    /// Reward index the account was last settled at.
+    index: u128,
+    unclaimed: Balance,
+}
+
+/// Cumulative reward index of the debt pool.
+/// Between two of its own joins or redemptions, the debt ratio of a participant only changes by the factor
+/// applied to all the debt ratios, tracked by `scale`. The debt ratio over the scale is thus the constant
+/// share of the participant, and the index accrues the emission per unit of it.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct DebtPoolRewards {
+    emission: Option<RewardEmission>,
+    /// Tokens funded through `ft_transfer_call` and not claimed yet, claims can't go beyond it.
+    budget: Balance,
+    index: u128,
+    /// Product of the factors the debt ratios were rescaled by, in `SCALE_PRECISION` units.
+    scale: u128,
+    updated_at: Timestamp,
+    /// Accounts without an entry joined the debt pool before the rewards and are settled from index 0.
+    accounts: LookupMap<AccountId, RewardAccount>,
+}
+
+impl DebtPoolRewards {
+    pub(crate) fn new() -> Self {
+        Self {
+            emission: None,
+            budget: 0,
+            index: 0,
+            scale: SCALE_PRECISION,
+            updated_at: env::block_timestamp(),
+            accounts: LookupMap::new(StorageKey::RewardAccounts),
+        }
+    }
+
+    fn query_index(&self, total_debt_ratio: u128, now: Timestamp) -> u128 {
+        let emission = match &self.emission {
+            Some(emission) => emission,
+            None => return self.index,
+        };
+        let end = std::cmp::min(now, emission.end_time);
+        // the emission is not distributed while nobody holds a debt ratio
+        if end <= self.updated_at || total_debt_ratio == 0 {
+            return self.index;
+        }
+        self.index + math::ratio(&[emission.rate, (end - self.updated_at) as u128, self.scale],
+                                 &[total_debt_ratio, 1_000_000_000])
+    }
+
+    fn accrue(&mut self, total_debt_ratio: u128, now: Timestamp) {
+        self.index = self.query_index(total_debt_ratio, now);
+        self.updated_at = now;
+    }
+
+    fn query_unclaimed(&self, account_id: &AccountId, debt_ratio: u128, index: u128) -> Balance {
+        let account = self.accounts.get(account_id).unwrap_or_default();
+        account.unclaimed + math::ratio(&[debt_ratio, index - account.index], &[self.scale])
+    }
+
+    fn settle(&mut self, account_id: &AccountId, debt_ratio: u128) {
+        let unclaimed = self.query_unclaimed(account_id, debt_ratio, self.index);
+        self.accounts.insert(account_id, &RewardAccount { index: self.index, unclaimed });
+    }
+
+    /// Follows `DebtPool::calc_all_debt_ratio` rescaling all the debt ratios by `old_total_value / new_total_value`.
+    /// The debt ratios are all zeroed when the old total is 0, and so are the rewards not settled yet.
+    pub(crate) fn rescale(&mut self, old_total_value: u128, new_total_value: u128) {
+        if new_total_value == 0 { return; }
+
+        self.scale = if old_total_value == 0 {
+            SCALE_PRECISION
+        } else {
+            std::cmp::max(math::mul_div(self.scale, old_total_value, new_total_value), 1)
+        };
+    }
+
+    /// Drops the entry of the account, returning its unclaimed rewards.
+    pub(crate) fn remove_account(&mut self, account_id: &AccountId) -> Balance {
+        self.accounts.remove(account_id).map(|account| account.unclaimed).unwrap_or(0)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Set the emission of the governance token to the debt pool participants. Only can be called by owner.
+    /// The tokens emitted are funded with `ft_transfer_call`, and the token can't be changed once set.
+    pub fn set_reward_emission(&mut self, token_id: AccountId, rate: U128, end_time: U64) {
+        self.assert_owner();
+        if let Some(emission) = &self.debtpool_rewards.emission {
+            assert!(emission.token_id == token_id, "{}", errors::REWARD_TOKEN_CHANGED);
+        }
+        self.assert_not_listed(&token_id);
+        // accrued at the previous rate up to now
+        self.internal_accrue_rewards();
+        self.debtpool_rewards.emission = Some(RewardEmission {
+            token_id,
+            rate: rate.into(),
+            end_time: end_time.into(),
+        });
+    }
+
+    /// Send the governance tokens earned in the debt pool to the caller, who must be registered on the token.
+    #[payable]
+    pub fn claim_rewards(&mut self) -> Promise {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let account_id = env::predecessor_account_id();
+        self.assert_not_blacklisted(&account_id);
+        let token_id = self.debtpool_rewards.emission.as_ref().expect(errors::REWARDS_NOT_CONFIGURED).token_id.clone();
+
+        self.internal_settle_rewards(&account_id);
+        let mut account = self.debtpool_rewards.accounts.get(&account_id).unwrap();
+        let amount = account.unclaimed;
+        assert!(amount > 0, "{}", errors::NO_REWARDS);
+        assert!(amount <= self.debtpool_rewards.budget, "{}", errors::INSUFFICIENT_BUDGET);
+        account.unclaimed = 0;
+        self.debtpool_rewards.accounts.insert(&account_id, &account);
+        self.debtpool_rewards.budget -= amount;
+
+        ext_fungible_token::ft_transfer(
+            account_id.clone(),
+            U128(amount),
+            None,
+            token_id.clone(),
+            utils::ONE_YOCTO,
+            self.gas_config.ft_transfer,
+        ).then(ext_self::claim_rewards_callback(
+            account_id,
+            token_id,
+            U128(amount),
+            env::current_account_id(),
+            utils::NO_DEPOSIT,
+            self.gas_config.resolve,
+        ))
+    }
+
+    pub fn get_reward_emission(&self) -> Option<RewardEmission> {
+        self.debtpool_rewards.emission.clone()
+    }
+
+    /// Tokens funded for the emission and not claimed yet.
+    pub fn get_reward_budget(&self) -> U128 {
+        U128(self.debtpool_rewards.budget)
+    }
+
+    /// Rewards of the account in the debt pool not claimed yet, including the ones accrued since the last settlement.
+    pub fn get_pending_rewards(&self, account_id: AccountId) -> U128 {
+        let index = self.debtpool_rewards.query_index(self.debt_pool.query_total_debt_ratio(), env::block_timestamp());
+        U128(self.debtpool_rewards.query_unclaimed(&account_id, self.debt_pool.query_debt_ratio(&account_id), index))
+    }
+
+    #[private]
+    pub fn claim_rewards_callback(&mut self, account_id: AccountId, token_id: AccountId, amount: U128) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => {
+                Event::ClaimRewards {
+                    account_id: &account_id,
+                    token_id: &token_id,
+                    amount,
+                }.emit();
+            }
+            PromiseResult::Failed => {
+                // This reverts the changes from claim_rewards function.
+                let mut account = self.debtpool_rewards.accounts.get(&account_id).unwrap_or_default();
+                account.unclaimed += amount.0;
+                self.debtpool_rewards.accounts.insert(&account_id, &account);
+                self.debtpool_rewards.budget += amount.0;
+                env::log_str(format!("Failed to claim {} {} of rewards", amount.0, token_id).as_str());
+            }
+        };
+    }
+}
+
+impl Contract {
+    /// Adds the tokens received to the budget of the emission.
+    pub(crate) fn internal_fund_rewards(&mut self, sender_id: &AccountId, token_id: &AccountId, amount: Balance) {
+        let emission = self.debtpool_rewards.emission.as_ref().expect(errors::REWARDS_NOT_CONFIGURED);
+        assert!(&emission.token_id == token_id, "{}", errors::REWARD_TOKEN_CHANGED);
+        self.debtpool_rewards.budget += amount;
+        Event::FundRewards { account_id: sender_id, token_id, amount: U128(amount) }.emit();
+    }
+
+    /// Brings the reward index up to now, to be called before the debt ratios change.
+    pub(crate) fn internal_accrue_rewards(&mut self) {
+        let total_debt_ratio = self.debt_pool.query_total_debt_ratio();
+        self.debtpool_rewards.accrue(total_debt_ratio, env::block_timestamp());
+    }
+
+    /// Credits the rewards earned by the account so far, to be called before its own debt ratio changes.
+    pub(crate) fn internal_settle_rewards(&mut self, account_id: &AccountId) {
+        self.internal_accrue_rewards();
+        let debt_ratio = self.debt_pool.query_debt_ratio(account_id);
+        self.debtpool_rewards.settle(account_id, debt_ratio);
+    }
+}
@@ -0,0 +1,112 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::{AccountId, Promise};
+
+use crate::*;
+use crate::timelock::{ChangeId, ParamChange};
+
+/// Risk class of an asset, selecting the bundle of parameters it's listed with.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Eq, PartialEq, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum RiskClass {
+    Stable,
+    Major,
+    Exotic,
+}
+
+/// Parameters shared by the assets of a risk class.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RiskParams {
+    /// Collateral ratio in percent of tokens listed in the class.
+    #[serde(with = "u128_dec_format")]
+    pub collateral_ratio: u128,
+    /// Liquidation threshold in percent of tokens listed in the class.
+    #[serde(with = "u128_dec_format")]
+    pub liquidation_threshold: u128,
+    /// Interest fee charged on redemption of NFT collaterals of the class, and of account book collaterals
+    /// in the flat interest mode, in `FEE_DIVISOR` units.
+    pub interest_fee: u32,
+    /// Stability fee per annum of account book collaterals of the class, in `FEE_DIVISOR` units.
+    pub stability_fee: u32,
+    /// Max supply of rafts listed in the class, 0 for no cap.
+    #[serde(with = "u128_dec_format")]
+    pub debt_ceiling: u128,
+    /// Max age in nanoseconds of the prices of assets of the class.
+    #[serde(with = "u64_dec_format")]
+    pub oracle_max_age: u64,
+}
+
+impl RiskClass {
+    /// Parameters of the class until changed by the owner.
+    pub(crate) fn default_params(&self) -> RiskParams {
+        let (collateral_ratio, liquidation_threshold, interest_fee, stability_fee, oracle_max_age) = match self {
+            RiskClass::Stable => (110, 105, 2, 10, 3_600),
+            RiskClass::Major => (150, 130, 5, 30, 900),
+            RiskClass::Exotic => (250, 200, 10, 80, 300),
+        };
+        RiskParams {
+            collateral_ratio,
+            liquidation_threshold,
+            interest_fee,
+            stability_fee,
+            debt_ceiling: 0,
+            oracle_max_age: oracle_max_age * 1_000_000_000,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Schedule change of the parameters of a risk class. Assets already listed keep their collateral ratio,
+    /// liquidation threshold and max supply. Only can be called by owner.
+    pub fn set_risk_params(&mut self, risk_class: RiskClass, params: RiskParams) -> ChangeId {
+        self.assert_owner();
+        assert!(params.liquidation_threshold <= params.collateral_ratio, "{}", errors::ILLEGAL_LIQUIDATION_THRESHOLD);
+        assert!(params.interest_fee <= utils::FEE_DIVISOR, "{}", errors::ILLEGAL_FEE);
+        assert!(params.stability_fee <= utils::FEE_DIVISOR, "{}", errors::ILLEGAL_FEE);
+        self.internal_schedule_param_change(ParamChange::RiskParams { risk_class, params })
+    }
+
+    pub fn get_risk_params(&self, risk_class: RiskClass) -> RiskParams {
+        self.query_risk_params(risk_class)
+    }
+
+    /// Schedule token listing with the collateral ratio and liquidation threshold of the risk class.
+    /// Only can be called by owner.
+    pub fn add_token_list_in_class(&mut self, standard: String, address: AccountId, feed_address: AccountId,
+                                   risk_class: RiskClass, state: AssetState) -> Promise {
+        self.assert_owner();
+        let mut asset = Asset::unnamed(standard, address, feed_address, 0, state);
+        asset.apply_risk_class(risk_class, &self.query_risk_params(risk_class));
+        self.internal_fetch_metadata(ParamChange::TokenListing { asset })
+    }
+
+    /// Schedule raft listing with the debt ceiling of the risk class as max supply. Only can be called by owner.
+    pub fn add_raft_list_in_class(&mut self, standard: String, address: AccountId, feed_address: AccountId,
+                                  risk_class: RiskClass, state: AssetState) -> Promise {
+        self.assert_owner();
+        self.assert_raft_capacity();
+        let mut asset = Asset::unnamed(standard, address, feed_address, 0, state);
+        asset.apply_risk_class(risk_class, &self.query_risk_params(risk_class));
+        asset.collateral_ratio = 0;
+        asset.liquidation_threshold = 0;
+        self.internal_fetch_metadata(ParamChange::RaftListing { asset })
+    }
+}
+
+impl Contract {
+    pub(crate) fn query_risk_params(&self, risk_class: RiskClass) -> RiskParams {
+        self.risk_params.get(&risk_class).unwrap_or_else(|| risk_class.default_params())
+    }
+
+    /// Flat interest fee charged on redemption of a collateral of the class, the global one for unclassified assets.
+    pub(crate) fn query_interest_fee(&self, risk_class: Option<RiskClass>) -> u32 {
+        risk_class.map_or(self.interest_fee, |risk_class| self.query_risk_params(risk_class).interest_fee)
+    }
+
+    /// Stability fee per annum of an account book collateral of the class, the global one for unclassified tokens.
+    pub(crate) fn query_stability_fee(&self, risk_class: Option<RiskClass>) -> u32 {
+        risk_class.map_or(self.stability_fee, |risk_class| self.query_risk_params(risk_class).stability_fee)
+    }
+}
@@ -0,0 +1,169 @@
+use near_sdk::AccountId;
+
+use crate::*;
+use crate::errors::CraftingError;
+
+/// Outcome of a dry run: the projected result, or the error the real call would fail with.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Simulation<T> {
+    Ok(T),
+    Err { code: u32, message: String },
+}
+
+impl<T> From<Result<T, CraftingError>> for Simulation<T> {
+    fn from(result: Result<T, CraftingError>) -> Self {
+        match result {
+            Ok(value) => Simulation::Ok(value),
+            Err(error) => Simulation::Err { code: error.code(), message: error.as_str().to_string() },
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintSimulation {
+    /// Collateral ratio of the new collateral in percent.
+    pub collateral_ratio: U128,
+    /// Collateral ratio in percent required in the account book, 0 when joining the debt pool.
+    pub min_collateral_ratio: U128,
+    /// Leverage ratio when joining the debt pool, 0 in the account book.
+    pub leverage_ratio: U128,
+    /// Rafts charged as mint fee.
+    pub mint_fee: U128,
+    /// Rafts credited, net of the mint fee.
+    pub raft_amount_out: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SwapSimulation {
+    /// Exchange fee charged in the old raft.
+    pub fee: U128,
+    /// Exchange fee charged in rUSD instead, for accounts paying their fees in rUSD.
+    pub rusd_fee: U128,
+    /// Amount of the new raft received.
+    pub amount_out: U128,
+}
+
+fn ensure(condition: bool, error: CraftingError) -> Result<(), CraftingError> {
+    if condition { Ok(()) } else { Err(error) }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Dry run of a mint of the account, with the checks and math of `mint` and `ft_on_transfer`.
+    pub fn simulate_mint(&self, account_id: AccountId, token_id: AccountId, token_amount: U128,
+                         raft_id: AccountId, raft_amount: U128, join_debtpool: bool) -> Simulation<MintSimulation> {
+        self.internal_simulate_mint(&account_id, &token_id, token_amount.0, &raft_id, raft_amount.0, join_debtpool)
+            .into()
+    }
+
+    /// Dry run of a swap of the account, with the checks and math of `swap_in_debtpool` or `swap_in_accountbook`.
+    pub fn simulate_swap(&self, account_id: AccountId, old_raft_id: AccountId, new_raft_id: AccountId,
+                         swap_amount: U128, in_debtpool: bool) -> Simulation<SwapSimulation> {
+        self.internal_simulate_swap(&account_id, &old_raft_id, &new_raft_id, swap_amount.0, in_debtpool).into()
+    }
+}
+
+impl Contract {
+    fn check_account(&self, account_id: &AccountId) -> Result<(), CraftingError> {
+        ensure(self.state == RunningState::Running, CraftingError::ContractPaused)?;
+        ensure(!self.blacklist.contains(account_id), CraftingError::AccountBlacklisted)?;
+        ensure(self.operation_locks.query_lock(account_id).is_none(), CraftingError::OperationPending)
+    }
+
+    fn check_asset(&self, asset: Option<Asset>, allowed: fn(&AssetState) -> bool) -> Result<(), CraftingError> {
+        let asset = asset.ok_or(CraftingError::AssetNotFound)?;
+        ensure(allowed(&asset.state), CraftingError::AssetPaused)?;
+        ensure(self.price_oracle.query_price(&asset.address).is_some(), CraftingError::PriceNotFound)
+    }
+
+    fn internal_simulate_mint(&self, account_id: &AccountId, token_id: &AccountId, token_amount: Balance,
+                              raft_id: &AccountId, raft_amount: Balance,
+                              join_debtpool: bool) -> Result<MintSimulation, CraftingError> {
+        self.check_account(account_id)?;
+        ensure(self.is_in_whitelisted_tokens(token_id), CraftingError::TokenNotWhitelisted)?;
+        ensure(self.is_in_whitelisted_rafts(raft_id), CraftingError::RaftNotWhitelisted)?;
+        self.check_asset(self.query_token(token_id), AssetState::is_mintable)?;
+        self.check_asset(self.query_raft(raft_id), AssetState::is_mintable)?;
+        ensure(token_amount > 0, CraftingError::NoAttachedDeposit)?;
+        ensure(raft_amount > 0, CraftingError::SyntheticAmountError)?;
+
+        let remaining = self.calc_remaining_supply(raft_id);
+        ensure(remaining.is_none_or(|remaining| raft_amount <= remaining), CraftingError::MaxSupply)?;
+        if let Some(limit) = self.borrow_limits.query_limit(account_id) {
+            let debt_value = self.calc_user_debt_value(account_id) + self.valuation().calc_value(raft_id, raft_amount);
+            ensure(debt_value <= limit, CraftingError::UserBorrowLimit)?;
+        }
+
+        let valuation = self.valuation();
+        let token_value = valuation.calc_value(token_id, token_amount);
+        let raft_value = valuation.calc_value(raft_id, raft_amount);
+        let collateral_ratio = math::ratio(&[token_value, 100], &[raft_value]);
+        let (min_collateral_ratio, leverage_ratio) = if join_debtpool {
+            let leverage_ratio = math::ratio(&[raft_value], &[token_value]);
+            let (min, max) = self.leverage_ratio;
+            ensure(leverage_ratio >= min.into() && leverage_ratio <= max.into(), CraftingError::IllegalLeverageRatio)?;
+            (0, leverage_ratio)
+        } else {
+            let min_collateral_ratio = self.query_min_collateral_ratio(token_id, raft_id);
+            ensure(collateral_ratio >= min_collateral_ratio, CraftingError::CollateralRatioTooLow)?;
+            (min_collateral_ratio, 0)
+        };
+
+        let mint_fee = math::share(raft_amount, self.query_mint_fee(raft_id), utils::BPS_DIVISOR);
+        Ok(MintSimulation {
+            collateral_ratio: U128(collateral_ratio),
+            min_collateral_ratio: U128(min_collateral_ratio),
+            leverage_ratio: U128(leverage_ratio),
+            mint_fee: U128(mint_fee),
+            raft_amount_out: U128(raft_amount - mint_fee),
+        })
+    }
+
+    fn internal_simulate_swap(&self, account_id: &AccountId, old_raft_id: &AccountId, new_raft_id: &AccountId,
+                              swap_amount: Balance, in_debtpool: bool) -> Result<SwapSimulation, CraftingError> {
+        self.check_account(account_id)?;
+        ensure(self.swap_cooldown.query_next_swap(account_id).is_none(), CraftingError::SwapCooldown)?;
+        ensure(self.is_in_whitelisted_rafts(old_raft_id), CraftingError::RaftNotWhitelisted)?;
+        ensure(self.is_in_whitelisted_rafts(new_raft_id), CraftingError::RaftNotWhitelisted)?;
+        self.check_asset(self.query_raft(old_raft_id), AssetState::is_tradable)?;
+        self.check_asset(self.query_raft(new_raft_id), AssetState::is_tradable)?;
+        ensure(swap_amount > 0, CraftingError::IllegalSwapAmount)?;
+
+        if in_debtpool {
+            let user_raft_amount = self.debt_pool.query_user_raft_amount(account_id, old_raft_id);
+            ensure(user_raft_amount >= swap_amount, CraftingError::NotEnoughRafts)?;
+        } else {
+            ensure(self.account_book.query_raft_amount(old_raft_id) >= swap_amount, CraftingError::NotEnoughRafts)?;
+            let user_raft_amount = self.account_book.query_user_raft_amount(account_id, old_raft_id);
+            ensure(user_raft_amount >= swap_amount, CraftingError::NotEnoughRafts)?;
+        }
+
+        // the fee is paid in rUSD by the accounts opting in, if they hold enough of it
+        let valuation = self.valuation();
+        let fee = math::share(swap_amount, self.check_swap_fee(old_raft_id, new_raft_id)?, utils::FEE_DIVISOR);
+        let rusd_fee = match self.query_rusd().map(|asset| asset.address) {
+            Some(rusd_id) if fee > 0 && &rusd_id != old_raft_id && self.rusd_fee_payers.contains(account_id) => {
+                self.check_asset(self.query_raft(&rusd_id), |_| true)?;
+                let rusd_fee = valuation.convert(old_raft_id, fee, &rusd_id);
+                let rusd_amount = self.account_book.query_user_raft_amount(account_id, &rusd_id);
+                if rusd_amount >= rusd_fee { rusd_fee } else { 0 }
+            }
+            _ => 0,
+        };
+        let fee = if rusd_fee > 0 { 0 } else { fee };
+
+        let amount_out = valuation.convert(old_raft_id, swap_amount - fee, new_raft_id);
+        let remaining = self.calc_remaining_supply(new_raft_id);
+        ensure(remaining.is_none_or(|remaining| amount_out <= remaining), CraftingError::MaxSupply)?;
+        self.check_raft_share(new_raft_id, amount_out)?;
+
+        Ok(SwapSimulation {
+            fee: U128(fee),
+            rusd_fee: U128(rusd_fee),
+            amount_out: U128(amount_out),
+        })
+    }
+}
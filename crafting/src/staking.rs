@@ -0,0 +1,294 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, UnorderedSet};
+use near_sdk::{assert_one_yocto, env, AccountId, Balance, Promise, PromiseResult, Timestamp};
+
+use crate::*;
+use crate::fees::FeeBucket;
+
+/// Precision of the fee indices of the stakers.
+const STAKING_INDEX_PRECISION: u128 = 1_000_000_000_000_000_000;
+
+#[derive(Clone, Default, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakingAccount {
+    /// Amount staked and earning fees.
+    #[serde(with = "u128_dec_format")]
+    pub staked: Balance,
+    /// Amount unstaked and waiting for the end of the cooldown to be withdrawn.
+    #[serde(with = "u128_dec_format")]
+    pub unstaking: Balance,
+    #[serde(with = "u64_dec_format")]
+    pub unlock_at: Timestamp,
+}
+
+#[derive(Default, BorshSerialize, BorshDeserialize)]
+pub struct StakerRevenue {
+    /// Fee index of the raft the staker was last settled at.
+    index: u128,
+    unclaimed: Balance,
+}
+
+/// Stakes of the governance token, sharing the fees of the `Stakers` bucket pro rata by stake.
+/// The bucket of a raft is moved to its fee index before the stakes change and on claims.
+/// While nothing is staked it's left in the bucket, for the first stakers.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Staking {
+    pub(crate) token_id: Option<AccountId>,
+    /// Time between an unstake and the withdrawal of the tokens, in nanoseconds.
+    cooldown: u64,
+    total_staked: Balance,
+    accounts: LookupMap<AccountId, StakingAccount>,
+    /// Mapping from raft to the fees distributed per staked unit, in `STAKING_INDEX_PRECISION` units.
+    indices: LookupMap<AccountId, u128>,
+    /// Rafts with a fee index, listed or not, all settled when a stake changes.
+    indexed_rafts: UnorderedSet<AccountId>,
+    /// Mapping from staker and raft to its fees not claimed yet.
+    revenues: LookupMap<(AccountId, AccountId), StakerRevenue>,
+}
+
+impl Staking {
+    pub(crate) fn new() -> Self {
+        Self {
+            token_id: None,
+            cooldown: utils::DEFAULT_UNSTAKE_COOLDOWN,
+            total_staked: 0,
+            accounts: LookupMap::new(StorageKey::StakingAccounts),
+            indices: LookupMap::new(StorageKey::StakingIndices),
+            indexed_rafts: UnorderedSet::new(StorageKey::StakingRafts),
+            revenues: LookupMap::new(StorageKey::StakerRevenues),
+        }
+    }
+
+    pub(crate) fn query_total_staked(&self) -> Balance {
+        self.total_staked
+    }
+
+    fn query_account(&self, account_id: &AccountId) -> StakingAccount {
+        self.accounts.get(account_id).unwrap_or_default()
+    }
+
+    fn query_index(&self, raft_id: &AccountId) -> u128 {
+        self.indices.get(raft_id).unwrap_or(0)
+    }
+
+    /// Index of the raft once `amount` more fees are distributed.
+    fn query_distributed_index(&self, raft_id: &AccountId, amount: Balance) -> u128 {
+        if self.total_staked == 0 {
+            return self.query_index(raft_id);
+        }
+        self.query_index(raft_id)
+            .saturating_add(math::ratio_saturating(&[amount, STAKING_INDEX_PRECISION], &[self.total_staked]))
+    }
+
+    fn query_revenue(&self, account_id: &AccountId, raft_id: &AccountId) -> StakerRevenue {
+        self.revenues.get(&(account_id.clone(), raft_id.clone())).unwrap_or_default()
+    }
+
+    fn query_unclaimed(&self, account_id: &AccountId, raft_id: &AccountId, index: u128) -> Balance {
+        let revenue = self.query_revenue(account_id, raft_id);
+        revenue.unclaimed
+            + math::mul_div(self.query_account(account_id).staked, index - revenue.index, STAKING_INDEX_PRECISION)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Set the governance token staked through `ft_transfer_call`. Only can be called by owner, once.
+    pub fn set_staking_token(&mut self, token_id: AccountId) {
+        self.assert_owner();
+        assert!(self.staking.token_id.is_none(), "{}", errors::ILLEGAL_STAKING_TOKEN);
+        self.staking.token_id = Some(token_id);
+    }
+
+    /// Change the cooldown between an unstake and the withdrawal of the tokens, in nanoseconds.
+    /// Only can be called by owner. Tokens already unstaking keep their unlock time.
+    pub fn set_unstake_cooldown(&mut self, cooldown: U64) {
+        self.assert_owner();
+        self.staking.cooldown = cooldown.into();
+    }
+
+    /// Stop earning fees with `amount` of the staked tokens, withdrawable with `withdraw_unstaked` after the cooldown.
+    /// The cooldown restarts for all the tokens unstaking.
+    #[payable]
+    pub fn unstake(&mut self, amount: U128) {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let account_id = env::predecessor_account_id();
+        let amount: Balance = amount.into();
+        let mut account = self.staking.query_account(&account_id);
+        assert!(amount > 0 && amount <= account.staked, "{}", errors::NOT_ENOUGH_STAKED);
+
+        self.internal_settle_staker(&account_id);
+        account.staked -= amount;
+        account.unstaking += amount;
+        account.unlock_at = env::block_timestamp() + self.staking.cooldown;
+        self.staking.total_staked -= amount;
+        self.staking.accounts.insert(&account_id, &account);
+
+        Event::Unstake {
+            account_id: &account_id,
+            amount: U128(amount),
+            unlock_at: U64(account.unlock_at),
+        }.emit();
+    }
+
+    /// Send the unstaked tokens of the caller back once the cooldown is over.
+    #[payable]
+    pub fn withdraw_unstaked(&mut self) -> Promise {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let account_id = env::predecessor_account_id();
+        self.assert_not_blacklisted(&account_id);
+        let token_id = self.staking.token_id.clone().expect(errors::ILLEGAL_STAKING_TOKEN);
+        let mut account = self.staking.query_account(&account_id);
+        assert!(account.unstaking > 0, "{}", errors::ILLEGAL_WITHDRAW_AMOUNT);
+        assert!(env::block_timestamp() >= account.unlock_at, "{}", errors::UNSTAKE_COOLING_DOWN);
+
+        let amount = account.unstaking;
+        account.unstaking = 0;
+        self.staking.accounts.insert(&account_id, &account);
+
+        ext_fungible_token::ft_transfer(
+            account_id.clone(),
+            U128(amount),
+            None,
+            token_id,
+            utils::ONE_YOCTO,
+            self.gas_config.ft_transfer,
+        ).then(ext_self::withdraw_unstaked_callback(
+            account_id,
+            U128(amount),
+            env::current_account_id(),
+            utils::NO_DEPOSIT,
+            self.gas_config.resolve,
+        ))
+    }
+
+    /// Credit the fees of the raft earned by the caller's stake to its account book.
+    pub fn claim_staking_revenue(&mut self, raft_id: AccountId) -> U128 {
+        self.assert_contract_running();
+        let account_id = env::predecessor_account_id();
+        self.assert_not_blacklisted(&account_id);
+
+        self.internal_settle_staker_raft(&account_id, &raft_id);
+        let mut revenue = self.staking.query_revenue(&account_id, &raft_id);
+        let amount = revenue.unclaimed;
+        assert!(amount > 0, "{}", errors::NO_REWARDS);
+        revenue.unclaimed = 0;
+        self.staking.revenues.insert(&(account_id.clone(), raft_id.clone()), &revenue);
+
+        self.account_book.mint(&account_id, &raft_id, amount);
+        Event::ClaimStakingRevenue {
+            account_id: &account_id,
+            raft_id: &raft_id,
+            amount: U128(amount),
+        }.emit();
+        U128(amount)
+    }
+
+    /// Staking token and unstake cooldown in nanoseconds.
+    pub fn get_staking_config(&self) -> (Option<AccountId>, U64) {
+        (self.staking.token_id.clone(), U64(self.staking.cooldown))
+    }
+
+    pub fn get_total_staked(&self) -> U128 {
+        U128(self.staking.query_total_staked())
+    }
+
+    pub fn get_staking_account(&self, account_id: AccountId) -> StakingAccount {
+        self.staking.query_account(&account_id)
+    }
+
+    /// Fees of each raft earned by the stake of the account and not claimed yet.
+    pub fn get_claimable_staking_revenue(&self, account_id: AccountId) -> Vec<(AccountId, U128)> {
+        self.query_staking_rafts().into_iter()
+            .map(|raft_id| {
+                let pending = self.fee_ledger.query_share(FeeBucket::Stakers, &raft_id);
+                let index = self.staking.query_distributed_index(&raft_id, pending);
+                let amount = self.staking.query_unclaimed(&account_id, &raft_id, index);
+                (raft_id, U128(amount))
+            })
+            .filter(|(_, amount)| amount.0 > 0)
+            .collect()
+    }
+
+    #[private]
+    pub fn withdraw_unstaked_callback(&mut self, account_id: AccountId, amount: U128) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => {
+                Event::WithdrawUnstaked {
+                    account_id: &account_id,
+                    amount,
+                }.emit();
+            }
+            PromiseResult::Failed => {
+                // This reverts the changes from withdraw_unstaked function.
+                let mut account = self.staking.query_account(&account_id);
+                account.unstaking += amount.0;
+                self.staking.accounts.insert(&account_id, &account);
+                env::log_str(format!("Failed to withdraw {} unstaked tokens", amount.0).as_str());
+            }
+        };
+    }
+}
+
+impl Contract {
+    /// Stakes the governance tokens received.
+    pub(crate) fn internal_stake(&mut self, sender_id: &AccountId, token_id: &AccountId, amount: Balance) {
+        assert_eq!(self.staking.token_id.as_ref(), Some(token_id), "{}", errors::ILLEGAL_STAKING_TOKEN);
+        self.assert_not_blacklisted(sender_id);
+
+        self.internal_settle_staker(sender_id);
+        let mut account = self.staking.query_account(sender_id);
+        account.staked += amount;
+        self.staking.total_staked += amount;
+        self.staking.accounts.insert(sender_id, &account);
+
+        Event::Stake {
+            account_id: sender_id,
+            amount: U128(amount),
+        }.emit();
+    }
+
+    /// Moves the fees of the `Stakers` bucket of the raft to its index.
+    fn internal_distribute_staking_fees(&mut self, raft_id: &AccountId) {
+        if self.staking.total_staked == 0 { return; }
+        let amount = self.fee_ledger.query_share(FeeBucket::Stakers, raft_id);
+        if amount == 0 { return; }
+        self.fee_ledger.withdraw(FeeBucket::Stakers, raft_id, amount);
+        let index = self.staking.query_distributed_index(raft_id, amount);
+        self.staking.indices.insert(raft_id, &index);
+        self.staking.indexed_rafts.insert(raft_id);
+    }
+
+    fn internal_settle_staker_raft(&mut self, account_id: &AccountId, raft_id: &AccountId) {
+        self.internal_distribute_staking_fees(raft_id);
+        let index = self.staking.query_index(raft_id);
+        let unclaimed = self.staking.query_unclaimed(account_id, raft_id, index);
+        self.staking.revenues.insert(&(account_id.clone(), raft_id.clone()), &StakerRevenue { index, unclaimed });
+    }
+
+    /// Listed rafts, whose fees may be pending distribution, and delisted rafts with a fee index.
+    fn query_staking_rafts(&self) -> Vec<AccountId> {
+        let mut raft_ids: Vec<AccountId> = self.raft_list.keys().collect();
+        raft_ids.extend(self.staking.indexed_rafts.iter().filter(|raft_id| self.raft_list.get(raft_id).is_none()));
+        raft_ids
+    }
+
+    /// Credits the fees of every raft earned by the staker so far, to be called before its stake changes.
+    /// This records the current index of each raft for the staker, so that a later stake only earns
+    /// the fees distributed after it.
+    fn internal_settle_staker(&mut self, account_id: &AccountId) {
+        for raft_id in self.query_staking_rafts().iter() {
+            self.internal_settle_staker_raft(account_id, raft_id);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::{assert_one_yocto, env, AccountId};
+
+use crate::*;
+use crate::timelock::{ChangeId, ParamChange};
+
+/// Direction in which the oracle price of the raft must cross the trigger price.
+#[derive(Clone, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum StopAction {
+    /// Converts the raft to rUSD once its price is at or below the trigger.
+    StopLoss,
+    /// Converts the raft to rUSD once its price is at or above the trigger.
+    TakeProfit,
+}
+
+/// Order converting the whole debt pool position of an account in a raft to rUSD, executed by keepers.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StopOrder {
+    /// Price of the raft in oracle precision.
+    pub trigger_price: U128,
+    pub action: StopAction,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Schedule change of the bounty paid to keepers out of executed stop orders, in `BPS_DIVISOR` units
+    /// of the converted position. Only can be called by owner.
+    pub fn set_stop_bounty(&mut self, bounty_bps: u32) -> ChangeId {
+        self.assert_owner();
+        assert!(bounty_bps <= utils::BPS_DIVISOR, "{}", errors::ILLEGAL_FEE);
+        self.internal_schedule_param_change(ParamChange::StopBounty { bounty_bps })
+    }
+
+    pub fn get_stop_bounty(&self) -> u32 {
+        self.stop_bounty
+    }
+
+    /// Authorize keepers to convert the debt pool position of the caller in the raft to rUSD once the oracle price
+    /// crosses `trigger_price`. Replaces the previous order on the raft.
+    #[payable]
+    pub fn set_stop(&mut self, raft_id: AccountId, trigger_price: U128, action: StopAction) {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let account_id = env::predecessor_account_id();
+        assert!(self.internal_get_account(&account_id).is_some(), "{}", errors::ACC_NOT_REGISTERED);
+        assert!(self.is_in_whitelisted_rafts(&raft_id), "{}", errors::RAFT_NOT_WHITELISTED);
+        assert!(self.rusd_id.as_ref() != Some(&raft_id), "{}", errors::ILLEGAL_STOP_ORDER);
+        assert!(trigger_price.0 > 0, "{}", errors::ILLEGAL_STOP_ORDER);
+
+        let order = StopOrder { trigger_price, action };
+        self.stop_orders.insert(&(account_id.clone(), raft_id.clone()), &order);
+        Event::SetStop { account_id: &account_id, raft_id: &raft_id, order: &order }.emit();
+    }
+
+    #[payable]
+    pub fn cancel_stop(&mut self, raft_id: AccountId) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        self.stop_orders.remove(&(account_id.clone(), raft_id.clone())).expect(errors::STOP_ORDER_NOT_FOUND);
+        Event::CancelStop { account_id: &account_id, raft_id: &raft_id }.emit();
+    }
+
+    pub fn get_stop(&self, account_id: AccountId, raft_id: AccountId) -> Option<StopOrder> {
+        self.stop_orders.get(&(account_id, raft_id))
+    }
+
+    /// Stop orders as (account, raft, order), for keepers to watch.
+    pub fn get_stops(&self, from_index: u64, limit: u64) -> Vec<(AccountId, AccountId, StopOrder)> {
+        self.stop_orders.iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .map(|((account_id, raft_id), order)| (account_id, raft_id, order))
+            .collect()
+    }
+
+    /// Executes the triggered stop order of the account on the raft, swapping its debt pool position to rUSD.
+    /// The caller gets the stop bounty out of the position, in its account book. Returns the rUSD received
+    /// by the account.
+    pub fn execute_stop(&mut self, account_id: AccountId, raft_id: AccountId) -> U128 {
+        self.assert_contract_running();
+        let keeper_id = env::predecessor_account_id();
+        let key = (account_id.clone(), raft_id.clone());
+        let order = self.stop_orders.get(&key).expect(errors::STOP_ORDER_NOT_FOUND);
+        let rusd_id = self.query_rusd().expect(errors::ASSET_NOT_FOUND).address;
+
+        let price = self.price_oracle.query_price(&raft_id).expect(errors::PRICE_NOT_FOUND);
+        let triggered = match order.action {
+            StopAction::StopLoss => price <= order.trigger_price.0,
+            StopAction::TakeProfit => price >= order.trigger_price.0,
+        };
+        assert!(triggered, "{}", errors::STOP_NOT_TRIGGERED);
+        self.stop_orders.remove(&key);
+
+        let amount = self.debt_pool.query_user_raft_amount(&account_id, &raft_id);
+        assert!(amount > 0, "{}", errors::NOT_ENOUGH_RAFTS);
+        let bounty_amount = math::share(amount, self.stop_bounty, utils::BPS_DIVISOR);
+        if bounty_amount > 0 {
+            let raft_amount = self.debt_pool.query_raft_amount(&raft_id);
+            self.debt_pool.calc_sub_raft_amount(&raft_id, &raft_amount, bounty_amount);
+            let valuation = oracle::Valuation::new(&self.price_oracle, &self.token_list, &self.raft_list);
+            self.debt_pool.insert_user_raft_amount(&valuation, &account_id, &raft_id, amount - bounty_amount);
+            self.account_book.mint(&keeper_id, &raft_id, bounty_amount);
+        }
+
+        let rusd_amount = self.debt_pool.query_user_raft_amount(&account_id, &rusd_id);
+        self.internal_swap_in_debtpool(&account_id, raft_id.clone(), rusd_id.clone(), amount - bounty_amount);
+        let rusd_amount = self.debt_pool.query_user_raft_amount(&account_id, &rusd_id) - rusd_amount;
+
+        Event::ExecuteStop {
+            account_id: &account_id,
+            keeper_id: &keeper_id,
+            raft_id: &raft_id,
+            amount: U128(amount),
+            bounty: U128(bounty_amount),
+            rusd_amount: U128(rusd_amount),
+        }.emit();
+        U128(rusd_amount)
+    }
+}
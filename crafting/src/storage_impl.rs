@@ -0,0 +1,94 @@
+use near_contract_standards::storage_management::{
+    StorageBalance, StorageBalanceBounds, StorageManagement,
+};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Promise};
+use near_sdk::json_types::U128;
+
+use crate::*;
+use crate::account::Account;
+
+#[near_bindgen]
+impl StorageManagement for Contract {
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        self.assert_contract_running();
+        let amount = env::attached_deposit();
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let registration_only = registration_only.unwrap_or(false);
+        let min_balance = self.storage_balance_bounds().min.0;
+        let already_registered = self.accounts.contains_key(&account_id);
+        if amount < min_balance && !already_registered {
+            env::panic_str(errors::INSUFFICIENT_STORAGE);
+        }
+        if registration_only {
+            // Registration only setups the account but doesn't leave space for tokens.
+            if already_registered {
+                env::log_str("The account is already registered, refunding the deposit");
+                if amount > 0 {
+                    Promise::new(env::predecessor_account_id()).transfer(amount);
+                }
+            } else {
+                self.internal_register_account(&account_id, min_balance);
+                let refund = amount - min_balance;
+                if refund > 0 {
+                    Promise::new(env::predecessor_account_id()).transfer(refund);
+                }
+            }
+        } else {
+            self.internal_register_account(&account_id, amount);
+        }
+        self.internal_bump_nonce(&account_id);
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let account_id = env::predecessor_account_id();
+        let amount = amount.unwrap_or(U128(0)).0;
+        let withdraw_amount = self.internal_storage_withdraw(&account_id, amount);
+        self.internal_bump_nonce(&account_id);
+        Promise::new(account_id.clone()).transfer(withdraw_amount);
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    /// Unregisters the caller and refunds the storage deposit.
+    /// Panics if the account still has token balances, open collaterals or debt,
+    /// force unregistration is not supported.
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        self.assert_contract_running();
+        assert!(!force.unwrap_or(false), "{}", errors::FORCE_UNREGISTER_UNSUPPORTED);
+
+        let account_id = env::predecessor_account_id();
+        if let Some(account) = self.internal_get_account(&account_id) {
+            let near_amount = self.internal_unregister_account(&account_id, account);
+            Promise::new(account_id.clone()).transfer(near_amount);
+            env::log_str(format!("Account {} unregistered", account_id).as_str());
+            true
+        } else {
+            false
+        }
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: Account::min_storage_usage().into(),
+            max: None,
+        }
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.internal_get_account(&account_id)
+            .map(|account| StorageBalance {
+                total: U128(account.near_amount),
+                available: U128(account.storage_available()),
+            })
+    }
+}
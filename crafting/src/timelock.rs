@@ -0,0 +1,380 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::UnorderedMap;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::json_types::U64;
+use near_sdk::{env, AccountId, Timestamp};
+
+use crate::*;
+use crate::buyback::BuybackConfig;
+use crate::fees::{FeeConfig, InterestMode};
+use crate::mt::MtAsset;
+use crate::peg::PegGuard;
+use crate::risk::{RiskClass, RiskParams};
+
+pub type ChangeId = u64;
+
+/// Sensitive parameter changes, which only become effective after the timelock delay.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ParamChange {
+    LeverageRatio { leverage_ratio: (u8, u8) },
+    InterestFee { interest_fee: u32 },
+    ExchangeFee { exchange_fee: u32 },
+    StabilityFee { stability_fee: u32 },
+    MintFee { mint_fee: u32 },
+    FeeConfig { config: FeeConfig },
+    InterestMode { interest_mode: InterestMode },
+    ReferralShare { share_bps: u32 },
+    CollateralRatio {
+        token_id: AccountId,
+        #[serde(with = "u128_dec_format")]
+        collateral_ratio: u128,
+    },
+    LiquidationThreshold {
+        token_id: AccountId,
+        #[serde(with = "u128_dec_format")]
+        liquidation_threshold: u128,
+    },
+    NftLiquidationThreshold {
+        collection_id: AccountId,
+        #[serde(with = "u128_dec_format")]
+        liquidation_threshold: u128,
+    },
+    PairCollateralRatio {
+        token_id: AccountId,
+        raft_id: AccountId,
+        /// `None` removes the override.
+        collateral_ratio: Option<U128>,
+    },
+    TokenListing { asset: Asset },
+    RaftListing { asset: Asset },
+    NftListing { asset: Asset },
+    MtListing { asset: MtAsset },
+    TokenUpdate { token_id: AccountId, patch: AssetPatch },
+    RaftUpdate { raft_id: AccountId, patch: AssetPatch },
+    TimelockDelay {
+        #[serde(with = "u64_dec_format")]
+        delay: u64,
+    },
+    RiskParams { risk_class: RiskClass, params: RiskParams },
+    GracePeriod {
+        #[serde(with = "u64_dec_format")]
+        grace_period: u64,
+    },
+    SwapCooldown {
+        #[serde(with = "u64_dec_format")]
+        interval: u64,
+    },
+    PsmFee { fee: u32 },
+    PsmCap {
+        token_id: AccountId,
+        #[serde(with = "u128_dec_format")]
+        cap: u128,
+    },
+    PegGuard { guard: Option<PegGuard> },
+    BuybackConfig { config: BuybackConfig },
+    MaxRaftShare { share_bps: u32 },
+    DeleverageFee { fee: u32 },
+    StopBounty { bounty_bps: u32 },
+    PriceRoundTolerance { tolerance_bps: u32 },
+    LiquidationSlippage { slippage_bps: u32 },
+}
+
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingChange {
+    pub change: ParamChange,
+    /// Timestamp after which the change can be applied.
+    #[serde(with = "u64_dec_format")]
+    pub eta: Timestamp,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Timelock {
+    /// Delay in nanoseconds between scheduling and applying a change.
+    delay: u64,
+    /// Id of the next scheduled change.
+    next_id: ChangeId,
+    /// Mapping from change id to changes waiting for the delay to pass.
+    pending: UnorderedMap<ChangeId, PendingChange>,
+}
+
+impl Timelock {
+    pub(crate) fn new(delay: u64) -> Self {
+        Self {
+            delay,
+            next_id: 0,
+            pending: UnorderedMap::new(StorageKey::PendingChanges),
+        }
+    }
+
+    pub(crate) fn query_delay(&self) -> u64 {
+        self.delay
+    }
+
+    pub(crate) fn schedule(&mut self, change: ParamChange) -> (ChangeId, Timestamp) {
+        let id = self.next_id;
+        let eta = env::block_timestamp() + self.delay;
+        self.pending.insert(&id, &PendingChange { change, eta });
+        self.next_id += 1;
+
+        (id, eta)
+    }
+
+    /// Removes the change from the queue, panics if its delay has not passed yet.
+    pub(crate) fn take_ready(&mut self, id: ChangeId) -> ParamChange {
+        let pending = self.pending.get(&id).expect(errors::CHANGE_NOT_FOUND);
+        assert!(env::block_timestamp() >= pending.eta, "{}", errors::TIMELOCK_NOT_EXPIRED);
+        self.pending.remove(&id);
+
+        pending.change
+    }
+
+    pub(crate) fn cancel(&mut self, id: ChangeId) {
+        assert!(self.pending.remove(&id).is_some(), "{}", errors::CHANGE_NOT_FOUND);
+    }
+
+    pub(crate) fn query_pending(&self) -> Vec<(ChangeId, PendingChange)> {
+        self.pending.to_vec()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Apply a scheduled change once its delay has passed. Only can be called by owner.
+    pub fn apply_param_change(&mut self, change_id: ChangeId) {
+        self.assert_owner();
+        let change = self.timelock.take_ready(change_id);
+        let old = self.query_param_value(&change);
+        self.internal_apply_param_change(change.clone());
+        Event::ParamChangeApplied { change_id, old: old.as_ref(), new: &change }.emit();
+    }
+
+    /// Cancel a scheduled change. Only can be called by owner.
+    pub fn cancel_param_change(&mut self, change_id: ChangeId) {
+        self.assert_owner();
+        self.timelock.cancel(change_id);
+        Event::ParamChangeCancelled { change_id }.emit();
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_schedule_param_change(&mut self, change: ParamChange) -> ChangeId {
+        let (change_id, eta) = self.timelock.schedule(change.clone());
+        Event::ParamChangeScheduled { change_id, change: &change, eta: U64(eta) }.emit();
+        change_id
+    }
+
+    /// Current value of the parameter the change sets, in the shape of the change.
+    /// Listings and asset updates return the listing of the asset, if it's listed.
+    fn query_param_value(&self, change: &ParamChange) -> Option<ParamChange> {
+        let value = match change {
+            ParamChange::LeverageRatio { .. } => ParamChange::LeverageRatio { leverage_ratio: self.leverage_ratio },
+            ParamChange::InterestFee { .. } => ParamChange::InterestFee { interest_fee: self.interest_fee },
+            ParamChange::ExchangeFee { .. } => ParamChange::ExchangeFee { exchange_fee: self.exchange_fee },
+            ParamChange::StabilityFee { .. } => ParamChange::StabilityFee { stability_fee: self.stability_fee },
+            ParamChange::MintFee { .. } => ParamChange::MintFee { mint_fee: self.mint_fee },
+            ParamChange::FeeConfig { .. } => ParamChange::FeeConfig { config: self.fee_ledger.config.clone() },
+            ParamChange::InterestMode { .. } => ParamChange::InterestMode { interest_mode: self.interest_mode },
+            ParamChange::ReferralShare { .. } => ParamChange::ReferralShare { share_bps: self.referrals.share_bps },
+            ParamChange::CollateralRatio { token_id, .. } => ParamChange::CollateralRatio {
+                token_id: token_id.clone(),
+                collateral_ratio: self.query_token(token_id)?.collateral_ratio,
+            },
+            ParamChange::LiquidationThreshold { token_id, .. } => ParamChange::LiquidationThreshold {
+                token_id: token_id.clone(),
+                liquidation_threshold: self.query_token(token_id)?.liquidation_threshold,
+            },
+            ParamChange::NftLiquidationThreshold { collection_id, .. } => ParamChange::NftLiquidationThreshold {
+                collection_id: collection_id.clone(),
+                liquidation_threshold: self.query_nft_collection(collection_id)?.liquidation_threshold,
+            },
+            ParamChange::PairCollateralRatio { token_id, raft_id, .. } => ParamChange::PairCollateralRatio {
+                token_id: token_id.clone(),
+                raft_id: raft_id.clone(),
+                collateral_ratio: self.pair_collateral_ratios.get(&(token_id.clone(), raft_id.clone())).map(U128),
+            },
+            ParamChange::TokenListing { asset: Asset { address, .. } }
+            | ParamChange::TokenUpdate { token_id: address, .. } => {
+                ParamChange::TokenListing { asset: self.query_token(address)? }
+            }
+            ParamChange::RaftListing { asset: Asset { address, .. } }
+            | ParamChange::RaftUpdate { raft_id: address, .. } => {
+                ParamChange::RaftListing { asset: self.query_raft(address)? }
+            }
+            ParamChange::NftListing { asset } => {
+                ParamChange::NftListing { asset: self.query_nft_collection(&asset.address)? }
+            }
+            ParamChange::MtListing { asset } => ParamChange::MtListing {
+                asset: self.query_mt(&(asset.contract_id.clone(), asset.token_id.clone()))?,
+            },
+            ParamChange::TimelockDelay { .. } => ParamChange::TimelockDelay { delay: self.timelock.delay },
+            ParamChange::RiskParams { risk_class, .. } => ParamChange::RiskParams {
+                risk_class: *risk_class,
+                params: self.query_risk_params(*risk_class),
+            },
+            ParamChange::GracePeriod { .. } => ParamChange::GracePeriod { grace_period: self.risk_graces.grace_period },
+            ParamChange::SwapCooldown { .. } => ParamChange::SwapCooldown { interval: self.swap_cooldown.interval },
+            ParamChange::PsmFee { .. } => ParamChange::PsmFee { fee: self.psm.fee },
+            ParamChange::PsmCap { token_id, .. } => ParamChange::PsmCap {
+                token_id: token_id.clone(),
+                cap: self.psm.query_stable(token_id).map_or(0, |stable| stable.cap),
+            },
+            ParamChange::PegGuard { .. } => ParamChange::PegGuard { guard: self.peg_guard.clone() },
+            ParamChange::BuybackConfig { .. } => ParamChange::BuybackConfig { config: self.buyback_config.clone() },
+            ParamChange::MaxRaftShare { .. } => ParamChange::MaxRaftShare { share_bps: self.max_raft_share },
+            ParamChange::DeleverageFee { .. } => ParamChange::DeleverageFee { fee: self.deleverage_fee },
+            ParamChange::StopBounty { .. } => ParamChange::StopBounty { bounty_bps: self.stop_bounty },
+            ParamChange::PriceRoundTolerance { .. } => {
+                ParamChange::PriceRoundTolerance { tolerance_bps: self.price_round_tolerance }
+            }
+            ParamChange::LiquidationSlippage { .. } => {
+                ParamChange::LiquidationSlippage { slippage_bps: self.liquidation_router.max_slippage }
+            }
+        };
+        Some(value)
+    }
+
+    fn internal_apply_param_change(&mut self, change: ParamChange) {
+        match change {
+            ParamChange::LeverageRatio { leverage_ratio } => {
+                self.leverage_ratio = leverage_ratio;
+            }
+            ParamChange::InterestFee { interest_fee } => {
+                self.interest_fee = interest_fee;
+            }
+            ParamChange::ExchangeFee { exchange_fee } => {
+                self.exchange_fee = exchange_fee;
+            }
+            ParamChange::StabilityFee { stability_fee } => {
+                self.stability_fee = stability_fee;
+            }
+            ParamChange::MintFee { mint_fee } => {
+                self.mint_fee = mint_fee;
+            }
+            ParamChange::FeeConfig { config } => {
+                // the fees accrued so far are split as they were charged
+                self.fee_ledger.settle_all();
+                self.fee_ledger.config = config;
+            }
+            ParamChange::InterestMode { interest_mode } => {
+                self.interest_mode = interest_mode;
+            }
+            ParamChange::ReferralShare { share_bps } => {
+                self.referrals.share_bps = share_bps;
+            }
+            ParamChange::CollateralRatio { token_id, collateral_ratio } => {
+                let mut asset = self.query_token(&token_id).expect(errors::ASSET_NOT_FOUND);
+                let old_ratio = asset.liquidation_ratio();
+                asset.collateral_ratio = collateral_ratio;
+                asset.assert_liquidation_threshold();
+                self.risk_graces.tighten(&token_id, old_ratio, asset.liquidation_ratio());
+                self.token_list.insert(&token_id, &asset);
+            }
+            ParamChange::LiquidationThreshold { token_id, liquidation_threshold } => {
+                let mut asset = self.query_token(&token_id).expect(errors::ASSET_NOT_FOUND);
+                let old_ratio = asset.liquidation_ratio();
+                asset.liquidation_threshold = liquidation_threshold;
+                asset.assert_liquidation_threshold();
+                self.risk_graces.tighten(&token_id, old_ratio, asset.liquidation_ratio());
+                self.token_list.insert(&token_id, &asset);
+            }
+            ParamChange::NftLiquidationThreshold { collection_id, liquidation_threshold } => {
+                let mut asset = self.query_nft_collection(&collection_id).expect(errors::ASSET_NOT_FOUND);
+                let old_ratio = asset.liquidation_ratio();
+                asset.liquidation_threshold = liquidation_threshold;
+                asset.assert_liquidation_threshold();
+                self.risk_graces.tighten(&collection_id, old_ratio, asset.liquidation_ratio());
+                self.nft_list.insert(&collection_id, &asset);
+            }
+            ParamChange::PairCollateralRatio { token_id, raft_id, collateral_ratio } => {
+                // the grace window of the token also covers its other pairs
+                let old_ratio = self.query_pair_liquidation_ratio(&token_id, &raft_id);
+                let key = (token_id.clone(), raft_id.clone());
+                match collateral_ratio {
+                    Some(collateral_ratio) => self.pair_collateral_ratios.insert(&key, &collateral_ratio.into()),
+                    None => self.pair_collateral_ratios.remove(&key),
+                };
+                self.risk_graces.tighten(&token_id, old_ratio, self.query_pair_liquidation_ratio(&token_id, &raft_id));
+            }
+            ParamChange::TokenListing { asset } => {
+                self.token_list.insert(&asset.address, &asset);
+            }
+            ParamChange::RaftListing { asset } => {
+                if self.query_raft(&asset.address).is_none() {
+                    self.assert_raft_capacity();
+                }
+                self.raft_list.insert(&asset.address, &asset);
+            }
+            ParamChange::NftListing { asset } => {
+                self.nft_list.insert(&asset.address, &asset);
+            }
+            ParamChange::MtListing { asset } => {
+                self.mt_list.insert(&(asset.contract_id.clone(), asset.token_id.clone()), &asset);
+            }
+            ParamChange::TokenUpdate { token_id, patch } => {
+                let mut asset = self.query_token(&token_id).expect(errors::ASSET_NOT_FOUND);
+                let old_ratio = asset.liquidation_ratio();
+                if let Some(risk_class) = patch.risk_class {
+                    asset.apply_risk_class(risk_class, &self.query_risk_params(risk_class));
+                }
+                asset.apply_patch(patch);
+                asset.assert_liquidation_threshold();
+                self.risk_graces.tighten(&token_id, old_ratio, asset.liquidation_ratio());
+                self.token_list.insert(&token_id, &asset);
+                env::log_str(format!("Token {} updated", token_id).as_str());
+            }
+            ParamChange::RaftUpdate { raft_id, patch } => {
+                let mut asset = self.query_raft(&raft_id).expect(errors::ASSET_NOT_FOUND);
+                if let Some(risk_class) = patch.risk_class {
+                    // rafts only take the debt ceiling of the class, as in `add_raft_list_in_class`
+                    asset.apply_risk_class(risk_class, &self.query_risk_params(risk_class));
+                    asset.collateral_ratio = 0;
+                    asset.liquidation_threshold = 0;
+                }
+                asset.apply_patch(patch);
+                self.raft_list.insert(&raft_id, &asset);
+                env::log_str(format!("Raft {} updated", raft_id).as_str());
+            }
+            ParamChange::TimelockDelay { delay } => {
+                self.timelock.delay = delay;
+            }
+            ParamChange::RiskParams { risk_class, params } => {
+                self.risk_params.insert(&risk_class, &params);
+            }
+            ParamChange::GracePeriod { grace_period } => {
+                self.risk_graces.grace_period = grace_period;
+            }
+            ParamChange::SwapCooldown { interval } => {
+                self.swap_cooldown.interval = interval;
+            }
+            ParamChange::PsmFee { fee } => {
+                self.psm.fee = fee;
+            }
+            ParamChange::PsmCap { token_id, cap } => {
+                self.psm.set_cap(&token_id, cap);
+            }
+            ParamChange::PegGuard { guard } => {
+                self.peg_guard = guard;
+            }
+            ParamChange::BuybackConfig { config } => {
+                self.buyback_config = config;
+            }
+            ParamChange::MaxRaftShare { share_bps } => {
+                self.max_raft_share = share_bps;
+            }
+            ParamChange::DeleverageFee { fee } => {
+                self.deleverage_fee = fee;
+            }
+            ParamChange::StopBounty { bounty_bps } => {
+                self.stop_bounty = bounty_bps;
+            }
+            ParamChange::PriceRoundTolerance { tolerance_bps } => {
+                self.price_round_tolerance = tolerance_bps;
+            }
+            ParamChange::LiquidationSlippage { slippage_bps } => {
+                self.liquidation_router.max_slippage = slippage_bps;
+            }
+        }
+    }
+}
@@ -1,13 +1,56 @@
 use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
-use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{serde_json, PromiseOrValue};
 
 use crate::*;
+use crate::execute::Action;
+
+/// Action to run with the transferred tokens, passed as `msg` of `ft_transfer_call`.
+/// An empty `msg` is a plain deposit.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+enum TokenReceiverMessage {
+    /// Deposits the tokens.
+    Deposit,
+    /// Deposits the tokens and locks them in a new collateral minting `raft_amount` of `raft_id`.
+    Mint {
+        raft_id: AccountId,
+        raft_amount: U128,
+        join_debtpool: bool,
+        /// Rejects the mint if a price moved beyond tolerance since its round.
+        price_rounds: Option<Vec<oracle::PriceRound>>,
+    },
+    /// Deposits the tokens and locks them in an open collateral of the sender.
+    AddCollateral { collateral_id: CollateralId },
+    /// Burns the transferred rafts to reduce the debt of an open account book collateral of the sender,
+    /// any amount above the debt is refunded.
+    Repay { collateral_id: CollateralId },
+    /// Deposits the tokens and runs the actions with the deposits of the sender.
+    Execute { actions: Vec<Action> },
+    /// Swaps the stablecoins for rUSD at par in the peg stability module.
+    PsmSwapIn,
+    /// Swaps the transferred rUSD for `token_id` at par in the peg stability module,
+    /// the stablecoins are credited to the deposits of the sender.
+    PsmSwapOut { token_id: AccountId },
+    /// Burns the transferred rUSD against the debts of the collaterals, given by ascending collateral ratio,
+    /// for their tokens credited to the deposits of the sender. Any amount not redeemed is refunded.
+    RedeemRusd { collateral_ids: Vec<CollateralId> },
+    /// Deposits the stablecoins of the peg stability module and locks them in a new account book collateral
+    /// minting `rusd_amount` of rUSD, or the most the collateral ratio of the pair allows.
+    MintRusd { rusd_amount: Option<U128> },
+    /// Stakes the governance tokens, sharing the fees of the stakers.
+    Stake,
+    /// Adds the tokens to the budget of the emission to the debt pool participants.
+    FundRewards,
+    /// Adds the reward tokens to the budget of the farm.
+    FundFarm { farm_id: farm::FarmId },
+    /// Funds the vesting schedule, any amount above its total is refunded.
+    FundVesting { vesting_id: vesting::VestingId },
+}
 
 #[near_bindgen]
 impl FungibleTokenReceiver for Contract {
     /// Callback on receiving tokens by this contract.
-    #[allow(unreachable_code)]
+    /// `msg` is parsed as a `TokenReceiverMessage`.
     fn ft_on_transfer(
         &mut self,
         sender_id: AccountId,
@@ -16,8 +59,158 @@ impl FungibleTokenReceiver for Contract {
     ) -> PromiseOrValue<U128> {
         self.assert_contract_running();
 
+        let message = if msg.is_empty() {
+            TokenReceiverMessage::Deposit
+        } else {
+            serde_json::from_str(&msg).expect(errors::ILLEGAL_TRANSFER_MSG)
+        };
+
         let token_id = env::predecessor_account_id();
-        self.internal_deposit(sender_id.as_ref(), &token_id, amount.into());
-        PromiseOrValue::Value(U128(0))
+        let unused_amount = match message {
+            TokenReceiverMessage::Deposit => {
+                self.internal_deposit_received(&sender_id, &token_id, amount.into());
+                0
+            }
+            TokenReceiverMessage::Mint { raft_id, raft_amount, join_debtpool, price_rounds } => {
+                let raft_amount: Balance = raft_amount.into();
+                self.assert_price_rounds(&price_rounds);
+                self.assert_mint_params(&token_id, amount.into(), &raft_id, raft_amount);
+                self.internal_deposit_received(&sender_id, &token_id, amount.into());
+                self.internal_mint(sender_id.clone(), token_id, amount.into(), raft_id, raft_amount, join_debtpool);
+                0
+            }
+            TokenReceiverMessage::AddCollateral { collateral_id } => {
+                self.internal_deposit_received(&sender_id, &token_id, amount.into());
+                self.internal_add_to_collateral(&sender_id, collateral_id, &token_id, amount.into());
+                0
+            }
+            TokenReceiverMessage::Repay { collateral_id } => {
+                self.internal_repay(&sender_id, collateral_id, &token_id, amount.into())
+            }
+            TokenReceiverMessage::Execute { actions } => {
+                self.internal_deposit_received(&sender_id, &token_id, amount.into());
+                self.internal_execute(&sender_id, actions);
+                0
+            }
+            TokenReceiverMessage::PsmSwapIn => {
+                self.internal_psm_swap_in(&sender_id, &token_id, amount.into());
+                0
+            }
+            TokenReceiverMessage::PsmSwapOut { token_id: stable_id } => {
+                self.internal_psm_swap_out(&sender_id, &token_id, &stable_id, amount.into());
+                0
+            }
+            TokenReceiverMessage::RedeemRusd { collateral_ids } => {
+                self.internal_redeem_rusd(&sender_id, &token_id, amount.into(), collateral_ids)
+            }
+            TokenReceiverMessage::MintRusd { rusd_amount } => {
+                self.internal_mint_rusd(sender_id.clone(), token_id, amount.into(), rusd_amount.map(Balance::from));
+                0
+            }
+            TokenReceiverMessage::Stake => {
+                self.internal_stake(&sender_id, &token_id, amount.into());
+                0
+            }
+            TokenReceiverMessage::FundRewards => {
+                self.internal_fund_rewards(&sender_id, &token_id, amount.into());
+                0
+            }
+            TokenReceiverMessage::FundFarm { farm_id } => {
+                self.internal_fund_farm(&sender_id, &token_id, farm_id, amount.into());
+                0
+            }
+            TokenReceiverMessage::FundVesting { vesting_id } => {
+                self.internal_fund_vesting(&sender_id, &token_id, vesting_id, amount.into())
+            }
+        };
+
+        self.internal_bump_nonce(&sender_id);
+        PromiseOrValue::Value(U128(unused_amount))
+    }
+}
+
+impl Contract {
+    fn internal_deposit_received(&mut self, sender_id: &AccountId, token_id: &AccountId, amount: Balance) {
+        self.assert_not_blacklisted(sender_id);
+        self.internal_deposit(sender_id, token_id, amount);
+        Event::Deposit { account_id: sender_id, token_id, amount: U128(amount) }.emit();
+        self.internal_record_operation(OperationKind::Deposit, sender_id, vec![token_id.clone()], vec![amount]);
+    }
+
+    /// Fast path of a mint of rUSD with stablecoins, at the pair collateral ratio set for the stablecoin
+    /// and out of the debt pool so without leverage check.
+    fn internal_mint_rusd(&mut self, sender_id: AccountId, token_id: AccountId, token_amount: Balance,
+                          rusd_amount: Option<Balance>) -> CollateralId {
+        assert!(self.psm.query_stable(&token_id).is_some(), "{}", errors::PSM_STABLE_NOT_FOUND);
+        let rusd_id = self.rusd_id.clone().expect(errors::ASSET_NOT_FOUND);
+        let rusd_amount = rusd_amount.unwrap_or_else(|| {
+            let valuation = self.valuation();
+            let token_value = valuation.calc_value(&token_id, token_amount);
+            let collateral_ratio = self.query_min_collateral_ratio(&token_id, &rusd_id);
+            valuation.calc_amount(&rusd_id, math::ratio(&[token_value, 100], &[collateral_ratio]))
+        });
+        self.assert_mint_params(&token_id, token_amount, &rusd_id, rusd_amount);
+        self.internal_deposit_received(&sender_id, &token_id, token_amount);
+        self.internal_mint(sender_id, token_id, token_amount, rusd_id, rusd_amount, false)
+    }
+
+    pub(crate) fn internal_unwrap_open_collateral(&self, sender_id: &AccountId, collateral_id: CollateralId) -> Collateral {
+        let collateral = self.query_collateral(collateral_id).expect(errors::COLLATERAL_NOT_FOUND);
+        assert_eq!(&collateral.issuer, sender_id, "{}", errors::NO_PERMISSION);
+        assert!(collateral.state == CollateralState::Open, "{}", errors::COLLATERAL_CLOSED);
+
+        collateral
+    }
+
+    pub(crate) fn internal_add_to_collateral(&mut self, sender_id: &AccountId, collateral_id: CollateralId,
+                                             token_id: &AccountId, amount: Balance) {
+        let mut collateral = self.internal_unwrap_open_collateral(sender_id, collateral_id);
+        assert_eq!(&collateral.token_id, token_id, "{}", errors::ILLEGAL_COLLATERAL_ASSET);
+        self.assert_token_state(token_id, AssetState::is_tradable);
+
+        collateral.token_amount += amount;
+        self.collaterals.replace(collateral_id, &collateral);
+        self.internal_lock_collateral(sender_id, token_id, amount);
+
+        Event::AddCollateral {
+            account_id: sender_id,
+            collateral_id,
+            token_id,
+            amount: U128(amount),
+        }.emit();
+        self.internal_record_operation(OperationKind::AddCollateral, sender_id, vec![token_id.clone()], vec![amount]);
+    }
+
+    /// Returns the amount exceeding the debt of the collateral, including its stability fee.
+    fn internal_repay(&mut self, sender_id: &AccountId, collateral_id: CollateralId,
+                      raft_id: &AccountId, amount: Balance) -> Balance {
+        let mut collateral = self.internal_unwrap_open_collateral(sender_id, collateral_id);
+        assert_eq!(&collateral.raft_id, raft_id, "{}", errors::ILLEGAL_COLLATERAL_ASSET);
+        assert!(!collateral.join_debtpool, "{}", errors::ILLEGAL_TRANSFER_MSG);
+        self.assert_raft_state(raft_id, AssetState::is_withdrawable);
+
+        // the accrued stability fee is repaid first
+        self.internal_accrue_stability_fee(&mut collateral);
+        let repaid_amount = std::cmp::min(amount, collateral.debt());
+        let fee_amount = std::cmp::min(repaid_amount, collateral.stability_fee);
+        collateral.stability_fee -= fee_amount;
+        collateral.raft_amount -= repaid_amount - fee_amount;
+        self.collaterals.replace(collateral_id, &collateral);
+        self.internal_accrue_fee(raft_id, fee_amount);
+
+        // the repaid rafts are out of circulation
+        if repaid_amount > 0 {
+            self.account_book.burn_in(raft_id, repaid_amount);
+            self.internal_burn_held_rafts(raft_id, repaid_amount);
+        }
+
+        Event::Repay {
+            account_id: sender_id,
+            collateral_id,
+            raft_id,
+            amount: U128(repaid_amount),
+        }.emit();
+        self.internal_record_operation(OperationKind::Repay, sender_id, vec![raft_id.clone()], vec![repaid_amount]);
+        amount - repaid_amount
     }
 }
@@ -1,25 +1,134 @@
-use near_sdk::{ext_contract, AccountId, Balance, Gas};
+use near_sdk::{ext_contract, Balance, Gas};
 use near_sdk::json_types::U128;
 
 pub const NO_DEPOSIT: Balance = 0;
 pub const ONE_YOCTO: Balance = 1;
-pub const ONE_NEAR: Balance = 10u128.pow(24);
 
 pub const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(20_000_000_000_000);
-pub const GAS_FOR_FT_TRANSFER_CALL: Gas = Gas(25_000_000_000_000 + GAS_FOR_RESOLVE_TRANSFER.0);
 
-/// Amount of gas for fungible token transfers, increased to 20T to support AS token contracts.
+/// Default amount of gas for fungible token transfers, increased to 20T to support AS token contracts.
+/// Also the default for raft mints and burns, see `GasConfig`.
 pub const GAS_FOR_FT_TRANSFER: Gas = Gas(20_000_000_000_000);
 
+/// Bounds of each amount of gas of the `GasConfig`.
+pub const MIN_CONFIG_GAS: Gas = Gas(5_000_000_000_000);
+pub const MAX_CONFIG_GAS: Gas = Gas(100_000_000_000_000);
+
+/// Amount of gas for reading the metadata of a fungible token.
+pub const GAS_FOR_FT_METADATA: Gas = Gas(10_000_000_000_000);
+
+/// Amount of gas for reading the storage balance of an account on a token.
+pub const GAS_FOR_STORAGE_BALANCE_OF: Gas = Gas(5_000_000_000_000);
+
+/// Amount of gas for a swap on Ref Finance.
+pub const GAS_FOR_REF_SWAP: Gas = Gas(20_000_000_000_000);
+
+/// Amount of gas for a withdrawal from Ref Finance, which transfers the tokens and resolves the transfer.
+pub const GAS_FOR_REF_WITHDRAW: Gas = Gas(50_000_000_000_000);
+
+/// Amount of gas for unwrapping wNEAR.
+pub const GAS_FOR_NEAR_WITHDRAW: Gas = Gas(10_000_000_000_000);
+
+/// Amount of gas for non fungible token transfers.
+pub const GAS_FOR_NFT_TRANSFER: Gas = Gas(20_000_000_000_000);
+
+/// Amount of gas for the receiver of a position token transferred with `nft_transfer_call`.
+pub const GAS_FOR_NFT_ON_TRANSFER: Gas = Gas(25_000_000_000_000);
+
+/// Amount of gas for initializing a raft token deployed by the raft factory.
+pub const GAS_FOR_RAFT_INIT: Gas = Gas(20_000_000_000_000);
+
+/// Gas for the callback resolving a call to the operation nonce of its caller.
+pub const GAS_FOR_NONCE_CALLBACK: Gas = Gas(5_000_000_000_000);
+
+/// Storage in bytes of a deployed raft token besides its code, covering the account and its initial state.
+pub const RAFT_ACCOUNT_EXTRA_STORAGE: u128 = 1_000;
+
 /// Fee divisor, allowing to provide fee in bps.
 pub const FEE_DIVISOR: u32 = 1_000;
 
+/// Basis points divisor, used to split the fees.
+pub const BPS_DIVISOR: u32 = 10_000;
+
 /// Ratio divisor, allowing to provide fee in bps.
 pub const RATIO_DIVISOR: u128 = 1_000_000;
 
+/// Min fee rate of redemptions from the debt pool, 0.5% in `RATIO_DIVISOR` units.
+pub const REDEMPTION_FEE_FLOOR: u128 = 5_000;
+
+/// Time in nanoseconds for the base rate of the redemption fee to halve.
+pub const REDEMPTION_HALF_LIFE: u64 = 12 * 60 * 60 * 1_000_000_000;
+
+/// Max decimals of listed assets, keeping `10^decimals` far from overflowing u128.
+pub const MAX_DECIMALS: u32 = 24;
+
+/// Default delay of the timelock, 1 day in nanoseconds.
+pub const DEFAULT_TIMELOCK_DELAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Max delay of the timelock, 30 days in nanoseconds.
+pub const MAX_TIMELOCK_DELAY: u64 = 30 * DEFAULT_TIMELOCK_DELAY;
+
+/// Default time between an unstake and the withdrawal of the tokens, 7 days in nanoseconds.
+pub const DEFAULT_UNSTAKE_COOLDOWN: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+/// Seconds in a year of 365 days, the period of stability fee rates.
+pub const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+
+/// Default max slippage of liquidation swaps below the oracle value of the seized tokens, in `BPS_DIVISOR` units.
+pub const DEFAULT_LIQUIDATION_SLIPPAGE: u32 = 500;
+
+/// Default max number of listed rafts.
+pub const DEFAULT_MAX_RAFTS: u64 = 50;
+
+/// Max number of fee promotions, bounding the loop over them when charging fees.
+pub const MAX_FEE_PROMOS: u64 = 20;
+
+/// Max number of open collaterals of an account in cross margin, bounding the loop over them on liquidations.
+pub const MAX_MARGIN_COLLATERALS: u64 = 20;
+
+/// Max age in nanoseconds of the prices of assets without a risk class.
+pub const ORACLE_MAX_AGE: u64 = 3_600 * 1_000_000_000;
+
+/// Max value of a gasless swap, in USD with `VALUE_DECIMALS` decimals.
+pub const MAX_RELAYED_SWAP_VALUE: u128 = 1_000 * 10u128.pow(VALUE_DECIMALS);
+
+/// Max number of records processed by a call of a maintenance task.
+pub const KEEPER_BATCH_SIZE: u64 = 50;
+
+/// Max number of actions in a batch call, bounding its gas.
+pub const MAX_BATCH_SIZE: usize = 10;
+
+/// Max number of collaterals of an account visited per debt pool redemption step,
+/// bounding its gas and the transfers it dispatches.
+pub const REDEEM_STEP_SIZE: u64 = 10;
+
+/// Max length in bytes of a collateral label.
+pub const MAX_LABEL_LEN: usize = 32;
+
+/// Max number of viewers an account can grant access to its views.
+pub const MAX_VIEW_GRANTS: usize = 10;
+
+/// Max number of vesting schedules of a beneficiary, bounding the transfers of a claim.
+pub const MAX_VESTING_SCHEDULES: usize = 5;
+
+/// Max number of farms of a target, bounding the loop syncing them on each stake change.
+pub const MAX_FARMS_PER_TARGET: usize = 5;
+
+/// Time in nanoseconds after which the lock of a pending operation expires, if its callback never released it.
+pub const OPERATION_LOCK_TIMEOUT: u64 = 10 * 60 * 1_000_000_000;
+
+/// Number of price rounds of an asset kept, bounding how old the round a call is quoted against can be.
+pub const PRICE_ROUND_HISTORY: u64 = 16;
+
+/// Number of operations kept in the operation log.
+pub const MAX_OPERATIONS: u64 = 10_000;
+
 /// Price precision, allowing to provide fee in bps.
 pub const PRICE_PRECISION: u32 = 100_000;
 
+/// Decimals of asset values, which are in USD.
+pub const VALUE_DECIMALS: u32 = 18;
+
 #[ext_contract(ext_self)]
 pub trait CrfExchange {
     fn exchange_callback_post_withdraw(
@@ -28,4 +137,52 @@ pub trait CrfExchange {
         sender_id: AccountId,
         amount: U128,
     );
+
+    fn exchange_callback_unwrap(&mut self, sender_id: AccountId, amount: U128);
+
+    fn account_callback_nonce(&self, nonce: u64) -> u64;
+}
+
+/// Serializes u128 fields of JSON structs as strings, as JS numbers can't hold them.
+pub mod u128_dec_format {
+    use near_sdk::serde::de;
+    use near_sdk::serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(num: &u128, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&num.to_string())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u128, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Serializes u64 fields of JSON structs as strings, as JS numbers can't hold them.
+pub mod u64_dec_format {
+    use near_sdk::serde::de;
+    use near_sdk::serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(num: &u64, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&num.to_string())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u64, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
 }
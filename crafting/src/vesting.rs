@@ -0,0 +1,271 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, UnorderedMap};
+use near_sdk::{assert_one_yocto, env, AccountId, Balance, Promise, PromiseOrValue, PromiseResult, Timestamp};
+
+use crate::*;
+
+pub type VestingId = u64;
+
+/// Allocation of tokens held by the contract, the governance token or a raft, released to the beneficiary
+/// linearly from `start` to `end`. Nothing is released before `cliff`, a cliff at `end` releases all at once.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VestingSchedule {
+    pub beneficiary: AccountId,
+    pub token_id: AccountId,
+    /// Amount allocated, lowered to the amount vested when revoked.
+    #[serde(with = "u128_dec_format")]
+    pub total: Balance,
+    /// Amount funded through `ft_transfer_call`, up to the total. Claims can't go beyond it.
+    #[serde(with = "u128_dec_format")]
+    pub funded: Balance,
+    /// Amount claimed, including the transfers in flight.
+    #[serde(with = "u128_dec_format")]
+    pub claimed: Balance,
+    /// Amount claimed whose transfer succeeded.
+    #[serde(with = "u128_dec_format")]
+    pub transferred: Balance,
+    #[serde(with = "u64_dec_format")]
+    pub start: Timestamp,
+    #[serde(with = "u64_dec_format")]
+    pub cliff: Timestamp,
+    #[serde(with = "u64_dec_format")]
+    pub end: Timestamp,
+    pub revoked: bool,
+}
+
+impl VestingSchedule {
+    fn is_valid(&self) -> bool {
+        self.total > 0 && self.start <= self.cliff && self.cliff <= self.end && self.start < self.end
+    }
+
+    fn query_vested(&self, now: Timestamp) -> Balance {
+        if self.revoked || now >= self.end {
+            self.total
+        } else if now < self.cliff {
+            0
+        } else {
+            math::ratio(&[self.total, (now - self.start) as u128], &[(self.end - self.start) as u128])
+        }
+    }
+
+    fn query_claimable(&self, now: Timestamp) -> Balance {
+        std::cmp::min(self.query_vested(now), self.funded) - self.claimed
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Vesting {
+    next_id: VestingId,
+    schedules: UnorderedMap<VestingId, VestingSchedule>,
+    /// Mapping from beneficiary to its schedules not fully claimed.
+    beneficiary_schedules: LookupMap<AccountId, Vec<VestingId>>,
+}
+
+impl Vesting {
+    pub(crate) fn new() -> Self {
+        Self {
+            next_id: 0,
+            schedules: UnorderedMap::new(StorageKey::VestingSchedules),
+            beneficiary_schedules: LookupMap::new(StorageKey::BeneficiarySchedules),
+        }
+    }
+
+    fn unwrap_schedule(&self, vesting_id: VestingId) -> VestingSchedule {
+        self.schedules.get(&vesting_id).expect(errors::VESTING_NOT_FOUND)
+    }
+
+    fn query_schedule_ids(&self, beneficiary: &AccountId) -> Vec<VestingId> {
+        self.beneficiary_schedules.get(beneficiary).unwrap_or_default()
+    }
+
+    /// Drops a schedule claimed in full, freeing the slot of its beneficiary.
+    fn remove_schedule(&mut self, vesting_id: VestingId, beneficiary: &AccountId) {
+        self.schedules.remove(&vesting_id);
+        let mut vesting_ids = self.query_schedule_ids(beneficiary);
+        vesting_ids.retain(|id| *id != vesting_id);
+        if vesting_ids.is_empty() {
+            self.beneficiary_schedules.remove(beneficiary);
+        } else {
+            self.beneficiary_schedules.insert(beneficiary, &vesting_ids);
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Create a vesting schedule of `total` of the token for the beneficiary. Only can be called by owner.
+    /// The tokens allocated are funded with `ft_transfer_call`.
+    pub fn create_vesting(&mut self, beneficiary: AccountId, token_id: AccountId, total: U128,
+                          start: U64, cliff: U64, end: U64) -> VestingId {
+        self.assert_owner();
+        let schedule = VestingSchedule {
+            beneficiary,
+            token_id,
+            total: total.into(),
+            funded: 0,
+            claimed: 0,
+            transferred: 0,
+            start: start.into(),
+            cliff: cliff.into(),
+            end: end.into(),
+            revoked: false,
+        };
+        assert!(schedule.is_valid(), "{}", errors::ILLEGAL_VESTING);
+        let mut vesting_ids = self.vesting.query_schedule_ids(&schedule.beneficiary);
+        assert!(vesting_ids.len() < utils::MAX_VESTING_SCHEDULES, "{}", errors::TOO_MANY_VESTINGS);
+
+        let vesting_id = self.vesting.next_id;
+        self.vesting.next_id += 1;
+        self.vesting.schedules.insert(&vesting_id, &schedule);
+        vesting_ids.push(vesting_id);
+        self.vesting.beneficiary_schedules.insert(&schedule.beneficiary, &vesting_ids);
+        Event::CreateVesting { vesting_id, schedule: &schedule }.emit();
+        vesting_id
+    }
+
+    /// Stop the vesting of a schedule, the beneficiary keeps the amount vested so far.
+    /// Only can be called by owner, who must be registered on the token to get back the unvested tokens funded.
+    pub fn revoke_vesting(&mut self, vesting_id: VestingId) -> PromiseOrValue<U128> {
+        self.assert_owner();
+        let mut schedule = self.vesting.unwrap_schedule(vesting_id);
+        assert!(!schedule.revoked, "{}", errors::ILLEGAL_VESTING);
+        let vested = schedule.query_vested(env::block_timestamp());
+        let unvested = schedule.total - vested;
+        let refund = schedule.funded.saturating_sub(vested);
+        schedule.total = vested;
+        schedule.funded -= refund;
+        schedule.revoked = true;
+        if schedule.transferred == schedule.total {
+            self.vesting.remove_schedule(vesting_id, &schedule.beneficiary);
+        } else {
+            self.vesting.schedules.insert(&vesting_id, &schedule);
+        }
+        Event::RevokeVesting { vesting_id, unvested: U128(unvested) }.emit();
+
+        if refund == 0 {
+            return PromiseOrValue::Value(U128(0));
+        }
+        ext_fungible_token::ft_transfer(
+            self.owner_id.clone(),
+            U128(refund),
+            None,
+            schedule.token_id,
+            utils::ONE_YOCTO,
+            self.gas_config.ft_transfer,
+        ).into()
+    }
+
+    /// Send the tokens vested by all the schedules of the caller and not claimed yet.
+    #[payable]
+    pub fn claim_vested(&mut self) -> Promise {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let beneficiary = env::predecessor_account_id();
+        self.assert_not_blacklisted(&beneficiary);
+
+        let now = env::block_timestamp();
+        let mut promise: Option<Promise> = None;
+        for vesting_id in self.vesting.query_schedule_ids(&beneficiary) {
+            let mut schedule = self.vesting.unwrap_schedule(vesting_id);
+            let amount = schedule.query_claimable(now);
+            if amount == 0 { continue; }
+            schedule.claimed += amount;
+            self.vesting.schedules.insert(&vesting_id, &schedule);
+
+            let transfer = ext_fungible_token::ft_transfer(
+                beneficiary.clone(),
+                U128(amount),
+                None,
+                schedule.token_id,
+                utils::ONE_YOCTO,
+                self.gas_config.ft_transfer,
+            ).then(ext_self::claim_vested_callback(
+                vesting_id,
+                U128(amount),
+                env::current_account_id(),
+                utils::NO_DEPOSIT,
+                self.gas_config.resolve,
+            ));
+            promise = Some(match promise {
+                Some(promise) => promise.and(transfer),
+                None => transfer,
+            });
+        }
+        promise.expect(errors::NOTHING_VESTED)
+    }
+
+    pub fn get_vesting_schedule(&self, vesting_id: VestingId) -> Option<VestingSchedule> {
+        self.vesting.schedules.get(&vesting_id)
+    }
+
+    /// Schedules of the beneficiary not fully claimed.
+    pub fn get_vesting_schedules(&self, beneficiary: AccountId) -> Vec<(VestingId, VestingSchedule)> {
+        self.vesting.query_schedule_ids(&beneficiary).into_iter()
+            .map(|vesting_id| (vesting_id, self.vesting.unwrap_schedule(vesting_id)))
+            .collect()
+    }
+
+    /// Amounts vested and not claimed yet of each schedule of the beneficiary.
+    pub fn get_claimable_vested(&self, beneficiary: AccountId) -> Vec<(VestingId, U128)> {
+        let now = env::block_timestamp();
+        self.vesting.query_schedule_ids(&beneficiary).into_iter()
+            .map(|vesting_id| (vesting_id, U128(self.vesting.unwrap_schedule(vesting_id).query_claimable(now))))
+            .collect()
+    }
+
+    /// Amount of the schedule left to be funded.
+    pub fn get_vesting_unfunded(&self, vesting_id: VestingId) -> U128 {
+        let schedule = self.vesting.unwrap_schedule(vesting_id);
+        U128(schedule.total - schedule.funded)
+    }
+
+    #[private]
+    pub fn claim_vested_callback(&mut self, vesting_id: VestingId, amount: U128) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            errors::CALLBACK_POST_WITHDRAW_INVALID
+        );
+
+        let mut schedule = self.vesting.unwrap_schedule(vesting_id);
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => {
+                Event::ClaimVested {
+                    vesting_id,
+                    account_id: &schedule.beneficiary,
+                    token_id: &schedule.token_id,
+                    amount,
+                }.emit();
+                schedule.transferred += amount.0;
+                if schedule.transferred == schedule.total {
+                    self.vesting.remove_schedule(vesting_id, &schedule.beneficiary);
+                } else {
+                    self.vesting.schedules.insert(&vesting_id, &schedule);
+                }
+            }
+            PromiseResult::Failed => {
+                // This reverts the changes from claim_vested function.
+                schedule.claimed -= amount.0;
+                self.vesting.schedules.insert(&vesting_id, &schedule);
+                env::log_str(format!("Failed to claim {} vested of schedule {}", amount.0, vesting_id).as_str());
+            }
+        };
+    }
+}
+
+impl Contract {
+    /// Funds the schedule with the tokens received, returning the amount above its total.
+    pub(crate) fn internal_fund_vesting(&mut self, sender_id: &AccountId, token_id: &AccountId,
+                                        vesting_id: VestingId, amount: Balance) -> Balance {
+        let mut schedule = self.vesting.unwrap_schedule(vesting_id);
+        assert!(&schedule.token_id == token_id, "{}", errors::ILLEGAL_VESTING);
+        let funded = std::cmp::min(amount, schedule.total - schedule.funded);
+        schedule.funded += funded;
+        self.vesting.schedules.insert(&vesting_id, &schedule);
+        Event::FundVesting { vesting_id, account_id: sender_id, amount: U128(funded) }.emit();
+        amount - funded
+    }
+}
@@ -0,0 +1,63 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::{env, AccountId};
+
+use crate::*;
+
+/// Viewers, such as portfolio trackers, allowed by an account to read its user-scoped views.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct ViewAccess {
+    /// Mapping from account to the viewers it granted access to.
+    grants: LookupMap<AccountId, Vec<AccountId>>,
+}
+
+impl ViewAccess {
+    pub(crate) fn new() -> Self {
+        Self {
+            grants: LookupMap::new(StorageKey::ViewGrants),
+        }
+    }
+
+    pub(crate) fn query_viewers(&self, account_id: &AccountId) -> Vec<AccountId> {
+        self.grants.get(account_id).unwrap_or_default()
+    }
+
+    pub(crate) fn is_granted(&self, account_id: &AccountId, viewer_id: &AccountId) -> bool {
+        self.query_viewers(account_id).contains(viewer_id)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Allow the viewer to read the user-scoped views of the caller. The caller must be registered.
+    pub fn grant_view_access(&mut self, viewer_id: AccountId) {
+        let account_id = env::predecessor_account_id();
+        assert!(self.internal_get_account(&account_id).is_some(), "{}", errors::ACC_NOT_REGISTERED);
+
+        let mut viewers = self.view_access.query_viewers(&account_id);
+        if viewers.contains(&viewer_id) {
+            return;
+        }
+        assert!(viewers.len() < utils::MAX_VIEW_GRANTS, "{}", errors::MAX_VIEW_GRANTS_REACHED);
+        viewers.push(viewer_id.clone());
+        self.view_access.grants.insert(&account_id, &viewers);
+        Event::GrantViewAccess { account_id: &account_id, viewer_id: &viewer_id }.emit();
+    }
+
+    pub fn revoke_view_access(&mut self, viewer_id: AccountId) {
+        let account_id = env::predecessor_account_id();
+        let mut viewers = self.view_access.query_viewers(&account_id);
+        let index = viewers.iter().position(|viewer| viewer == &viewer_id).expect(errors::VIEWER_NOT_GRANTED);
+        viewers.swap_remove(index);
+        if viewers.is_empty() {
+            self.view_access.grants.remove(&account_id);
+        } else {
+            self.view_access.grants.insert(&account_id, &viewers);
+        }
+        Event::RevokeViewAccess { account_id: &account_id, viewer_id: &viewer_id }.emit();
+    }
+
+    pub fn get_view_grants(&self, account_id: AccountId) -> Vec<AccountId> {
+        self.view_access.query_viewers(&account_id)
+    }
+}
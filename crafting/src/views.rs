@@ -1,5 +1,114 @@
+use near_sdk::json_types::{I128, U64};
+
 use crate::*;
+use crate::account::{Account, AccountStorageState};
 use crate::debtpool::WrappedBalance;
+use crate::fees::InterestMode;
+use crate::mt::{MtAsset, MtCollateral, MtCollateralId};
+use crate::nft::{NftCollateral, NftCollateralId};
+use crate::oplog::{Operation, OperationId};
+use crate::timelock::{ChangeId, PendingChange};
+
+/// Open collateral with its current health.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollateralOverview {
+    pub collateral_id: CollateralId,
+    pub collateral: Collateral,
+    /// Current collateral ratio in percent.
+    pub collateral_ratio: U128,
+    /// Collateral ratio in percent required by the collateral token.
+    pub min_collateral_ratio: U128,
+    /// Collateral ratio in percent under which the collateral can be liquidated.
+    pub liquidation_threshold: U128,
+    pub label: Option<String>,
+}
+
+/// Everything held by a user, returned in a single call.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct UserOverview {
+    pub nonce: u64,
+    pub last_action_timestamp: U64,
+    /// Deposited token balances.
+    pub tokens: Vec<(AccountId, U128)>,
+    pub collaterals: Vec<CollateralOverview>,
+    pub debt_ratio: U128,
+    /// Raft amounts in the debt pool.
+    pub debtpool_rafts: Vec<(AccountId, U128)>,
+    /// Raft amounts in the account book.
+    pub accountbook_rafts: Vec<(AccountId, U128)>,
+}
+
+/// Open interest of a raft.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RaftOpenInterest {
+    pub raft_id: AccountId,
+    /// Amount minted through the account book.
+    pub accountbook_amount: U128,
+    /// Net exposure of the debt pool, negative when more was swapped out than minted.
+    pub debtpool_amount: WrappedBalance,
+    /// Users holding the raft in the account book.
+    pub accountbook_holders: u64,
+    /// Users holding the raft in the debt pool.
+    pub debtpool_holders: u64,
+    /// Amount minted out as tokens.
+    pub circulating_amount: U128,
+    /// Supply counted against the max supply.
+    pub supply: U128,
+    /// Max supply, 0 for no cap.
+    pub max_supply: U128,
+}
+
+/// Outstanding rUSD and the collateral backing it.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RusdStats {
+    /// Amount held in the account book.
+    pub accountbook_amount: U128,
+    /// Net long exposure of the debt pool.
+    pub debtpool_amount: U128,
+    /// Amount minted out as tokens, as tracked by the contract rather than read from the raft supply.
+    pub tracked_circulating_amount: U128,
+    /// Total outstanding amount.
+    pub total_amount: U128,
+    /// Value of the tokens of the open account book rUSD collaterals, the share of the debt pool collaterals
+    /// matching the share of rUSD in the debt pool and the reserves of the peg stability module,
+    /// in USD with `VALUE_DECIMALS` decimals. The collateral values are the ones summed by `refresh_rusd_backing`.
+    pub backing_value: U128,
+    /// Timestamp of the start of the `refresh_rusd_backing` pass the collateral values come from.
+    pub backing_timestamp: U64,
+    /// Backing value over the value of the total amount, in percent like collateral ratios, 0 if none is outstanding.
+    pub backing_ratio: U128,
+}
+
+/// Contract configuration, returned in a single call.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractConfig {
+    pub owner_id: AccountId,
+    pub treasury_id: AccountId,
+    pub state: RunningState,
+    pub leverage_ratio: (u8, u8),
+    pub interest_fee: u32,
+    pub exchange_fee: u32,
+    /// Stability fee per annum of collaterals of unclassified tokens.
+    pub stability_fee: u32,
+    pub interest_mode: InterestMode,
+    /// Fee charged on minted rafts, in `BPS_DIVISOR` units.
+    pub mint_fee: u32,
+    pub fee_divisor: u32,
+    pub price_precision: u32,
+    /// Decimals of asset values, which are in USD.
+    pub value_decimals: u32,
+    pub ratio_divisor: U128,
+    pub timelock_delay: U64,
+    /// Max number of listed rafts, bounding the gas of the calls looping over all rafts.
+    pub max_rafts: u64,
+    pub token_count: u64,
+    pub raft_count: u64,
+}
 
 #[near_bindgen]
 impl Contract {
@@ -8,12 +117,23 @@ impl Contract {
         self.query_token(&token_id)
     }
 
-    pub fn whitelisted_tokens(&self) -> Vec<Asset> {
+    /// Collateral ratio required to mint the raft against the token.
+    pub fn get_pair_collateral_ratio(&self, token_id: AccountId, raft_id: AccountId) -> U128 {
+        U128(self.query_min_collateral_ratio(&token_id, &raft_id))
+    }
+
+    /// Collateral ratio under which a collateral of the token minting the raft can be liquidated.
+    pub fn get_liquidation_threshold(&self, token_id: AccountId, raft_id: AccountId) -> U128 {
+        U128(self.query_liquidation_threshold(&token_id, &raft_id))
+    }
+
+    /// Pages of at most `max_rafts` assets, the first page by default.
+    pub fn whitelisted_tokens(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<Asset> {
+        let limit = std::cmp::min(limit.unwrap_or(self.max_rafts), self.max_rafts);
         let mut vec: Vec<Asset> = Vec::new();
-        for account_id in self.whitelisted_tokens.iter() {
-            let asset = self.query_token(&account_id);
-            if asset.is_some() {
-                vec.push(asset.unwrap());
+        for account_id in self.whitelisted_tokens.iter().skip(from_index.unwrap_or(0) as usize).take(limit as usize) {
+            if let Some(asset) = self.query_token(&account_id) {
+                vec.push(asset);
             }
         }
 
@@ -25,12 +145,13 @@ impl Contract {
         self.query_raft(&raft_id)
     }
 
-    pub fn whitelisted_rafts(&self) -> Vec<Asset> {
+    /// Pages of at most `max_rafts` assets, the first page by default.
+    pub fn whitelisted_rafts(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<Asset> {
+        let limit = std::cmp::min(limit.unwrap_or(self.max_rafts), self.max_rafts);
         let mut vec: Vec<Asset> = Vec::new();
-        for account_id in self.whitelisted_rafts.iter() {
-            let asset = self.query_raft(&account_id);
-            if asset.is_some() {
-                vec.push(asset.unwrap());
+        for account_id in self.whitelisted_rafts.iter().skip(from_index.unwrap_or(0) as usize).take(limit as usize) {
+            if let Some(asset) = self.query_raft(&account_id) {
+                vec.push(asset);
             }
         }
 
@@ -42,29 +163,180 @@ impl Contract {
         self.collaterals.len()
     }
 
+    /// Collateral with its stability fee accrued up to now.
     pub fn get_collateral(&self, collateral_id: CollateralId) -> Option<Collateral> {
-        self.query_collateral(collateral_id)
+        self.query_accrued_collateral(collateral_id)
+    }
+
+    /// Collateral token price at which the collateral can be liquidated,
+    /// `None` if the collateral is closed or joined the debt pool.
+    pub fn get_liquidation_price(&self, collateral_id: CollateralId) -> Option<U128> {
+        self.query_accrued_collateral(collateral_id)
+            .filter(|collateral| collateral.state == CollateralState::Open && !collateral.join_debtpool)
+            .map(|collateral| U128(self.calc_liquidation_price(&collateral)))
+    }
+
+    /// Max amount of the raft an account book collateral of `token_amount` of the token can mint,
+    /// within the collateral ratio of the pair and the remaining supply of the raft.
+    /// The mint fee is taken out of the minted amount.
+    pub fn max_mintable(&self, token_id: AccountId, token_amount: U128, raft_id: AccountId) -> U128 {
+        let min_collateral_ratio = self.query_token_collateral_ratio(&token_id, &raft_id);
+        let valuation = self.valuation();
+        let max_value = math::ratio(&[valuation.calc_value(&token_id, token_amount.0), 100], &[min_collateral_ratio]);
+        let raft_amount = valuation.calc_amount(&raft_id, max_value);
+        U128(self.calc_remaining_supply(&raft_id).map_or(raft_amount, |remaining| raft_amount.min(remaining)))
+    }
+
+    /// Min amount of the token an account book collateral minting `raft_amount` of the raft must lock,
+    /// to meet the collateral ratio of the pair.
+    pub fn required_collateral(&self, token_id: AccountId, raft_id: AccountId, raft_amount: U128) -> U128 {
+        let min_collateral_ratio = self.query_token_collateral_ratio(&token_id, &raft_id);
+        let valuation = self.valuation();
+        let min_value = math::ratio_ceil(&[valuation.calc_value(&raft_id, raft_amount.0), min_collateral_ratio], &[100]);
+        U128(valuation.calc_amount_ceil(&token_id, min_value))
+    }
+
+    /// Paginated list of all the collaterals by id, including closed ones not pruned yet.
+    pub fn get_collaterals(&self, from_index: u64, limit: u64) -> Vec<(CollateralId, Collateral)> {
+        (from_index..std::cmp::min(from_index.saturating_add(limit), self.collaterals.len()))
+            .filter_map(|collateral_id| self.collaterals.get(collateral_id).map(|collateral| (collateral_id, collateral)))
+            .collect()
+    }
+
+    /// Paginated list of the open collaterals of the issuer.
+    pub fn get_collaterals_by_issuer(&self, issuer: AccountId, from_index: u64, limit: u64) -> Vec<(CollateralId, Collateral)> {
+        let opt_collateral_ids = self.user_collaterals.get(&issuer);
+        if opt_collateral_ids.is_none() {
+            return vec![];
+        }
+
+        let collateral_ids = opt_collateral_ids.unwrap();
+        (from_index..std::cmp::min(from_index + limit, collateral_ids.len()))
+            .map(|index| {
+                let collateral_id = collateral_ids.as_vector().get(index).unwrap();
+                (collateral_id, self.collaterals.get(collateral_id).unwrap())
+            })
+            .collect()
+    }
+
+    /// NFT collateral Related
+    pub fn get_nft_list(&self) -> Vec<Asset> {
+        self.nft_list.values().collect()
     }
 
+    pub fn get_nft_collection(&self, collection_id: AccountId) -> Option<Asset> {
+        self.query_nft_collection(&collection_id)
+    }
+
+    pub fn get_nft_collateral(&self, collateral_id: NftCollateralId) -> Option<NftCollateral> {
+        self.query_nft_collateral(collateral_id)
+    }
+
+    /// Paginated list of the open NFT collaterals of the issuer.
+    pub fn get_nft_collaterals_by_issuer(&self, issuer: AccountId, from_index: u64, limit: u64) -> Vec<(NftCollateralId, NftCollateral)> {
+        let opt_collateral_ids = self.user_nft_collaterals.get(&issuer);
+        if opt_collateral_ids.is_none() {
+            return vec![];
+        }
+
+        let collateral_ids = opt_collateral_ids.unwrap();
+        (from_index..std::cmp::min(from_index + limit, collateral_ids.len()))
+            .map(|index| {
+                let collateral_id = collateral_ids.as_vector().get(index).unwrap();
+                (collateral_id, self.nft_collaterals.get(collateral_id).unwrap())
+            })
+            .collect()
+    }
+
+    /// Multi-token collateral Related
+    pub fn get_mt_list(&self) -> Vec<MtAsset> {
+        self.mt_list.values().collect()
+    }
+
+    pub fn get_mt(&self, contract_id: AccountId, token_id: String) -> Option<MtAsset> {
+        self.query_mt(&(contract_id, token_id))
+    }
+
+    pub fn get_mt_collateral(&self, collateral_id: MtCollateralId) -> Option<MtCollateral> {
+        self.query_mt_collateral(collateral_id)
+    }
+
+    /// Paginated list of the open multi-token collaterals of the issuer.
+    pub fn get_mt_collaterals_by_issuer(&self, issuer: AccountId, from_index: u64, limit: u64) -> Vec<(MtCollateralId, MtCollateral)> {
+        let opt_collateral_ids = self.user_mt_collaterals.get(&issuer);
+        if opt_collateral_ids.is_none() {
+            return vec![];
+        }
+
+        let collateral_ids = opt_collateral_ids.unwrap();
+        (from_index..std::cmp::min(from_index + limit, collateral_ids.len()))
+            .map(|index| {
+                let collateral_id = collateral_ids.as_vector().get(index).unwrap();
+                (collateral_id, self.mt_collaterals.get(collateral_id).unwrap())
+            })
+            .collect()
+    }
+
+    /// Open collaterals of the user, closed ones are skipped.
     pub fn user_collaterals(&self, user: AccountId) -> Vec<Collateral> {
         self.assert_query_authority(user.clone());
 
         let mut vec: Vec<Collateral> = Vec::new();
-        let collateral_ids: Option<Vector<CollateralId>> = self.user_collaterals.get(&user);
+        let collateral_ids: Option<UnorderedSet<CollateralId>> = self.user_collaterals.get(&user);
         if collateral_ids.is_none() {
             return vec;
         }
 
         for collateral_id in collateral_ids.unwrap().iter() {
             let opt_collateral = self.query_collateral(collateral_id);
-            if opt_collateral.is_some() {
-                vec.push(opt_collateral.unwrap());
+            if let Some(collateral) = opt_collateral {
+                if collateral.state == CollateralState::Open {
+                    vec.push(collateral);
+                }
             }
         }
 
         vec
     }
 
+    pub fn get_user_overview(&self, user: AccountId) -> UserOverview {
+        self.assert_query_authority(user.clone());
+
+        let (nonce, last_action_timestamp, tokens) = self.internal_get_account(&user)
+            .map(|account| (account.nonce, account.last_action_timestamp, account.tokens.to_vec()))
+            .unwrap_or_default();
+
+        let mut collaterals: Vec<CollateralOverview> = Vec::new();
+        if let Some(collateral_ids) = self.user_collaterals.get(&user) {
+            for collateral_id in collateral_ids.iter() {
+                let collateral = self.query_accrued_collateral(collateral_id).unwrap();
+                if collateral.state != CollateralState::Open { continue; }
+                let collateral_ratio = self.calc_collateral_ratio(&collateral.token_id, collateral.token_amount,
+                                                                  &collateral.raft_id, collateral.debt());
+                let min_collateral_ratio = self.query_min_collateral_ratio(&collateral.token_id, &collateral.raft_id);
+                let liquidation_threshold = self.query_liquidation_threshold(&collateral.token_id, &collateral.raft_id);
+                collaterals.push(CollateralOverview {
+                    collateral_id,
+                    collateral,
+                    collateral_ratio: U128(collateral_ratio),
+                    min_collateral_ratio: U128(min_collateral_ratio),
+                    liquidation_threshold: U128(liquidation_threshold),
+                    label: self.collateral_labels.get(&collateral_id),
+                });
+            }
+        }
+
+        UserOverview {
+            nonce,
+            last_action_timestamp: U64(last_action_timestamp),
+            tokens: to_json_amounts(tokens),
+            collaterals,
+            debt_ratio: U128(self.debt_pool.query_debt_ratio(&user)),
+            debtpool_rafts: to_json_amounts(self.debt_pool.query_user_raft_amounts(&user)),
+            accountbook_rafts: to_json_amounts(self.account_book.query_user_raft_amounts(&user)),
+        }
+    }
+
     /// Debt Pool Related
     pub fn debtpool_raft_amount(&self, raft_id: AccountId) -> WrappedBalance {
         self.is_in_whitelisted_rafts(&raft_id);
@@ -72,86 +344,281 @@ impl Contract {
         self.debt_pool.query_raft_amount(&raft_id)
     }
 
-    pub fn debtpool_raft_value(&self, raft_id: AccountId) -> (WrappedBalance, u128) {
+    pub fn debtpool_raft_value(&self, raft_id: AccountId) -> (WrappedBalance, U128) {
         let raft_amount = self.debtpool_raft_amount(raft_id.clone());
-        let value = self.debt_pool.calc_raft_value(&self.price_oracle, &raft_id, raft_amount.amount);
-        (raft_amount, value)
+        let value = self.debt_pool.calc_raft_value(&self.valuation(), &raft_id, raft_amount.amount);
+        (raft_amount, U128(value))
     }
 
-    pub fn debtpool_raft_total_value(&self) -> u128 {
-        self.debt_pool.calc_raft_total_value(&self.price_oracle)
+    /// Open interest of a listed raft.
+    pub fn get_raft_open_interest(&self, raft_id: AccountId) -> RaftOpenInterest {
+        let raft = self.query_raft(&raft_id).expect(errors::ASSET_NOT_FOUND);
+
+        RaftOpenInterest {
+            accountbook_amount: U128(self.account_book.query_raft_amount(&raft_id)),
+            debtpool_amount: self.debt_pool.query_raft_amount(&raft_id),
+            accountbook_holders: self.account_book.query_holder_count(&raft_id),
+            debtpool_holders: self.debt_pool.query_holder_count(&raft_id),
+            circulating_amount: U128(self.account_book.query_circulating(&raft_id)),
+            supply: U128(self.calc_raft_supply(&raft_id)),
+            max_supply: U128(raft.max_supply),
+            raft_id,
+        }
     }
 
-    pub fn debtpool_user_raft_amount(&self, user: AccountId, raft_id: AccountId) -> Balance {
+    /// Amount of the raft that can still be minted, `None` if the raft has no max supply.
+    pub fn get_raft_remaining_supply(&self, raft_id: AccountId) -> Option<U128> {
+        self.calc_remaining_supply(&raft_id).map(U128)
+    }
+
+    /// Open interest of every listed raft.
+    pub fn get_rafts_open_interest(&self) -> Vec<RaftOpenInterest> {
+        self.raft_list.keys().map(|raft_id| self.get_raft_open_interest(raft_id)).collect()
+    }
+
+    /// Supply and backing of rUSD, panics if rUSD isn't set.
+    pub fn get_rusd_stats(&self) -> RusdStats {
+        let rusd_id = self.query_rusd().expect(errors::ASSET_NOT_FOUND).address;
+        let valuation = self.valuation();
+
+        let debtpool_amount = self.debt_pool.query_raft_amount(&rusd_id);
+        let debtpool_amount = if debtpool_amount.is_positive { debtpool_amount.amount } else { 0 };
+        let total_amount = self.calc_raft_supply(&rusd_id);
+
+        let backing = &self.rusd_backing.completed;
+        let debtpool_total_value = self.debt_pool.calc_raft_total_value(&valuation);
+        let debtpool_share_value = if debtpool_total_value == 0 { 0 } else {
+            math::mul_div(backing.debtpool, valuation.calc_value(&rusd_id, debtpool_amount), debtpool_total_value)
+        };
+        let psm_value: u128 = self.get_psm_stables().iter()
+            .map(|(token_id, stable)| valuation.calc_value(token_id, stable.reserve))
+            .sum();
+
+        let backing_value = backing.accountbook + debtpool_share_value + psm_value;
+        let total_value = valuation.calc_value(&rusd_id, total_amount);
+        RusdStats {
+            accountbook_amount: U128(self.account_book.query_raft_amount(&rusd_id)),
+            debtpool_amount: U128(debtpool_amount),
+            tracked_circulating_amount: U128(self.account_book.query_circulating(&rusd_id)),
+            total_amount: U128(total_amount),
+            backing_value: U128(backing_value),
+            backing_timestamp: U64(backing.timestamp),
+            backing_ratio: U128(if total_value == 0 { 0 } else { math::mul_div(backing_value, 100, total_value) }),
+        }
+    }
+
+    /// Prices every raft, see `get_total_values` for the total stored by keepers
+    /// and `debtpool_raft_values` to page through the rafts.
+    pub fn debtpool_raft_total_value(&self) -> U128 {
+        U128(self.debt_pool.calc_raft_total_value(&self.valuation()))
+    }
+
+    /// Rafts of the debt pool as (raft, amount, value), `limit` capped at `max_rafts`.
+    pub fn debtpool_raft_values(&self, from_index: u64, limit: u64) -> Vec<(AccountId, WrappedBalance, U128)> {
+        let valuation = self.valuation();
+        self.debt_pool.query_raft_amounts(from_index, std::cmp::min(limit, self.max_rafts)).into_iter()
+            .map(|(raft_id, amount)| {
+                let value = self.debt_pool.calc_raft_value(&valuation, &raft_id, amount.amount);
+                (raft_id, amount, U128(value))
+            })
+            .collect()
+    }
+
+    pub fn debtpool_user_raft_amount(&self, user: AccountId, raft_id: AccountId) -> U128 {
         self.assert_query_authority(user.clone());
         self.is_in_whitelisted_rafts(&raft_id);
 
-        self.debt_pool.query_user_raft_amount(&user, &raft_id)
+        U128(self.debt_pool.query_user_raft_amount(&user, &raft_id))
     }
 
-    pub fn debtpool_user_raft_value(&self, user: AccountId, raft_id: AccountId) -> (Balance, u128) {
+    pub fn debtpool_user_raft_value(&self, user: AccountId, raft_id: AccountId) -> (U128, U128) {
         let amount = self.debtpool_user_raft_amount(user.clone(), raft_id.clone());
-        let value = self.debt_pool.calc_raft_value(&self.price_oracle, &raft_id, amount);
-        (amount, value)
+        let value = self.debt_pool.calc_raft_value(&self.valuation(), &raft_id, amount.0);
+        (amount, U128(value))
     }
 
-    pub fn debtpool_user_raft_total_value(&self, user: AccountId) -> u128 {
+    pub fn debtpool_user_raft_total_value(&self, user: AccountId) -> U128 {
         self.assert_query_authority(user.clone());
 
-        self.debt_pool.calc_user_raft_total_value(&self.price_oracle, &user)
+        U128(self.debt_pool.query_user_value(&self.valuation(), &user))
     }
 
-    pub fn debtpool_user_profit(&self, user: AccountId) -> i128 {
+    pub fn debtpool_user_profit(&self, user: AccountId) -> I128 {
         self.assert_query_authority(user.clone());
 
-        (self.debt_pool.calc_user_raft_total_value(&self.price_oracle, &user) -
-            (self.debtpool_raft_total_value() * self.debtpool_debt_ratio(user)) / utils::RATIO_DIVISOR) as i128
+        I128((self.debt_pool.query_user_value(&self.valuation(), &user) -
+            math::mul_div(self.debtpool_raft_total_value().0, self.debtpool_debt_ratio(user).0, utils::RATIO_DIVISOR)) as i128)
     }
 
-    pub fn debtpool_debt_ratio(&self, user: AccountId) -> u128 {
+    pub fn debtpool_debt_ratio(&self, user: AccountId) -> U128 {
         self.assert_query_authority(user.clone());
 
-        self.debt_pool.query_debt_ratio(&user)
+        U128(self.debt_pool.query_debt_ratio(&user))
     }
 
     /// AccountBook Related
-    pub fn accountbook_raft_amount(&self, raft_id: AccountId) -> Balance {
+    pub fn accountbook_raft_amount(&self, raft_id: AccountId) -> U128 {
         self.is_in_whitelisted_rafts(&raft_id);
 
-        self.account_book.query_raft_amount(&raft_id)
+        U128(self.account_book.query_raft_amount(&raft_id))
     }
 
-    pub fn accountbook_raft_value(&self, raft_id: AccountId) -> (Balance, u128) {
+    pub fn accountbook_raft_value(&self, raft_id: AccountId) -> (U128, U128) {
         let amount = self.accountbook_raft_amount(raft_id.clone());
-        let value = self.account_book.calc_raft_value(&self.price_oracle, &raft_id, amount);
-        (amount, value)
+        let value = self.account_book.calc_raft_value(&self.valuation(), &raft_id, amount.0);
+        (amount, U128(value))
     }
 
-    pub fn accountbook_raft_total_value(&self) -> u128 {
-        self.account_book.calc_raft_total_value(&self.price_oracle)
+    /// Prices every raft, see `get_total_values` for the total stored by keepers
+    /// and `accountbook_raft_values` to page through the rafts.
+    pub fn accountbook_raft_total_value(&self) -> U128 {
+        U128(self.account_book.calc_raft_total_value(&self.valuation()))
     }
 
-    pub fn accountbook_user_raft_amount(&self, user: AccountId, raft_id: AccountId) -> Balance {
+    /// Rafts of the account book as (raft, amount, value), `limit` capped at `max_rafts`.
+    pub fn accountbook_raft_values(&self, from_index: u64, limit: u64) -> Vec<(AccountId, U128, U128)> {
+        let valuation = self.valuation();
+        self.account_book.query_raft_amounts(from_index, std::cmp::min(limit, self.max_rafts)).into_iter()
+            .map(|(raft_id, amount)| {
+                let value = self.account_book.calc_raft_value(&valuation, &raft_id, amount);
+                (raft_id, U128(amount), U128(value))
+            })
+            .collect()
+    }
+
+    pub fn accountbook_user_raft_amount(&self, user: AccountId, raft_id: AccountId) -> U128 {
         self.assert_query_authority(user.clone());
         self.is_in_whitelisted_rafts(&raft_id);
 
-        self.account_book.query_user_raft_amount(&user, &raft_id)
+        U128(self.account_book.query_user_raft_amount(&user, &raft_id))
     }
 
-    pub fn accountbook_user_raft_value(&self, user: AccountId, raft_id: AccountId) -> (Balance, u128) {
+    pub fn accountbook_user_raft_value(&self, user: AccountId, raft_id: AccountId) -> (U128, U128) {
         let amount = self.accountbook_user_raft_amount(user.clone(), raft_id.clone());
-        let value = self.account_book.calc_raft_value(&self.price_oracle, &raft_id, amount);
-        (amount, value)
+        let value = self.account_book.calc_raft_value(&self.valuation(), &raft_id, amount.0);
+        (amount, U128(value))
     }
 
-    pub fn accountbook_user_raft_total_value(&self, user: AccountId) -> u128 {
+    pub fn accountbook_user_raft_total_value(&self, user: AccountId) -> U128 {
         self.assert_query_authority(user.clone());
 
-        self.account_book.calc_user_raft_total_value(&self.price_oracle, &user)
+        U128(self.account_book.calc_user_raft_total_value(&self.valuation(), &user))
+    }
+
+    /// Account Related
+    /// Paginated list of registered accounts.
+    pub fn get_accounts(&self, from_index: u64, limit: u64) -> Vec<AccountId> {
+        self.account_ids.iter().skip(from_index as usize).take(limit as usize).collect()
+    }
+
+    /// Operation nonce of the account, `None` if it's not registered.
+    pub fn get_account_nonce(&self, account_id: AccountId) -> Option<u64> {
+        self.internal_get_account(&account_id).map(|account| account.nonce)
+    }
+
+    /// Amount of given token kept in lost-found for the account.
+    pub fn get_lostfound(&self, account_id: AccountId, token_id: AccountId) -> U128 {
+        U128(self.lostfound.get(&(account_id, token_id)).unwrap_or(0))
+    }
+
+    /// Number of registered accounts.
+    pub fn account_count(&self) -> u64 {
+        self.account_ids.len()
+    }
+
+    /// Storage Related
+    /// NEAR needed to register an account.
+    pub fn min_storage_balance(&self) -> U128 {
+        U128(Account::min_storage_usage())
+    }
+
+    /// NEAR needed to register `count` more tokens in an account.
+    pub fn estimate_storage_for_tokens(&self, count: u64) -> U128 {
+        U128(Account::storage_usage_for_tokens(count))
+    }
+
+    pub fn account_storage_state(&self, account_id: AccountId) -> Option<AccountStorageState> {
+        self.internal_get_account(&account_id).map(|account| account.storage_state())
+    }
+
+    /// Every error the contract can fail with, as `(code, message)`.
+    pub fn get_error_codes(&self) -> Vec<(u32, String)> {
+        errors::CraftingError::ALL.iter().map(|error| (error.code(), error.message().to_string())).collect()
+    }
+
+    /// Config Related
+    pub fn get_config(&self) -> ContractConfig {
+        ContractConfig {
+            owner_id: self.owner_id.clone(),
+            treasury_id: self.treasury_id.clone(),
+            state: self.state.clone(),
+            leverage_ratio: self.leverage_ratio,
+            interest_fee: self.interest_fee,
+            exchange_fee: self.exchange_fee,
+            stability_fee: self.stability_fee,
+            interest_mode: self.interest_mode,
+            mint_fee: self.mint_fee,
+            fee_divisor: utils::FEE_DIVISOR,
+            price_precision: utils::PRICE_PRECISION,
+            value_decimals: utils::VALUE_DECIMALS,
+            ratio_divisor: U128(utils::RATIO_DIVISOR),
+            timelock_delay: U64(self.timelock.query_delay()),
+            max_rafts: self.max_rafts,
+            token_count: self.token_list.len(),
+            raft_count: self.raft_list.len(),
+        }
     }
 
     /// Owner Related
     pub fn contract_owner(&self) -> AccountId {
         self.owner_id.clone()
     }
+
+    pub fn get_treasury(&self) -> AccountId {
+        self.treasury_id.clone()
+    }
+
+    pub fn get_guardians(&self) -> Vec<AccountId> {
+        self.guardians.to_vec()
+    }
+
+    pub fn get_keepers(&self) -> Vec<AccountId> {
+        self.keepers.to_vec()
+    }
+
+    pub fn get_rusd(&self) -> Option<AccountId> {
+        self.rusd_id.clone()
+    }
+
+    pub fn get_settlement_rafts(&self) -> Vec<AccountId> {
+        self.settlement_rafts.to_vec()
+    }
+
+    pub fn get_wnear(&self) -> Option<AccountId> {
+        self.wnear_id.clone()
+    }
+
+    /// Timelock Related
+    pub fn get_timelock_delay(&self) -> U64 {
+        U64(self.timelock.query_delay())
+    }
+
+    pub fn get_pending_param_changes(&self) -> Vec<(ChangeId, PendingChange)> {
+        self.timelock.query_pending()
+    }
+
+    /// Operation log Related
+    /// Paginated list of the logged operations, ids below `get_operation_bounds().0` have been dropped.
+    pub fn get_operations(&self, from_id: OperationId, limit: u64) -> Vec<(OperationId, Operation)> {
+        self.oplog.query_range(from_id, limit)
+    }
+
+    /// Id of the oldest kept operation and id of the next one.
+    pub fn get_operation_bounds(&self) -> (OperationId, OperationId) {
+        self.oplog.query_bounds()
+    }
+}
+
+fn to_json_amounts(amounts: Vec<(AccountId, Balance)>) -> Vec<(AccountId, U128)> {
+    amounts.into_iter().map(|(id, amount)| (id, U128(amount))).collect()
 }
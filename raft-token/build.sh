@@ -3,4 +3,5 @@ set -e
 
 RUSTFLAGS='-C link-arg=-s' cargo +stable build --target wasm32-unknown-unknown --release
 cd ..
+mkdir -p res
 cp target/wasm32-unknown-unknown/release/raft_token.wasm res/
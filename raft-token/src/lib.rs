@@ -13,6 +13,16 @@ use near_sdk::{
 #[near_bindgen]
 #[derive(BorshSerialize, BorshDeserialize, PanicOnDefault)]
 pub struct Contract {
+    owner_id: AccountId,
+    /// Account allowed to mint and burn, the crafting contract.
+    controller_id: AccountId,
+    token: FungibleToken,
+    metadata: LazyOption<FungibleTokenMetadata>,
+}
+
+/// Layout of the token before the controller was added.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct ContractV0 {
     owner_id: AccountId,
     token: FungibleToken,
     metadata: LazyOption<FungibleTokenMetadata>,
@@ -49,15 +59,19 @@ impl Contract {
                 reference_hash: None,
                 decimals: 18,
             },
+            None,
         )
     }
 
+    /// Initializes the token, `controller_id` defaults to the owner.
     #[init]
-    pub fn new(owner_id: AccountId, total_supply: U128, metadata: FungibleTokenMetadata) -> Self {
+    pub fn new(owner_id: AccountId, total_supply: U128, metadata: FungibleTokenMetadata,
+               controller_id: Option<AccountId>) -> Self {
         require!(!env::state_exists(), "Already initialized");
         metadata.assert_valid();
         let mut this = Self {
             owner_id: owner_id.clone(),
+            controller_id: controller_id.unwrap_or_else(|| owner_id.clone()),
             token: FungibleToken::new(StorageKey::FungibleToken),
             metadata: LazyOption::new(StorageKey::Metadata, Some(&metadata)),
         };
@@ -66,6 +80,20 @@ impl Contract {
         this
     }
 
+    /// Adds the controller to a token deployed before it existed, which minted and burned as its owner.
+    /// Tokens initialized with a controller already have the current layout and must not call it.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate(controller_id: AccountId) -> Self {
+        let old: ContractV0 = env::state_read().expect("Not initialized");
+        Self {
+            owner_id: old.owner_id,
+            controller_id,
+            token: old.token,
+            metadata: old.metadata,
+        }
+    }
+
     fn on_account_closed(&mut self, account_id: AccountId, balance: Balance) {
         log!("Closed @{} with {}", account_id, balance);
     }
@@ -74,15 +102,29 @@ impl Contract {
         log!("Account @{} burned {}", account_id, amount);
     }
 
-    pub fn mint(&mut self, account_id: AccountId, amount: U128) {
+    pub fn set_controller(&mut self, controller_id: AccountId) {
         assert_eq!(env::predecessor_account_id(), self.owner_id, "Unauthorized");
 
-        self.token.internal_register_account(&account_id);
+        log!("Controller changed from @{} to @{}", self.controller_id, controller_id);
+        self.controller_id = controller_id;
+    }
+
+    pub fn get_controller(&self) -> AccountId {
+        self.controller_id.clone()
+    }
+
+    /// Mints to the account, which is registered first if needed.
+    pub fn mint(&mut self, account_id: AccountId, amount: U128) {
+        assert_eq!(env::predecessor_account_id(), self.controller_id, "Unauthorized");
+
+        if !self.token.accounts.contains_key(&account_id) {
+            self.token.internal_register_account(&account_id);
+        }
         self.token.internal_deposit(&account_id, amount.into());
     }
 
     pub fn burn(&mut self, account_id: AccountId, amount: U128) {
-        assert_eq!(env::predecessor_account_id(), self.owner_id, "Unauthorized");
+        assert_eq!(env::predecessor_account_id(), self.controller_id, "Unauthorized");
 
         self.token.internal_withdraw(&account_id, amount.into());
     }
@@ -120,7 +162,7 @@ mod tests {
     fn test_new() {
         let mut context = get_context(accounts(1));
         testing_env!(context.build());
-        let contract = Contract::new_default_meta(accounts(1).into(), TOTAL_SUPPLY.into());
+        let contract = Contract::new_default_meta(accounts(1), TOTAL_SUPPLY.into());
         testing_env!(context.is_view(true).build());
         assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY);
         assert_eq!(contract.ft_balance_of(accounts(1)).0, TOTAL_SUPPLY);
@@ -138,7 +180,7 @@ mod tests {
     fn test_transfer() {
         let mut context = get_context(accounts(2));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(2).into(), TOTAL_SUPPLY.into());
+        let mut contract = Contract::new_default_meta(accounts(2), TOTAL_SUPPLY.into());
         testing_env!(context
             .storage_usage(env::storage_usage())
             .attached_deposit(contract.storage_balance_bounds().min.into())
@@ -167,9 +209,9 @@ mod tests {
 
     #[test]
     fn test_basics() {
-        let mut context = VMContextBuilder::new();
+        let mut context = get_context(accounts(1));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(1).into(), 0.into());
+        let mut contract = Contract::new_default_meta(accounts(1), 0.into());
         testing_env!(context
             .attached_deposit(125 * env::storage_byte_cost())
             .build());
@@ -187,7 +229,55 @@ mod tests {
         contract.ft_transfer(accounts(1), 1_000.into(), None);
         assert_eq!(contract.ft_balance_of(accounts(1)), 1_000.into());
 
+        testing_env!(context
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(1))
+            .build());
         contract.burn(accounts(1), 500.into());
         assert_eq!(contract.ft_balance_of(accounts(1)), 500.into());
     }
+
+    #[test]
+    fn test_controller() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 0.into());
+        contract.set_controller(accounts(3));
+        assert_eq!(contract.get_controller(), accounts(3));
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.mint(accounts(0), 1_000.into());
+        contract.mint(accounts(0), 1_000.into());
+        assert_eq!(contract.ft_balance_of(accounts(0)), 2_000.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_mint_unauthorized() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 0.into());
+        contract.set_controller(accounts(3));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.mint(accounts(0), 1_000.into());
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = Contract::new_default_meta(accounts(1), TOTAL_SUPPLY.into());
+        env::state_write(&ContractV0 {
+            owner_id: contract.owner_id,
+            token: contract.token,
+            metadata: contract.metadata,
+        });
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        let contract = Contract::migrate(accounts(3));
+        assert_eq!(contract.get_controller(), accounts(3));
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, TOTAL_SUPPLY);
+        assert_eq!(contract.ft_metadata().symbol, "RAFT");
+    }
 }